@@ -0,0 +1,185 @@
+// Control API for scripts, test harnesses and dashboards, with --api <[host]:port>. Over HTTP:
+//   GET  /registers            V0-VF, I, PC, SP, timers, stack, keypad and run state as JSON
+//   GET  /state                the whole machine as JSON, the format of the console's export
+//   PUT  /state                restore a state in that format, the body
+//   GET  /screen               the display as a PNG
+//   POST /pause, /resume       stop and continue emulation
+//   POST /reset                restart the rom
+//   POST /load                 load the rom at the path in the body
+//   POST /save-state/<slot>    save state slot 0-9, as Ctrl+0-9 and F7 do
+//   POST /load-state/<slot>
+//   POST /key/<key>/down       press CHIP-8 key 0-F, and /up to release it
+//   POST /command              run a debugger console command, the body, and return its output
+// Errors come back as 400 with the message as text. A WebSocket to any path takes the same
+// requests as text messages, "POST /pause" or "PUT /state <json>", and answers each with a text
+// message of the status code, a space and the body, or for /screen a binary message of the PNG.
+//
+// Every request needs the token printed at startup, as "Authorization: Bearer <token>" or, for
+// browser WebSockets which can't set headers, a ?token=<token> query. Requests from other web sites
+// open in a browser are refused by their Origin and Host, see websocket::check_origin, though
+// without the token they'd fail anyway.
+//   curl -H "Authorization: Bearer $TOKEN" -d "set v3 9" http://127.0.0.1:8765/command
+//
+// The address defaults to 127.0.0.1 as with --gdb.
+
+use std::fmt::Write;
+use std::net::TcpListener;
+
+use rand::random;
+
+use crate::chip8::{parse_key, VM};
+use crate::command;
+use crate::savestate::SLOTS;
+use crate::snapshot::Snapshot;
+use crate::websocket::{self, Connection, Message, Request};
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn text(text: String) -> Self {
+        Self { status: 200, content_type: "text/plain; charset=utf-8", body: text.into_bytes() }
+    }
+
+    fn json(json: String) -> Self {
+        Self { status: 200, content_type: "application/json", body: json.into_bytes() }
+    }
+
+    fn error(status: u16, message: String) -> Self {
+        Self { status, content_type: "text/plain; charset=utf-8", body: message.into_bytes() }
+    }
+
+    fn status_line(&self) -> &'static str {
+        match self.status {
+            200 => "200 OK",
+            403 => "403 Forbidden",
+            404 => "404 Not Found",
+            405 => "405 Method Not Allowed",
+            _ => "400 Bad Request",
+        }
+    }
+}
+
+pub struct ApiServer {
+    listener: TcpListener,
+    clients: Vec<Connection>,
+    token: String,
+}
+
+impl ApiServer {
+    pub fn listen(address: &str) -> Result<Self, String> {
+        let (listener, address) = websocket::listen(address, "control API")?;
+        let token = format!("{:016x}{:016x}", random::<u64>(), random::<u64>());
+        println!("Control API on http://{}/, token {}", address, token);
+        Ok(Self { listener, clients: Vec::new(), token })
+    }
+
+    // Accept connections and answer the requests that have arrived
+    pub fn poll(&mut self, vm: &mut VM) {
+        self.clients.extend(websocket::accept(&self.listener));
+        for client in &mut self.clients {
+            client.read();
+            if let Some(request) = client.request() {
+                let allowed = websocket::check_origin(&request, &self.listener).and_then(|()| check_token(&request, &self.token));
+                match (allowed, request.websocket_key) {
+                    (Err(e), _) => {
+                        let response = Response::error(403, format!("{}\n", e));
+                        client.respond(response.status_line(), response.content_type, &response.body);
+                    }
+                    (Ok(()), Some(key)) => client.upgrade(&key),
+                    (Ok(()), None) => {
+                        let path = request.path.split('?').next().unwrap_or_default();
+                        let response = handle(vm, &request.method, path, &request.body);
+                        client.respond(response.status_line(), response.content_type, &response.body);
+                    }
+                }
+            }
+            for message in client.messages() {
+                let Message::Text(text) = message else { continue };
+                let mut words = text.splitn(3, ' ');
+                let (method, path) = (words.next().unwrap_or_default(), words.next().unwrap_or_default());
+                let response = handle(vm, method, path, words.next().unwrap_or_default().as_bytes());
+                if response.content_type == "image/png" {
+                    client.send(&Message::Binary(response.body));
+                } else {
+                    client.send(&Message::Text(format!("{} {}", response.status, String::from_utf8_lossy(&response.body))));
+                }
+            }
+        }
+        self.clients.retain(|client| !client.finished());
+    }
+}
+
+fn check_token(request: &Request, token: &str) -> Result<(), String> {
+    let bearer = request.authorization.as_deref().and_then(|value| value.strip_prefix("Bearer ")).map(str::trim);
+    let query = request.path.split_once('?').and_then(|(_, query)| query.split('&').find_map(|pair| pair.strip_prefix("token=")));
+    match bearer.or(query) {
+        Some(given) if given == token => Ok(()),
+        Some(_) => Err("wrong token".to_string()),
+        None => Err("no token, see the one printed at startup".to_string()),
+    }
+}
+
+fn handle(vm: &mut VM, method: &str, path: &str, body: &[u8]) -> Response {
+    let body = String::from_utf8_lossy(body).trim().to_string();
+    let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+    let result = match (method, segments.as_slice()) {
+        ("GET", ["registers"]) => Ok(Response::json(registers(vm))),
+        ("GET", ["state"]) => Ok(Response::json(vm.snapshot().to_json())),
+        ("PUT", ["state"]) => Snapshot::from_json(&body).map(|snapshot| {
+            vm.restore(&snapshot);
+            Response::text("state restored".to_string())
+        }),
+        ("GET", ["screen"]) => Ok(Response { status: 200, content_type: "image/png", body: vm.display_png(1) }),
+        ("POST", ["pause"]) => command::execute(vm, "pause").map(Response::text),
+        ("POST", ["resume"]) => command::execute(vm, "continue").map(Response::text),
+        ("POST", ["reset"]) => command::execute(vm, "reset").map(Response::text),
+        ("POST", ["load"]) if !body.is_empty() => command::execute(vm, &format!("load {}", body)).map(Response::text),
+        ("POST", ["save-state", slot]) => parse_slot(slot).and_then(|slot| {
+            vm.save_state(&vm.slot_path(slot))?;
+            Ok(Response::text(format!("saved slot {}", slot)))
+        }),
+        ("POST", ["load-state", slot]) => parse_slot(slot).and_then(|slot| {
+            vm.load_state(&vm.slot_path(slot))?;
+            Ok(Response::text(format!("loaded slot {}", slot)))
+        }),
+        ("POST", ["key", key, action @ ("down" | "up")]) => parse_key(key).map(|key| {
+            vm.keypad[key] = *action == "down";
+            Response::text(format!("key {:X} {}", key, action))
+        }),
+        ("POST", ["command"]) => command::execute(vm, &body).map(Response::text),
+        (_, ["registers" | "state" | "screen" | "pause" | "resume" | "reset" | "load" | "save-state" | "load-state" | "key" | "command", ..]) => {
+            Ok(Response::error(405, format!("{} {} isn't supported, see the list in api.rs", method, path)))
+        }
+        _ => Ok(Response::error(404, format!("no {}", path))),
+    };
+    result.unwrap_or_else(|e| Response::error(400, e))
+}
+
+fn parse_slot(text: &str) -> Result<u8, String> {
+    match text.parse() {
+        Ok(slot) if slot < SLOTS => Ok(slot),
+        _ => Err(format!("invalid slot {}, expected 0-{}", text, SLOTS - 1)),
+    }
+}
+
+fn registers(vm: &VM) -> String {
+    let list = |values: &mut dyn Iterator<Item = u64>| values.map(|v| v.to_string()).collect::<Vec<_>>().join(", ");
+    let mut json = String::from("{\n");
+    for (name, value) in [("pc", vm.pc as u64), ("i", vm.i as u64), ("sp", vm.sp as u64), ("delay", vm.delay as u64), ("sound", vm.sound as u64), ("frame", vm.frame), ("cycles", vm.cycles)] {
+        writeln!(json, "  \"{}\": {},", name, value).unwrap();
+    }
+    writeln!(json, "  \"v\": [{}],", list(&mut vm.v.iter().map(|v| *v as u64))).unwrap();
+    writeln!(json, "  \"stack\": [{}],", list(&mut vm.stack.iter().map(|v| *v as u64))).unwrap();
+    writeln!(json, "  \"keypad\": [{}],", vm.keypad.iter().map(|down| down.to_string()).collect::<Vec<_>>().join(", ")).unwrap();
+    writeln!(json, "  \"paused\": {},", vm.paused).unwrap();
+    match &vm.pause_message {
+        Some(message) => writeln!(json, "  \"pause_message\": \"{}\"", message.replace('\\', "\\\\").replace('"', "\\\"")).unwrap(),
+        None => json.push_str("  \"pause_message\": null\n"),
+    }
+    json.push_str("}\n");
+    json
+}
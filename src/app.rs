@@ -0,0 +1,914 @@
+// The emulator's window and main loop: SDL events and hotkeys, and the timing of emulation,
+// the 60 Hz timers and drawing
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod, Scancode};
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::Sdl;
+
+use crate::api::ApiServer;
+use crate::audio::{self, VOLUME_STEP};
+use crate::bezel::Bezel;
+use crate::chat::ChatInput;
+use crate::chip8::VM;
+use crate::config::Config;
+use crate::command;
+use crate::coverage::Coverage;
+#[cfg(feature = "egui")]
+use crate::debugwindow::DebugWindow;
+use crate::frameblend::FrameBlend;
+use crate::gdb::GdbStub;
+use crate::gif::GifRecorder;
+use crate::hotkeys::{describe_chord, Chord, Hotkey};
+use crate::input::{InputProfile, Joysticks};
+use crate::inputscript::InputScript;
+use crate::keypad::{OnscreenKeypad, Pointer, TOUCH_MOUSE_ID};
+use crate::layout::FullscreenMode;
+use crate::loopdetect::LoopDetector;
+use crate::macros::MacroState;
+use crate::memview::KeyResult;
+#[cfg(feature = "metrics")]
+use crate::metrics::MetricsServer;
+use crate::movie::{Movie, MovieMode};
+use crate::palette::{next_preset, Adjustment};
+use crate::phosphor::Phosphor;
+use crate::profile::Profiler;
+use crate::remap::{Remap, RemapResult};
+use crate::renderer::create_canvas;
+use crate::romsettings::RomSettings;
+use crate::savestate::SLOTS;
+use crate::screenshot::FrameDump;
+use crate::script::Script;
+use crate::smc::SmcWatch;
+use crate::speed::{describe_speed, NORMAL_SPEED};
+use crate::stream::StreamServer;
+use crate::symbols::Symbols;
+use crate::toolwindow::ToolWindows;
+use crate::trace::{TraceComparer, Tracer};
+use crate::video::VideoRecorder;
+use crate::wav::WavRecorder;
+
+// The emulator with its window, what chip8-rust runs unless built without the sdl feature
+pub fn run(args: Vec<String>) -> Result<(), String> {
+    let config = Config::from_args(args)?;
+    // Ask Windows for real pixels instead of a bitmap it stretches on scaled displays
+    sdl2::hint::set("SDL_WINDOWS_DPI_AWARENESS", "permonitorv2");
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+    // In logical pixels (points on macOS); on a high-DPI display the drawable has more real pixels
+    // and the layout is scaled to them, see layout.rs
+    let window_scale = 10;
+    let mut canvas = create_canvas(&video_subsystem, &config.renderer, config.vsync, 64 * window_scale, 32 * window_scale)?;
+    canvas.set_draw_color(Color::RGB(255, 255, 255));
+    canvas.clear();
+    canvas.present();
+
+    // Scale quality is fixed when a texture is created, so this has to come before any are
+    sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", if config.smooth { "linear" } else { "nearest" });
+    let texture_creator = canvas.texture_creator();
+    let mut vm = VM::new(canvas, &texture_creator);
+    vm.initialize_texture()?;
+    vm.integer_scaling = config.integer_scale;
+    vm.key_wait_policy = config.key_wait;
+    if let Some(path) = &config.bezel {
+        vm.bezel = Some(Bezel::load(&texture_creator, path, config.bezel_margins)?);
+    }
+    if config.keypad {
+        vm.onscreen_keypad = Some(OnscreenKeypad::default());
+    }
+    vm.fit_window(window_scale)?;
+    vm.init_font_set();
+    if let Some(path) = &config.symbols {
+        vm.symbols = Symbols::load(path)?;
+    }
+    vm.load_rom(&config.rom)?;
+    let mut rom_settings = RomSettings::load(vm.rom_hash);
+    if let Some(rotation) = config.rotation.or(rom_settings.rotation) {
+        vm.set_rotation(rotation, window_scale)?;
+    }
+    if config.fullscreen {
+        set_fullscreen(&mut vm, &sdl_context, config.fullscreen_mode, true)?;
+    }
+    vm.muted = config.mute;
+    vm.palette = config.palette;
+    vm.adjustment = Adjustment { contrast: config.contrast, gamma: config.gamma };
+    vm.phosphor = config.phosphor.map(Phosphor::new);
+    vm.frame_blend = config.frame_blend.map(FrameBlend::new);
+    vm.crt = config.crt;
+    vm.visual_buzzer = config.visual_buzzer;
+    if let Some(path) = &config.trace {
+        vm.tracer = Some(Tracer::create(path)?);
+    }
+    if let Some(path) = &config.compare_trace {
+        vm.trace_comparer = Some(TraceComparer::open(path)?);
+    }
+    vm.loop_detector = LoopDetector::new(config.loop_threshold);
+    if let Some(mode) = config.smc {
+        vm.smc_watch = Some(SmcWatch::new(mode));
+    }
+    if config.coverage || config.heatmap.is_some() {
+        vm.coverage = Some(Coverage::new());
+    }
+    if config.profile {
+        vm.profiler = Some(Profiler::new());
+    }
+
+    // Offer to continue from the state written when this rom was last closed
+    let autosave = vm.autosave_path();
+    let mut resume_offer = config.autosave && autosave.exists() && config.play.is_none() && config.record.is_none();
+    if resume_offer {
+        vm.paused = true;
+        vm.pause_message = Some("Y RESUMES LAST SESSION".to_string());
+    }
+
+    if let Some(path) = &config.play {
+        vm.movie = Some(Movie::play(path, &mut vm, config.instructions_per_frame)?);
+    } else if let Some(path) = &config.record {
+        vm.movie = Some(Movie::record(path, &mut vm, config.instructions_per_frame));
+    }
+
+    if let Some(path) = &config.gif {
+        vm.gif = Some(GifRecorder::create(path, GIF_SCALE, vm.display_palette())?);
+    }
+    if let Some(directory) = &config.dump_frames {
+        vm.frame_dump = Some(FrameDump::new(directory, config.screenshot_scale.unwrap_or(1), config.dump_limit)?);
+    }
+    if let Some(path) = &config.video {
+        vm.video = Some(VideoRecorder::create(path, window_scale as usize, vm.display_palette())?);
+    }
+    if let Some(path) = &config.record_audio {
+        vm.wav = Some(WavRecorder::create(path, &config)?);
+    }
+
+    let script = match &config.script {
+        Some(path) => Some(Script::load(path, &mut vm)?),
+        None => None,
+    };
+    let mut input_script = match &config.input_script {
+        Some(path) => Some(InputScript::open(path, true)?),
+        None => None,
+    };
+    let mut gdb = match &config.gdb {
+        Some(address) => Some(GdbStub::listen(address)?),
+        None => None,
+    };
+    let mut stream = match &config.stream {
+        Some(address) => Some(StreamServer::listen(address)?),
+        None => None,
+    };
+    let mut api = match &config.api {
+        Some(address) => Some(ApiServer::listen(address)?),
+        None => None,
+    };
+    #[cfg(feature = "metrics")]
+    let mut metrics = match &config.metrics {
+        Some(address) => Some(MetricsServer::listen(address)?),
+        None => None,
+    };
+    #[cfg(not(feature = "metrics"))]
+    if config.metrics.is_some() {
+        return Err("this build has no metrics support, rebuild with --features metrics".to_string());
+    }
+    let mut chat = match &config.chat {
+        Some(channel) => Some(ChatInput::connect(channel, config.chat_hold, config.chat_cooldown, &config.chat_keys)?),
+        None => None,
+    };
+
+    let mut buzzer = match audio::open(&sdl_context, &config) {
+        Ok(buzzer) => {
+            let spec = buzzer.spec();
+            if let Some(latency) = spec.latency() {
+                println!("Audio at {} Hz, {} sample buffer ({:.1} ms)", spec.sample_rate, spec.buffer.unwrap_or(0), latency.as_secs_f64() * 1000.0);
+            }
+            vm.audio_spec = Some(spec);
+            Some(buzzer)
+        }
+        Err(e) => {
+            eprintln!("No audio, {}", e);
+            None
+        }
+    };
+
+    // Timers tick on a fixed schedule: each tick moves the deadline on by exactly 1/60 s, so a slow
+    // pass of the loop is made up by the next ones instead of stretching the frame
+    let timer_interval = Duration::from_secs_f64(1.0 / 60.0);
+    let mut next_timer_tick = Instant::now() + timer_interval;
+    // Least time between redraws from running code. With vsync and no --fps cap, one per refresh,
+    // since presenting more often would block on the display and slow emulation down.
+    let present_interval = match config.fps {
+        Some(fps) => Duration::from_secs_f64(1.0 / fps as f64),
+        None if config.vsync => {
+            let refresh_rate = video_subsystem.current_display_mode(0).map(|mode| mode.refresh_rate).unwrap_or(60);
+            Duration::from_secs_f64(1.0 / refresh_rate.max(1) as f64)
+        }
+        None => Duration::ZERO,
+    };
+    let mut next_present = Instant::now();
+    let emulation_interval = Duration::from_secs_f64(1.0 / (60.0 * config.instructions_per_frame as f64));
+    let mut last_emulation_cycle = Instant::now();
+    // Counts towards the next timer tick when running uncapped, see speed.rs
+    let mut cycles_since_tick = 0;
+    let mut slow_motion = false;
+
+    let mut tool_windows = ToolWindows::open(&video_subsystem, &config.tool_windows)?;
+    #[cfg(feature = "egui")]
+    let mut debug_window: Option<DebugWindow> = None;
+    let mut input = InputProfile::open(config.input.as_deref())?;
+    if let Some(keys) = config.keys {
+        input.set_layout(keys);
+    }
+    if let Some(keycode) = config.fullscreen_key {
+        input.hotkeys.set(Hotkey::Fullscreen, &[Chord::key(keycode)]);
+    }
+    let mut joysticks = Joysticks::new(sdl_context.joystick()?);
+
+    // SDL event loop to keep the window open
+    let mut event_pump = sdl_context.event_pump()?;
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            // Any of the windows losing focus pauses and any gaining it resumes, so moving between
+            // the display and the tool windows carries on. Frozen emulation silences the buzzer.
+            if config.focus_pause {
+                match event {
+                    Event::Window { win_event: WindowEvent::FocusLost, .. } if !vm.paused => {
+                        vm.pause_for_focus();
+                        vm.draw_display(window_scale);
+                    }
+                    Event::Window { win_event: WindowEvent::FocusGained, .. } if vm.focus_paused() => {
+                        vm.resume();
+                        vm.draw_display(window_scale);
+                    }
+                    _ => {}
+                }
+            }
+            if tool_windows.handle_event(&mut vm, &event) {
+                continue;
+            }
+            #[cfg(feature = "egui")]
+            if let Some(window) = debug_window.as_mut() {
+                let handled = window.handle_event(&event);
+                if !window.open {
+                    debug_window = None;
+                }
+                if handled {
+                    continue;
+                }
+            }
+            let hotkey = match &event {
+                Event::KeyDown { keycode: Some(k), scancode, keymod, .. } => input.hotkey(*k, *scancode, *keymod),
+                _ => None,
+            };
+            match event {
+                Event::Quit { .. } => { break 'running }
+                // With tool windows open, closing the main window doesn't send Quit
+                Event::Window { win_event: WindowEvent::Close, .. } => { break 'running }
+                Event::Window { win_event: WindowEvent::SizeChanged(..) | WindowEvent::Exposed, .. } => {
+                    vm.apply_layout(window_scale)?;
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::Console) && vm.memory_view.input.is_none() => {
+                    vm.console.toggle();
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { keycode: Some(k), .. } if vm.console.open => {
+                    if let Some(line) = vm.console.handle_key(k) {
+                        let output = match command::execute(&mut vm, &line) {
+                            Ok(output) => output,
+                            Err(error) => format!("error: {}", error),
+                        };
+                        vm.console.print(&output);
+                    }
+                    vm.draw_display(window_scale);
+                }
+                Event::TextInput { text, .. } if vm.console.open => {
+                    vm.console.handle_text(&text);
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { keycode: Some(k), repeat: false, .. } if vm.remap.is_some() => {
+                    match vm.remap.as_mut().unwrap().handle_key(k) {
+                        RemapResult::Waiting => {}
+                        RemapResult::Done(chosen) => {
+                            vm.remap = None;
+                            for (keycode, key) in chosen {
+                                input.rebind_key(keycode, key);
+                            }
+                            match input.save() {
+                                Ok(()) => vm.notify("KEYS SAVED".to_string()),
+                                Err(error) => eprintln!("{}", error),
+                            }
+                        }
+                        RemapResult::Cancelled => vm.remap = None,
+                    }
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::Remap) => {
+                    vm.remap = Some(Remap::new(&input));
+                    vm.keypad = [false; 16];
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::Hud) => {
+                    vm.show_hud = !vm.show_hud;
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::MemoryView) => {
+                    if vm.memory_view.open {
+                        vm.memory_view.open = false;
+                    } else {
+                        let pc = vm.pc;
+                        vm.memory_view.open_at(pc);
+                    }
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::Coverage) => {
+                    match &vm.coverage {
+                        Some(coverage) => print!("{}", coverage.report(vm.rom_size)),
+                        None => println!("Coverage is not being recorded, start with --coverage"),
+                    }
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::Heatmap) => {
+                    // Start recording on first use so the heatmap works without --coverage
+                    vm.coverage.get_or_insert_with(Coverage::new);
+                    vm.show_heatmap = !vm.show_heatmap;
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { keycode: Some(k), .. } if vm.memory_view.open => {
+                    match vm.memory_view.handle_key(k) {
+                        KeyResult::Ignored => continue,
+                        KeyResult::Handled => {}
+                        KeyResult::Command(line) => {
+                            vm.memory_view.message = match command::execute(&mut vm, &line) {
+                                Ok(message) => message,
+                                Err(error) => format!("ERROR: {}", error),
+                            };
+                        }
+                    }
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::Magnifier) => {
+                    vm.magnifier.open = !vm.magnifier.open;
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { keycode: Some(k), keymod, .. } if vm.magnifier.open && vm.magnifier.handle_key(k, keymod) => {
+                    vm.draw_display(window_scale);
+                }
+                // Only until the first frame has run, later Y presses belong to the game
+                Event::KeyDown { keycode: Some(Keycode::Y), repeat: false, .. } if resume_offer && vm.frame == 0 => {
+                    resume_offer = false;
+                    match vm.load_state(&autosave) {
+                        Ok(()) => vm.notify("RESUMED".to_string()),
+                        Err(error) => eprintln!("{}", error),
+                    }
+                    vm.resume();
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { keycode: Some(Keycode::Return), repeat: false, .. } if vm.paused => {
+                    resume_offer = false;
+                    vm.resume();
+                    vm.draw_display(window_scale);
+                }
+                #[cfg(feature = "egui")]
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::Debugger) => {
+                    debug_window = match debug_window {
+                        Some(_) => None,
+                        None => Some(DebugWindow::open(&video_subsystem, config.instructions_per_frame)?),
+                    };
+                }
+                #[cfg(not(feature = "egui"))]
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::Debugger) => {
+                    let visible = !vm.show_debugger;
+                    vm.set_debugger_visible(visible, window_scale)?;
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { .. } if hotkey == Some(Hotkey::FrameAdvance) && !vm.memory_view.open => {
+                    // The first press pauses, every following press advances one frame
+                    if vm.paused {
+                        if let Err(report) = vm.step_frame(config.instructions_per_frame) {
+                            eprintln!("{}", report);
+                        }
+                    } else {
+                        vm.paused = true;
+                    }
+                    if vm.pause_message.is_none() {
+                        let key = input.hotkeys.chords(Hotkey::FrameAdvance).next().map(describe_chord).unwrap_or_default();
+                        vm.pause_message = Some(format!("FRAME ADVANCE ({})", key.to_uppercase()));
+                    }
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::BreakOnDraw) => {
+                    vm.break_on_draw = !vm.break_on_draw;
+                    println!("Break on draw {}", if vm.break_on_draw { "on" } else { "off" });
+                }
+                Event::KeyDown { repeat: false, .. } if matches!(hotkey, Some(Hotkey::SaveState | Hotkey::LoadState)) => {
+                    let (slot, path) = (vm.state_slot, vm.slot_path(vm.state_slot));
+                    let result = if hotkey == Some(Hotkey::LoadState) {
+                        vm.load_state(&path).map(|_| format!("LOADED SLOT {}", slot))
+                    } else {
+                        vm.save_state(&path).map(|_| format!("SAVED SLOT {}", slot))
+                    };
+                    match result {
+                        Ok(message) => vm.notify(message),
+                        Err(error) => {
+                            eprintln!("{}", error);
+                            vm.notify(format!("SLOT {} FAILED", slot));
+                        }
+                    }
+                    if vm.slot_picker.open {
+                        vm.open_slot_picker();
+                    }
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::SlotPicker) => {
+                    if vm.slot_picker.open {
+                        vm.slot_picker.open = false;
+                    } else {
+                        vm.open_slot_picker();
+                    }
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { keycode: Some(k), .. } if vm.slot_picker.open => {
+                    if vm.slot_picker_key(k) {
+                        let (slot, path) = (vm.state_slot, vm.slot_path(vm.state_slot));
+                        match vm.load_state(&path) {
+                            Ok(()) => {
+                                vm.slot_picker.open = false;
+                                vm.notify(format!("LOADED SLOT {}", slot));
+                            }
+                            Err(error) => {
+                                eprintln!("{}", error);
+                                vm.notify(format!("SLOT {} FAILED", slot));
+                            }
+                        }
+                    }
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { keycode: Some(k), keymod, repeat: false, .. } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) && slot_key(k).is_some() => {
+                    vm.state_slot = slot_key(k).unwrap();
+                    let used = if vm.slot_path(vm.state_slot).exists() { "" } else { " (EMPTY)" };
+                    vm.notify(format!("SLOT {}{}", vm.state_slot, used));
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::Gif) => {
+                    match vm.gif.take() {
+                        Some(gif) => {
+                            let path = gif.path.clone();
+                            let frames = gif.finish()?;
+                            vm.notify(format!("GIF SAVED, {} FRAMES", frames));
+                            println!("Saved {}", path);
+                        }
+                        None => {
+                            let path = format!("chip8-{}.gif", timestamp());
+                            vm.gif = Some(GifRecorder::create(&path, GIF_SCALE, vm.display_palette())?);
+                            vm.notify("RECORDING GIF".to_string());
+                        }
+                    }
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::Screenshot) => {
+                    let scale = config.screenshot_scale.unwrap_or(window_scale);
+                    match vm.save_screenshot(&config.screenshot_dir, scale, &format!("chip8-{}.png", timestamp())) {
+                        Ok(path) => vm.notify(format!("SAVED {}", path.to_uppercase())),
+                        Err(error) => eprintln!("{}", error),
+                    }
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::KeypadOverlay) => {
+                    vm.show_keypad = !vm.show_keypad;
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::Reset) => {
+                    resume_offer = false;
+                    vm.restart();
+                    vm.notify("RESET".to_string());
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::Rotate) => {
+                    let rotation = (vm.rotation + 90) % 360;
+                    vm.set_rotation(rotation, window_scale)?;
+                    rom_settings.rotation = Some(rotation);
+                    if let Err(e) = rom_settings.save(vm.rom_hash) {
+                        eprintln!("{}", e);
+                    }
+                    vm.notify(format!("ROTATION {}", rotation));
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::Palette) => {
+                    let current = if vm.inverted { [vm.palette[1], vm.palette[0]] } else { vm.palette };
+                    let (name, palette) = next_preset(&current);
+                    vm.palette = if vm.inverted { [palette[1], palette[0]] } else { palette };
+                    vm.notify(format!("PALETTE {}", name.to_uppercase()));
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::Invert) => {
+                    vm.palette.swap(0, 1);
+                    vm.inverted = !vm.inverted;
+                    vm.notify(if vm.inverted { "COLOURS INVERTED" } else { "COLOURS NORMAL" }.to_string());
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::Pause) => {
+                    if vm.paused {
+                        resume_offer = false;
+                        vm.resume();
+                    } else {
+                        vm.pause();
+                    }
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::Fullscreen) => {
+                    let fullscreen = vm.windowed_bounds.is_none();
+                    set_fullscreen(&mut vm, &sdl_context, config.fullscreen_mode, fullscreen)?;
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::SlowMotion) => {
+                    slow_motion = !slow_motion;
+                    vm.speed = if slow_motion { config.slow_motion } else { NORMAL_SPEED };
+                    vm.notify(format!("SPEED {}", describe_speed(vm.speed)));
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::FastForward) => {
+                    vm.speed = config.fast_forward;
+                    vm.draw_display(window_scale);
+                }
+                // Back to slow motion if it was on before fast-forwarding
+                Event::KeyUp { keycode: Some(k), .. } if input.is_hotkey_key(Hotkey::FastForward, k) => {
+                    vm.speed = if slow_motion { config.slow_motion } else { NORMAL_SPEED };
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::RecordMacro) => {
+                    if vm.input_macro.state == MacroState::Recording {
+                        vm.input_macro.stop_recording();
+                        match vm.input_macro.save(vm.rom_hash) {
+                            Ok(()) => vm.notify(format!("MACRO SAVED, {} FRAMES", vm.input_macro.frame_count())),
+                            Err(e) => vm.notify(e),
+                        }
+                    } else {
+                        vm.input_macro.start_recording();
+                        vm.notify("RECORDING MACRO".to_string());
+                    }
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::PlayMacro) => {
+                    match vm.input_macro.state {
+                        MacroState::Recording => {}
+                        // Pressing it again cuts the macro short
+                        MacroState::Playing(_) => {
+                            vm.input_macro.state = MacroState::Idle;
+                            vm.keypad = [false; 16];
+                        }
+                        MacroState::Idle if vm.input_macro.frame_count() == 0 => vm.notify("NO MACRO RECORDED".to_string()),
+                        MacroState::Idle => vm.input_macro.state = MacroState::Playing(0),
+                    }
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { repeat: false, .. } if hotkey == Some(Hotkey::Mute) => {
+                    vm.muted = !vm.muted;
+                    vm.notify(if vm.muted { "MUTED" } else { "SOUND ON" }.to_string());
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { .. } if matches!(hotkey, Some(Hotkey::VolumeUp | Hotkey::VolumeDown)) => {
+                    match &mut buzzer {
+                        Some(buzzer) => {
+                            let volume = if hotkey == Some(Hotkey::VolumeDown) {
+                                buzzer.volume().saturating_sub(VOLUME_STEP)
+                            } else {
+                                buzzer.volume() + VOLUME_STEP
+                            };
+                            buzzer.set_volume(volume);
+                            vm.notify(format!("VOLUME {}%", buzzer.volume()));
+                        }
+                        None => vm.notify("NO AUDIO DEVICE".to_string()),
+                    }
+                    vm.draw_display(window_scale);
+                }
+                Event::TextInput { text, .. } if vm.memory_view.input.is_some() => {
+                    vm.memory_view.handle_text(&text);
+                    vm.draw_display(window_scale);
+                }
+                Event::KeyDown { keycode: Some(k), scancode, .. } => {
+                    println!("Key down: {}", k);
+                    update_keypad(&mut vm, &mut input, k, scancode, true);
+                }
+                Event::KeyUp { keycode: Some(k), scancode, .. } => {
+                    println!("Key up: {}", k);
+                    update_keypad(&mut vm, &mut input, k, scancode, false);
+                }
+                Event::MouseButtonDown { mouse_btn: MouseButton::Left, which, x, y, .. } if which != TOUCH_MOUSE_ID => {
+                    let (x, y) = vm.layout_point(x, y, window_scale);
+                    let key = vm.onscreen_keypad_key(x, y, window_scale);
+                    point_keypad(&mut vm, &mut input, Pointer::Mouse, key);
+                }
+                Event::MouseMotion { mousestate, which, x, y, .. } if mousestate.left() && which != TOUCH_MOUSE_ID => {
+                    let (x, y) = vm.layout_point(x, y, window_scale);
+                    let key = vm.onscreen_keypad_key(x, y, window_scale);
+                    point_keypad(&mut vm, &mut input, Pointer::Mouse, key);
+                }
+                Event::MouseButtonUp { mouse_btn: MouseButton::Left, which, .. } if which != TOUCH_MOUSE_ID => {
+                    point_keypad(&mut vm, &mut input, Pointer::Mouse, None);
+                }
+                Event::FingerDown { .. } if vm.onscreen_keypad.is_none() => {
+                    vm.onscreen_keypad = Some(OnscreenKeypad::default());
+                    vm.fit_window(window_scale)?;
+                    vm.draw_display(window_scale);
+                }
+                Event::FingerDown { finger_id, x, y, .. } | Event::FingerMotion { finger_id, x, y, .. } => {
+                    let (x, y) = vm.touch_point(x, y, window_scale)?;
+                    let key = vm.onscreen_keypad_key(x, y, window_scale);
+                    point_keypad(&mut vm, &mut input, Pointer::Finger(finger_id), key);
+                }
+                Event::FingerUp { finger_id, .. } => point_keypad(&mut vm, &mut input, Pointer::Finger(finger_id), None),
+                Event::JoyDeviceAdded { which, .. } => joysticks.add(which),
+                Event::JoyDeviceRemoved { which, .. } => joysticks.remove(which),
+                Event::JoyAxisMotion { which, axis_idx, value, .. } => input.update_axis(&mut vm, joysticks.player(which), axis_idx, value),
+                Event::JoyHatMotion { which, hat_idx, state, .. } => input.update_hat(&mut vm, joysticks.player(which), hat_idx, state),
+                Event::JoyButtonDown { which, button_idx, .. } => input.update_button(&mut vm, joysticks.player(which), button_idx, true),
+                Event::JoyButtonUp { which, button_idx, .. } => input.update_button(&mut vm, joysticks.player(which), button_idx, false),
+                _ => {}
+            }
+        }
+
+        if let Some(stub) = gdb.as_mut() {
+            let was_connected = stub.connected();
+            stub.poll(&mut vm);
+            if stub.connected() != was_connected {
+                vm.draw_display(window_scale);
+            }
+        }
+        if let Some(server) = stream.as_mut() {
+            server.poll(|key, pressed| input.set(&mut vm, key, pressed));
+            server.send(&vm);
+        }
+        if let Some(server) = api.as_mut() {
+            server.poll(&mut vm);
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(server) = metrics.as_mut() {
+            server.audio_underruns = buzzer.as_mut().map_or(0, |buzzer| buzzer.underruns());
+            server.poll(&vm);
+        }
+
+        vm.update_title()?;
+        tool_windows.draw(&mut vm)?;
+        #[cfg(feature = "egui")]
+        if let Some(window) = debug_window.as_mut() {
+            window.draw(&mut vm)?;
+        }
+        let now = Instant::now();
+        input.autofire(&mut vm, now);
+        let uncapped = vm.speed.is_infinite();
+        let (emulation_step, timer_step) = if uncapped {
+            (Duration::ZERO, timer_interval)
+        } else {
+            (emulation_interval.div_f64(vm.speed), timer_interval.div_f64(vm.speed))
+        };
+        // Off normal speed there is no point drawing more often than the display refreshes
+        let present_step = if vm.speed == NORMAL_SPEED { present_interval } else { present_interval.max(timer_interval) };
+        let frozen = vm.memory_view.open || vm.slot_picker.open || vm.remap.is_some() || vm.paused;
+        if let Some(buzzer) = &mut buzzer {
+            buzzer.set_active(!frozen && !vm.muted && vm.sound > 0);
+        }
+        if frozen {
+            // Emulation is frozen while paused or while the memory viewer is open
+            last_emulation_cycle = now;
+            next_timer_tick = now + timer_step;
+            std::thread::sleep(Duration::from_millis(10));
+            continue;
+        }
+
+        if vm.movie.is_some() {
+            if uncapped || now >= next_timer_tick {
+                next_timer_tick = catch_up(next_timer_tick, now, timer_step);
+                if let Err(report) = run_movie_frame(&mut vm, &mut gdb, &script) {
+                    eprintln!("{}", report);
+                    let pc = vm.pc;
+                    vm.memory_view.open_at(pc);
+                    vm.memory_view.message = "STOPPED: TRACE DIVERGED".to_string();
+                }
+                if vm.paused {
+                    println!("{}", vm.pause_message.as_deref().unwrap_or("Paused"));
+                } else if capture_frame(&mut vm)? {
+                    break 'running;
+                }
+                if vm.paused || now >= next_present {
+                    vm.draw_display(window_scale);
+                    next_present = now + present_step;
+                }
+            }
+            continue;
+        }
+
+        if now.duration_since(last_emulation_cycle) >= emulation_step {
+            if vm.check_breakpoint() || gdb.as_mut().is_some_and(|stub| stub.hit_breakpoint(&mut vm)) {
+                vm.draw_display(window_scale);
+                continue;
+            }
+            if let Some(script) = &script {
+                script.before_instruction(&mut vm);
+            }
+            if let Err(report) = vm.emulate_cycle() {
+                eprintln!("{}", report);
+                let pc = vm.pc;
+                vm.memory_view.open_at(pc);
+                vm.memory_view.message = "STOPPED: TRACE DIVERGED".to_string();
+                vm.draw_display(window_scale);
+                continue;
+            }
+            cycles_since_tick += 1;
+            if vm.paused {
+                println!("{}", vm.pause_message.as_deref().unwrap_or("Paused"));
+                vm.draw_display(window_scale);
+                continue;
+            }
+            let redraw = vm.drawflag || vm.visual_buzzer || vm.phosphor.is_some() || vm.frame_blend.is_some() || vm.show_hud || vm.show_keypad || vm.onscreen_keypad.is_some() || vm.osd.is_some() || vm.show_heatmap || vm.show_debugger || vm.console.open || vm.speed != NORMAL_SPEED;
+            if redraw && now >= next_present {
+                vm.draw_display(window_scale);
+                next_present = now + present_step;
+            }
+            last_emulation_cycle = now;
+        }
+
+        let mut ticks = 0;
+        if uncapped {
+            if cycles_since_tick >= config.instructions_per_frame {
+                ticks = 1;
+            }
+            next_timer_tick = now + timer_interval;
+        }
+        while !uncapped && now >= next_timer_tick {
+            next_timer_tick = catch_up(next_timer_tick, now, timer_step);
+            ticks += 1;
+        }
+        for _ in 0..ticks {
+            cycles_since_tick = 0;
+            vm.next_macro_frame();
+            if input_script.as_mut().is_some_and(|script| script.next_frame(|key, pressed| input.set(&mut vm, key, pressed))) {
+                break 'running;
+            }
+            if let Some(chat) = chat.as_mut() {
+                chat.next_frame(|key, pressed| input.set(&mut vm, key, pressed));
+            }
+            vm.tick_timers();
+            if let Some(script) = &script {
+                script.after_frame(&mut vm);
+            }
+            if let Some(blend) = vm.frame_blend.as_mut() {
+                blend.push(&vm.display);
+            }
+            if capture_frame(&mut vm)? {
+                break 'running;
+            }
+        }
+    }
+
+    if let Some(movie) = vm.movie.as_ref().filter(|movie| movie.mode == MovieMode::Recording) {
+        movie.save()?;
+        println!("Saved {} frames to {}", movie.frame_count(), movie.path);
+    }
+    if let Some(gif) = vm.gif.take() {
+        let path = gif.path.clone();
+        println!("Saved {} frames to {}", gif.finish()?, path);
+    }
+    if let Some(dump) = &vm.frame_dump {
+        println!("Dumped {} frames", dump.written());
+    }
+    if let Some(video) = vm.video.take() {
+        let path = video.path.clone();
+        video.finish()?;
+        println!("Saved video to {}", path);
+    }
+    if let Some(wav) = vm.wav.take() {
+        let path = wav.path.clone();
+        println!("Saved {:.1} s of audio to {}", wav.finish()?, path);
+    }
+    if config.autosave {
+        if let Err(error) = vm.save_state(&autosave) {
+            eprintln!("{}", error);
+        }
+    }
+    if let Some(coverage) = &vm.coverage {
+        if config.coverage {
+            print!("{}", coverage.report(vm.rom_size));
+        }
+        if let Some(path) = &config.heatmap {
+            coverage.export_heatmap(path)?;
+        }
+    }
+    if let Some(profiler) = &vm.profiler {
+        print!("{}", profiler.report());
+    }
+    Ok(())
+}
+
+// Recording scale of gif captures
+const GIF_SCALE: usize = 4;
+
+// Longest stall the 60 Hz timers make up for
+const MAX_TIMER_CATCH_UP: Duration = Duration::from_secs(1);
+
+// Hand the display to the recorders after each 60 Hz frame. Returns true when a bounded frame
+// dump is complete and the emulator should exit.
+fn capture_frame(vm: &mut VM) -> Result<bool, String> {
+    if let Some(gif) = vm.gif.as_mut() {
+        gif.add_frame(&vm.display)?;
+    }
+    if let Some(video) = vm.video.as_mut() {
+        video.add_frame(&vm.display, vm.sound > 0)?;
+    }
+    if let Some(wav) = vm.wav.as_mut() {
+        wav.add_frame(vm.sound > 0)?;
+    }
+    if let Some(scale) = vm.frame_dump.as_ref().map(|dump| dump.scale) {
+        let png = vm.display_png(scale);
+        return vm.frame_dump.as_mut().unwrap().write(&png);
+    }
+    Ok(false)
+}
+
+// Deadline after the timer tick due at `deadline`. Shorter stalls are made up one tick per call,
+// anything longer (a suspended laptop, a debugger breakpoint in the host) is
+// dropped rather than replayed as a burst of ticks.
+fn catch_up(deadline: Instant, now: Instant, interval: Duration) -> Instant {
+    if now.duration_since(deadline) > MAX_TIMER_CATCH_UP {
+        now + interval
+    } else {
+        deadline + interval
+    }
+}
+
+fn set_fullscreen(vm: &mut VM, sdl_context: &Sdl, mode: FullscreenMode, fullscreen: bool) -> Result<(), String> {
+    vm.set_fullscreen(mode, fullscreen)?;
+    // The cursor stays for clicking the --keypad panel
+    sdl_context.mouse().show_cursor(!fullscreen || vm.onscreen_keypad.is_some());
+    Ok(())
+}
+
+// Milliseconds since the epoch, for naming captures
+fn timestamp() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0)
+}
+
+// One frame of movie lockstep: next input, the rest of the frame's instructions, then the timers.
+// Breakpoints and pauses can interrupt the frame, it continues where it left off afterwards.
+fn run_movie_frame(vm: &mut VM, gdb: &mut Option<GdbStub>, script: &Option<Script>) -> Result<(), String> {
+    let mut movie = vm.movie.take().unwrap();
+    if movie.cycles_left == 0 && !movie.next_frame(vm) {
+        // Carry on recording from the end, like movie record, once unpaused
+        movie.resume_recording();
+        movie.cycles_left = 0;
+        vm.movie = Some(movie);
+        vm.paused = true;
+        vm.pause_message = Some("MOVIE ENDED, NOW RECORDING".to_string());
+        return Ok(());
+    }
+    vm.movie = Some(movie);
+
+    while vm.movie.as_ref().is_some_and(|movie| movie.cycles_left > 0) {
+        if vm.check_breakpoint() || gdb.as_mut().is_some_and(|stub| stub.hit_breakpoint(vm)) {
+            return Ok(());
+        }
+        if let Some(script) = script {
+            script.before_instruction(vm);
+        }
+        vm.emulate_cycle()?;
+        if let Some(movie) = vm.movie.as_mut() {
+            movie.cycles_left -= 1;
+        }
+        if vm.paused {
+            return Ok(());
+        }
+    }
+
+    vm.tick_timers();
+    if let Some(script) = script {
+        script.after_frame(vm);
+    }
+    if let Some(blend) = vm.frame_blend.as_mut() {
+        blend.push(&vm.display);
+    }
+    Ok(())
+}
+
+// Ctrl+0-9 picks a save state slot
+fn slot_key(keycode: Keycode) -> Option<u8> {
+    let slot = keycode.into_i32() - Keycode::Num0.into_i32();
+    (0..SLOTS as i32).contains(&slot).then_some(slot as u8)
+}
+
+// Press and release keys as the mouse or a finger moves over the on-screen keypad
+fn point_keypad(vm: &mut VM, input: &mut InputProfile, pointer: Pointer, key: Option<usize>) {
+    let Some(keypad) = vm.onscreen_keypad.as_mut() else { return };
+    let (released, pressed) = keypad.point(pointer, key);
+    if let Some(key) = released {
+        input.set(vm, key, false);
+    }
+    if let Some(key) = pressed {
+        input.set(vm, key, true);
+    }
+}
+
+fn update_keypad(vm: &mut VM, input: &mut InputProfile, keycode: Keycode, scancode: Option<Scancode>, pressed: bool) {
+    if let Some(key) = input.key(keycode, scancode) {
+        input.set(vm, key, pressed);
+    }
+}
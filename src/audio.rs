@@ -0,0 +1,221 @@
+// Buzzer played while the sound timer is non-zero. The output stream runs for the whole session
+// and outputs silence when the buzzer is off, so starting and stopping a beep is only a flag change.
+
+use std::time::{Duration, Instant};
+
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::{AudioSubsystem, Sdl};
+
+use crate::config::Config;
+
+// Amplitude at 100% volume, leaving headroom so the buzzer isn't painfully loud
+const MAX_AMPLITUDE: f32 = 0.3;
+// Attack and release time. Cutting a wave off mid-cycle clicks, which is very audible in games
+// that pulse the sound timer every frame.
+const ENVELOPE_SECONDS: f32 = 0.005;
+pub const VOLUME_STEP: u8 = 10;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+    Noise,
+}
+
+pub fn parse_waveform(waveform: &str) -> Result<Waveform, String> {
+    match waveform {
+        "square" => Ok(Waveform::Square),
+        "sine" => Ok(Waveform::Sine),
+        "triangle" => Ok(Waveform::Triangle),
+        "noise" => Ok(Waveform::Noise),
+        _ => Err(format!("invalid --waveform {}, expected square, sine, triangle or noise", waveform)),
+    }
+}
+
+// Backends that can play the buzzer. SDL audio is always available, cpal only in builds with the
+// cpal feature, for platforms where SDL audio is unreliable.
+pub trait AudioBackend {
+    // Called every pass of the main loop, so implementations should only touch the audio thread
+    // when this changes
+    fn set_active(&mut self, active: bool);
+    fn volume(&self) -> u8;
+    fn set_volume(&mut self, percent: u8);
+    // What the device actually gave us, which can differ from what was asked for
+    fn spec(&self) -> AudioSpec;
+    // Times the device ran dry so far, see Tone::start_buffer
+    fn underruns(&mut self) -> u64;
+}
+
+#[derive(Clone, Copy)]
+pub struct AudioSpec {
+    pub sample_rate: u32,
+    // Samples per buffer, None when the driver doesn't say
+    pub buffer: Option<u32>,
+}
+
+impl AudioSpec {
+    // Time to play one buffer, the least delay between a sound timer change and hearing it
+    pub fn latency(&self) -> Option<Duration> {
+        self.buffer.map(|samples| Duration::from_secs_f64(samples as f64 / self.sample_rate as f64))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum AudioDriver {
+    Sdl,
+    Cpal,
+}
+
+pub fn parse_driver(driver: &str) -> Result<AudioDriver, String> {
+    match driver {
+        "sdl" => Ok(AudioDriver::Sdl),
+        "cpal" => Ok(AudioDriver::Cpal),
+        _ => Err(format!("invalid --audio {}, expected sdl or cpal", driver)),
+    }
+}
+
+pub fn open(sdl_context: &Sdl, config: &Config) -> Result<Box<dyn AudioBackend>, String> {
+    match config.audio_driver {
+        AudioDriver::Sdl => Ok(Box::new(SdlBuzzer::open(&sdl_context.audio()?, config)?)),
+        #[cfg(feature = "cpal")]
+        AudioDriver::Cpal => Ok(Box::new(crate::audio_cpal::CpalBuzzer::open(config)?)),
+        #[cfg(not(feature = "cpal"))]
+        AudioDriver::Cpal => Err("this build has no cpal support, rebuild with --features cpal".to_string()),
+    }
+}
+
+// Sample generator shared by the backends
+pub struct Tone {
+    // Position within one period, 0 to 1
+    phase: f32,
+    step: f32,
+    volume: f32,
+    active: bool,
+    // Envelope level, moves towards 1 while active and towards 0 otherwise
+    gain: f32,
+    gain_step: f32,
+    waveform: Waveform,
+    // Xorshift state for the noise waveform, which takes a new value every half period
+    noise: u32,
+    noise_level: f32,
+    sample_rate: u32,
+    // When the last buffer was asked for and its length in samples
+    last_buffer: Option<(Instant, usize)>,
+    pub underruns: u64,
+}
+
+impl Tone {
+    pub fn new(config: &Config, sample_rate: u32) -> Self {
+        Self {
+            phase: 0.0,
+            step: config.pitch as f32 / sample_rate as f32,
+            volume: config.volume as f32 / 100.0 * MAX_AMPLITUDE,
+            active: false,
+            gain: 0.0,
+            gain_step: 1.0 / (ENVELOPE_SECONDS * sample_rate as f32),
+            waveform: config.waveform,
+            noise: 0x12345678,
+            noise_level: 1.0,
+            sample_rate,
+            last_buffer: None,
+            underruns: 0,
+        }
+    }
+
+    pub fn set_active(&mut self, active: bool) {
+        self.active = active;
+    }
+
+    pub fn set_volume(&mut self, percent: u8) {
+        self.volume = percent as f32 / 100.0 * MAX_AMPLITUDE;
+    }
+
+    // Called by the backends before filling a buffer of `samples`. Buffers are asked for as the
+    // previous one plays, so one coming more than a buffer late means the device had nothing to play.
+    pub fn start_buffer(&mut self, samples: usize) {
+        let now = Instant::now();
+        if let Some((last, length)) = self.last_buffer {
+            if now - last > Duration::from_secs_f64(2.0 * length as f64 / self.sample_rate as f64) {
+                self.underruns += 1;
+            }
+        }
+        self.last_buffer = Some((now, samples));
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let level = match self.waveform {
+            Waveform::Square => if self.phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Sine => (self.phase * std::f32::consts::TAU).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (self.phase - 0.5).abs(),
+            Waveform::Noise => self.noise_level,
+        };
+        self.gain = if self.active { (self.gain + self.gain_step).min(1.0) } else { (self.gain - self.gain_step).max(0.0) };
+        let sample = level * self.volume * self.gain;
+
+        let previous = self.phase;
+        self.phase = (self.phase + self.step) % 1.0;
+        if (previous < 0.5) != (self.phase < 0.5) {
+            self.noise ^= self.noise << 13;
+            self.noise ^= self.noise >> 17;
+            self.noise ^= self.noise << 5;
+            self.noise_level = if self.noise & 1 == 1 { 1.0 } else { -1.0 };
+        }
+        sample
+    }
+}
+
+impl AudioCallback for Tone {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.start_buffer(out.len());
+        for sample in out.iter_mut() {
+            *sample = self.next_sample();
+        }
+    }
+}
+
+pub struct SdlBuzzer {
+    device: AudioDevice<Tone>,
+    active: bool,
+    // Percent, 0 to 100
+    volume: u8,
+}
+
+impl SdlBuzzer {
+    pub fn open(audio: &AudioSubsystem, config: &Config) -> Result<Self, String> {
+        let samples = config.audio_buffer.map(|samples| samples as u16);
+        let desired = AudioSpecDesired { freq: Some(config.sample_rate as i32), channels: Some(1), samples };
+        let device = audio.open_playback(None, &desired, |spec| Tone::new(config, spec.freq as u32))?;
+        device.resume();
+        Ok(Self { device, active: false, volume: config.volume })
+    }
+}
+
+impl AudioBackend for SdlBuzzer {
+    fn set_active(&mut self, active: bool) {
+        if active != self.active {
+            self.device.lock().set_active(active);
+            self.active = active;
+        }
+    }
+
+    fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    fn set_volume(&mut self, percent: u8) {
+        self.volume = percent.min(100);
+        self.device.lock().set_volume(self.volume);
+    }
+
+    fn spec(&self) -> AudioSpec {
+        let spec = self.device.spec();
+        AudioSpec { sample_rate: spec.freq as u32, buffer: Some(spec.samples as u32) }
+    }
+
+    fn underruns(&mut self) -> u64 {
+        self.device.lock().underruns
+    }
+}
@@ -0,0 +1,96 @@
+// Buzzer through cpal instead of SDL audio, built with --features cpal and picked with --audio cpal
+
+use std::sync::{Arc, Mutex};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{BufferSize, FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+
+use crate::audio::{AudioBackend, AudioSpec, Tone};
+use crate::config::Config;
+
+pub struct CpalBuzzer {
+    // Playback stops when the stream is dropped
+    _stream: Stream,
+    tone: Arc<Mutex<Tone>>,
+    active: bool,
+    volume: u8,
+    spec: AudioSpec,
+}
+
+impl CpalBuzzer {
+    pub fn open(config: &Config) -> Result<Self, String> {
+        let device = cpal::default_host().default_output_device().ok_or("no audio output device")?;
+        // Use the requested rate when the device supports it, otherwise its preferred one
+        let supported = device
+            .supported_output_configs()
+            .map_err(|e| e.to_string())?
+            .find(|range| (range.min_sample_rate().0..=range.max_sample_rate().0).contains(&config.sample_rate))
+            .map(|range| range.with_sample_rate(cpal::SampleRate(config.sample_rate)));
+        let supported = match supported {
+            Some(supported) => supported,
+            None => device.default_output_config().map_err(|e| e.to_string())?,
+        };
+        let format = supported.sample_format();
+        let mut stream_config: StreamConfig = supported.into();
+        if let Some(samples) = config.audio_buffer {
+            stream_config.buffer_size = BufferSize::Fixed(samples);
+        }
+        let spec = AudioSpec { sample_rate: stream_config.sample_rate.0, buffer: config.audio_buffer };
+        let tone = Arc::new(Mutex::new(Tone::new(config, stream_config.sample_rate.0)));
+
+        let stream = match format {
+            SampleFormat::F32 => build_stream::<f32>(&device, &stream_config, tone.clone()),
+            SampleFormat::I16 => build_stream::<i16>(&device, &stream_config, tone.clone()),
+            SampleFormat::U16 => build_stream::<u16>(&device, &stream_config, tone.clone()),
+            _ => return Err(format!("unsupported sample format {}", format)),
+        }?;
+        stream.play().map_err(|e| e.to_string())?;
+        Ok(Self { _stream: stream, tone, active: false, volume: config.volume, spec })
+    }
+}
+
+// The tone is mono, every channel of a frame gets the same sample
+fn build_stream<T: SizedSample + FromSample<f32>>(device: &cpal::Device, config: &StreamConfig, tone: Arc<Mutex<Tone>>) -> Result<Stream, String> {
+    let channels = config.channels as usize;
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                let mut tone = tone.lock().unwrap();
+                tone.start_buffer(data.len() / channels);
+                for frame in data.chunks_mut(channels) {
+                    let sample = T::from_sample(tone.next_sample());
+                    frame.fill(sample);
+                }
+            },
+            |e| eprintln!("Audio error, {}", e),
+            None,
+        )
+        .map_err(|e| e.to_string())
+}
+
+impl AudioBackend for CpalBuzzer {
+    fn set_active(&mut self, active: bool) {
+        if active != self.active {
+            self.tone.lock().unwrap().set_active(active);
+            self.active = active;
+        }
+    }
+
+    fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    fn set_volume(&mut self, percent: u8) {
+        self.volume = percent.min(100);
+        self.tone.lock().unwrap().set_volume(self.volume);
+    }
+
+    fn spec(&self) -> AudioSpec {
+        self.spec
+    }
+
+    fn underruns(&mut self) -> u64 {
+        self.tone.lock().unwrap().underruns
+    }
+}
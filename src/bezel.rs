@@ -0,0 +1,67 @@
+// Artwork framing the display, loaded with --bezel. The image is stretched over the whole layout
+// and the display, along with everything drawn over it, goes in the hole left by the margins.
+// SDL can only read BMP files without the SDL_image library, so other formats need converting.
+
+use sdl2::rect::Rect;
+use sdl2::render::{Texture, TextureCreator};
+use sdl2::surface::Surface;
+use sdl2::video::WindowContext;
+
+use crate::chip8::VM;
+
+// Margins in layout pixels when --bezel-margins isn't given, four CHIP-8 pixels at the default scale
+pub const DEFAULT_MARGINS: Margins = Margins { left: 40, top: 40, right: 40, bottom: 40 };
+
+#[derive(Clone, Copy)]
+pub struct Margins {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+// One value for all sides, or left,top,right,bottom
+pub fn parse_margins(text: &str) -> Result<Margins, String> {
+    let values = text
+        .split(',')
+        .map(|value| value.trim().parse::<u32>().map_err(|_| format!("invalid margin {}", value)))
+        .collect::<Result<Vec<_>, _>>()?;
+    match values.as_slice() {
+        [all] => Ok(Margins { left: *all, top: *all, right: *all, bottom: *all }),
+        [left, top, right, bottom] => Ok(Margins { left: *left, top: *top, right: *right, bottom: *bottom }),
+        _ => Err(format!("invalid margins {}, expected one value or left,top,right,bottom", text)),
+    }
+}
+
+pub struct Bezel<'a> {
+    pub texture: Texture<'a>,
+    pub margins: Margins,
+}
+
+impl<'a> Bezel<'a> {
+    pub fn load(texture_creator: &'a TextureCreator<WindowContext>, path: &str, margins: Margins) -> Result<Self, String> {
+        let surface = Surface::load_bmp(path).map_err(|e| format!("Error reading {}, {}", path, e))?;
+        let texture = texture_creator.create_texture_from_surface(&surface).map_err(|e| e.to_string())?;
+        Ok(Self { texture, margins })
+    }
+}
+
+impl VM<'_> {
+    // Draw the artwork over the layout and narrow the viewport to the hole in it. Returns the
+    // viewport to restore once the frame is drawn.
+    pub fn draw_bezel(&mut self) -> Result<Option<Rect>, String> {
+        let Some(bezel) = &self.bezel else {
+            return Ok(None);
+        };
+        let outer = self.canvas.viewport();
+        self.canvas.copy(&bezel.texture, None, Rect::new(0, 0, outer.width(), outer.height()))?;
+        let margins = bezel.margins;
+        self.canvas.set_viewport(Rect::new(
+            outer.x() + margins.left as i32,
+            outer.y() + margins.top as i32,
+            outer.width().saturating_sub(margins.left + margins.right).max(1),
+            outer.height().saturating_sub(margins.top + margins.bottom).max(1),
+        ));
+        Ok(Some(outer))
+    }
+}
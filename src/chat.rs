@@ -0,0 +1,143 @@
+// Chat plays, with --chat <[server[:port]/]#channel>: joins an IRC channel, a Twitch channel by
+// default, and presses the keys its viewers type, for crowd-played streams. A message that is only
+// a command counts, anything else is chatter:
+//   0-9, a-f                  press that CHIP-8 key
+//   up, down, left, right     press 5, 8, 7 or 9, the WASD block of the keypad most games use
+// --chat-keys up=2,fire=5 changes or adds words. Each press holds the key for --chat-hold frames
+// (default 10), and a viewer's commands are ignored for --chat-cooldown frames after one of theirs
+// was taken (default 30), so nobody can flood the keypad.
+//
+// It logs in anonymously, which Twitch allows for reading chat, so no account or token is needed:
+//   chip8-rust --chat '#somechannel' rom.ch8
+//   chip8-rust --chat irc.libera.chat/#chip8-plays rom.ch8
+// A lost connection is retried every few seconds.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use crate::chip8::parse_key;
+
+const TWITCH: &str = "irc.chat.twitch.tv:6667";
+const RECONNECT: Duration = Duration::from_secs(5);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const WORDS: [(&str, usize); 4] = [("up", 5), ("down", 8), ("left", 7), ("right", 9)];
+
+// "word=key,..." for --chat-keys
+pub fn parse_chat_keys(text: &str) -> Result<Vec<(String, usize)>, String> {
+    text.split(',')
+        .map(|binding| {
+            let (word, key) = binding.split_once('=').ok_or(format!("invalid chat key {}, expected word=key", binding))?;
+            let word = word.trim().to_lowercase();
+            if word.is_empty() || word.contains(char::is_whitespace) {
+                return Err(format!("invalid chat word {:?}", word));
+            }
+            Ok((word, parse_key(key.trim())?))
+        })
+        .collect()
+}
+
+pub struct ChatInput {
+    // Filled by a thread reading the connection: who typed, and the key
+    presses: Receiver<(String, usize)>,
+    // Frames each key stays held
+    held: [u32; 16],
+    hold: u32,
+    cooldown: u32,
+    // Frame after which each viewer may press again
+    ready: HashMap<String, u64>,
+    frame: u64,
+}
+
+impl ChatInput {
+    pub fn connect(target: &str, hold: u32, cooldown: u32, extra_keys: &[(String, usize)]) -> Result<Self, String> {
+        let (server, channel) = match target.split_once('/') {
+            Some((server, channel)) if server.contains(':') => (server.to_string(), channel),
+            Some((server, channel)) => (format!("{}:6667", server), channel),
+            None => (TWITCH.to_string(), target),
+        };
+        let channel = match channel.strip_prefix('#') {
+            Some(name) if !name.is_empty() && !name.contains([' ', ',']) => format!("#{}", name.to_lowercase()),
+            _ => return Err(format!("invalid chat channel {}, expected e.g. #name", channel)),
+        };
+        let mut keys: HashMap<String, usize> = (0..16).map(|key| (format!("{:x}", key), key)).collect();
+        keys.extend(WORDS.iter().map(|(word, key)| (word.to_string(), *key)));
+        keys.extend(extra_keys.iter().cloned());
+
+        let (sender, presses) = mpsc::channel();
+        thread::spawn(move || loop {
+            match read_chat(&server, &channel, &keys, &sender) {
+                // The emulator has exited
+                Ok(()) => return,
+                Err(e) => eprintln!("Chat {} on {}: {}, reconnecting", channel, server, e),
+            }
+            thread::sleep(RECONNECT);
+        });
+        Ok(Self { presses, held: [0; 16], hold: hold.max(1), cooldown, ready: HashMap::new(), frame: 0 })
+    }
+
+    // Called at the start of every frame, releases keys held long enough and presses new ones
+    pub fn next_frame(&mut self, mut set_key: impl FnMut(usize, bool)) {
+        self.frame += 1;
+        for (key, held) in self.held.iter_mut().enumerate() {
+            if *held > 0 {
+                *held -= 1;
+                if *held == 0 {
+                    set_key(key, false);
+                }
+            }
+        }
+        while let Ok((viewer, key)) = self.presses.try_recv() {
+            if self.ready.get(&viewer).is_some_and(|ready| *ready > self.frame) {
+                continue;
+            }
+            self.ready.insert(viewer, self.frame + self.cooldown as u64);
+            if self.held[key] == 0 {
+                set_key(key, true);
+            }
+            self.held[key] = self.hold;
+        }
+        // Forget viewers that may press again, the map would only grow on a busy channel
+        if self.frame.is_multiple_of(3600) {
+            let frame = self.frame;
+            self.ready.retain(|_, ready| *ready > frame);
+        }
+    }
+}
+
+// Read the channel until the connection fails, or Ok when nobody is listening anymore
+fn read_chat(server: &str, channel: &str, keys: &HashMap<String, usize>, sender: &Sender<(String, usize)>) -> Result<(), String> {
+    let address = server.to_socket_addrs().map_err(|e| e.to_string())?.next().ok_or("no address")?;
+    let mut stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT).map_err(|e| e.to_string())?;
+    // Twitch's name for anonymous readers, other servers take it as any nickname
+    let nick = format!("justinfan{}", 10000 + std::process::id() % 90000);
+    write!(stream, "NICK {}\r\nUSER {} 0 * :chip8-rust\r\nJOIN {}\r\n", nick, nick, channel).map_err(|e| e.to_string())?;
+    println!("Reading chat {} on {}", channel, server);
+
+    let mut reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Err("connection closed".to_string());
+        }
+        let line = line.trim_end();
+        if let Some(token) = line.strip_prefix("PING ") {
+            write!(stream, "PONG {}\r\n", token).map_err(|e| e.to_string())?;
+            continue;
+        }
+        // :nick!user@host PRIVMSG #channel :text
+        let Some((prefix, rest)) = line.strip_prefix(':').and_then(|line| line.split_once(' ')) else { continue };
+        let Some(text) = rest.strip_prefix("PRIVMSG ").and_then(|rest| rest.split_once(" :")).map(|(_, text)| text) else { continue };
+        let viewer = prefix.split('!').next().unwrap_or(prefix);
+        if let Some(key) = keys.get(&text.trim().to_lowercase()) {
+            if sender.send((viewer.to_string(), *key)).is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
@@ -1,10 +1,90 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
-use rand::random;
-use sdl2::pixels::PixelFormatEnum;
+#[cfg(not(feature = "sdl"))]
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+use rand::rngs::StdRng;
+use rand::{random, Rng, SeedableRng};
+#[cfg(feature = "sdl")]
+use sdl2::pixels::{Color, PixelFormatEnum};
+#[cfg(feature = "sdl")]
 use sdl2::rect::Rect;
-use sdl2::render::{Texture, TextureCreator, WindowCanvas};
+#[cfg(feature = "sdl")]
+use sdl2::render::{BlendMode, Texture, TextureCreator, WindowCanvas};
+#[cfg(feature = "sdl")]
 use sdl2::video::WindowContext;
-use crate::FONT_BITMAP;
+#[cfg(feature = "sdl")]
+use crate::audio::AudioSpec;
+#[cfg(feature = "sdl")]
+use crate::console::Console;
+#[cfg(feature = "sdl")]
+use crate::bezel::Bezel;
+use crate::coverage::Coverage;
+#[cfg(feature = "sdl")]
+use crate::crt::CrtEffects;
+use crate::frameblend::FrameBlend;
+use crate::gif::GifRecorder;
+#[cfg(feature = "sdl")]
+use crate::text::draw_text;
+use crate::loopdetect::LoopDetector;
+#[cfg(feature = "lua")]
+use crate::lua::Shape;
+use crate::macros::InputMacro;
+#[cfg(feature = "sdl")]
+use crate::keypad::OnscreenKeypad;
+use crate::keywait::{KeyWait, KeyWaitPolicy};
+#[cfg(feature = "sdl")]
+use crate::magnifier::Magnifier;
+#[cfg(feature = "sdl")]
+use crate::memview::MemoryView;
+use crate::movie::Movie;
+use crate::octo;
+use crate::palette::{Adjustment, Palette, PRESETS};
+#[cfg(feature = "sdl")]
+use crate::phosphor::blend;
+use crate::phosphor::Phosphor;
+use crate::profile::Profiler;
+use crate::screenshot::FrameDump;
+use crate::rewind::{keypad_mask, Rewind};
+#[cfg(feature = "sdl")]
+use crate::remap::Remap;
+use crate::rpl::RPL_FLAGS;
+use crate::savestate::rom_hash;
+#[cfg(feature = "sdl")]
+use crate::slotpicker::SlotPicker;
+use crate::smc::{SmcMode, SmcWatch};
+use crate::snapshot::Snapshot;
+#[cfg(feature = "sdl")]
+use crate::speed::NORMAL_SPEED;
+use crate::symbols::Symbols;
+use crate::trace::{Registers, TraceComparer, Tracer};
+use crate::video::VideoRecorder;
+#[cfg(feature = "sdl")]
+use crate::wav::WavRecorder;
+use crate::watch::{format_message, Watch};
+
+const FONT_BITMAP: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80  // F
+];
+
+// Pause message while paused with P or Space
+const USER_PAUSE: &str = "PAUSED";
+const FOCUS_PAUSE: &str = "PAUSED WHILE IN ANOTHER WINDOW";
 
 pub struct VM<'a> {
     pub op: u16,
@@ -17,15 +97,136 @@ pub struct VM<'a> {
     pub sound: u8,
     pub memory: [u8; 4096],
     pub display: [u8; 64 * 32],
+    // Colors of unlit and lit pixels
+    pub palette: Palette,
+    // Ctrl+I swapped the palette's colours
+    pub inverted: bool,
+    pub adjustment: Adjustment,
     pub drawflag: bool,
     pub keypad: [bool; 16],
+    pub paused: bool,
+    // Number of 60 Hz frames since power on
+    pub frame: u64,
+    pub pause_message: Option<String>,
+    pub loop_detector: LoopDetector,
+    pub breakpoints: BTreeSet<u16>,
+    // Log-only breakpoints: address -> message template, see watch::format_message
+    pub tracepoints: BTreeMap<u16, String>,
+    // One-shot breakpoint set by the "until" command
+    pub run_to: Option<u16>,
+    // Pause after every DXYN
+    pub break_on_draw: bool,
+    pub watches: Vec<Watch>,
+    // CXKK random numbers come from a seeded generator so movies and save states can replay them
+    pub seed: u64,
+    pub random_draws: u64,
+    rng: StdRng,
+    // Named snapshots taken with the "snap" command
+    pub snapshots: BTreeMap<String, Snapshot>,
+    pub movie: Option<Movie>,
+    pub gif: Option<GifRecorder>,
+    pub video: Option<VideoRecorder>,
+    #[cfg(feature = "sdl")]
+    pub wav: Option<WavRecorder>,
+    pub frame_dump: Option<FrameDump>,
+    // What Lua scripts drew this frame, see lua.rs
+    #[cfg(feature = "lua")]
+    pub overlay: Vec<Shape>,
+    // Instructions executed since power on, and the history used to step back through them
+    pub cycles: u64,
+    pub rewind: Rewind,
+    // DXYN executed and frames drawn since start, unlike cycles not reset or rewound
+    pub draw_calls: u64,
+    pub frames_rendered: u64,
+    break_skip: Option<u16>,
+    pub show_hud: bool,
+    pub show_heatmap: bool,
+    pub show_debugger: bool,
+    pub show_keypad: bool,
+    // Which key FX0A reports, and what it has seen while waiting, see keywait.rs
+    pub key_wait_policy: KeyWaitPolicy,
+    pub key_wait: Option<KeyWait>,
+    // Clickable keypad beside the display, for --keypad
+    #[cfg(feature = "sdl")]
+    pub onscreen_keypad: Option<OnscreenKeypad>,
+    // Buzzer output is silenced but the sound timer keeps running
+    pub muted: bool,
+    // Flash a border while the buzzer sounds, for when it can't be heard
+    pub visual_buzzer: bool,
+    // Fade out pixels that turn off, for --phosphor
+    pub phosphor: Option<Phosphor>,
+    // Average the last frames instead, for --frame-blend
+    pub frame_blend: Option<FrameBlend>,
+    // Post-processing for --crt, and its cached vignette overlay
+    #[cfg(feature = "sdl")]
+    pub crt: Option<CrtEffects>,
+    // Scale by whole pixels only, see layout.rs
+    pub integer_scaling: bool,
+    // Degrees clockwise, and the texture frames are drawn into when it isn't 0, see layout.rs
+    pub rotation: u16,
+    #[cfg(feature = "sdl")]
+    pub rotation_target: Option<Texture<'a>>,
+    // Where the window was before going fullscreen, None while windowed
+    #[cfg(feature = "sdl")]
+    pub windowed_bounds: Option<Rect>,
+    #[cfg(feature = "sdl")]
+    pub crt_vignette: Option<Texture<'a>>,
+    // Set once audio is open, for the HUD
+    #[cfg(feature = "sdl")]
+    pub audio_spec: Option<AudioSpec>,
+    #[cfg(feature = "sdl")]
+    pub memory_view: MemoryView,
+    #[cfg(feature = "sdl")]
+    pub magnifier: Magnifier,
+    #[cfg(feature = "sdl")]
+    pub remap: Option<Remap>,
+    // Recorded keypad macro for the loaded rom
+    pub input_macro: InputMacro,
+    // Multiple of normal emulation speed, infinite when uncapped
+    #[cfg(feature = "sdl")]
+    pub speed: f64,
+    #[cfg(feature = "sdl")]
+    pub console: Console,
+    pub tracer: Option<Tracer>,
+    pub trace_comparer: Option<TraceComparer>,
+    pub coverage: Option<Coverage>,
+    pub smc_watch: Option<SmcWatch>,
+    pub profiler: Option<Profiler>,
+    pub rom_size: usize,
+    pub rom_path: String,
+    // The loaded rom as read, for resetting without going back to the file
+    pub rom: Vec<u8>,
+    pub rom_hash: u64,
+    // SUPER-CHIP user flags, persisted per rom
+    pub rpl: [u8; RPL_FLAGS],
+    // Save state slot used by the F7 hotkeys
+    pub state_slot: u8,
+    #[cfg(feature = "sdl")]
+    pub slot_picker: SlotPicker,
+    // Short on-screen notification and when it disappears
+    pub osd: Option<(String, Instant)>,
+    pub symbols: Symbols,
+    #[cfg(feature = "sdl")]
     pub canvas: WindowCanvas,
+    #[cfg(feature = "sdl")]
     pub display_texture: Option<Texture<'a>>,
+    #[cfg(feature = "sdl")]
+    pub bezel: Option<Bezel<'a>>,
+    // What the display texture was last filled with, None when unknown
+    #[cfg(feature = "sdl")]
+    texture_contents: Option<([u8; 64 * 32], Palette)>,
+    #[cfg(feature = "sdl")]
     pub texture_creator: &'a TextureCreator<WindowContext>,
+    // Textures borrow from the texture creator, headless builds have neither
+    #[cfg(not(feature = "sdl"))]
+    lifetime: PhantomData<&'a ()>,
 }
 
 impl<'a> VM<'a> {
-    pub fn new(canvas: WindowCanvas, texture_creator: &'a TextureCreator<WindowContext>) -> Self {
+    // Headless builds have nothing to pass in, a Default would only hide that
+    #[cfg_attr(not(feature = "sdl"), allow(clippy::new_without_default))]
+    pub fn new(#[cfg(feature = "sdl")] canvas: WindowCanvas, #[cfg(feature = "sdl")] texture_creator: &'a TextureCreator<WindowContext>) -> Self {
+        let seed = random::<u64>();
         Self {
             op: 0,
             v: [0; 16],
@@ -37,14 +238,103 @@ impl<'a> VM<'a> {
             sound: 0,
             memory: [0; 4096],
             display: [0; 64 * 32],
+            palette: PRESETS[0].1,
+            inverted: false,
+            adjustment: Adjustment::default(),
             drawflag: false,
             keypad: [false; 16],
+            paused: false,
+            frame: 0,
+            pause_message: None,
+            loop_detector: LoopDetector::new(100),
+            breakpoints: BTreeSet::new(),
+            tracepoints: BTreeMap::new(),
+            run_to: None,
+            break_on_draw: false,
+            watches: Vec::new(),
+            seed,
+            random_draws: 0,
+            rng: StdRng::seed_from_u64(seed),
+            snapshots: BTreeMap::new(),
+            movie: None,
+            gif: None,
+            video: None,
+            #[cfg(feature = "sdl")]
+            wav: None,
+            frame_dump: None,
+            #[cfg(feature = "lua")]
+            overlay: Vec::new(),
+            cycles: 0,
+            draw_calls: 0,
+            frames_rendered: 0,
+            rewind: Rewind::default(),
+            break_skip: None,
+            show_hud: false,
+            show_heatmap: false,
+            show_debugger: false,
+            show_keypad: false,
+            key_wait_policy: KeyWaitPolicy::Lowest,
+            key_wait: None,
+            #[cfg(feature = "sdl")]
+            onscreen_keypad: None,
+            muted: false,
+            visual_buzzer: false,
+            phosphor: None,
+            frame_blend: None,
+            #[cfg(feature = "sdl")]
+            crt: None,
+            integer_scaling: false,
+            rotation: 0,
+            #[cfg(feature = "sdl")]
+            rotation_target: None,
+            #[cfg(feature = "sdl")]
+            windowed_bounds: None,
+            #[cfg(feature = "sdl")]
+            crt_vignette: None,
+            #[cfg(feature = "sdl")]
+            audio_spec: None,
+            #[cfg(feature = "sdl")]
+            memory_view: MemoryView::new(),
+            #[cfg(feature = "sdl")]
+            magnifier: Magnifier::new(),
+            #[cfg(feature = "sdl")]
+            remap: None,
+            input_macro: InputMacro::default(),
+            #[cfg(feature = "sdl")]
+            speed: NORMAL_SPEED,
+            #[cfg(feature = "sdl")]
+            console: Console::new(),
+            tracer: None,
+            trace_comparer: None,
+            coverage: None,
+            smc_watch: None,
+            profiler: None,
+            rom_size: 0,
+            rom_path: String::new(),
+            rom: Vec::new(),
+            rom_hash: 0,
+            rpl: [0; RPL_FLAGS],
+            state_slot: 0,
+            #[cfg(feature = "sdl")]
+            slot_picker: SlotPicker::default(),
+            osd: None,
+            symbols: Symbols::default(),
+            #[cfg(feature = "sdl")]
             canvas,
+            #[cfg(feature = "sdl")]
             display_texture: None, // Initialize as None, create later
+            #[cfg(feature = "sdl")]
+            bezel: None,
+            #[cfg(feature = "sdl")]
+            texture_contents: None,
+            #[cfg(feature = "sdl")]
             texture_creator,
+            #[cfg(not(feature = "sdl"))]
+            lifetime: PhantomData,
         }
     }
 
+    #[cfg(feature = "sdl")]
     pub fn initialize_texture(&mut self) -> Result<(), String> {
         let display_texture = self
             .texture_creator
@@ -52,6 +342,7 @@ impl<'a> VM<'a> {
             .map_err(|e| e.to_string())?;
 
         self.display_texture = Some(display_texture);
+        self.texture_contents = None;
         Ok(())
     }
 
@@ -63,50 +354,358 @@ impl<'a> VM<'a> {
 }
 
 impl VM<'_> {
-    pub fn emulate_cycle(&mut self) {
+    pub fn emulate_cycle(&mut self) -> Result<(), String> {
         self.op = (self.memory[self.pc as usize] as u16) << 8 | self.memory[(self.pc + 1) as usize] as u16;
+        if self.loop_detector.check(self.pc, self.op) {
+            self.paused = true;
+            self.pause_message = Some(format!("PROGRAM HALTED AT {:03X}", self.pc));
+            return Ok(());
+        }
+        if self.rewind.before_instruction(self.cycles, keypad_mask(&self.keypad)) {
+            let snapshot = self.snapshot();
+            self.rewind.add_checkpoint(self.cycles, snapshot, keypad_mask(&self.keypad), self.rpl);
+        }
+        self.cycles += 1;
+        if let Some(template) = self.tracepoints.get(&self.pc) {
+            self.log_tracepoint(template.clone());
+        }
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record(self.pc, self.op);
+        }
+        if let Some(watch) = self.smc_watch.as_mut() {
+            watch.record_execution(self.pc);
+        }
+        if self.tracer.is_none() && self.trace_comparer.is_none() {
+            self.execute();
+            return Ok(());
+        }
+
+        let pc = self.pc;
+        let before = self.registers();
+        if let Some(comparer) = self.trace_comparer.as_mut() {
+            comparer.check(pc, self.op, &before)?;
+        }
+        self.execute();
+        let after = self.registers();
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.record(pc, self.op, &before, &after, &self.symbols);
+        }
+        Ok(())
+    }
+
+    // Append a tracepoint's message to the trace, or print it when no trace is being written
+    fn log_tracepoint(&mut self, template: String) {
+        let message = if template.is_empty() {
+            let registers: Vec<String> = self.v.iter().enumerate().map(|(r, value)| format!("V{:X}:{:02X}", r, value)).collect();
+            format!("{} I:{:03X}", registers.join(" "), self.i)
+        } else {
+            format_message(&template, self).unwrap_or_else(|e| format!("{} ({})", template, e))
+        };
+        match self.tracer.as_mut() {
+            Some(tracer) => tracer.note(self.pc, &message),
+            None => println!("{:03X}: {}", self.pc, message),
+        }
+    }
+
+    // Run the fetched instruction, timing it when the profiler is on
+    fn execute(&mut self) {
+        if self.profiler.is_none() {
+            parse_op_code(self);
+            return;
+        }
+        let op = self.op;
+        let start = Instant::now();
         parse_op_code(self);
+        let elapsed = start.elapsed();
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.record(op, elapsed);
+        }
     }
 
-    pub fn read_input(&self) {}
+    // Run one 60 Hz frame while paused: `instructions` cycles followed by a timer tick
+    pub fn step_frame(&mut self, instructions: u32) -> Result<(), String> {
+        self.pause_message = None;
+        for _ in 0..instructions {
+            self.emulate_cycle()?;
+            if self.pause_message.is_some() {
+                // Halted or hit a break condition partway through the frame
+                return Ok(());
+            }
+        }
+        self.tick_timers();
+        Ok(())
+    }
 
-    pub fn load_rom(&mut self, rom: &str) {
-        let rom_content = match fs::read(rom) {
-            Ok(b) => { b }
-            Err(e) => { panic!("Error loading rom, {}", e) }
-        };
+    pub fn tick_timers(&mut self) {
+        self.rewind.tick(self.cycles);
+        self.frame += 1;
+        if self.delay > 0 {
+            self.delay -= 1;
+        }
+        if self.sound > 0 {
+            self.sound -= 1;
+        }
+    }
+
+    // Pause from the keyboard, dimming the display, as opposed to a stop in the debugger
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.pause_message = Some(USER_PAUSE.to_string());
+    }
+
+    // Paused because the window lost focus, resumed when it gets it back
+    pub fn pause_for_focus(&mut self) {
+        self.paused = true;
+        self.pause_message = Some(FOCUS_PAUSE.to_string());
+    }
+
+    pub fn focus_paused(&self) -> bool {
+        self.paused && self.pause_message.as_deref() == Some(FOCUS_PAUSE)
+    }
 
-        if rom_content.len() > self.memory.len() - 0x200 {
-            panic!("Selected rom is too large for chip8, exiting")
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.pause_message = None;
+        self.break_skip = Some(self.pc);
+        self.loop_detector.ignore_current();
+    }
+
+    // Restart the random number generator at `seed`, then skip the first `draws` numbers
+    pub fn reseed(&mut self, seed: u64, draws: u64) {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+        for _ in 0..draws {
+            self.rng.random::<u8>();
         }
+        self.random_draws = draws;
+    }
+
+    // Show `message` over the display for a couple of seconds
+    pub fn notify(&mut self, message: String) {
+        println!("{}", message);
+        self.osd = Some((message, Instant::now() + Duration::from_secs(2)));
+    }
 
+    // Opcode at PC, i.e. the next instruction to execute
+    pub fn current_op(&self) -> u16 {
+        (self.memory[self.pc as usize % 4096] as u16) << 8 | self.memory[(self.pc as usize + 1) % 4096] as u16
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers { v: self.v, i: self.i, sp: self.sp, delay: self.delay, sound: self.sound }
+    }
+
+    pub fn read_input(&self) {}
+
+    pub fn load_rom(&mut self, rom: &str) -> Result<(), String> {
+        let (rom_content, symbols) = read_rom_with_symbols(rom)?;
+        self.symbols.merge(symbols);
+        self.load_rom_content(rom, rom_content);
+        println!("Loaded rom \"{}\" of length {}", rom, self.rom.len());
+        Ok(())
+    }
+
+    // A rom that is already in memory, like one handed to the browser build. `rom` stands in for
+    // its path and the size has been checked.
+    pub fn load_rom_content(&mut self, rom: &str, rom_content: Vec<u8>) {
         for (i, e) in rom_content.iter().enumerate() {
-            //self.memory.offset()
             self.memory[0x200 + i] = *e;
         }
+        self.rom_size = rom_content.len();
+        self.rom_path = rom.to_string();
+        self.rom_hash = rom_hash(&rom_content);
+        self.rom = rom_content;
+        self.load_rpl_flags();
+        self.input_macro = InputMacro::load(self.rom_hash);
+    }
+
+    // Replace the rom image in memory while keeping registers, stack, display and timers, for
+    // live patching. Bytes of the previous image past the end of the new one are cleared.
+    pub fn hot_swap(&mut self, rom: &str) -> Result<(), String> {
+        let old_end = 0x200 + self.rom_size;
+        self.load_rom(rom)?;
+        let new_end = 0x200 + self.rom_size;
+        if old_end > new_end {
+            self.memory[new_end..old_end].fill(0);
+        }
+        self.loop_detector.ignore_current();
+        // History from before the swap would replay the old rom
+        self.rewind.clear();
+        Ok(())
+    }
+
+    // Power cycle the machine and load the rom at `rom` again
+    pub fn reset(&mut self, rom: &str) -> Result<(), String> {
+        self.clear_machine();
+        self.load_rom(rom)
+    }
+
+    // Full machine reset with the rom image already loaded, for when the file may have changed or
+    // gone since
+    pub fn restart(&mut self) {
+        self.clear_machine();
+        self.memory[0x200..0x200 + self.rom.len()].copy_from_slice(&self.rom);
+    }
+
+    // Everything back to power on state except the rom, with the font loaded
+    fn clear_machine(&mut self) {
+        self.op = 0;
+        self.v = [0; 16];
+        self.i = 0;
+        self.pc = 0x200;
+        self.stack = [0; 16];
+        self.sp = 0;
+        self.delay = 0;
+        self.sound = 0;
+        self.memory = [0; 4096];
+        self.display = [0; 64 * 32];
+        self.drawflag = true;
+        self.keypad = [false; 16];
+        self.key_wait = None;
+        self.paused = false;
+        self.pause_message = None;
+        self.run_to = None;
+        self.frame = 0;
+        self.cycles = 0;
+        self.rewind.clear();
+        self.init_font_set();
+    }
+
+    // Pauses and returns true when PC is on a breakpoint, except right after resuming from it
+    pub fn check_breakpoint(&mut self) -> bool {
+        if self.break_skip.take() == Some(self.pc) {
+            return false;
+        }
+        if self.run_to == Some(self.pc) {
+            self.run_to = None;
+            self.paused = true;
+            self.pause_message = Some(format!("REACHED {:03X}", self.pc));
+            return true;
+        }
+        if !self.breakpoints.contains(&self.pc) {
+            return false;
+        }
+        self.paused = true;
+        self.pause_message = Some(format!("BREAKPOINT AT {:03X}", self.pc));
+        true
+    }
 
-        println!("Loaded rom \"{}\" of length {}", rom, rom_content.len())
+    // Stores made by instructions go through here so they can be watched
+    fn write_memory(&mut self, address: u16, value: u8) {
+        let address = address % 4096;
+        if let Some(watch) = &self.smc_watch {
+            if watch.is_code(address) {
+                println!("Self-modifying write: {:03X} wrote {:02X} to {:03X} (was {:02X})", self.pc, value, address, self.memory[address as usize]);
+                if watch.mode == SmcMode::Break {
+                    self.paused = true;
+                    self.pause_message = Some(format!("CODE WRITE AT {:03X} FROM {:03X}", address, self.pc));
+                }
+            }
+        }
+        self.memory[address as usize] = value;
+    }
+
+    // Stop on an unrecoverable error and leave a crash dump behind
+    fn fatal(&mut self, message: String) {
+        println!("{}", message);
+        match self.write_crash_dump(&message) {
+            Ok(path) => println!("Crash dump written to {}", path),
+            Err(e) => eprintln!("{}", e),
+        }
+        self.paused = true;
+        self.pause_message = Some(message);
+    }
+
+    fn unknown_opcode(&mut self) {
+        self.fatal(format!("UNKNOWN OPCODE {:04X} AT {:03X}", self.op, self.pc));
+    }
+
+    // Call sites of the active subroutines, innermost first
+    pub fn call_stack(&self) -> Vec<u16> {
+        (1..=self.sp as usize).rev().map(|frame| self.stack[frame]).collect()
     }
 
     // display | drawing
+    #[cfg(feature = "sdl")]
     pub fn draw_display(&mut self, window_scale: u32) {
-        self.display_texture.as_mut().unwrap().with_lock(None, |buffer: &mut [u8], pitch: usize| {
-            for y in 0..32 {
-                for x in 0..64 {
-                    let offset = y * pitch + x * 3; // Each pixel occupies 3 bytes (RGB)
-                    let pixel_value = if self.display[y * 64 + x] == 1 { 0xFF } else { 0x00 }; // white or black
-
-                    // Set the RGB values for the pixel
-                    buffer[offset] = pixel_value;     // R
-                    buffer[offset + 1] = pixel_value; // G
-                    buffer[offset + 2] = pixel_value; // B
-                }
+        self.frames_rendered += 1;
+        let intensity = match &mut self.phosphor {
+            Some(phosphor) => Some(*phosphor.update(&self.display)),
+            None => self.frame_blend.as_ref().map(|blend| blend.intensity(&self.display)),
+        };
+
+        // Only rows that differ from what the texture already holds are uploaded. The fading
+        // effects change colours without the display changing, so they always upload everything.
+        let palette = self.display_palette();
+        let changed_rows = match (&self.texture_contents, &intensity) {
+            (Some((display, uploaded_palette)), None) if *uploaded_palette == palette => {
+                let changed = |row: &usize| display[row * 64..(row + 1) * 64] != self.display[row * 64..(row + 1) * 64];
+                (0..32).find(changed).map(|first| first..=(0..32).rev().find(changed).unwrap())
             }
-        }).unwrap();
+            _ => Some(0..=31),
+        };
+        if let Some(rows) = changed_rows {
+            let (first, count) = (*rows.start(), (rows.end() - rows.start() + 1) as u32);
+            self.display_texture.as_mut().unwrap().with_lock(Rect::new(0, first as i32, 64, count), |buffer: &mut [u8], pitch: usize| {
+                for y in rows {
+                    for x in 0..64 {
+                        let offset = (y - first) * pitch + x * 3; // Each pixel occupies 3 bytes (RGB)
+                        let color = match &intensity {
+                            Some(intensity) => blend(&palette, intensity[y * 64 + x]),
+                            None => palette[self.display[y * 64 + x].min(1) as usize],
+                        };
+                        buffer[offset..offset + 3].copy_from_slice(&color);
+                    }
+                }
+            }).unwrap();
+            self.texture_contents = if intensity.is_none() { Some((self.display, palette)) } else { None };
+        }
 
+        self.begin_frame(window_scale).unwrap();
+        // Also the colour of the letterbox bars when the window's shape doesn't match
+        let [r, g, b] = palette[0];
+        self.canvas.set_draw_color(Color::RGB(r, g, b));
         self.canvas.clear();
-        self.canvas.copy(&self.display_texture.as_ref().unwrap(), None, Some(Rect::new(0, 0, 64 * window_scale, 32 * window_scale))).unwrap();
-        self.canvas.present();
+        let outer_viewport = self.draw_bezel().unwrap();
+        let area = Rect::new(0, 0, 64 * window_scale, 32 * window_scale);
+        match self.crt {
+            Some(effects) => self.draw_crt(effects, area).unwrap(),
+            None => self.canvas.copy(self.display_texture.as_ref().unwrap(), None, Some(area)).unwrap(),
+        }
+        if matches!(self.pause_message.as_deref(), Some(USER_PAUSE | FOCUS_PAUSE)) {
+            self.canvas.set_blend_mode(BlendMode::Blend);
+            self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 140));
+            self.canvas.fill_rect(area).unwrap();
+            self.canvas.set_blend_mode(BlendMode::None);
+        }
+        if self.visual_buzzer && self.sound > 0 { self.draw_buzzer_border(window_scale).unwrap() }
+        #[cfg(feature = "lua")]
+        if !self.overlay.is_empty() { self.draw_overlay(window_scale).unwrap() }
+        if self.show_debugger { self.draw_debugger(window_scale).unwrap() }
+        if self.onscreen_keypad.is_some() { self.draw_onscreen_keypad(window_scale).unwrap() }
+        if self.show_heatmap { self.draw_heatmap().unwrap() }
+        if self.speed != NORMAL_SPEED { self.draw_speed_indicator().unwrap() }
+        if self.show_hud { self.draw_hud().unwrap() }
+        if self.show_keypad { self.draw_keypad_overlay(window_scale).unwrap() }
+        if self.magnifier.open { self.draw_magnifier(window_scale).unwrap() }
+        if self.muted { self.draw_mute_indicator(window_scale).unwrap() }
+        if self.memory_view.open { self.draw_memory_view().unwrap() }
+        if self.console.open { self.draw_console(window_scale).unwrap() }
+        if self.slot_picker.open { self.draw_slot_picker(window_scale).unwrap() }
+        if self.remap.is_some() { self.draw_remap(window_scale).unwrap() }
+        if self.osd.as_ref().is_some_and(|(_, until)| Instant::now() >= *until) { self.osd = None }
+        if self.osd.is_some() { self.draw_osd(window_scale).unwrap() }
+        if let Some(message) = &self.pause_message {
+            let text = format!("{} - ENTER TO CONTINUE", message);
+            self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+            self.canvas.fill_rect(Rect::new(0, (32 * window_scale - 20) as i32, 64 * window_scale, 20)).unwrap();
+            draw_text(&mut self.canvas, 4, (32 * window_scale - 17) as i32, 2, &text, Color::RGB(255, 200, 0)).unwrap();
+        }
+        if let Some(viewport) = outer_viewport {
+            self.canvas.set_viewport(viewport);
+        }
+        self.end_frame(window_scale).unwrap();
+        self.drawflag = false;
     }
 
     // OpCodes
@@ -116,6 +715,10 @@ impl VM<'_> {
     }
 
     fn _0x00ee(&mut self) {
+        if self.sp == 0 {
+            self.fatal(format!("RETURN WITH EMPTY STACK AT {:03X}", self.pc));
+            return;
+        }
         self.pc = self.stack[self.sp as usize] + 2;
         self.sp -= 1;
     }
@@ -124,6 +727,10 @@ impl VM<'_> {
     }
 
     fn _2nnn(&mut self, nnn: u16) {
+        if self.sp as usize == self.stack.len() - 1 {
+            self.fatal(format!("CALL STACK OVERFLOW AT {:03X}", self.pc));
+            return;
+        }
         self.sp += 1;
         self.stack[self.sp as usize] = self.pc;
         self.pc = nnn;
@@ -173,17 +780,17 @@ impl VM<'_> {
     }
 
     fn _8xy1(&mut self, x: u16, y: u16) {
-        self.v[x as usize] = self.v[x as usize] | self.v[y as usize];
+        self.v[x as usize] |= self.v[y as usize];
         self.pc += 2;
     }
 
     fn _8xy2(&mut self, x: u16, y: u16) {
-        self.v[x as usize] = self.v[x as usize] & self.v[y as usize];
+        self.v[x as usize] &= self.v[y as usize];
         self.pc += 2;
     }
 
     fn _8xy3(&mut self, x: u16, y: u16) {
-        self.v[x as usize] = self.v[x as usize] ^ self.v[y as usize];
+        self.v[x as usize] ^= self.v[y as usize];
         self.pc += 2;
     }
 
@@ -243,7 +850,8 @@ impl VM<'_> {
     }
 
     fn _cxkk(&mut self, x: u16, kk: u8) {
-        self.v[x as usize] = random::<u8>() & kk;
+        self.random_draws += 1;
+        self.v[x as usize] = self.rng.random::<u8>() & kk;
         self.pc += 2;
     }
 
@@ -253,9 +861,10 @@ impl VM<'_> {
         let y_pos = self.v[y as usize] as usize;
         let height = self.op & 0x000F;
         self.v[0xF] = 0;
+        self.draw_calls += 1;
 
         for y_line in 0..height {
-            let pixel = self.memory[(self.i + y_line) as usize];
+            let pixel = self.memory[self.i.wrapping_add(y_line) as usize % 4096];
             for x_line in 0..8 {
                 let index = ((y_pos + y_line as usize) % 32) * 64 + (x_pos + x_line as usize) % 64;
                 let sprite_pixel = (pixel >> (7 - x_line)) & 1;
@@ -269,11 +878,27 @@ impl VM<'_> {
         }
 
         self.drawflag = true;
+        if self.break_on_draw {
+            let sprite = (0..height).map(|row| self.memory[self.i.wrapping_add(row) as usize % 4096]);
+            for row in sprite {
+                println!("    {:08b}", row);
+            }
+            self.paused = true;
+            self.pause_message = Some(format!(
+                "DRAW AT {:03X}: 8X{} FROM {:03X} AT {},{} {}",
+                self.pc,
+                height,
+                self.i,
+                x_pos,
+                y_pos,
+                if self.v[0xF] == 1 { "COLLISION" } else { "NO COLLISION" }
+            ));
+        }
         self.pc += 2;
     }
 
     fn _ex9e(&mut self, x: u16) {
-        if self.keypad[self.v[x as usize] as usize] == true {
+        if self.keypad[self.v[x as usize] as usize] {
             //self.keypad[self.v[x as usize] as usize] = 0;
             self.pc += 4
         } else {
@@ -296,14 +921,10 @@ impl VM<'_> {
     }
 
     fn _fx0a(&mut self, x: u16) {
-        for key in self.keypad {
-            if key == true {
-                self.v[x as usize] = key as u8;
-                self.pc += 2;
-                //return;
-            }
+        if let Some(key) = self.waited_key() {
+            self.v[x as usize] = key as u8;
+            self.pc += 2;
         }
-        self.keypad[self.v[x as usize] as usize] = false;
     }
 
     fn _fx15(&mut self, x: u16) {
@@ -317,7 +938,7 @@ impl VM<'_> {
     }
 
     fn _fx1e(&mut self, x: u16) {
-        self.i += self.v[x as usize] as u16;
+        self.i = self.i.wrapping_add(self.v[x as usize] as u16);
         self.pc += 2;
     }
 
@@ -328,25 +949,76 @@ impl VM<'_> {
 
     fn _fx33(&mut self, x: u16) {
         // I'm way too stupid for this function. Thank you bradford-hamilton.
-        self.memory[self.i as usize] = self.v[x as usize] / 100;
-        self.memory[(self.i + 1) as usize] = (self.v[x as usize] / 10) % 10;
-        self.memory[(self.i + 2) as usize] = (self.v[x as usize] % 100) % 10;
+        self.write_memory(self.i, self.v[x as usize] / 100);
+        self.write_memory(self.i.wrapping_add(1), (self.v[x as usize] / 10) % 10);
+        self.write_memory(self.i.wrapping_add(2), (self.v[x as usize] % 100) % 10);
         self.pc += 2;
     }
 
     fn _fx55(&mut self, x: u16) {
         for register_index in 0..x {
-            self.memory[(self.i + register_index) as usize] = self.v[register_index as usize];
+            self.write_memory(self.i.wrapping_add(register_index), self.v[register_index as usize]);
         }
         self.pc += 2;
     }
 
     fn _fx65(&mut self, x: u16) {
         for register_index in 0..x {
-            self.v[register_index as usize] = self.memory[(self.i + register_index) as usize];
+            self.v[register_index as usize] = self.memory[self.i.wrapping_add(register_index) as usize % 4096];
         }
         self.pc += 2;
     }
+
+    fn _fx75(&mut self, x: u16) {
+        if x as usize >= RPL_FLAGS {
+            return self.fatal(format!("FX75 WITH V{:X} AT {:03X}, ONLY V0-V7 HAVE FLAGS", x, self.pc));
+        }
+        self.rpl[..=x as usize].copy_from_slice(&self.v[..=x as usize]);
+        // Re-running history for step_back wrote these already the first time round
+        if !self.rewind.replaying() {
+            if let Err(e) = self.save_rpl_flags() {
+                eprintln!("{}", e);
+            }
+        }
+        self.pc += 2;
+    }
+
+    fn _fx85(&mut self, x: u16) {
+        if x as usize >= RPL_FLAGS {
+            return self.fatal(format!("FX85 WITH V{:X} AT {:03X}, ONLY V0-V7 HAVE FLAGS", x, self.pc));
+        }
+        self.v[..=x as usize].copy_from_slice(&self.rpl[..=x as usize]);
+        self.pc += 2;
+    }
+}
+
+// Read a rom image from disk, assembling it first if it is an Octo source file
+pub fn read_rom(rom: &str) -> Result<Vec<u8>, String> {
+    read_rom_with_symbols(rom).map(|(rom_content, _)| rom_content)
+}
+
+// Like read_rom, but also returns the labels of Octo sources
+pub fn read_rom_with_symbols(rom: &str) -> Result<(Vec<u8>, Symbols), String> {
+    let (rom_content, symbols) = if rom.ends_with(".8o") {
+        let source = fs::read_to_string(rom).map_err(|e| format!("Error loading rom, {}", e))?;
+        let (rom_content, labels) = octo::assemble_with_labels(&source).map_err(|e| format!("Error assembling \"{}\", {}", rom, e))?;
+        (rom_content, Symbols::from_labels(&labels))
+    } else {
+        (fs::read(rom).map_err(|e| format!("Error loading rom, {}", e))?, Symbols::default())
+    };
+
+    if rom_content.len() > 4096 - 0x200 {
+        return Err("Selected rom is too large for chip8".to_string());
+    }
+    Ok((rom_content, symbols))
+}
+
+// A CHIP-8 key as a hex digit
+pub fn parse_key(text: &str) -> Result<usize, String> {
+    match usize::from_str_radix(text, 16) {
+        Ok(key) if key < 16 && text.len() == 1 => Ok(key),
+        _ => Err(format!("invalid CHIP-8 key {}, expected 0-F", text)),
+    }
 }
 
 pub fn parse_op_code(vm: &mut VM) {
@@ -355,8 +1027,6 @@ pub fn parse_op_code(vm: &mut VM) {
     let nn: u8 = (vm.op & 0x00FF) as u8;
     let nnn = vm.op & 0x0FFF;
 
-    println!("Op: {:#06x} | x: {} y: {} nn: {} nnn: {}", vm.op, x, y, nn, nnn);
-
     match vm.op & 0xF000 {
         0x0000 => {
             match vm.op & 0x00FF {
@@ -382,8 +1052,8 @@ pub fn parse_op_code(vm: &mut VM) {
                 0x0005 => { vm._8xy5(x, y) }
                 0x0006 => { vm._8xy6(x, y) }
                 0x0007 => { vm._8xy7(x, y) }
-                0x0008 => { vm._8xye(x, y) }
-                _ => {}
+                0x000E => { vm._8xye(x, y) }
+                _ => { vm.unknown_opcode() }
             }
         }
         0x9000 => { vm._9xy0(x, y) }
@@ -395,7 +1065,7 @@ pub fn parse_op_code(vm: &mut VM) {
             match vm.op & 0x00FF {
                 0x009e => { vm._ex9e(x) }
                 0x00a1 => { vm._exa1(x) }
-                _ => {}
+                _ => { vm.unknown_opcode() }
             }
         }
         0xf000 => {
@@ -410,10 +1080,12 @@ pub fn parse_op_code(vm: &mut VM) {
                 0x0033 => { vm._fx33(x) }
                 0x0055 => { vm._fx55(x) }
                 0x0065 => { vm._fx65(x) }
-                _ => { panic!("Unknown opcode {:#06x}", vm.op) }
+                0x0075 => { vm._fx75(x) }
+                0x0085 => { vm._fx85(x) }
+                _ => { vm.unknown_opcode() }
             }
         }
 
-        _ => { panic!("Unknown opcode {:#06x}", vm.op) }
+        _ => { vm.unknown_opcode() }
     }
 }
\ No newline at end of file
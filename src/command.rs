@@ -0,0 +1,328 @@
+// Debugger commands for inspecting and patching the machine state
+
+use crate::chip8::{parse_op_code, VM};
+use crate::disasm::{disassemble, disassemble_with, opcode_class};
+use crate::movie::MovieMode;
+use crate::profile::Profiler;
+use crate::snapshot::Snapshot;
+use crate::watch::{format_message, Watch};
+
+pub fn execute(vm: &mut VM, line: &str) -> Result<String, String> {
+    let mut args: Vec<&str> = line.split_whitespace().collect();
+    // The memory view's font only has capitals, so commands typed there come in upper case
+    let command = args.first().map(|command| command.to_ascii_lowercase());
+    if let Some(command) = &command {
+        args[0] = command;
+    }
+    match args.as_slice() {
+        [] => Ok(String::new()),
+        ["set", target, value] => set(vm, target, value),
+        ["poke", address, bytes @ ..] if !bytes.is_empty() => poke(vm, address, bytes),
+        ["peek", address] => peek(vm, address, "1"),
+        ["peek", address, length] => peek(vm, address, length),
+        ["break"] => Ok(list_breakpoints(vm)),
+        ["break", address] => {
+            let address = parse_limited(address, 0xFFF)?;
+            vm.breakpoints.insert(address);
+            Ok(format!("breakpoint set at {:03X}", address))
+        }
+        ["break", address, "log", message @ ..] => {
+            let address = parse_limited(address, 0xFFF)?;
+            let message = message.join(" ");
+            format_message(&message, vm)?;
+            vm.tracepoints.insert(address, message);
+            Ok(format!("tracepoint set at {:03X}", address))
+        }
+        ["unbreak", address] => {
+            let address = parse_limited(address, 0xFFF)?;
+            match vm.breakpoints.remove(&address) | vm.tracepoints.remove(&address).is_some() {
+                true => Ok(format!("breakpoint at {:03X} removed", address)),
+                false => Err(format!("no breakpoint at {:03X}", address)),
+            }
+        }
+        ["until", address] => {
+            let address = parse_limited(address, 0xFFF)?;
+            vm.run_to = Some(address);
+            vm.resume();
+            Ok(format!("running until {:03X}", address))
+        }
+        ["watch"] => Ok(list_watches(vm)),
+        ["watch", expression @ ..] => {
+            let watch = Watch::parse(&expression.join(" "))?;
+            let description = watch.describe(vm);
+            vm.watches.push(watch);
+            Ok(format!("watch {}: {}", vm.watches.len(), description))
+        }
+        ["unwatch", index] => {
+            let index = parse_number(index)? as usize;
+            if index == 0 || index > vm.watches.len() {
+                return Err(format!("no watch {}", index));
+            }
+            let watch = vm.watches.remove(index - 1);
+            Ok(format!("removed watch {}", watch.source))
+        }
+        ["back"] => back(vm, "1"),
+        ["back", count] => back(vm, count),
+        ["step"] => step(vm, "1"),
+        ["step", count] => step(vm, count),
+        ["pause"] => {
+            vm.paused = true;
+            Ok(format!("paused at {:03X}", vm.pc))
+        }
+        ["continue"] => {
+            vm.resume();
+            Ok("running".to_string())
+        }
+        ["reset"] => {
+            let rom = vm.rom_path.clone();
+            vm.reset(&rom)?;
+            Ok(format!("reset, reloaded {}", rom))
+        }
+        ["load", rom @ ..] if !rom.is_empty() => {
+            let rom = rom.join(" ");
+            vm.reset(&rom)?;
+            Ok(format!("loaded {}", rom))
+        }
+        ["profile"] => match &vm.profiler {
+            Some(profiler) => Ok(profiler.report().trim_end().to_string()),
+            None => {
+                vm.profiler = Some(Profiler::new());
+                Ok("profiling started".to_string())
+            }
+        },
+        ["profile", "reset"] => match vm.profiler.as_mut() {
+            Some(profiler) => {
+                profiler.clear();
+                Ok("profile cleared".to_string())
+            }
+            None => Err("profiler is not running".to_string()),
+        },
+        ["profile", "stop"] => match vm.profiler.take() {
+            Some(profiler) => Ok(profiler.report().trim_end().to_string()),
+            None => Err("profiler is not running".to_string()),
+        },
+        ["swap"] => {
+            let rom = vm.rom_path.clone();
+            vm.hot_swap(&rom)?;
+            Ok(format!("swapped in {} without reset", rom))
+        }
+        ["swap", rom @ ..] => {
+            let rom = rom.join(" ");
+            vm.hot_swap(&rom)?;
+            Ok(format!("swapped in {} without reset", rom))
+        }
+        ["exec", op] => exec(vm, op),
+        ["drawbreak"] => {
+            vm.break_on_draw = !vm.break_on_draw;
+            Ok(format!("break on draw {}", if vm.break_on_draw { "on" } else { "off" }))
+        }
+        ["snap", name] => {
+            let snapshot = vm.snapshot();
+            vm.snapshots.insert(name.to_string(), snapshot);
+            Ok(format!("snapshot {} taken at {:03X}, frame {}", name, vm.pc, vm.frame))
+        }
+        ["diff", before] => Ok(snapshot(vm, before)?.diff(&vm.snapshot()).trim_end().to_string()),
+        ["diff", before, after] => Ok(snapshot(vm, before)?.diff(snapshot(vm, after)?).trim_end().to_string()),
+        ["movie"] => match &vm.movie {
+            Some(movie) if movie.mode == MovieMode::Recording => Ok(format!("recording {}, {} frames", movie.path, movie.frame_count())),
+            Some(movie) => Ok(format!("playing {}, {} frames", movie.path, movie.frame_count())),
+            None => Ok("no movie".to_string()),
+        },
+        ["movie", "record"] => match vm.movie.as_mut() {
+            Some(movie) if movie.mode == MovieMode::Playing => {
+                movie.resume_recording();
+                Ok(format!("recording into {} from frame {}", movie.path, movie.frame_count()))
+            }
+            Some(_) => Err("already recording".to_string()),
+            None => Err("no movie is playing, start with --play".to_string()),
+        },
+        ["movie", "stop"] => match vm.movie.take() {
+            Some(movie) if movie.mode == MovieMode::Recording => {
+                movie.save()?;
+                Ok(format!("saved {} frames to {}", movie.frame_count(), movie.path))
+            }
+            Some(_) => Ok("playback stopped".to_string()),
+            None => Err("no movie".to_string()),
+        },
+        ["export", path @ ..] if !path.is_empty() => {
+            let path = path.join(" ");
+            vm.export_json(&path)?;
+            Ok(format!("state written to {}", path))
+        }
+        ["import", path @ ..] if !path.is_empty() => {
+            let path = path.join(" ");
+            vm.import_json(&path)?;
+            Ok(format!("state loaded from {}", path))
+        }
+        ["screen"] => {
+            print!("{}", vm.display_ascii());
+            Ok("display printed to stdout".to_string())
+        }
+        ["screen", path @ ..] => {
+            let path = path.join(" ");
+            std::fs::write(&path, vm.display_ascii()).map_err(|e| format!("error writing {}, {}", path, e))?;
+            Ok(format!("display written to {}", path))
+        }
+        ["help"] => Ok(HELP.to_string()),
+        ["set", ..] => Err("usage: set <V0-VF|I|PC|SP|DT|ST> <value>".to_string()),
+        ["poke", ..] => Err("usage: poke <address> <byte> [byte...]".to_string()),
+        ["peek", ..] => Err("usage: peek <address> [length]".to_string()),
+        ["break", ..] | ["unbreak", ..] => Err("usage: break [address [log message]], unbreak <address>".to_string()),
+        ["load", ..] => Err("usage: load <rom>".to_string()),
+        ["unwatch", ..] => Err("usage: unwatch <number>".to_string()),
+        ["exec", ..] => Err("usage: exec <opcode>".to_string()),
+        ["movie", ..] => Err("usage: movie [record|stop]".to_string()),
+        ["export", ..] | ["import", ..] => Err("usage: export <file.json>, import <file.json>".to_string()),
+        ["snap", ..] => Err("usage: snap <name>".to_string()),
+        ["diff", ..] => Err("usage: diff <snapshot> [snapshot], compares with the current state by default".to_string()),
+        ["until", ..] => Err("usage: until <address>".to_string()),
+        ["profile", ..] => Err("usage: profile [reset|stop]".to_string()),
+        [command, ..] => Err(format!("unknown command '{}'", command)),
+    }
+}
+
+const HELP: &str = "set <reg> <value>, poke <addr> <bytes..>, peek <addr> [len]
+break [addr [log msg {V0}]], unbreak <addr>, drawbreak, step [n], back [n]
+until <addr>, exec <op>
+pause, continue
+watch [expr], unwatch <n>, snap <name>, diff <name> [name]
+export <json>, import <json>, screen [file], reset, load <rom>, swap [rom]
+profile [reset|stop], movie [record|stop]";
+
+pub fn parse_number(text: &str) -> Result<u16, String> {
+    let result = if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u16::from_str_radix(hex, 16)
+    } else if let Some(hex) = text.strip_prefix('$') {
+        u16::from_str_radix(hex, 16)
+    } else {
+        text.parse::<u16>()
+    };
+    result.map_err(|_| format!("invalid number '{}'", text))
+}
+
+fn parse_limited(text: &str, max: u16) -> Result<u16, String> {
+    let value = parse_number(text)?;
+    if value > max {
+        return Err(format!("{} is out of range (max {:#x})", text, max));
+    }
+    Ok(value)
+}
+
+fn set(vm: &mut VM, target: &str, value: &str) -> Result<String, String> {
+    let name = target.to_ascii_uppercase();
+    match name.as_str() {
+        "I" => vm.i = parse_limited(value, 0xFFF)?,
+        "PC" => vm.pc = parse_limited(value, 0xFFE)?,
+        "SP" => vm.sp = parse_limited(value, 0xF)?,
+        "DT" => vm.delay = parse_limited(value, 0xFF)? as u8,
+        "ST" => vm.sound = parse_limited(value, 0xFF)? as u8,
+        _ => match name.strip_prefix('V').and_then(|r| u8::from_str_radix(r, 16).ok()) {
+            Some(r) if r < 16 && name.len() == 2 => vm.v[r as usize] = parse_limited(value, 0xFF)? as u8,
+            _ => return Err(format!("unknown register '{}'", target)),
+        },
+    }
+    // Going back can't replay an edit, so history starts again from here, see rewind.rs
+    vm.rewind.clear();
+    Ok(format!("{} = {}", name, value))
+}
+
+fn poke(vm: &mut VM, address: &str, bytes: &[&str]) -> Result<String, String> {
+    let start = parse_limited(address, 0xFFF)? as usize;
+    if start + bytes.len() > vm.memory.len() {
+        return Err("write goes past the end of memory".to_string());
+    }
+
+    let values = bytes.iter().map(|b| parse_limited(b, 0xFF).map(|v| v as u8)).collect::<Result<Vec<u8>, String>>()?;
+    vm.memory[start..start + values.len()].copy_from_slice(&values);
+    vm.rewind.clear();
+    Ok(format!("wrote {} byte(s) at {:03X}", values.len(), start))
+}
+
+fn peek(vm: &VM, address: &str, length: &str) -> Result<String, String> {
+    let start = parse_limited(address, 0xFFF)? as usize;
+    let length = parse_limited(length, 0x100)? as usize;
+    let end = (start + length).min(vm.memory.len());
+
+    let mut lines = Vec::new();
+    for row in (start..end).step_by(8) {
+        let bytes: Vec<String> = vm.memory[row..(row + 8).min(end)].iter().map(|b| format!("{:02X}", b)).collect();
+        lines.push(format!("{:03X}: {}", row, bytes.join(" ")));
+    }
+    Ok(lines.join("\n"))
+}
+
+fn list_breakpoints(vm: &VM) -> String {
+    if vm.breakpoints.is_empty() && vm.tracepoints.is_empty() {
+        return "no breakpoints".to_string();
+    }
+    let addresses: Vec<String> = vm.breakpoints.iter().map(|a| format!("{:03X}", a)).collect();
+    let mut lines = vec![format!("breakpoints: {}", addresses.join(" "))];
+    for (address, message) in &vm.tracepoints {
+        lines.push(format!("{:03X} log {}", address, message));
+    }
+    lines.join("\n")
+}
+
+fn snapshot<'a>(vm: &'a VM, name: &str) -> Result<&'a Snapshot, String> {
+    vm.snapshots.get(name).ok_or_else(|| format!("no snapshot named '{}'", name))
+}
+
+fn list_watches(vm: &VM) -> String {
+    if vm.watches.is_empty() {
+        return "no watches".to_string();
+    }
+    let lines: Vec<String> = vm.watches.iter().enumerate().map(|(index, watch)| format!("{}: {}", index + 1, watch.describe(vm))).collect();
+    lines.join("\n")
+}
+
+// Run one instruction that isn't in memory against the current state. PC is put back afterwards,
+// so a skip only reports whether it would have been taken, and instructions that only exist to
+// change control flow are refused, as is FX0A, which waits by not moving PC. It shows up in the
+// trace, but not in coverage, which is about the rom.
+fn exec(vm: &mut VM, op: &str) -> Result<String, String> {
+    let op = parse_number(op)?;
+    match opcode_class(op) {
+        "????" => return Err(format!("{:04X} is not a valid instruction", op)),
+        "00EE" | "0NNN" | "1NNN" | "2NNN" | "BNNN" => return Err(format!("{} changes control flow, use set pc instead", disassemble(op))),
+        "FX0A" => return Err(format!("{} waits for a key, which exec doesn't support", disassemble(op))),
+        _ => {}
+    }
+
+    let (pc, current_op) = (vm.pc, vm.op);
+    let before = vm.registers();
+    vm.op = op;
+    parse_op_code(vm);
+    let skipped = vm.pc == pc + 4;
+    vm.pc = pc;
+    vm.op = current_op;
+    vm.rewind.clear();
+    let after = vm.registers();
+    if let Some(tracer) = vm.tracer.as_mut() {
+        tracer.record(pc, op, &before, &after, &vm.symbols);
+    }
+    if skipped {
+        return Ok(format!("executed {:04X}  {}, skip taken, PC left at {:03X}", op, disassemble(op), vm.pc));
+    }
+    Ok(format!("executed {:04X}  {}", op, disassemble(op)))
+}
+
+// Undo the last `count` instructions by re-running from the nearest history checkpoint
+fn back(vm: &mut VM, count: &str) -> Result<String, String> {
+    let count = parse_number(count)?;
+    vm.step_back(count as u64)?;
+    Ok(format!("PC {}  {}", vm.symbols.address(vm.pc), disassemble_with(vm.current_op(), &vm.symbols)))
+}
+
+// Execute instructions one at a time, ignoring breakpoints, and leave the machine paused
+fn step(vm: &mut VM, count: &str) -> Result<String, String> {
+    let count = parse_number(count)?;
+    vm.paused = true;
+    vm.pause_message = None;
+    for _ in 0..count {
+        vm.emulate_cycle()?;
+        if vm.pause_message.is_some() {
+            break;
+        }
+    }
+    Ok(format!("PC {}  {}", vm.symbols.address(vm.pc), disassemble_with(vm.current_op(), &vm.symbols)))
+}
@@ -0,0 +1,448 @@
+// Command line options
+//
+// Usage: chip8-rust [options] <rom>
+//        chip8-rust check <rom>      report suspicious content in a rom without running it
+//        chip8-rust stats <rom>      print an opcode histogram, call depth and memory footprint
+//        chip8-rust --tui <rom>      play in the terminal, in builds with the tui feature, see tui.rs
+//   --ipf <n>                 instructions executed per 60 Hz frame (default 8)
+//   --trace <file>            write every executed instruction to <file>
+//   --compare-trace <file>    stop at the first instruction that differs from a reference trace
+//   --coverage                print an opcode and rom coverage report on exit (F3 prints it any time)
+//   --heatmap <file>          write per-address execution counts to <file> as csv on exit
+//   --record <file>           record keypad input to a movie, emulation runs in lockstep with frames
+//   --play <file>             play back a movie recorded with --record, which records on once it ends ("movie record" switches sooner)
+//   --gif <file>              record the display to an animated gif (Shift+F12 starts and stops one)
+//   --dump-frames <dir>       write every frame to <dir>/frame-NNNNNN.png (--screenshot-scale applies, default 1)
+//   --dump-limit <n>          exit after dumping <n> frames
+//   --record-video <file>     record display and buzzer through ffmpeg, e.g. --record-video out.mp4
+//   --record-audio <file>     record the buzzer to a wav file, with or without --record-video
+//   --screenshot-dir <dir>    where F12 screenshots go (default: current directory)
+//   --screenshot-scale <n>    screenshot size in pixels per CHIP-8 pixel, 1 is native (default: window scale)
+//   --palette <name>          display colours: classic (default), amber, green, gameboy, octo, paper, lcd, plasma, or
+//                             foreground,background as RRGGBB, e.g. --palette FFB000,1A0F00 (Ctrl+P cycles presets,
+//                             Ctrl+I swaps foreground and background)
+//   --contrast <percent>      stretch (over 100) or narrow (under 100) the difference between the palette's colours
+//   --gamma <value>           brighten (over 1.0) or darken (under 1.0) the palette's colours, 0.2-5.0
+//   --phosphor <frames>       fade pixels out over <frames> frames after they turn off, hides XOR flicker
+//   --frame-blend <2|3>       draw each pixel as its average over the last 2 or 3 frames, instead of --phosphor
+//   --bezel <file.bmp>        artwork drawn around the display, e.g. a cabinet or a streaming layout
+//   --bezel-margins <pixels>  space the artwork takes on each side of the display at the default size,
+//                             one value or left,top,right,bottom (default 40)
+//   --fullscreen              start in fullscreen
+//   --fullscreen-mode <mode>  desktop (default), exclusive to switch the display mode, or borderless for a
+//                             plain window covering the screen
+//   --integer-scale           only scale the display by whole pixels, with wider borders in return
+//   --fullscreen-key <key>    key that toggles fullscreen, by SDL key name (default F11), overrides the
+//                             input profile's [hotkeys]
+//   --renderer <name>         SDL render backend: accelerated, software or a driver name such as opengl,
+//                             falls back to software if it fails (default: SDL's choice)
+//   --vsync                   wait for the display's vertical refresh when presenting, no tearing
+//   --fps <n>                 redraw at most <n> times a second, independent of --ipf
+//   --filter <nearest|linear> how the display is scaled, linear is smoother at odd window sizes (default nearest)
+//   --rotate <degrees>        turn the output by 0, 90, 180 or 270 degrees; Ctrl+R turns it and remembers
+//                             the rotation for the rom, this overrides the remembered one
+//   --crt <effects>           monitor look, any of scanlines,curvature,vignette or all
+//   --audio <sdl|cpal>        audio output, cpal needs a build with --features cpal (default sdl)
+//   --sample-rate <hz>        audio sample rate (default 44100)
+//   --audio-buffer <samples>  audio buffer size, larger avoids crackling on slow machines at the cost of latency
+//   --waveform <shape>        buzzer sound: square (default), sine, triangle or noise
+//   --pitch <hz>              buzzer frequency (default 440)
+//   --volume <percent>        buzzer volume, 0-100 (default 50, +/- change it while running)
+//   --mute                    start with audio muted (M toggles it)
+//   --visual-buzzer           flash a border around the display while the buzzer sounds
+//   --input <file>            input profile binding keys, joystick axes, hats and buttons to the keypad, see input.rs;
+//                             Ctrl+K remaps the keys and saves them to it (default: input.txt in the data directory)
+//   --input-script <file>     press keys from commands read from <file>, a named pipe or - for stdin, see
+//                             inputscript.rs
+//   --keys <layout>           keypad block by the keys of a layout: qwerty, azerty, qwertz, dvorak or colemak,
+//                             instead of by position, which works on any layout as long as scancodes are right
+//   --key-wait <policy>       key FX0A reports when several are held: lowest (default), recent, or release to
+//                             wait for a key to be let go like the COSMAC VIP, see keywait.rs
+//   --keypad                  show a keypad beside the display to play with the mouse or a touchscreen (the first
+//                             touch shows it too)
+//   --tool-windows <views>    open extra windows next to the display, any of registers,memory,keypad
+//   --fast-forward <speed>    how much faster emulation runs while Tab is held, a factor or max (default 4)
+//   --slow-motion <percent>   speed of the slow motion Shift+Tab toggles, in percent of normal (default 25)
+//   --profile                 print host time spent per opcode class on exit ("profile" in the console)
+//   --smc <log|break>         report writes into memory that has already been executed as code
+//   --symbols <file>          label names for addresses, one addr=name per line
+//   --script <file>           run debugger hook script, see script.rs, or a Lua script with the lua feature, see lua.rs
+//   --gdb <[host]:port>       listen for a gdb remote connection, e.g. --gdb :3333
+//   --stream <[host]:port>    show the display in browsers on http://host:port/ and take their keys, see stream.rs
+//   --api <[host]:port>       control the emulator over HTTP or WebSocket, see api.rs
+//   --chat <[server/]#channel> press the keys a Twitch or IRC channel types, see chat.rs
+//   --chat-keys <word=key,..> more chat commands, e.g. fire=5,jump=2
+//   --chat-hold <frames>      how long a chat command holds its key (default 10)
+//   --chat-cooldown <frames>  how long a viewer waits between chat commands (default 30)
+//   --metrics <[host]:port>   serve Prometheus metrics on /metrics, in builds with the metrics feature, see metrics.rs
+//   --no-focus-pause          keep running while the window is in the background, instead of pausing and muting
+//   --no-autosave             don't save the session on exit or offer to resume it on the next launch
+//   --loop-threshold <n>      pause after the same address runs <n> times in a row (default 100, 0 disables)
+
+use sdl2::keyboard::Keycode;
+
+use crate::audio::{parse_driver, parse_waveform, AudioDriver, Waveform};
+use crate::bezel::{parse_margins, Margins, DEFAULT_MARGINS};
+use crate::chat::parse_chat_keys;
+use crate::crt::{parse_effects, CrtEffects};
+use crate::input::parse_layout;
+use crate::keywait::{parse_key_wait, KeyWaitPolicy};
+use crate::layout::{parse_fullscreen_mode, FullscreenMode};
+use crate::palette::{parse_contrast, parse_gamma, parse_palette, Palette, PRESETS};
+use crate::renderer::{parse_renderer, Renderer};
+use crate::romsettings::parse_rotation;
+use crate::smc::{parse_mode, SmcMode};
+use crate::speed::{parse_fast_forward, parse_slow_motion, DEFAULT_FAST_FORWARD, DEFAULT_SLOW_MOTION};
+use crate::toolwindow::{parse_tool_views, ToolView};
+
+pub struct Config {
+    pub rom: String,
+    pub instructions_per_frame: u32,
+    pub trace: Option<String>,
+    pub compare_trace: Option<String>,
+    pub coverage: bool,
+    pub heatmap: Option<String>,
+    pub profile: bool,
+    pub record: Option<String>,
+    pub play: Option<String>,
+    pub gif: Option<String>,
+    pub video: Option<String>,
+    pub record_audio: Option<String>,
+    pub screenshot_dir: String,
+    pub dump_frames: Option<String>,
+    pub dump_limit: Option<u64>,
+    pub screenshot_scale: Option<u32>,
+    pub palette: Palette,
+    pub contrast: u32,
+    pub gamma: f32,
+    pub phosphor: Option<u32>,
+    pub frame_blend: Option<usize>,
+    pub crt: Option<CrtEffects>,
+    pub bezel: Option<String>,
+    pub bezel_margins: Margins,
+    pub fullscreen: bool,
+    pub fullscreen_mode: FullscreenMode,
+    pub integer_scale: bool,
+    pub renderer: Renderer,
+    pub vsync: bool,
+    pub fps: Option<u32>,
+    pub rotation: Option<u16>,
+    pub smooth: bool,
+    pub fullscreen_key: Option<Keycode>,
+    pub audio_driver: AudioDriver,
+    pub sample_rate: u32,
+    pub audio_buffer: Option<u32>,
+    pub waveform: Waveform,
+    pub pitch: u32,
+    pub volume: u8,
+    pub mute: bool,
+    pub visual_buzzer: bool,
+    pub input: Option<String>,
+    pub keys: Option<&'static str>,
+    pub input_script: Option<String>,
+    pub key_wait: KeyWaitPolicy,
+    pub keypad: bool,
+    pub tool_windows: Vec<ToolView>,
+    pub fast_forward: f64,
+    pub slow_motion: f64,
+    pub loop_threshold: u32,
+    pub autosave: bool,
+    pub focus_pause: bool,
+    pub smc: Option<SmcMode>,
+    pub gdb: Option<String>,
+    pub stream: Option<String>,
+    pub api: Option<String>,
+    pub chat: Option<String>,
+    pub chat_keys: Vec<(String, usize)>,
+    pub chat_hold: u32,
+    pub chat_cooldown: u32,
+    pub metrics: Option<String>,
+    pub script: Option<String>,
+    pub symbols: Option<String>,
+}
+
+impl Config {
+    pub fn from_args(args: Vec<String>) -> Result<Self, String> {
+        let mut rom = None;
+        let mut instructions_per_frame = 8;
+        let mut trace = None;
+        let mut compare_trace = None;
+        let mut coverage = false;
+        let mut heatmap = None;
+        let mut profile = false;
+        let mut record = None;
+        let mut play = None;
+        let mut gif = None;
+        let mut video = None;
+        let mut record_audio = None;
+        let mut screenshot_dir = ".".to_string();
+        let mut screenshot_scale = None;
+        let mut dump_frames = None;
+        let mut dump_limit = None;
+        let mut palette = PRESETS[0].1;
+        let mut contrast = 100;
+        let mut gamma = 1.0;
+        let mut phosphor = None;
+        let mut frame_blend = None;
+        let mut crt = None;
+        let mut bezel = None;
+        let mut bezel_margins = DEFAULT_MARGINS;
+        let mut fullscreen = false;
+        let mut fullscreen_mode = FullscreenMode::Desktop;
+        let mut integer_scale = false;
+        let mut renderer = Renderer::Default;
+        let mut vsync = false;
+        let mut fps = None;
+        let mut rotation = None;
+        let mut smooth = false;
+        let mut fullscreen_key = None;
+        let mut audio_driver = AudioDriver::Sdl;
+        let mut sample_rate = 44100;
+        let mut audio_buffer = None;
+        let mut waveform = Waveform::Square;
+        let mut pitch = 440;
+        let mut volume = 50;
+        let mut mute = false;
+        let mut visual_buzzer = false;
+        let mut input = None;
+        let mut keys = None;
+        let mut input_script = None;
+        let mut key_wait = KeyWaitPolicy::Lowest;
+        let mut keypad = false;
+        let mut tool_windows = Vec::new();
+        let mut fast_forward = DEFAULT_FAST_FORWARD;
+        let mut slow_motion = DEFAULT_SLOW_MOTION;
+        let mut loop_threshold = 100;
+        let mut autosave = true;
+        let mut focus_pause = true;
+        let mut smc = None;
+        let mut gdb = None;
+        let mut stream = None;
+        let mut api = None;
+        let mut chat = None;
+        let mut chat_keys = Vec::new();
+        let mut chat_hold = 10;
+        let mut chat_cooldown = 30;
+        let mut metrics = None;
+        let mut script = None;
+        let mut symbols = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--ipf" => {
+                    let value = args.next().ok_or("--ipf needs a number")?;
+                    instructions_per_frame = match value.parse() {
+                        Ok(n) if n > 0 => n,
+                        _ => return Err(format!("invalid instructions per frame {}", value)),
+                    };
+                }
+                "--trace" => trace = Some(args.next().ok_or("--trace needs a file name")?),
+                "--compare-trace" => compare_trace = Some(args.next().ok_or("--compare-trace needs a file name")?),
+                "--coverage" => coverage = true,
+                "--heatmap" => heatmap = Some(args.next().ok_or("--heatmap needs a file name")?),
+                "--profile" => profile = true,
+                "--record" => record = Some(args.next().ok_or("--record needs a file name")?),
+                "--play" => play = Some(args.next().ok_or("--play needs a file name")?),
+                "--gif" => gif = Some(args.next().ok_or("--gif needs a file name")?),
+                "--screenshot-dir" => screenshot_dir = args.next().ok_or("--screenshot-dir needs a directory")?,
+                "--screenshot-scale" => {
+                    let value = args.next().ok_or("--screenshot-scale needs a number")?;
+                    screenshot_scale = match value.parse() {
+                        Ok(n) if n > 0 => Some(n),
+                        _ => return Err(format!("invalid screenshot scale {}", value)),
+                    };
+                }
+                "--dump-frames" => dump_frames = Some(args.next().ok_or("--dump-frames needs a directory")?),
+                "--dump-limit" => {
+                    let value = args.next().ok_or("--dump-limit needs a number")?;
+                    dump_limit = Some(value.parse().map_err(|_| format!("invalid dump limit {}", value))?);
+                }
+                "--record-video" => video = Some(args.next().ok_or("--record-video needs a file name")?),
+                "--palette" => palette = parse_palette(&args.next().ok_or("--palette needs a name or colours")?)?,
+                "--contrast" => contrast = parse_contrast(&args.next().ok_or("--contrast needs a percentage")?)?,
+                "--gamma" => gamma = parse_gamma(&args.next().ok_or("--gamma needs a value")?)?,
+                "--phosphor" => {
+                    let value = args.next().ok_or("--phosphor needs a number of frames")?;
+                    phosphor = match value.parse() {
+                        Ok(n) if n > 0 => Some(n),
+                        _ => return Err(format!("invalid phosphor decay {}", value)),
+                    };
+                }
+                "--frame-blend" => {
+                    let value = args.next().ok_or("--frame-blend needs a number of frames")?;
+                    frame_blend = match value.parse() {
+                        Ok(n @ 2..=3) => Some(n),
+                        _ => return Err(format!("invalid frame blend {}, expected 2 or 3", value)),
+                    };
+                }
+                "--bezel" => bezel = Some(args.next().ok_or("--bezel needs a file name")?),
+                "--bezel-margins" => bezel_margins = parse_margins(&args.next().ok_or("--bezel-margins needs a size")?)?,
+                "--crt" => crt = Some(parse_effects(&args.next().ok_or("--crt needs a list of effects")?)?),
+                "--fullscreen" => fullscreen = true,
+                "--fullscreen-mode" => fullscreen_mode = parse_fullscreen_mode(&args.next().ok_or("--fullscreen-mode needs a mode")?)?,
+                "--integer-scale" => integer_scale = true,
+                "--renderer" => renderer = parse_renderer(&args.next().ok_or("--renderer needs a name")?),
+                "--vsync" => vsync = true,
+                "--filter" => {
+                    smooth = match args.next().ok_or("--filter needs nearest or linear")?.as_str() {
+                        "nearest" => false,
+                        "linear" => true,
+                        other => return Err(format!("invalid filter {}, expected nearest or linear", other)),
+                    };
+                }
+                "--rotate" => rotation = Some(parse_rotation(&args.next().ok_or("--rotate needs an angle")?)?),
+                "--fps" => {
+                    let value = args.next().ok_or("--fps needs a number")?;
+                    fps = match value.parse() {
+                        Ok(n) if n > 0 => Some(n),
+                        _ => return Err(format!("invalid frame rate {}", value)),
+                    };
+                }
+                "--fullscreen-key" => {
+                    let value = args.next().ok_or("--fullscreen-key needs a key name")?;
+                    fullscreen_key = Some(Keycode::from_name(&value).ok_or(format!("unknown key {}", value))?);
+                }
+                "--audio" => audio_driver = parse_driver(&args.next().ok_or("--audio needs a driver")?)?,
+                "--sample-rate" => {
+                    let value = args.next().ok_or("--sample-rate needs a frequency")?;
+                    sample_rate = match value.parse() {
+                        Ok(n) if (8000..=192000).contains(&n) => n,
+                        _ => return Err(format!("invalid sample rate {}, expected 8000-192000", value)),
+                    };
+                }
+                "--audio-buffer" => {
+                    let value = args.next().ok_or("--audio-buffer needs a number of samples")?;
+                    audio_buffer = match value.parse() {
+                        Ok(n) if (16..=32768).contains(&n) => Some(n),
+                        _ => return Err(format!("invalid audio buffer {}, expected 16-32768 samples", value)),
+                    };
+                }
+                "--waveform" => waveform = parse_waveform(&args.next().ok_or("--waveform needs a shape")?)?,
+                "--pitch" => {
+                    let value = args.next().ok_or("--pitch needs a frequency")?;
+                    pitch = match value.parse() {
+                        Ok(n) if (20..=20000).contains(&n) => n,
+                        _ => return Err(format!("invalid pitch {}, expected 20-20000 Hz", value)),
+                    };
+                }
+                "--volume" => {
+                    let value = args.next().ok_or("--volume needs a percentage")?;
+                    volume = match value.parse() {
+                        Ok(n) if n <= 100 => n,
+                        _ => return Err(format!("invalid volume {}, expected 0-100", value)),
+                    };
+                }
+                "--mute" => mute = true,
+                "--visual-buzzer" => visual_buzzer = true,
+                "--input" => input = Some(args.next().ok_or("--input needs a file name")?),
+                "--input-script" => input_script = Some(args.next().ok_or("--input-script needs a file name")?),
+                "--keys" => keys = Some(parse_layout(&args.next().ok_or("--keys needs a layout")?)?),
+                "--fast-forward" => fast_forward = parse_fast_forward(&args.next().ok_or("--fast-forward needs a speed")?)?,
+                "--slow-motion" => slow_motion = parse_slow_motion(&args.next().ok_or("--slow-motion needs a percentage")?)?,
+                "--key-wait" => key_wait = parse_key_wait(&args.next().ok_or("--key-wait needs a policy")?)?,
+                "--keypad" => keypad = true,
+                "--tool-windows" => tool_windows = parse_tool_views(&args.next().ok_or("--tool-windows needs a list of views")?)?,
+                "--record-audio" => record_audio = Some(args.next().ok_or("--record-audio needs a file name")?),
+                "--smc" => smc = Some(parse_mode(&args.next().ok_or("--smc needs a mode")?)?),
+                "--symbols" => symbols = Some(args.next().ok_or("--symbols needs a file name")?),
+                "--script" => script = Some(args.next().ok_or("--script needs a file name")?),
+                "--gdb" => gdb = Some(args.next().ok_or("--gdb needs an address")?),
+                "--stream" => stream = Some(args.next().ok_or("--stream needs an address")?),
+                "--api" => api = Some(args.next().ok_or("--api needs an address")?),
+                "--chat" => chat = Some(args.next().ok_or("--chat needs a channel")?),
+                "--chat-keys" => chat_keys.extend(parse_chat_keys(&args.next().ok_or("--chat-keys needs word=key pairs")?)?),
+                "--chat-hold" => {
+                    let value = args.next().ok_or("--chat-hold needs a number")?;
+                    chat_hold = match value.parse() {
+                        Ok(n) if n > 0 => n,
+                        _ => return Err(format!("invalid chat hold {}", value)),
+                    };
+                }
+                "--chat-cooldown" => {
+                    let value = args.next().ok_or("--chat-cooldown needs a number")?;
+                    chat_cooldown = value.parse().map_err(|_| format!("invalid chat cooldown {}", value))?;
+                }
+                "--metrics" => metrics = Some(args.next().ok_or("--metrics needs an address")?),
+                "--no-autosave" => autosave = false,
+                "--no-focus-pause" => focus_pause = false,
+                "--loop-threshold" => {
+                    let value = args.next().ok_or("--loop-threshold needs a number")?;
+                    loop_threshold = value.parse().map_err(|_| format!("invalid loop threshold {}", value))?;
+                }
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                _ => rom = Some(arg),
+            }
+        }
+
+        if phosphor.is_some() && frame_blend.is_some() {
+            return Err("--phosphor and --frame-blend are alternatives, pick one".to_string());
+        }
+
+        Ok(Self {
+            rom: rom.unwrap_or_else(|| "D:\\Downloads\\IBM Logo.ch8".to_string()),
+            instructions_per_frame,
+            trace,
+            compare_trace,
+            coverage,
+            heatmap,
+            profile,
+            record,
+            play,
+            gif,
+            video,
+            record_audio,
+            screenshot_dir,
+            screenshot_scale,
+            dump_frames,
+            dump_limit,
+            palette,
+            contrast,
+            gamma,
+            phosphor,
+            frame_blend,
+            crt,
+            bezel,
+            bezel_margins,
+            fullscreen,
+            fullscreen_mode,
+            integer_scale,
+            renderer,
+            vsync,
+            fps,
+            rotation,
+            smooth,
+            fullscreen_key,
+            audio_driver,
+            sample_rate,
+            audio_buffer,
+            waveform,
+            pitch,
+            volume,
+            mute,
+            visual_buzzer,
+            input,
+            keys,
+            input_script,
+            key_wait,
+            keypad,
+            tool_windows,
+            fast_forward,
+            slow_motion,
+            loop_threshold,
+            autosave,
+            focus_pause,
+            smc,
+            gdb,
+            stream,
+            api,
+            chat,
+            chat_keys,
+            chat_hold,
+            chat_cooldown,
+            metrics,
+            script,
+            symbols,
+        })
+    }
+}
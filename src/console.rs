@@ -0,0 +1,110 @@
+// In-emulator debug console, opened with ~. Commands are run by command::execute.
+
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::BlendMode;
+
+use crate::chip8::VM;
+use crate::text::{draw_text, GLYPH_HEIGHT};
+
+const MAX_OUTPUT_LINES: usize = 100;
+
+pub struct Console {
+    pub open: bool,
+    input: String,
+    output: Vec<String>,
+    // Previously entered commands, recalled with up/down
+    history: Vec<String>,
+    history_index: usize,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            input: String::new(),
+            output: vec!["type help for a list of commands".to_string()],
+            history: Vec::new(),
+            history_index: 0,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.input.clear();
+    }
+
+    pub fn handle_text(&mut self, text: &str) {
+        // The key that opens the console also produces a text event
+        self.input.extend(text.chars().filter(|c| *c != '`' && *c != '~'));
+    }
+
+    // Returns the command to run when Enter is pressed
+    pub fn handle_key(&mut self, keycode: Keycode) -> Option<String> {
+        match keycode {
+            Keycode::Return | Keycode::KpEnter => {
+                let line = std::mem::take(&mut self.input);
+                self.print(&format!("> {}", line));
+                if !line.trim().is_empty() {
+                    self.history.push(line.clone());
+                }
+                self.history_index = self.history.len();
+                return Some(line);
+            }
+            Keycode::Backspace => {
+                self.input.pop();
+            }
+            Keycode::Escape => self.open = false,
+            Keycode::Up if self.history_index > 0 => {
+                self.history_index -= 1;
+                self.input = self.history[self.history_index].clone();
+            }
+            Keycode::Down if self.history_index < self.history.len() => {
+                self.history_index += 1;
+                self.input = self.history.get(self.history_index).cloned().unwrap_or_default();
+            }
+            _ => {}
+        }
+        None
+    }
+
+    pub fn print(&mut self, text: &str) {
+        for line in text.lines() {
+            self.output.push(line.to_string());
+        }
+        if self.output.len() > MAX_OUTPUT_LINES {
+            self.output.drain(..self.output.len() - MAX_OUTPUT_LINES);
+        }
+    }
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VM<'_> {
+    pub fn draw_console(&mut self, window_scale: u32) -> Result<(), String> {
+        let scale = 2;
+        let line_height = ((GLYPH_HEIGHT + 1) * scale) as i32;
+        let width = 64 * window_scale;
+        let height = 32 * window_scale / 2 + 8;
+        let top = (32 * window_scale - height) as i32;
+
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 32, 220));
+        self.canvas.fill_rect(Rect::new(0, top, width, height))?;
+        self.canvas.set_blend_mode(BlendMode::None);
+
+        let rows = (height as i32 - 8) / line_height - 1;
+        let output = &self.console.output;
+        let visible: Vec<String> = output[output.len().saturating_sub(rows as usize)..].to_vec();
+        for (row, line) in visible.iter().enumerate() {
+            draw_text(&mut self.canvas, 4, top + 4 + row as i32 * line_height, scale, line, Color::RGB(200, 200, 200))?;
+        }
+        let prompt = format!("> {}_", self.console.input);
+        draw_text(&mut self.canvas, 4, top + 4 + rows * line_height, scale, &prompt, Color::RGB(255, 255, 255))
+    }
+}
@@ -0,0 +1,85 @@
+// Opcode coverage: how often each instruction class ran and which rom bytes were ever fetched
+
+use std::collections::HashMap;
+use std::fs;
+
+use crate::disasm::{opcode_class, OPCODE_CLASSES};
+
+pub struct Coverage {
+    class_counts: HashMap<&'static str, u64>,
+    fetched: [bool; 4096],
+    // Number of times an instruction was executed at each address
+    pub executions: Vec<u64>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self { class_counts: HashMap::new(), fetched: [false; 4096], executions: vec![0; 4096] }
+    }
+
+    pub fn record(&mut self, pc: u16, op: u16) {
+        *self.class_counts.entry(opcode_class(op)).or_insert(0) += 1;
+        self.fetched[pc as usize % 4096] = true;
+        self.fetched[(pc as usize + 1) % 4096] = true;
+        self.executions[pc as usize % 4096] += 1;
+    }
+
+    // Write "address,count" for every address that was executed at least once
+    pub fn export_heatmap(&self, path: &str) -> Result<(), String> {
+        let mut csv = String::from("address,count\n");
+        for (address, count) in self.executions.iter().enumerate().filter(|(_, c)| **c > 0) {
+            csv.push_str(&format!("{:#05x},{}\n", address, count));
+        }
+        fs::write(path, csv).map_err(|e| format!("Error writing heatmap {}, {}", path, e))
+    }
+
+    pub fn report(&self, rom_size: usize) -> String {
+        let mut report = String::from("Opcode coverage:\n");
+        let mut unused = Vec::new();
+        for class in OPCODE_CLASSES.iter().chain(["????"].iter()) {
+            match self.class_counts.get(class) {
+                Some(count) => report.push_str(&format!("    {}  {:>10}\n", class, count)),
+                None if *class != "????" => unused.push(*class),
+                None => {}
+            }
+        }
+        if !unused.is_empty() {
+            report.push_str(&format!("Never executed: {}\n", unused.join(" ")));
+        }
+
+        let rom = 0x200..0x200 + rom_size;
+        let fetched = rom.clone().filter(|a| self.fetched[*a]).count();
+        let percent = if rom_size == 0 { 0.0 } else { fetched as f64 * 100.0 / rom_size as f64 };
+        report.push_str(&format!("Rom bytes fetched: {} / {} ({:.1}%)\n", fetched, rom_size, percent));
+
+        let mut ranges = Vec::new();
+        let mut start = None;
+        for address in rom.clone() {
+            match (self.fetched[address], start) {
+                (false, None) => start = Some(address),
+                (true, Some(s)) => {
+                    ranges.push(format_range(s, address - 1));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(s) = start {
+            ranges.push(format_range(s, rom.end - 1));
+        }
+        if !ranges.is_empty() {
+            report.push_str(&format!("Never fetched: {}\n", ranges.join(", ")));
+        }
+        report
+    }
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn format_range(start: usize, end: usize) -> String {
+    if start == end { format!("{:03X}", start) } else { format!("{:03X}-{:03X}", start, end) }
+}
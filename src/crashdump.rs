@@ -0,0 +1,55 @@
+// Crash dumps written when the machine stops on an unrecoverable error (unknown opcode, stack
+// underflow or overflow), so the state can be attached to a bug report
+
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::chip8::VM;
+use crate::disasm::disassemble_with;
+
+impl VM<'_> {
+    // Writes the dump next to the working directory and returns its file name
+    pub fn write_crash_dump(&self, reason: &str) -> Result<String, String> {
+        let stem = Path::new(&self.rom_path).file_stem().and_then(|s| s.to_str()).unwrap_or("chip8");
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let path = format!("{}-crash-{}.txt", stem, seconds);
+        fs::write(&path, self.crash_report(reason)).map_err(|e| format!("Error writing crash dump {}, {}", path, e))?;
+        Ok(path)
+    }
+
+    fn crash_report(&self, reason: &str) -> String {
+        let mut report = format!("{}\nrom: {} ({} bytes)\nframe: {}\n\n", reason, self.rom_path, self.rom_size, self.frame);
+
+        report.push_str(&format!("PC {:03X}  I {:03X}  SP {:X}  DT {:02X}  ST {:02X}  OP {:04X}\n", self.pc, self.i, self.sp, self.delay, self.sound, self.op));
+        for (offset, registers) in self.v.chunks(8).enumerate() {
+            let line: Vec<String> = registers.iter().enumerate().map(|(index, value)| format!("V{:X} {:02X}", offset * 8 + index, value)).collect();
+            report.push_str(&format!("{}\n", line.join("  ")));
+        }
+
+        report.push_str("\nCall stack (innermost first):\n");
+        let call_stack = self.call_stack();
+        if call_stack.is_empty() {
+            report.push_str("    empty\n");
+        }
+        for call_site in call_stack {
+            report.push_str(&format!("    {:03X}  called from {}\n", call_site + 2, self.symbols.address(call_site)));
+        }
+
+        report.push_str("\nDisassembly:\n");
+        let first = (self.pc as usize).saturating_sub(16);
+        for address in (first..(self.pc as usize + 18).min(4095)).step_by(2) {
+            let op = (self.memory[address] as u16) << 8 | self.memory[address + 1] as u16;
+            let marker = if address == self.pc as usize { ">" } else { " " };
+            let label = self.symbols.name(address as u16).map(|l| format!("  <{}>", l)).unwrap_or_default();
+            report.push_str(&format!("{} {:03X}  {:04X}  {}{}\n", marker, address, op, disassemble_with(op, &self.symbols), label));
+        }
+
+        report.push_str("\nMemory:\n");
+        for (row, bytes) in self.memory.chunks(16).enumerate() {
+            let bytes: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            report.push_str(&format!("{:03X}: {}\n", row * 16, bytes.join(" ")));
+        }
+        report
+    }
+}
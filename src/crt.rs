@@ -0,0 +1,107 @@
+// CRT-style post-processing for --crt: scanlines, screen curvature and a vignette. Done with plain
+// SDL 2D drawing so it works on every render driver: curvature copies each CHIP-8 pixel to a
+// warped rectangle, scanlines and the vignette are blended on top.
+
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::BlendMode;
+
+use crate::chip8::VM;
+
+// How far the corners are pulled in, as a fraction of the half width and height
+const CURVATURE: f32 = 0.04;
+const SCANLINE_ALPHA: u8 = 70;
+// Darkness of the vignette at the corners
+const VIGNETTE_ALPHA: f32 = 150.0;
+
+#[derive(Clone, Copy, Default)]
+pub struct CrtEffects {
+    pub scanlines: bool,
+    pub curvature: bool,
+    pub vignette: bool,
+}
+
+// Comma separated list of effects, or "all"
+pub fn parse_effects(text: &str) -> Result<CrtEffects, String> {
+    let mut effects = CrtEffects::default();
+    for effect in text.split(',') {
+        match effect.trim() {
+            "all" => effects = CrtEffects { scanlines: true, curvature: true, vignette: true },
+            "scanlines" => effects.scanlines = true,
+            "curvature" => effects.curvature = true,
+            "vignette" => effects.vignette = true,
+            other => return Err(format!("unknown --crt effect {}, expected scanlines, curvature, vignette or all", other)),
+        }
+    }
+    Ok(effects)
+}
+
+// Barrel warp of a point in -1..1 coordinates: the further from the centre line on one axis, the
+// more the other axis is pulled in, so edges bow and corners round off
+fn warp(u: f32, v: f32) -> (f32, f32) {
+    (u * (1.0 - CURVATURE * v * v), v * (1.0 - CURVATURE * u * u))
+}
+
+impl VM<'_> {
+    // Copy the display texture into `area` through the enabled effects
+    pub fn draw_crt(&mut self, effects: CrtEffects, area: Rect) -> Result<(), String> {
+        let texture = self.display_texture.as_ref().unwrap();
+        if effects.curvature {
+            self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+            self.canvas.fill_rect(area)?;
+            let to_screen = |u: f32, v: f32| {
+                let (u, v) = warp(u, v);
+                (area.x() as f32 + (u + 1.0) / 2.0 * area.width() as f32, area.y() as f32 + (v + 1.0) / 2.0 * area.height() as f32)
+            };
+            for y in 0..32 {
+                for x in 0..64 {
+                    let (u, v) = (x as f32 / 32.0 - 1.0, y as f32 / 16.0 - 1.0);
+                    let (left, top) = to_screen(u, v);
+                    let (right, bottom) = to_screen(u + 1.0 / 32.0, v + 1.0 / 16.0);
+                    let destination = Rect::new(left.round() as i32, top.round() as i32, (right.round() - left.round()).max(1.0) as u32, (bottom.round() - top.round()).max(1.0) as u32);
+                    self.canvas.copy(texture, Rect::new(x, y, 1, 1), destination)?;
+                }
+            }
+        } else {
+            self.canvas.copy(texture, None, area)?;
+        }
+
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        if effects.scanlines {
+            self.canvas.set_draw_color(Color::RGBA(0, 0, 0, SCANLINE_ALPHA));
+            let lines: Vec<Rect> = (area.y()..area.bottom()).step_by(2).map(|y| Rect::new(area.x(), y + 1, area.width(), 1)).collect();
+            self.canvas.fill_rects(&lines)?;
+        }
+        if effects.vignette {
+            self.draw_vignette(area)?;
+        }
+        self.canvas.set_blend_mode(BlendMode::None);
+        Ok(())
+    }
+
+    // The vignette is a black texture whose alpha grows towards the corners, rendered once per size
+    fn draw_vignette(&mut self, area: Rect) -> Result<(), String> {
+        let stale = self.crt_vignette.as_ref().is_none_or(|texture| {
+            let query = texture.query();
+            (query.width, query.height) != (area.width(), area.height())
+        });
+        if stale {
+            let mut texture = self.texture_creator.create_texture_static(PixelFormatEnum::RGBA32, area.width(), area.height()).map_err(|e| e.to_string())?;
+            let (width, height) = (area.width() as usize, area.height() as usize);
+            let mut pixels = vec![0u8; width * height * 4];
+            for y in 0..height {
+                for x in 0..width {
+                    let u = x as f32 / width as f32 * 2.0 - 1.0;
+                    let v = y as f32 / height as f32 * 2.0 - 1.0;
+                    // 0 in the middle, 1 at the corners
+                    let distance = ((u * u + v * v) / 2.0).sqrt();
+                    pixels[(y * width + x) * 4 + 3] = (distance.powf(2.5) * VIGNETTE_ALPHA) as u8;
+                }
+            }
+            texture.update(None, &pixels, width * 4).map_err(|e| e.to_string())?;
+            texture.set_blend_mode(BlendMode::Blend);
+            self.crt_vignette = Some(texture);
+        }
+        self.canvas.copy(self.crt_vignette.as_ref().unwrap(), None, area)
+    }
+}
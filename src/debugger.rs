@@ -0,0 +1,170 @@
+// Debugger panels drawn alongside the game display, toggled with F5. The window grows to the
+// right (registers, stack, keypad, memory at I) and below (disassembly around PC). Builds with the
+// egui feature open the dockable debugger window of debugwindow.rs on F5 instead.
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+use crate::chip8::VM;
+use crate::disasm::disassemble_with;
+use crate::text::{draw_text, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+pub const PANEL_WIDTH: u32 = 480;
+pub const PANEL_HEIGHT: u32 = 176;
+
+const TEXT_SCALE: u32 = 2;
+const TITLE: Color = Color::RGB(255, 200, 0);
+const TEXT: Color = Color::RGB(220, 220, 220);
+const DIM: Color = Color::RGB(128, 128, 160);
+
+// Keypad layout as printed on the COSMAC VIP
+pub const KEYPAD_LAYOUT: [[usize; 4]; 4] = [[0x1, 0x2, 0x3, 0xC], [0x4, 0x5, 0x6, 0xD], [0x7, 0x8, 0x9, 0xE], [0xA, 0x0, 0xB, 0xF]];
+
+fn line_height() -> i32 {
+    ((GLYPH_HEIGHT + 1) * TEXT_SCALE) as i32
+}
+
+impl VM<'_> {
+    // Resize the window to make room for the panels, or back to just the display
+    pub fn set_debugger_visible(&mut self, visible: bool, window_scale: u32) -> Result<(), String> {
+        self.show_debugger = visible;
+        self.fit_window(window_scale)
+    }
+
+    pub fn draw_debugger(&mut self, window_scale: u32) -> Result<(), String> {
+        let left = (64 * window_scale) as i32;
+        let top = (32 * window_scale) as i32;
+        self.canvas.set_draw_color(Color::RGB(16, 16, 32));
+        self.canvas.fill_rect(Rect::new(left, 0, PANEL_WIDTH, 32 * window_scale + PANEL_HEIGHT))?;
+        self.canvas.fill_rect(Rect::new(0, top, 64 * window_scale, PANEL_HEIGHT))?;
+
+        let mut y = 8;
+        y = self.draw_registers_panel(left + 8, y)?;
+        y = self.draw_stack_panel(left + 8, y + line_height() / 2)?;
+        y = self.draw_keypad_panel(left + 8, y + line_height() / 2)?;
+        y = self.draw_memory_panel(left + 8, y + line_height() / 2)?;
+        self.draw_watch_panel(left + 8, y + line_height() / 2, 32 * window_scale as i32 + PANEL_HEIGHT as i32)?;
+        self.draw_disassembly_panel(8, top + 8)?;
+        Ok(())
+    }
+
+    fn draw_registers_panel(&mut self, x: i32, mut y: i32) -> Result<i32, String> {
+        draw_text(&mut self.canvas, x, y, TEXT_SCALE, "REGISTERS", TITLE)?;
+        y += line_height();
+        let summary = format!("PC {:03X}  I {:03X}  SP {:X}  DT {:02X}  ST {:02X}", self.pc, self.i, self.sp, self.delay, self.sound);
+        draw_text(&mut self.canvas, x, y, TEXT_SCALE, &summary, TEXT)?;
+        y += line_height();
+
+        for (offset, registers) in self.v.chunks(4).enumerate() {
+            let mut line = String::new();
+            for (index, value) in registers.iter().enumerate() {
+                line.push_str(&format!("V{:X} {:02X}  ", offset * 4 + index, value));
+            }
+            draw_text(&mut self.canvas, x, y, TEXT_SCALE, &line, TEXT)?;
+            y += line_height();
+        }
+        Ok(y)
+    }
+
+    fn draw_stack_panel(&mut self, x: i32, mut y: i32) -> Result<i32, String> {
+        let call_stack = self.call_stack();
+        draw_text(&mut self.canvas, x, y, TEXT_SCALE, &format!("STACK ({})", call_stack.len()), TITLE)?;
+        y += line_height();
+        if call_stack.is_empty() {
+            draw_text(&mut self.canvas, x, y, TEXT_SCALE, "EMPTY", DIM)?;
+            y += line_height();
+        }
+        for call_site in call_stack.iter().take(4) {
+            let from = match self.symbols.name(*call_site) {
+                Some(label) => label.to_string(),
+                None => format!("{:03X}", call_site),
+            };
+            draw_text(&mut self.canvas, x, y, TEXT_SCALE, &format!("RET {:03X}  FROM {}", call_site + 2, from), TEXT)?;
+            y += line_height();
+        }
+        if call_stack.len() > 4 {
+            draw_text(&mut self.canvas, x, y, TEXT_SCALE, &format!("... {} MORE", call_stack.len() - 4), DIM)?;
+            y += line_height();
+        }
+        Ok(y)
+    }
+
+    fn draw_keypad_panel(&mut self, x: i32, mut y: i32) -> Result<i32, String> {
+        draw_text(&mut self.canvas, x, y, TEXT_SCALE, "KEYPAD", TITLE)?;
+        y += line_height();
+        let cell = line_height() + 4;
+        for row in KEYPAD_LAYOUT {
+            for (column, key) in row.iter().enumerate() {
+                let cell_x = x + column as i32 * cell;
+                if self.keypad[*key] {
+                    self.canvas.set_draw_color(Color::RGB(0, 160, 0));
+                    self.canvas.fill_rect(Rect::new(cell_x, y, (cell - 2) as u32, (cell - 2) as u32))?;
+                } else {
+                    self.canvas.set_draw_color(DIM);
+                    self.canvas.draw_rect(Rect::new(cell_x, y, (cell - 2) as u32, (cell - 2) as u32))?;
+                }
+                let label_x = cell_x + (cell - 2 - (GLYPH_WIDTH * TEXT_SCALE) as i32) / 2;
+                draw_text(&mut self.canvas, label_x, y + 4, TEXT_SCALE, &format!("{:X}", key), TEXT)?;
+            }
+            y += cell;
+        }
+        Ok(y)
+    }
+
+    fn draw_memory_panel(&mut self, x: i32, mut y: i32) -> Result<i32, String> {
+        draw_text(&mut self.canvas, x, y, TEXT_SCALE, "MEMORY AT I", TITLE)?;
+        y += line_height();
+        let start = (self.i as usize & !7) % 4096;
+        for row in 0..4 {
+            let address = (start + row * 8) % 4096;
+            let mut line = format!("{:03X}:", address);
+            for offset in 0..8 {
+                line.push_str(&format!(" {:02X}", self.memory[(address + offset) % 4096]));
+            }
+            draw_text(&mut self.canvas, x, y, TEXT_SCALE, &line, TEXT)?;
+            y += line_height();
+        }
+        Ok(y)
+    }
+
+    // Watch expressions, as many as fit above `bottom`
+    fn draw_watch_panel(&mut self, x: i32, mut y: i32, bottom: i32) -> Result<i32, String> {
+        if self.watches.is_empty() {
+            return Ok(y);
+        }
+        draw_text(&mut self.canvas, x, y, TEXT_SCALE, "WATCH", TITLE)?;
+        y += line_height();
+        let lines: Vec<String> = self.watches.iter().map(|watch| watch.describe(self)).collect();
+        for line in lines {
+            if y + line_height() > bottom {
+                break;
+            }
+            draw_text(&mut self.canvas, x, y, TEXT_SCALE, &line, TEXT)?;
+            y += line_height();
+        }
+        Ok(y)
+    }
+
+    fn draw_disassembly_panel(&mut self, x: i32, mut y: i32) -> Result<i32, String> {
+        draw_text(&mut self.canvas, x, y, TEXT_SCALE, "DISASSEMBLY", TITLE)?;
+        y += line_height();
+        let first = self.pc as i32 - 8;
+        for index in 0..9 {
+            let address = first + index * 2;
+            if !(0..4095).contains(&address) {
+                y += line_height();
+                continue;
+            }
+            let address = address as usize;
+            let op = (self.memory[address] as u16) << 8 | self.memory[address + 1] as u16;
+            let current = address == self.pc as usize;
+            let mut line = format!("{} {:03X}  {:04X}  {}", if current { ">" } else { " " }, address, op, disassemble_with(op, &self.symbols));
+            if let Some(label) = self.symbols.name(address as u16) {
+                line.push_str(&format!("  <{}>", label));
+            }
+            draw_text(&mut self.canvas, x, y, TEXT_SCALE, &line, if current { Color::RGB(255, 255, 255) } else { DIM })?;
+            y += line_height();
+        }
+        Ok(y)
+    }
+}
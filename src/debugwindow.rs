@@ -0,0 +1,501 @@
+// The egui debugger, in builds with the egui feature: F5 opens it in its own window instead of the
+// panels of debugger.rs. Its panels are dockable tabs, dragged to split the window or stacked as
+// tabs: registers, disassembly, memory, stack, keypad, watches and a console for the debugger
+// commands of command.rs. Above them are buttons to pause, continue, step one instruction and
+// advance one frame.
+//
+// Registers are edited by dragging or double clicking their values, clicking a disassembly line
+// sets or clears a breakpoint there, and holding a keypad button holds that CHIP-8 key. Keys typed
+// while no text field has focus still reach the game, as with the tool windows.
+//
+// egui is drawn with SDL_RenderGeometry on the window's own renderer, so no OpenGL is needed.
+
+use std::collections::HashMap;
+use std::os::raw::c_int;
+use std::ptr;
+use std::time::{Duration, Instant};
+
+use egui_dock::egui::{self, epaint, ClippedPrimitive, Color32, Key, Modifiers, PointerButton, Pos2, RichText, TextureId, Ui, Vec2};
+use egui_dock::{DockArea, DockState, NodeIndex, TabViewer};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Mod;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::WindowCanvas;
+use sdl2::sys;
+use sdl2::VideoSubsystem;
+
+use crate::chip8::VM;
+use crate::command;
+use crate::debugger::KEYPAD_LAYOUT;
+use crate::disasm::disassemble_with;
+
+const REDRAW_INTERVAL: Duration = Duration::from_millis(33);
+// Instructions either side of PC in the disassembly
+const DISASSEMBLY_BEFORE: i32 = 16;
+const DISASSEMBLY_AFTER: i32 = 48;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Tab {
+    Registers,
+    Disassembly,
+    Memory,
+    Stack,
+    Keypad,
+    Watches,
+    Console,
+}
+
+impl Tab {
+    fn title(self) -> &'static str {
+        match self {
+            Tab::Registers => "Registers",
+            Tab::Disassembly => "Disassembly",
+            Tab::Memory => "Memory",
+            Tab::Stack => "Stack",
+            Tab::Keypad => "Keypad",
+            Tab::Watches => "Watches",
+            Tab::Console => "Console",
+        }
+    }
+}
+
+pub struct DebugWindow {
+    canvas: WindowCanvas,
+    ctx: egui::Context,
+    dock: DockState<Tab>,
+    // Input collected since the last redraw
+    events: Vec<egui::Event>,
+    modifiers: Modifiers,
+    // egui's textures, the font atlas mostly. Raw since the renderer outlives no texture creator
+    textures: HashMap<TextureId, *mut sys::SDL_Texture>,
+    start: Instant,
+    next_redraw: Instant,
+    instructions_per_frame: u32,
+    // The keypad key held down with the mouse
+    held_key: Option<usize>,
+    command: String,
+    output: Vec<String>,
+    // Cleared when the window is closed, the main loop then drops it
+    pub open: bool,
+}
+
+impl DebugWindow {
+    pub fn open(video: &VideoSubsystem, instructions_per_frame: u32) -> Result<Self, String> {
+        let window = video.window("CHIP-8 debugger", 900, 640)
+            .allow_highdpi()
+            .resizable()
+            .build()
+            .map_err(|e| e.to_string())?;
+        let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+
+        let mut dock = DockState::new(vec![Tab::Disassembly, Tab::Memory]);
+        let surface = dock.main_surface_mut();
+        let [left, right] = surface.split_right(NodeIndex::root(), 0.55, vec![Tab::Registers, Tab::Watches]);
+        surface.split_below(right, 0.45, vec![Tab::Stack, Tab::Keypad]);
+        surface.split_below(left, 0.7, vec![Tab::Console]);
+
+        Ok(Self {
+            canvas,
+            ctx: egui::Context::default(),
+            dock,
+            events: Vec::new(),
+            modifiers: Modifiers::default(),
+            textures: HashMap::new(),
+            start: Instant::now(),
+            next_redraw: Instant::now(),
+            instructions_per_frame,
+            held_key: None,
+            command: String::new(),
+            output: Vec::new(),
+            open: true,
+        })
+    }
+
+    // Take an event meant for the debugger window, false if it should go to the main loop too
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        if event.get_window_id() != Some(self.canvas.window().id()) {
+            return false;
+        }
+        self.next_redraw = Instant::now();
+        match event {
+            Event::Window { win_event: WindowEvent::Close, .. } => self.open = false,
+            Event::Window { win_event: WindowEvent::Leave, .. } => self.events.push(egui::Event::PointerGone),
+            Event::MouseMotion { x, y, .. } => self.events.push(egui::Event::PointerMoved(Pos2::new(*x as f32, *y as f32))),
+            Event::MouseButtonDown { mouse_btn, x, y, .. } | Event::MouseButtonUp { mouse_btn, x, y, .. } => {
+                let button = match mouse_btn {
+                    MouseButton::Left => PointerButton::Primary,
+                    MouseButton::Right => PointerButton::Secondary,
+                    MouseButton::Middle => PointerButton::Middle,
+                    _ => return true,
+                };
+                self.events.push(egui::Event::PointerButton {
+                    pos: Pos2::new(*x as f32, *y as f32),
+                    button,
+                    pressed: matches!(event, Event::MouseButtonDown { .. }),
+                    modifiers: self.modifiers,
+                });
+            }
+            Event::MouseWheel { precise_x, precise_y, .. } => self.events.push(egui::Event::MouseWheel {
+                unit: egui::MouseWheelUnit::Line,
+                delta: Vec2::new(*precise_x, *precise_y),
+                phase: egui::TouchPhase::Move,
+                modifiers: self.modifiers,
+            }),
+            Event::TextInput { text, .. } => self.events.push(egui::Event::Text(text.clone())),
+            Event::KeyDown { keycode: Some(keycode), keymod, repeat, .. } | Event::KeyUp { keycode: Some(keycode), keymod, repeat, .. } => {
+                self.modifiers = modifiers(*keymod);
+                let pressed = matches!(event, Event::KeyDown { .. });
+                if let Some(key) = Key::from_name(&keycode.name()) {
+                    self.events.push(egui::Event::Key { key, physical_key: None, pressed, repeat: *repeat, modifiers: self.modifiers });
+                }
+                // Typing into the console or a register stays here, other keys play the game
+                return self.ctx.egui_wants_keyboard_input();
+            }
+            _ => {}
+        }
+        true
+    }
+
+    // Redraw the window, at most about 30 times a second unless there was input
+    pub fn draw(&mut self, vm: &mut VM) -> Result<(), String> {
+        let now = Instant::now();
+        if now < self.next_redraw {
+            return Ok(());
+        }
+        self.next_redraw = now + REDRAW_INTERVAL;
+
+        let (width, height) = self.canvas.window().size();
+        let (pixel_width, _) = self.canvas.window().drawable_size();
+        let pixels_per_point = pixel_width as f32 / width.max(1) as f32;
+        let mut input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(Pos2::ZERO, Vec2::new(width as f32, height as f32))),
+            time: Some(self.start.elapsed().as_secs_f64()),
+            events: std::mem::take(&mut self.events),
+            ..Default::default()
+        };
+        input.viewports.entry(egui::ViewportId::ROOT).or_default().native_pixels_per_point = Some(pixels_per_point);
+
+        let mut panels = Panels {
+            vm,
+            instructions_per_frame: self.instructions_per_frame,
+            held_key: &mut self.held_key,
+            command: &mut self.command,
+            output: &mut self.output,
+        };
+        let dock = &mut self.dock;
+        let output = self.ctx.run_ui(input, |ui| {
+            egui::Frame::central_panel(ui.style()).show(ui, |ui| {
+                panels.controls(ui);
+                ui.separator();
+                DockArea::new(dock).show_close_buttons(false).show_inside(ui, &mut panels);
+            });
+        });
+
+        for (id, deltas) in &output.textures_delta.set {
+            for delta in deltas {
+                self.set_texture(*id, delta)?;
+            }
+        }
+        let primitives = self.ctx.tessellate(output.shapes, output.pixels_per_point);
+        self.canvas.set_draw_color(sdl2::pixels::Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        let result = self.paint(&primitives, output.pixels_per_point);
+        self.canvas.set_clip_rect(None);
+        result?;
+        self.canvas.present();
+        for id in &output.textures_delta.free {
+            if let Some(texture) = self.textures.remove(id) {
+                unsafe { sys::SDL_DestroyTexture(texture) };
+            }
+        }
+        Ok(())
+    }
+
+    fn paint(&mut self, primitives: &[ClippedPrimitive], pixels_per_point: f32) -> Result<(), String> {
+        for ClippedPrimitive { clip_rect, primitive } in primitives {
+            let epaint::Primitive::Mesh(mesh) = primitive else { continue };
+            let clip = clip_rect.min.to_vec2() * pixels_per_point;
+            let size = clip_rect.size() * pixels_per_point;
+            if size.x < 1.0 || size.y < 1.0 {
+                continue;
+            }
+            self.canvas.set_clip_rect(sdl2::rect::Rect::new(clip.x as i32, clip.y as i32, size.x.ceil() as u32, size.y.ceil() as u32));
+
+            let vertices: Vec<sys::SDL_Vertex> = mesh.vertices.iter().map(|vertex| {
+                let [r, g, b, a] = vertex.color.to_srgba_unmultiplied();
+                sys::SDL_Vertex {
+                    position: sys::SDL_FPoint { x: vertex.pos.x * pixels_per_point, y: vertex.pos.y * pixels_per_point },
+                    color: sys::SDL_Color { r, g, b, a },
+                    tex_coord: sys::SDL_FPoint { x: vertex.uv.x, y: vertex.uv.y },
+                }
+            }).collect();
+            let indices: Vec<c_int> = mesh.indices.iter().map(|index| *index as c_int).collect();
+            let texture = self.textures.get(&mesh.texture_id).copied().unwrap_or(ptr::null_mut());
+            let result = unsafe {
+                sys::SDL_RenderGeometry(self.canvas.raw(), texture, vertices.as_ptr(), vertices.len() as c_int, indices.as_ptr(), indices.len() as c_int)
+            };
+            if result != 0 {
+                return Err(sdl2::get_error());
+            }
+        }
+        Ok(())
+    }
+
+    // Create or patch one of egui's textures
+    fn set_texture(&mut self, id: TextureId, delta: &epaint::ImageDelta) -> Result<(), String> {
+        let epaint::ImageData::Color(image) = &delta.image;
+        let [width, height] = image.size;
+        let pixels: Vec<u8> = image.pixels.iter().flat_map(Color32::to_srgba_unmultiplied).collect();
+        let texture = match (delta.pos, self.textures.get(&id)) {
+            (Some(_), Some(texture)) => *texture,
+            _ => {
+                let texture = unsafe {
+                    sys::SDL_CreateTexture(
+                        self.canvas.raw(),
+                        PixelFormatEnum::RGBA32 as u32,
+                        sys::SDL_TextureAccess::SDL_TEXTUREACCESS_STATIC as c_int,
+                        width as c_int,
+                        height as c_int,
+                    )
+                };
+                if texture.is_null() {
+                    return Err(sdl2::get_error());
+                }
+                unsafe { sys::SDL_SetTextureBlendMode(texture, sys::SDL_BlendMode::SDL_BLENDMODE_BLEND) };
+                if let Some(old) = self.textures.insert(id, texture) {
+                    unsafe { sys::SDL_DestroyTexture(old) };
+                }
+                texture
+            }
+        };
+        let [x, y] = delta.pos.unwrap_or([0, 0]);
+        let rect = sys::SDL_Rect { x: x as c_int, y: y as c_int, w: width as c_int, h: height as c_int };
+        if unsafe { sys::SDL_UpdateTexture(texture, &rect, pixels.as_ptr().cast(), (width * 4) as c_int) } != 0 {
+            return Err(sdl2::get_error());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for DebugWindow {
+    fn drop(&mut self) {
+        for texture in self.textures.values() {
+            unsafe { sys::SDL_DestroyTexture(*texture) };
+        }
+    }
+}
+
+fn modifiers(keymod: Mod) -> Modifiers {
+    let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+    let gui = keymod.intersects(Mod::LGUIMOD | Mod::RGUIMOD);
+    Modifiers {
+        alt: keymod.intersects(Mod::LALTMOD | Mod::RALTMOD),
+        ctrl,
+        shift: keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD),
+        mac_cmd: cfg!(target_os = "macos") && gui,
+        command: if cfg!(target_os = "macos") { gui } else { ctrl },
+    }
+}
+
+// What the tabs draw from, for one redraw
+struct Panels<'v, 'a> {
+    vm: &'v mut VM<'a>,
+    instructions_per_frame: u32,
+    held_key: &'v mut Option<usize>,
+    command: &'v mut String,
+    output: &'v mut Vec<String>,
+}
+
+impl Panels<'_, '_> {
+    fn run(&mut self, line: &str) {
+        match command::execute(self.vm, line) {
+            Ok(message) if message.is_empty() => {}
+            Ok(message) => self.output.push(message),
+            Err(error) => self.output.push(format!("error: {}", error)),
+        }
+    }
+
+    fn controls(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            if self.vm.paused {
+                if ui.button("Continue").clicked() {
+                    self.run("continue");
+                }
+            } else if ui.button("Pause").clicked() {
+                self.run("pause");
+            }
+            if ui.button("Step").clicked() {
+                self.run("step");
+            }
+            if ui.button("Frame").clicked() {
+                self.vm.paused = true;
+                if let Err(report) = self.vm.step_frame(self.instructions_per_frame) {
+                    self.output.push(report);
+                }
+            }
+            ui.separator();
+            let status = match &self.vm.pause_message {
+                Some(message) => message.clone(),
+                None if self.vm.paused => "paused".to_string(),
+                None => "running".to_string(),
+            };
+            ui.label(format!("{}   frame {}   {} instructions", status, self.vm.frame, self.vm.cycles));
+        });
+    }
+
+    fn registers(&mut self, ui: &mut Ui) {
+        let vm = &mut *self.vm;
+        let mut changed = false;
+        egui::Grid::new("registers").num_columns(4).spacing([24.0, 4.0]).show(ui, |ui| {
+            ui.monospace("PC");
+            ui.monospace(format!("{:03X}", vm.pc));
+            ui.monospace("I");
+            changed |= ui.add(egui::DragValue::new(&mut vm.i).range(0..=0xFFF).hexadecimal(3, false, true)).changed();
+            ui.end_row();
+            ui.monospace("DT");
+            changed |= ui.add(egui::DragValue::new(&mut vm.delay).hexadecimal(2, false, true)).changed();
+            ui.monospace("ST");
+            changed |= ui.add(egui::DragValue::new(&mut vm.sound).hexadecimal(2, false, true)).changed();
+            ui.end_row();
+            for (row, registers) in vm.v.chunks_mut(2).enumerate() {
+                for (column, value) in registers.iter_mut().enumerate() {
+                    ui.monospace(format!("V{:X}", row * 2 + column));
+                    changed |= ui.add(egui::DragValue::new(value).hexadecimal(2, false, true)).changed();
+                }
+                ui.end_row();
+            }
+        });
+        // Going back can't replay an edit, so history starts again from here
+        if changed {
+            vm.rewind.clear();
+        }
+    }
+
+    fn disassembly(&mut self, ui: &mut Ui) {
+        let pc = self.vm.pc as i32;
+        for index in -DISASSEMBLY_BEFORE..DISASSEMBLY_AFTER {
+            let address = pc + index * 2;
+            if !(0..4095).contains(&address) {
+                continue;
+            }
+            let address = address as u16;
+            let op = (self.vm.memory[address as usize] as u16) << 8 | self.vm.memory[address as usize + 1] as u16;
+            let breakpoint = self.vm.breakpoints.contains(&address);
+            let mut line = format!("{} {:03X}  {:04X}  {}", if breakpoint { "●" } else { " " }, address, op, disassemble_with(op, &self.vm.symbols));
+            if let Some(label) = self.vm.symbols.name(address) {
+                line.push_str(&format!("  <{}>", label));
+            }
+            let current = address == self.vm.pc;
+            let text = RichText::new(line).monospace();
+            let response = ui.selectable_label(current, if breakpoint { text.color(Color32::from_rgb(230, 80, 80)) } else { text });
+            if current && self.vm.paused {
+                response.scroll_to_me(Some(egui::Align::Center));
+            }
+            if response.clicked() {
+                self.run(&format!("{} {:03X}", if breakpoint { "unbreak" } else { "break" }, address));
+            }
+        }
+    }
+
+    fn memory(&mut self, ui: &mut Ui) {
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        let i = self.vm.i as usize;
+        ui.label(format!("I at {:03X}, PC at {:03X}", i, self.vm.pc));
+        egui::ScrollArea::vertical().auto_shrink(false).show_rows(ui, row_height, 4096 / 16, |ui, rows| {
+            for row in rows {
+                let address = row * 16;
+                let bytes: Vec<String> = self.vm.memory[address..address + 16].iter().map(|byte| format!("{:02X}", byte)).collect();
+                let text = RichText::new(format!("{:03X}: {}", address, bytes.join(" "))).monospace();
+                ui.label(if (address..address + 16).contains(&i) { text.strong() } else { text });
+            }
+        });
+    }
+
+    fn stack(&mut self, ui: &mut Ui) {
+        let call_stack = self.vm.call_stack();
+        if call_stack.is_empty() {
+            ui.weak("empty");
+        }
+        for call_site in call_stack {
+            let from = match self.vm.symbols.name(call_site) {
+                Some(label) => label.to_string(),
+                None => format!("{:03X}", call_site),
+            };
+            ui.monospace(format!("return to {:03X}, called from {}", call_site + 2, from));
+        }
+    }
+
+    fn keypad(&mut self, ui: &mut Ui) {
+        egui::Grid::new("keypad").spacing([4.0, 4.0]).show(ui, |ui| {
+            for row in KEYPAD_LAYOUT {
+                for key in row {
+                    let button = egui::Button::new(RichText::new(format!("{:X}", key)).monospace().size(18.0))
+                        .min_size(Vec2::splat(36.0))
+                        .selected(self.vm.keypad[key]);
+                    let response = ui.add(button);
+                    if response.is_pointer_button_down_on() {
+                        self.vm.keypad[key] = true;
+                        *self.held_key = Some(key);
+                    } else if *self.held_key == Some(key) {
+                        self.vm.keypad[key] = false;
+                        *self.held_key = None;
+                    }
+                }
+                ui.end_row();
+            }
+        });
+    }
+
+    fn watches(&mut self, ui: &mut Ui) {
+        if self.vm.watches.is_empty() {
+            ui.weak("no watches, add one with the console's watch command");
+        }
+        for watch in &self.vm.watches {
+            ui.monospace(watch.describe(self.vm));
+        }
+    }
+
+    fn console(&mut self, ui: &mut Ui) {
+        let input = ui.add(egui::TextEdit::singleline(self.command).desired_width(f32::INFINITY).hint_text("help for the commands"));
+        if input.lost_focus() && ui.input(|input| input.key_pressed(Key::Enter)) {
+            let line = std::mem::take(self.command);
+            self.output.push(format!("> {}", line));
+            self.run(&line);
+            input.request_focus();
+        }
+        egui::ScrollArea::vertical().auto_shrink(false).stick_to_bottom(true).show(ui, |ui| {
+            for line in self.output.iter() {
+                ui.monospace(line);
+            }
+        });
+    }
+}
+
+impl TabViewer for Panels<'_, '_> {
+    type Tab = Tab;
+
+    fn id(&mut self, tab: &mut Tab) -> egui::Id {
+        egui::Id::new(tab.title())
+    }
+
+    fn title(&mut self, tab: &mut Tab) -> egui::WidgetText {
+        tab.title().into()
+    }
+
+    fn ui(&mut self, ui: &mut Ui, tab: &mut Tab) {
+        match tab {
+            Tab::Registers => self.registers(ui),
+            Tab::Disassembly => self.disassembly(ui),
+            Tab::Memory => self.memory(ui),
+            Tab::Stack => self.stack(ui),
+            Tab::Keypad => self.keypad(ui),
+            Tab::Watches => self.watches(ui),
+            Tab::Console => self.console(ui),
+        }
+    }
+
+    fn is_closeable(&self, _tab: &Tab) -> bool {
+        false
+    }
+}
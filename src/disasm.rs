@@ -0,0 +1,136 @@
+// Opcode mnemonics, following the naming in Cowgod's technical reference
+// http://devernay.free.fr/hacks/chip8/C8TECH10.HTM
+
+use crate::symbols::Symbols;
+
+pub fn disassemble(op: u16) -> String {
+    disassemble_with(op, &Symbols::default())
+}
+
+// Disassemble using label names for address operands where the symbol table has one
+pub fn disassemble_with(op: u16, symbols: &Symbols) -> String {
+    let x = (op & 0x0F00) >> 8;
+    let y = (op & 0x00F0) >> 4;
+    let n = op & 0x000F;
+    let kk = op & 0x00FF;
+    let nnn = symbols.address(op & 0x0FFF);
+
+    match op & 0xF000 {
+        0x0000 => match op {
+            0x00E0 => "CLS".to_string(),
+            0x00EE => "RET".to_string(),
+            _ => format!("SYS {}", nnn),
+        },
+        0x1000 => format!("JP {}", nnn),
+        0x2000 => format!("CALL {}", nnn),
+        0x3000 => format!("SE V{:X}, {:#04x}", x, kk),
+        0x4000 => format!("SNE V{:X}, {:#04x}", x, kk),
+        0x5000 if n == 0 => format!("SE V{:X}, V{:X}", x, y),
+        0x6000 => format!("LD V{:X}, {:#04x}", x, kk),
+        0x7000 => format!("ADD V{:X}, {:#04x}", x, kk),
+        0x8000 => match n {
+            0x0 => format!("LD V{:X}, V{:X}", x, y),
+            0x1 => format!("OR V{:X}, V{:X}", x, y),
+            0x2 => format!("AND V{:X}, V{:X}", x, y),
+            0x3 => format!("XOR V{:X}, V{:X}", x, y),
+            0x4 => format!("ADD V{:X}, V{:X}", x, y),
+            0x5 => format!("SUB V{:X}, V{:X}", x, y),
+            0x6 => format!("SHR V{:X}, V{:X}", x, y),
+            0x7 => format!("SUBN V{:X}, V{:X}", x, y),
+            0xE => format!("SHL V{:X}, V{:X}", x, y),
+            _ => unknown(op),
+        },
+        0x9000 if n == 0 => format!("SNE V{:X}, V{:X}", x, y),
+        0xA000 => format!("LD I, {}", nnn),
+        0xB000 => format!("JP V0, {}", nnn),
+        0xC000 => format!("RND V{:X}, {:#04x}", x, kk),
+        0xD000 => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        0xE000 => match kk {
+            0x9E => format!("SKP V{:X}", x),
+            0xA1 => format!("SKNP V{:X}", x),
+            _ => unknown(op),
+        },
+        0xF000 => match kk {
+            0x07 => format!("LD V{:X}, DT", x),
+            0x0A => format!("LD V{:X}, K", x),
+            0x15 => format!("LD DT, V{:X}", x),
+            0x18 => format!("LD ST, V{:X}", x),
+            0x1E => format!("ADD I, V{:X}", x),
+            0x29 => format!("LD F, V{:X}", x),
+            0x33 => format!("LD B, V{:X}", x),
+            0x55 => format!("LD [I], V{:X}", x),
+            0x65 => format!("LD V{:X}, [I]", x),
+            0x75 => format!("LD R, V{:X}", x),
+            0x85 => format!("LD V{:X}, R", x),
+            _ => unknown(op),
+        },
+        _ => unknown(op),
+    }
+}
+
+fn unknown(op: u16) -> String {
+    format!("DW {:#06x}", op)
+}
+
+pub const OPCODE_CLASSES: [&str; 37] = [
+    "00E0", "00EE", "0NNN", "1NNN", "2NNN", "3XKK", "4XKK", "5XY0", "6XKK", "7XKK", "8XY0", "8XY1", "8XY2",
+    "8XY3", "8XY4", "8XY5", "8XY6", "8XY7", "8XYE", "9XY0", "ANNN", "BNNN", "CXKK", "DXYN", "EX9E", "EXA1",
+    "FX07", "FX0A", "FX15", "FX18", "FX1E", "FX29", "FX33", "FX55", "FX65", "FX75", "FX85",
+];
+
+// Instruction pattern an opcode belongs to, e.g. 0x6A02 -> "6XKK". Undecodable opcodes are "????".
+pub fn opcode_class(op: u16) -> &'static str {
+    let n = op & 0x000F;
+    let kk = op & 0x00FF;
+    match op & 0xF000 {
+        0x0000 => match op {
+            0x00E0 => "00E0",
+            0x00EE => "00EE",
+            _ => "0NNN",
+        },
+        0x1000 => "1NNN",
+        0x2000 => "2NNN",
+        0x3000 => "3XKK",
+        0x4000 => "4XKK",
+        0x5000 if n == 0 => "5XY0",
+        0x6000 => "6XKK",
+        0x7000 => "7XKK",
+        0x8000 => match n {
+            0x0 => "8XY0",
+            0x1 => "8XY1",
+            0x2 => "8XY2",
+            0x3 => "8XY3",
+            0x4 => "8XY4",
+            0x5 => "8XY5",
+            0x6 => "8XY6",
+            0x7 => "8XY7",
+            0xE => "8XYE",
+            _ => "????",
+        },
+        0x9000 if n == 0 => "9XY0",
+        0xA000 => "ANNN",
+        0xB000 => "BNNN",
+        0xC000 => "CXKK",
+        0xD000 => "DXYN",
+        0xE000 => match kk {
+            0x9E => "EX9E",
+            0xA1 => "EXA1",
+            _ => "????",
+        },
+        0xF000 => match kk {
+            0x07 => "FX07",
+            0x0A => "FX0A",
+            0x15 => "FX15",
+            0x18 => "FX18",
+            0x1E => "FX1E",
+            0x29 => "FX29",
+            0x33 => "FX33",
+            0x55 => "FX55",
+            0x65 => "FX65",
+            0x75 => "FX75",
+            0x85 => "FX85",
+            _ => "????",
+        },
+        _ => "????",
+    }
+}
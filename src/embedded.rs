@@ -0,0 +1,63 @@
+// The display drawn to anything implementing embedded-graphics' DrawTarget, which is how drivers
+// for small panels like the SSD1306 OLED or the ILI9341 TFT take pixels, to build a handheld around
+// the emulator. The core uses std, so that's a Raspberry Pi or a microcontroller with std such as
+// the ESP32. Add chip8-rust as a library with the embedded feature and no default features, then
+// per 60 Hz frame:
+//   vm.step_frame(8)?;
+//   panel.draw(&vm, &mut display, [BinaryColor::Off, BinaryColor::On])?;
+// where panel is an EmbeddedDisplay. A 128x64 SSD1306 fits the display at scale 2; colour panels
+// can take the palette's colours from vm.palette_colors().
+//
+// Panels behind SPI or I2C are slow to write to, so after the first frame only the CHIP-8 pixels
+// that changed are sent, and nothing when none did.
+
+use embedded_graphics::pixelcolor::{PixelColor, Rgb888};
+use embedded_graphics::prelude::{DrawTarget, Point, Size};
+use embedded_graphics::primitives::Rectangle;
+
+use crate::chip8::VM;
+
+pub struct EmbeddedDisplay {
+    // Top left corner of the display on the panel, and the panel pixels per CHIP-8 pixel
+    pub origin: Point,
+    pub scale: u32,
+    // What the panel shows, None until the first draw or after invalidate
+    shown: Option<[u8; 64 * 32]>,
+}
+
+impl EmbeddedDisplay {
+    pub fn new(origin: Point, scale: u32) -> Self {
+        Self { origin, scale: scale.max(1), shown: None }
+    }
+
+    // Draw everything next time, e.g. after something else drew over the panel
+    pub fn invalidate(&mut self) {
+        self.shown = None;
+    }
+
+    // `colors` are for unlit and lit pixels
+    pub fn draw<D, C>(&mut self, vm: &VM, target: &mut D, colors: [C; 2]) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = C>,
+        C: PixelColor,
+    {
+        let size = Size::new(self.scale, self.scale);
+        for (index, pixel) in vm.display.iter().enumerate() {
+            if self.shown.is_some_and(|shown| shown[index] == *pixel) {
+                continue;
+            }
+            let (x, y) = ((index % 64) as u32 * self.scale, (index / 64) as u32 * self.scale);
+            let area = Rectangle::new(self.origin + Point::new(x as i32, y as i32), size);
+            target.fill_solid(&area, colors[(*pixel).min(1) as usize])?;
+        }
+        self.shown = Some(vm.display);
+        Ok(())
+    }
+}
+
+impl VM<'_> {
+    // The palette's unlit and lit colours, in a colour panel's format such as Rgb565
+    pub fn palette_colors<C: PixelColor + From<Rgb888>>(&self) -> [C; 2] {
+        self.display_palette().map(|[r, g, b]| C::from(Rgb888::new(r, g, b)))
+    }
+}
@@ -0,0 +1,37 @@
+// Frame blending: each pixel is drawn at its average over the last few 60 Hz frames, a simpler
+// anti-flicker option than phosphor decay. A sprite that is erased and redrawn every other frame
+// shows at half brightness instead of flashing.
+
+use std::collections::VecDeque;
+
+pub struct FrameBlend {
+    // Displays at the end of the previous frames, newest last
+    history: VecDeque<[u8; 64 * 32]>,
+    // Frames averaged, including the one being drawn
+    frames: usize,
+}
+
+impl FrameBlend {
+    pub fn new(frames: usize) -> Self {
+        Self { history: VecDeque::with_capacity(frames), frames }
+    }
+
+    // Called at the end of every emulated frame
+    pub fn push(&mut self, display: &[u8; 64 * 32]) {
+        if self.history.len() == self.frames - 1 {
+            self.history.pop_front();
+        }
+        self.history.push_back(*display);
+    }
+
+    pub fn intensity(&self, display: &[u8; 64 * 32]) -> [f32; 64 * 32] {
+        let mut intensity = [0.0; 64 * 32];
+        for frame in self.history.iter().chain([display]) {
+            for (intensity, pixel) in intensity.iter_mut().zip(frame) {
+                *intensity += (*pixel).min(1) as f32;
+            }
+        }
+        let count = (self.history.len() + 1) as f32;
+        intensity.map(|sum| sum / count)
+    }
+}
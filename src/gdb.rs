@@ -0,0 +1,361 @@
+// GDB remote serial protocol stub
+// https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+//
+// The socket is non-blocking and polled from the main loop. Registers are described to gdb
+// through target.xml: V0-VF (8 bit), I (16 bit), PC (16 bit), SP, DT and ST (8 bit).
+
+use std::collections::HashSet;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::chip8::VM;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" regnum="0"/>
+    <reg name="v1" bitsize="8"/>
+    <reg name="v2" bitsize="8"/>
+    <reg name="v3" bitsize="8"/>
+    <reg name="v4" bitsize="8"/>
+    <reg name="v5" bitsize="8"/>
+    <reg name="v6" bitsize="8"/>
+    <reg name="v7" bitsize="8"/>
+    <reg name="v8" bitsize="8"/>
+    <reg name="v9" bitsize="8"/>
+    <reg name="va" bitsize="8"/>
+    <reg name="vb" bitsize="8"/>
+    <reg name="vc" bitsize="8"/>
+    <reg name="vd" bitsize="8"/>
+    <reg name="ve" bitsize="8"/>
+    <reg name="vf" bitsize="8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8"/>
+    <reg name="dt" bitsize="8"/>
+    <reg name="st" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    buffer: Vec<u8>,
+    breakpoints: HashSet<u16>,
+    running: bool,
+    // Address of the breakpoint we are continuing from, so it doesn't trigger again immediately
+    skip_pc: Option<u16>,
+}
+
+impl GdbStub {
+    pub fn listen(address: &str) -> Result<Self, String> {
+        let address = if address.starts_with(':') { format!("127.0.0.1{}", address) } else { address.to_string() };
+        let listener = TcpListener::bind(&address).map_err(|e| format!("Error starting gdb stub on {}, {}", address, e))?;
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+        println!("Waiting for gdb on {}", address);
+
+        Ok(Self { listener, client: None, buffer: Vec::new(), breakpoints: HashSet::new(), running: false, skip_pc: None })
+    }
+
+    pub fn connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    // Accept connections and handle any packets that have arrived
+    pub fn poll(&mut self, vm: &mut VM) {
+        if self.client.is_none() {
+            match self.listener.accept() {
+                Ok((stream, peer)) => {
+                    println!("gdb connected from {}", peer);
+                    let _ = stream.set_nonblocking(true);
+                    let _ = stream.set_nodelay(true);
+                    self.client = Some(stream);
+                    self.buffer.clear();
+                    self.running = false;
+                    vm.paused = true;
+                    vm.pause_message = Some("STOPPED BY GDB".to_string());
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    println!("gdb accept failed, {}", e);
+                    return;
+                }
+            }
+        }
+
+        let mut chunk = [0; 1024];
+        loop {
+            let Some(client) = self.client.as_mut() else { return };
+            match client.read(&mut chunk) {
+                Ok(0) => {
+                    self.disconnect(vm);
+                    return;
+                }
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => {
+                    self.disconnect(vm);
+                    return;
+                }
+            }
+        }
+
+        while let Some(packet) = self.next_packet(vm) {
+            let reply = self.handle(vm, &packet);
+            if let Some(reply) = reply {
+                self.send(&reply);
+            }
+        }
+
+        // The machine stopped on its own (halt detection, stack error...) while gdb was waiting
+        if self.running && vm.paused {
+            self.running = false;
+            self.send("S05");
+        }
+    }
+
+    // Called before each instruction while running, returns true when a breakpoint stops execution
+    pub fn hit_breakpoint(&mut self, vm: &mut VM) -> bool {
+        if !self.running {
+            return false;
+        }
+        if self.skip_pc.take() == Some(vm.pc) {
+            return false;
+        }
+        if !self.breakpoints.contains(&vm.pc) {
+            return false;
+        }
+
+        self.running = false;
+        vm.paused = true;
+        vm.pause_message = Some(format!("GDB BREAKPOINT AT {:03X}", vm.pc));
+        self.send("S05");
+        true
+    }
+
+    fn disconnect(&mut self, vm: &mut VM) {
+        println!("gdb disconnected");
+        self.client = None;
+        self.running = false;
+        self.breakpoints.clear();
+        vm.resume();
+    }
+
+    // Extract the next complete "$data#cs" packet, acknowledging it. Ctrl-C is returned as "\x03".
+    fn next_packet(&mut self, vm: &mut VM) -> Option<String> {
+        loop {
+            let first = *self.buffer.first()?;
+            match first {
+                b'$' => break,
+                0x03 => {
+                    self.buffer.remove(0);
+                    vm.paused = true;
+                    vm.pause_message = Some("STOPPED BY GDB".to_string());
+                    return Some("\x03".to_string());
+                }
+                _ => {
+                    // Acks and noise
+                    self.buffer.remove(0);
+                }
+            }
+        }
+
+        let hash = self.buffer.iter().position(|b| *b == b'#')?;
+        if self.buffer.len() < hash + 3 {
+            return None;
+        }
+        let data = String::from_utf8_lossy(&self.buffer[1..hash]).to_string();
+        let checksum = std::str::from_utf8(&self.buffer[hash + 1..hash + 3]).ok().and_then(|c| u8::from_str_radix(c, 16).ok());
+        self.buffer.drain(..hash + 3);
+
+        let expected = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        if checksum == Some(expected) {
+            self.write_raw(b"+");
+            Some(data)
+        } else {
+            self.write_raw(b"-");
+            None
+        }
+    }
+
+    fn send(&mut self, data: &str) {
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        self.write_raw(format!("${}#{:02x}", data, checksum).as_bytes());
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) {
+        if let Some(client) = self.client.as_mut() {
+            // The socket is non-blocking, but replies are small enough to always fit the send buffer
+            let _ = client.write_all(bytes);
+        }
+    }
+
+    fn handle(&mut self, vm: &mut VM, packet: &str) -> Option<String> {
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, |c| c.len_utf8()));
+        let reply = match command {
+            "\x03" => {
+                self.running = false;
+                "S05".to_string()
+            }
+            "?" => "S05".to_string(),
+            "g" => {
+                let mut registers = String::new();
+                for r in 0..21 {
+                    registers.push_str(&read_register(vm, r));
+                }
+                registers
+            }
+            "G" => {
+                let bytes = decode_hex(args);
+                // PC has to leave room to fetch a whole instruction, as with the console's set pc
+                if bytes.len() < 23 || u16::from_le_bytes([bytes[18], bytes[19]]) > 0xFFE {
+                    return Some("E01".to_string());
+                }
+                vm.v.copy_from_slice(&bytes[..16]);
+                vm.i = u16::from_le_bytes([bytes[16], bytes[17]]) & 0xFFF;
+                vm.pc = u16::from_le_bytes([bytes[18], bytes[19]]);
+                vm.sp = bytes[20] as u16 & 0xF;
+                vm.delay = bytes[21];
+                vm.sound = bytes[22];
+                // Going back can't replay an edit, see rewind.rs
+                vm.rewind.clear();
+                "OK".to_string()
+            }
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(r) if r < 21 => read_register(vm, r),
+                _ => "E01".to_string(),
+            },
+            "P" => {
+                let Some((register, value)) = args.split_once('=') else { return Some("E01".to_string()) };
+                let bytes = decode_hex(value);
+                match (usize::from_str_radix(register, 16), bytes.first()) {
+                    (Ok(r), Some(low)) if r < 16 => vm.v[r] = *low,
+                    (Ok(16), Some(low)) => vm.i = u16::from_le_bytes([*low, *bytes.get(1).unwrap_or(&0)]) & 0xFFF,
+                    (Ok(17), Some(low)) => match u16::from_le_bytes([*low, *bytes.get(1).unwrap_or(&0)]) {
+                        pc @ 0..=0xFFE => vm.pc = pc,
+                        _ => return Some("E01".to_string()),
+                    },
+                    (Ok(18), Some(low)) => vm.sp = *low as u16 & 0xF,
+                    (Ok(19), Some(low)) => vm.delay = *low,
+                    (Ok(20), Some(low)) => vm.sound = *low,
+                    _ => return Some("E01".to_string()),
+                }
+                vm.rewind.clear();
+                "OK".to_string()
+            }
+            "m" => match parse_range(args).and_then(|(address, length)| memory_range(vm, address, length)) {
+                Some((start, end)) => vm.memory[start..end].iter().map(|b| format!("{:02x}", b)).collect(),
+                None => "E01".to_string(),
+            },
+            "M" => {
+                let Some((range, data)) = args.split_once(':') else { return Some("E01".to_string()) };
+                let bytes = decode_hex(data);
+                match parse_range(range).and_then(|(address, length)| memory_range(vm, address, length)) {
+                    Some((start, end)) if end - start == bytes.len() => {
+                        vm.memory[start..end].copy_from_slice(&bytes);
+                        vm.rewind.clear();
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            "c" => {
+                self.running = true;
+                self.skip_pc = Some(vm.pc);
+                vm.resume();
+                return None;
+            }
+            "s" => {
+                if let Err(report) = vm.emulate_cycle() {
+                    eprintln!("{}", report);
+                }
+                vm.paused = true;
+                vm.pause_message = Some("STOPPED BY GDB".to_string());
+                "S05".to_string()
+            }
+            "Z" | "z" => {
+                let mut fields = args.split(',');
+                let kind = fields.next();
+                let address = fields.next().and_then(|a| u16::from_str_radix(a, 16).ok());
+                match (kind, address) {
+                    (Some("0") | Some("1"), Some(address)) => {
+                        if command == "Z" {
+                            self.breakpoints.insert(address);
+                        } else {
+                            self.breakpoints.remove(&address);
+                        }
+                        "OK".to_string()
+                    }
+                    _ => String::new(),
+                }
+            }
+            "k" => {
+                self.disconnect(vm);
+                return None;
+            }
+            "D" => {
+                self.send("OK");
+                self.disconnect(vm);
+                return None;
+            }
+            "H" => "OK".to_string(),
+            "q" => self.query(args),
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            return "PacketSize=1000;qXfer:features:read+".to_string();
+        }
+        if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let Some((offset, length)) = parse_range(range) else { return "E01".to_string() };
+            let xml = TARGET_XML.as_bytes();
+            if offset >= xml.len() {
+                return "l".to_string();
+            }
+            let end = offset.saturating_add(length).min(xml.len());
+            let marker = if end == xml.len() { 'l' } else { 'm' };
+            return format!("{}{}", marker, String::from_utf8_lossy(&xml[offset..end]));
+        }
+        match query {
+            "Attached" => "1".to_string(),
+            "C" => "QC1".to_string(),
+            "fThreadInfo" => "m1".to_string(),
+            "sThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+// Start and end of `length` bytes at `address`, None when they aren't all in memory
+fn memory_range(vm: &VM, address: usize, length: usize) -> Option<(usize, usize)> {
+    address.checked_add(length).filter(|end| *end <= vm.memory.len()).map(|end| (address, end))
+}
+
+fn read_register(vm: &VM, register: usize) -> String {
+    match register {
+        0..=15 => format!("{:02x}", vm.v[register]),
+        16 => vm.i.to_le_bytes().iter().map(|b| format!("{:02x}", b)).collect(),
+        17 => vm.pc.to_le_bytes().iter().map(|b| format!("{:02x}", b)).collect(),
+        18 => format!("{:02x}", vm.sp),
+        19 => format!("{:02x}", vm.delay),
+        _ => format!("{:02x}", vm.sound),
+    }
+}
+
+// "addr,length" in hex
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    let (address, length) = range.split_once(',')?;
+    Some((usize::from_str_radix(address, 16).ok()?, usize::from_str_radix(length, 16).ok()?))
+}
+
+fn decode_hex(hex: &str) -> Vec<u8> {
+    hex.as_bytes()
+        .chunks(2)
+        .filter_map(|pair| std::str::from_utf8(pair).ok().and_then(|p| u8::from_str_radix(p, 16).ok()))
+        .collect()
+}
@@ -0,0 +1,193 @@
+// Animated GIF capture of the display. Frames are taken at 60 Hz; frames identical to the previous
+// one only extend its delay, and changed frames store just the rectangle that differs.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+
+pub struct GifRecorder {
+    writer: BufWriter<File>,
+    pub path: String,
+    scale: usize,
+    // Last frame written to the file, and the one waiting for its delay to be known
+    previous: Option<Vec<u8>>,
+    pending: Option<(Vec<u8>, u64)>,
+    frames: u64,
+}
+
+impl GifRecorder {
+    // `palette` holds the colors of unlit and lit pixels
+    pub fn create(path: &str, scale: usize, palette: [[u8; 3]; 2]) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("Error creating {}, {}", path, e))?;
+        let mut recorder = Self { writer: BufWriter::new(file), path: path.to_string(), scale, previous: None, pending: None, frames: 0 };
+
+        let (width, height) = ((WIDTH * scale) as u16, (HEIGHT * scale) as u16);
+        let mut header = b"GIF89a".to_vec();
+        header.extend_from_slice(&width.to_le_bytes());
+        header.extend_from_slice(&height.to_le_bytes());
+        // Global color table of two entries, then background color and aspect ratio
+        header.extend_from_slice(&[0x80, 0, 0]);
+        header.extend_from_slice(&palette[0]);
+        header.extend_from_slice(&palette[1]);
+        // Loop forever
+        header.extend_from_slice(&[0x21, 0xFF, 0x0B]);
+        header.extend_from_slice(b"NETSCAPE2.0");
+        header.extend_from_slice(&[0x03, 0x01, 0x00, 0x00, 0x00]);
+        recorder.write(&header)?;
+        Ok(recorder)
+    }
+
+    // Called once per 60 Hz frame with the display contents
+    pub fn add_frame(&mut self, display: &[u8; WIDTH * HEIGHT]) -> Result<(), String> {
+        let frame = self.frames;
+        self.frames += 1;
+        if self.pending.as_ref().is_some_and(|(pixels, _)| pixels[..] == display[..]) {
+            return Ok(());
+        }
+        if let Some((pixels, start)) = self.pending.take() {
+            self.write_frame(&pixels, start, frame)?;
+        }
+        self.pending = Some((display.to_vec(), frame));
+        Ok(())
+    }
+
+    // Write the last frame and the trailer. Returns the number of frames captured.
+    pub fn finish(mut self) -> Result<u64, String> {
+        if let Some((pixels, start)) = self.pending.take() {
+            self.write_frame(&pixels, start, self.frames.max(start + 1))?;
+        }
+        self.write(&[0x3B])?;
+        self.writer.flush().map_err(|e| format!("Error writing {}, {}", self.path, e))?;
+        Ok(self.frames)
+    }
+
+    // Frame shown from 60 Hz frame `start` until `end`
+    fn write_frame(&mut self, pixels: &[u8], start: u64, end: u64) -> Result<(), String> {
+        // GIF delays are in hundredths of a second, round the frame boundaries instead of each delay
+        let centiseconds = |frame: u64| (frame * 100 + 30) / 60;
+        let delay = (centiseconds(end) - centiseconds(start)).clamp(1, 0xFFFF) as u16;
+
+        let (left, top, right, bottom) = match &self.previous {
+            Some(previous) => changed_area(previous, pixels).unwrap_or((0, 0, 1, 1)),
+            None => (0, 0, WIDTH, HEIGHT),
+        };
+        let scale = self.scale;
+        let (width, height) = ((right - left) * scale, (bottom - top) * scale);
+
+        let mut block = vec![0x21, 0xF9, 0x04, 0x04];
+        block.extend_from_slice(&delay.to_le_bytes());
+        block.extend_from_slice(&[0x00, 0x00, 0x2C]);
+        for value in [left * scale, top * scale, width, height] {
+            block.extend_from_slice(&(value as u16).to_le_bytes());
+        }
+        block.push(0x00);
+
+        let mut indices = Vec::with_capacity(width * height);
+        for y in top * scale..bottom * scale {
+            for x in left * scale..right * scale {
+                indices.push(pixels[(y / scale) * WIDTH + x / scale].min(1));
+            }
+        }
+        block.push(MIN_CODE_SIZE as u8);
+        for chunk in lzw_encode(&indices).chunks(255) {
+            block.push(chunk.len() as u8);
+            block.extend_from_slice(chunk);
+        }
+        block.push(0x00);
+
+        self.write(&block)?;
+        self.previous = Some(pixels.to_vec());
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.writer.write_all(bytes).map_err(|e| format!("Error writing {}, {}", self.path, e))
+    }
+}
+
+// Bounding box (left, top, right, bottom) of the pixels that differ
+fn changed_area(before: &[u8], after: &[u8]) -> Option<(usize, usize, usize, usize)> {
+    let mut area: Option<(usize, usize, usize, usize)> = None;
+    for (index, _) in before.iter().zip(after.iter()).enumerate().filter(|(_, (a, b))| a != b) {
+        let (x, y) = (index % WIDTH, index / WIDTH);
+        area = Some(match area {
+            Some((left, top, right, bottom)) => (left.min(x), top.min(y), right.max(x + 1), bottom.max(y + 1)),
+            None => (x, y, x + 1, y + 1),
+        });
+    }
+    area
+}
+
+// Two colors still need the minimum code size GIF allows
+const MIN_CODE_SIZE: u32 = 2;
+
+fn lzw_encode(indices: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << MIN_CODE_SIZE;
+    let end = clear + 1;
+    let mut output = BitWriter::default();
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut code_size = MIN_CODE_SIZE + 1;
+    let mut next_code = end + 1;
+
+    output.write(clear, code_size);
+    let Some((&first, rest)) = indices.split_first() else {
+        output.write(end, code_size);
+        return output.finish();
+    };
+    let mut prefix = first as u16;
+    for &index in rest {
+        if let Some(&code) = table.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+        output.write(prefix, code_size);
+        if next_code < 4096 {
+            if next_code >= 1 << code_size && code_size < 12 {
+                code_size += 1;
+            }
+            table.insert((prefix, index), next_code);
+            next_code += 1;
+        } else {
+            output.write(clear, code_size);
+            table.clear();
+            code_size = MIN_CODE_SIZE + 1;
+            next_code = end + 1;
+        }
+        prefix = index as u16;
+    }
+    output.write(prefix, code_size);
+    if next_code >= 1 << code_size && code_size < 12 {
+        code_size += 1;
+    }
+    output.write(end, code_size);
+    output.finish()
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u32) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
@@ -0,0 +1,190 @@
+// Headless runner, what chip8-rust is when built without the sdl (default) and winit features:
+//   cargo build --no-default-features
+// for machines without the SDL2 libraries, CI and scripts. It runs the rom for a number of 60 Hz
+// frames as fast as it can, then prints or writes the display and the machine state:
+//   chip8-rust [options] <rom>
+//   --frames <n>              60 Hz frames to run (default 60)
+//   --ipf <n>                 instructions executed per frame (default 8)
+//   --seed <n>                seed the random number generator, for repeatable runs
+//   --key-wait <policy>       which held key FX0A reports, see keywait.rs
+//   --input-script <file>     drive the keypad from a file or - for stdin, see inputscript.rs
+//   --load-state <file.json>  start from a state exported with the console's "export" command
+//   --screen <file.png>       write the display as a PNG instead of printing it as text
+//   --scale <n>               size of a display pixel in the PNG (default 1)
+//   --state <file.json>       write the machine state as JSON when done, - prints it
+//   --stream <[host]:port>    show the display in browsers and take their keys, see stream.rs
+//   --api <[host]:port>       control the emulator over HTTP or WebSocket, see api.rs
+//   --metrics <[host]:port>   serve Prometheus metrics, with the metrics feature, see metrics.rs
+// With --stream, --api or --metrics the rom runs in real time, and until quit unless --frames is
+// given.
+// The check and stats commands work as in the full build.
+
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::api::ApiServer;
+use crate::chip8::VM;
+use crate::inputscript::InputScript;
+use crate::keywait::{parse_key_wait, KeyWaitPolicy};
+#[cfg(feature = "metrics")]
+use crate::metrics::MetricsServer;
+use crate::stream::StreamServer;
+
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+struct Options {
+    rom: String,
+    frames: Option<u64>,
+    instructions_per_frame: u32,
+    seed: Option<u64>,
+    key_wait: KeyWaitPolicy,
+    input_script: Option<String>,
+    load_state: Option<String>,
+    screen: Option<String>,
+    scale: u32,
+    state: Option<String>,
+    stream: Option<String>,
+    api: Option<String>,
+    metrics: Option<String>,
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or(format!("{} needs a number", option))?;
+    value.parse().map_err(|_| format!("invalid number {} for {}", value, option))
+}
+
+fn parse_options(args: Vec<String>) -> Result<Options, String> {
+    let mut options = Options {
+        rom: String::new(),
+        frames: None,
+        instructions_per_frame: 8,
+        seed: None,
+        key_wait: KeyWaitPolicy::Lowest,
+        input_script: None,
+        load_state: None,
+        screen: None,
+        scale: 1,
+        state: None,
+        stream: None,
+        api: None,
+        metrics: None,
+    };
+    let mut rom = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => options.frames = Some(parse_number(&arg, args.next())?),
+            "--ipf" => options.instructions_per_frame = parse_number(&arg, args.next())?,
+            "--seed" => options.seed = Some(parse_number(&arg, args.next())?),
+            "--key-wait" => options.key_wait = parse_key_wait(&args.next().ok_or("--key-wait needs a policy")?)?,
+            "--input-script" => options.input_script = Some(args.next().ok_or("--input-script needs a file name or -")?),
+            "--load-state" => options.load_state = Some(args.next().ok_or("--load-state needs a file name")?),
+            "--screen" => options.screen = Some(args.next().ok_or("--screen needs a file name")?),
+            "--scale" => options.scale = parse_number(&arg, args.next())?,
+            "--state" => options.state = Some(args.next().ok_or("--state needs a file name or -")?),
+            "--stream" => options.stream = Some(args.next().ok_or("--stream needs an address")?),
+            "--api" => options.api = Some(args.next().ok_or("--api needs an address")?),
+            "--metrics" => options.metrics = Some(args.next().ok_or("--metrics needs an address")?),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {} in the headless build", arg)),
+            _ => rom = Some(arg),
+        }
+    }
+    if options.instructions_per_frame == 0 || options.scale == 0 {
+        return Err("--ipf and --scale must be at least 1".to_string());
+    }
+    options.rom = rom.ok_or("usage: chip8-rust [options] <rom>")?;
+    Ok(options)
+}
+
+pub fn run(args: Vec<String>) -> Result<(), String> {
+    let options = parse_options(args)?;
+    let mut vm = VM::new();
+    vm.key_wait_policy = options.key_wait;
+    vm.init_font_set();
+    vm.load_rom(&options.rom)?;
+    if let Some(seed) = options.seed {
+        vm.reseed(seed, 0);
+    }
+    if let Some(path) = &options.load_state {
+        vm.import_json(path)?;
+    }
+    let mut stream = match &options.stream {
+        Some(address) => Some(StreamServer::listen(address)?),
+        None => None,
+    };
+
+    let mut api = match &options.api {
+        Some(address) => Some(ApiServer::listen(address)?),
+        None => None,
+    };
+
+    #[cfg(feature = "metrics")]
+    let mut metrics = match &options.metrics {
+        Some(address) => Some(MetricsServer::listen(address)?),
+        None => None,
+    };
+    #[cfg(not(feature = "metrics"))]
+    if options.metrics.is_some() {
+        return Err("this build has no metrics support, rebuild with --features metrics".to_string());
+    }
+
+    let real_time = stream.is_some() || api.is_some() || options.metrics.is_some();
+    let mut input_script = match &options.input_script {
+        Some(path) => Some(InputScript::open(path, real_time)?),
+        None => None,
+    };
+    let frames = options.frames.unwrap_or(if real_time { u64::MAX } else { 60 });
+    let mut next_frame = Instant::now();
+    for _ in 0..frames {
+        vm.next_macro_frame();
+        let keypad = &mut vm.keypad;
+        if input_script.as_mut().is_some_and(|script| script.next_frame(|key, pressed| keypad[key] = pressed)) {
+            break;
+        }
+        if let Some(server) = stream.as_mut() {
+            server.poll(|key, pressed| keypad[key] = pressed);
+        }
+        if let Some(server) = api.as_mut() {
+            server.poll(&mut vm);
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(server) = metrics.as_mut() {
+            server.poll(&vm);
+        }
+        if real_time {
+            thread::sleep(next_frame.saturating_duration_since(Instant::now()));
+            next_frame = (next_frame + FRAME).max(Instant::now());
+        }
+        // Paused through the API, the clients keep being served
+        if vm.paused && api.is_some() {
+            continue;
+        }
+        vm.step_frame(options.instructions_per_frame)?;
+        // Nothing is drawn, each frame's display is what a window would have shown
+        vm.frames_rendered += 1;
+        if let Some(server) = stream.as_mut() {
+            server.send(&vm);
+        }
+        if vm.paused && api.is_some() {
+            // Halted, a client can still reset or load another rom
+            continue;
+        }
+        if let Some(message) = vm.pause_message.take() {
+            // Halted, there is nothing left to run
+            eprintln!("Stopped at frame {}: {}", vm.frame, message);
+            break;
+        }
+    }
+
+    match &options.screen {
+        Some(path) => fs::write(path, vm.display_png(options.scale)).map_err(|e| format!("Error writing {}, {}", path, e))?,
+        None => print!("{}", vm.display_ascii()),
+    }
+    match options.state.as_deref() {
+        Some("-") => println!("{}", vm.snapshot().to_json()),
+        Some(path) => vm.export_json(path)?,
+        None => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,41 @@
+// Execution heatmap overlay, toggled with F4. Each cell is one memory address, 64 per row,
+// colored from blue (rarely executed) to red (hottest address). Unexecuted addresses stay dark.
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+use crate::chip8::VM;
+use crate::text::draw_text;
+
+const COLUMNS: usize = 64;
+
+impl VM<'_> {
+    pub fn draw_heatmap(&mut self) -> Result<(), String> {
+        let Some(coverage) = &self.coverage else { return Ok(()) };
+        let (width, height) = self.canvas.output_size()?;
+        let cell = (height / (4096 / COLUMNS) as u32).max(1);
+        let left = width as i32 - (cell * COLUMNS as u32) as i32;
+
+        let hottest = coverage.executions.iter().copied().max().unwrap_or(0).max(1);
+        let scale = (hottest as f64).ln().max(1.0);
+        let mut cells = Vec::with_capacity(4096);
+        for (address, count) in coverage.executions.iter().enumerate() {
+            let color = if *count == 0 {
+                Color::RGB(20, 20, 20)
+            } else {
+                // Logarithmic so that hot loops don't wash out everything else
+                let heat = ((*count as f64).ln() / scale).clamp(0.0, 1.0);
+                Color::RGB((heat * 255.0) as u8, 40, ((1.0 - heat) * 255.0) as u8)
+            };
+            let x = left + ((address % COLUMNS) as u32 * cell) as i32;
+            let y = ((address / COLUMNS) as u32 * cell) as i32;
+            cells.push((color, Rect::new(x, y, cell, cell)));
+        }
+
+        for (color, rect) in cells {
+            self.canvas.set_draw_color(color);
+            self.canvas.fill_rect(rect)?;
+        }
+        draw_text(&mut self.canvas, left + 2, 2, 1, &format!("MAX {}", hottest), Color::RGB(255, 255, 255))
+    }
+}
@@ -0,0 +1,233 @@
+// Emulator hotkeys, rebindable in the [hotkeys] section of the input profile (see input.rs) with
+// one "name = chord" line each. A chord is an SDL key name with any of Ctrl+, Shift+ and Alt+ in
+// front, several chords are separated by commas and "none" unbinds the hotkey:
+//   [hotkeys]
+//   pause = Pause, Ctrl+Space
+//   screenshot = Ctrl+S
+//   mute = none
+// A chord with Shift wins over the same chord without it, which otherwise works with Shift held
+// too. Chords without Ctrl or Alt on a key the keypad also uses go to the keypad; a profile that
+// binds one that way explicitly is rejected. Ctrl+0 to Ctrl+9, which pick a save state slot, and
+// the keys of the console, memory viewer, magnifier and slot picker while open aren't hotkeys.
+
+use sdl2::keyboard::{Keycode, Mod};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Hotkey {
+    Console,
+    Remap,
+    Hud,
+    MemoryView,
+    Coverage,
+    Heatmap,
+    Magnifier,
+    Debugger,
+    FrameAdvance,
+    SaveState,
+    LoadState,
+    BreakOnDraw,
+    KeypadOverlay,
+    SlotPicker,
+    Fullscreen,
+    Screenshot,
+    Gif,
+    Reset,
+    Rotate,
+    Palette,
+    Invert,
+    Pause,
+    FastForward,
+    SlowMotion,
+    RecordMacro,
+    PlayMacro,
+    Mute,
+    VolumeUp,
+    VolumeDown,
+}
+
+const NAMES: [(Hotkey, &str); 29] = [
+    (Hotkey::Console, "console"),
+    (Hotkey::Remap, "remap"),
+    (Hotkey::Hud, "hud"),
+    (Hotkey::MemoryView, "memory"),
+    (Hotkey::Coverage, "coverage"),
+    (Hotkey::Heatmap, "heatmap"),
+    (Hotkey::Magnifier, "magnifier"),
+    (Hotkey::Debugger, "debugger"),
+    (Hotkey::FrameAdvance, "frame-advance"),
+    (Hotkey::SaveState, "save-state"),
+    (Hotkey::LoadState, "load-state"),
+    (Hotkey::BreakOnDraw, "break-on-draw"),
+    (Hotkey::KeypadOverlay, "keypad"),
+    (Hotkey::SlotPicker, "slots"),
+    (Hotkey::Fullscreen, "fullscreen"),
+    (Hotkey::Screenshot, "screenshot"),
+    (Hotkey::Gif, "gif"),
+    (Hotkey::Reset, "reset"),
+    (Hotkey::Rotate, "rotate"),
+    (Hotkey::Palette, "palette"),
+    (Hotkey::Invert, "invert"),
+    (Hotkey::Pause, "pause"),
+    (Hotkey::FastForward, "fast-forward"),
+    (Hotkey::SlowMotion, "slow-motion"),
+    (Hotkey::RecordMacro, "record-macro"),
+    (Hotkey::PlayMacro, "play-macro"),
+    (Hotkey::Mute, "mute"),
+    (Hotkey::VolumeUp, "volume-up"),
+    (Hotkey::VolumeDown, "volume-down"),
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Chord {
+    pub keycode: Keycode,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl Chord {
+    pub const fn key(keycode: Keycode) -> Self {
+        Self { keycode, ctrl: false, shift: false, alt: false }
+    }
+
+    const fn ctrl(keycode: Keycode) -> Self {
+        Self { keycode, ctrl: true, shift: false, alt: false }
+    }
+
+    const fn shift(keycode: Keycode) -> Self {
+        Self { keycode, ctrl: false, shift: true, alt: false }
+    }
+
+    const fn ctrl_shift(keycode: Keycode) -> Self {
+        Self { keycode, ctrl: true, shift: true, alt: false }
+    }
+
+    // Whether the keypad gets the key first, see the top of this file
+    pub fn is_plain(&self) -> bool {
+        !self.ctrl && !self.alt
+    }
+}
+
+const DEFAULTS: [(Chord, Hotkey); 33] = [
+    (Chord::key(Keycode::Backquote), Hotkey::Console),
+    (Chord::ctrl(Keycode::K), Hotkey::Remap),
+    (Chord::key(Keycode::F1), Hotkey::Hud),
+    (Chord::key(Keycode::F2), Hotkey::MemoryView),
+    (Chord::key(Keycode::F3), Hotkey::Coverage),
+    (Chord::key(Keycode::F4), Hotkey::Heatmap),
+    (Chord::ctrl(Keycode::Z), Hotkey::Magnifier),
+    (Chord::key(Keycode::F5), Hotkey::Debugger),
+    (Chord::key(Keycode::F6), Hotkey::FrameAdvance),
+    (Chord::key(Keycode::F7), Hotkey::SaveState),
+    (Chord::shift(Keycode::F7), Hotkey::LoadState),
+    (Chord::key(Keycode::F8), Hotkey::BreakOnDraw),
+    (Chord::key(Keycode::F9), Hotkey::KeypadOverlay),
+    (Chord::key(Keycode::F10), Hotkey::SlotPicker),
+    (Chord::key(Keycode::F11), Hotkey::Fullscreen),
+    (Chord::key(Keycode::F12), Hotkey::Screenshot),
+    (Chord::shift(Keycode::F12), Hotkey::Gif),
+    (Chord::ctrl_shift(Keycode::R), Hotkey::Reset),
+    (Chord::ctrl(Keycode::R), Hotkey::Rotate),
+    (Chord::ctrl(Keycode::P), Hotkey::Palette),
+    (Chord::ctrl(Keycode::I), Hotkey::Invert),
+    (Chord::key(Keycode::P), Hotkey::Pause),
+    (Chord::key(Keycode::Space), Hotkey::Pause),
+    (Chord::key(Keycode::Tab), Hotkey::FastForward),
+    (Chord::shift(Keycode::Tab), Hotkey::SlowMotion),
+    (Chord::ctrl_shift(Keycode::M), Hotkey::RecordMacro),
+    (Chord::ctrl(Keycode::M), Hotkey::PlayMacro),
+    (Chord::key(Keycode::M), Hotkey::Mute),
+    (Chord::key(Keycode::Equals), Hotkey::VolumeUp),
+    (Chord::key(Keycode::Plus), Hotkey::VolumeUp),
+    (Chord::key(Keycode::KpPlus), Hotkey::VolumeUp),
+    (Chord::key(Keycode::Minus), Hotkey::VolumeDown),
+    (Chord::key(Keycode::KpMinus), Hotkey::VolumeDown),
+];
+
+pub fn parse_hotkey(text: &str) -> Result<Hotkey, String> {
+    match NAMES.iter().find(|(_, name)| *name == text) {
+        Some((hotkey, _)) => Ok(*hotkey),
+        None => Err(format!("unknown hotkey {}", text)),
+    }
+}
+
+pub fn hotkey_name(hotkey: Hotkey) -> &'static str {
+    NAMES.iter().find(|(named, _)| *named == hotkey).map_or("", |(_, name)| name)
+}
+
+// "Ctrl+Shift+R", modifiers in any order and case, then the SDL key name
+pub fn parse_chord(text: &str) -> Result<Chord, String> {
+    let mut chord = Chord::key(Keycode::A);
+    let mut rest = text.trim();
+    loop {
+        let lower = rest.to_lowercase();
+        let flag = if lower.starts_with("ctrl+") {
+            &mut chord.ctrl
+        } else if lower.starts_with("shift+") {
+            &mut chord.shift
+        } else if lower.starts_with("alt+") {
+            &mut chord.alt
+        } else {
+            break;
+        };
+        *flag = true;
+        rest = &rest[rest.find('+').unwrap() + 1..];
+    }
+    chord.keycode = Keycode::from_name(rest).ok_or(format!("unknown key {}", rest))?;
+    Ok(chord)
+}
+
+pub fn describe_chord(chord: &Chord) -> String {
+    let mut text = String::new();
+    for (held, name) in [(chord.ctrl, "Ctrl+"), (chord.shift, "Shift+"), (chord.alt, "Alt+")] {
+        if held {
+            text.push_str(name);
+        }
+    }
+    text + &chord.keycode.name()
+}
+
+pub struct Hotkeys {
+    pub bindings: Vec<(Chord, Hotkey)>,
+}
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        Self { bindings: DEFAULTS.to_vec() }
+    }
+}
+
+impl Hotkeys {
+    // Replace the chords of a hotkey, an empty list unbinds it
+    pub fn set(&mut self, hotkey: Hotkey, chords: &[Chord]) {
+        self.bindings.retain(|(_, bound)| *bound != hotkey);
+        self.bindings.extend(chords.iter().map(|chord| (*chord, hotkey)));
+    }
+
+    pub fn chords(&self, hotkey: Hotkey) -> impl Iterator<Item = &Chord> {
+        self.bindings.iter().filter(move |(_, bound)| *bound == hotkey).map(|(chord, _)| chord)
+    }
+
+    // Hotkeys whose chords aren't the default ones, the ones a profile has to save
+    pub fn changed(&self) -> Vec<Hotkey> {
+        let defaults = Self::default();
+        NAMES
+            .iter()
+            .map(|(hotkey, _)| *hotkey)
+            .filter(|hotkey| !self.chords(*hotkey).eq(defaults.chords(*hotkey)))
+            .collect()
+    }
+
+    // The chord a key press matches, exactly or else with Shift ignored
+    pub fn find(&self, keycode: Keycode, keymod: Mod) -> Option<(Chord, Hotkey)> {
+        let pressed = Chord {
+            keycode,
+            ctrl: keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD),
+            shift: keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD),
+            alt: keymod.intersects(Mod::LALTMOD | Mod::RALTMOD),
+        };
+        let exact = self.bindings.iter().find(|(chord, _)| *chord == pressed);
+        let unshifted = || self.bindings.iter().find(|(chord, _)| *chord == Chord { shift: false, ..pressed });
+        exact.or_else(unshifted).copied()
+    }
+}
@@ -0,0 +1,176 @@
+// On-screen debug overlay showing the machine state, toggled with F1
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::BlendMode;
+
+use crate::chip8::VM;
+use crate::debugger::KEYPAD_LAYOUT;
+use crate::disasm::disassemble_with;
+use crate::text::{draw_lines, draw_text, text_height, text_width, GLYPH_HEIGHT};
+
+const MAX_STACK_LINES: usize = 10;
+const KEYPAD_CELL: u32 = 14;
+
+// Width and height of the keypad drawn with `cell` pixels per key
+pub fn keypad_size(cell: u32) -> u32 {
+    4 * cell + 6
+}
+
+impl VM<'_> {
+    pub fn draw_hud(&mut self) -> Result<(), String> {
+        let scale = 2;
+        let op = (self.memory[self.pc as usize] as u16) << 8 | self.memory[(self.pc as usize + 1) % 4096] as u16;
+
+        let mut lines = vec![
+            format!("PC {:03X}  I {:03X}  SP {:X}  DT {:02X}  ST {:02X}", self.pc, self.i, self.sp, self.delay, self.sound),
+            format!("{:04X}  {}", op, disassemble_with(op, &self.symbols)),
+        ];
+        for (offset, registers) in self.v.chunks(4).enumerate() {
+            let mut line = String::new();
+            for (index, value) in registers.iter().enumerate() {
+                line.push_str(&format!("V{:X} {:02X} ", offset * 4 + index, value));
+            }
+            lines.push(line);
+        }
+
+        let call_stack = self.call_stack();
+        lines.push(format!("STACK DEPTH {}", call_stack.len()));
+        for call_site in call_stack.iter().take(MAX_STACK_LINES) {
+            let call = (self.memory[*call_site as usize % 4096] as u16) << 8 | self.memory[(*call_site as usize + 1) % 4096] as u16;
+            lines.push(format!(" RET {:03X}  {:03X} {}", call_site + 2, call_site, disassemble_with(call, &self.symbols)));
+        }
+        if call_stack.len() > MAX_STACK_LINES {
+            lines.push(format!(" ... {} MORE", call_stack.len() - MAX_STACK_LINES));
+        }
+        for watch in &self.watches {
+            lines.push(watch.describe(self));
+        }
+        if let Some(spec) = self.audio_spec {
+            lines.push(match spec.latency() {
+                Some(latency) => format!("AUDIO {} HZ  {} SAMPLES  {:.1} MS", spec.sample_rate, spec.buffer.unwrap_or(0), latency.as_secs_f64() * 1000.0),
+                None => format!("AUDIO {} HZ  DEFAULT BUFFER", spec.sample_rate),
+            });
+        }
+
+        let width = lines.iter().map(|l| text_width(l, scale)).max().unwrap_or(0) + 8;
+        let height = text_height(lines.len(), scale) + 8;
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 180));
+        self.canvas.fill_rect(Rect::new(0, 0, width, height))?;
+        self.canvas.set_blend_mode(BlendMode::None);
+
+        draw_lines(&mut self.canvas, 4, 4, scale, &lines, Color::RGB(0, 255, 0))?;
+
+        // Preview as many rows as the pending DXYN will draw, or the largest possible sprite otherwise
+        let rows = if op & 0xF000 == 0xD000 && op & 0x000F != 0 { (op & 0x000F) as usize } else { 15 };
+        self.draw_sprite_preview(rows)
+    }
+
+    // Notification line centred near the top of the display
+    pub fn draw_osd(&mut self, window_scale: u32) -> Result<(), String> {
+        let Some((message, _)) = &self.osd else { return Ok(()) };
+        let scale = 2;
+        let width = text_width(message, scale) + 8;
+        let height = GLYPH_HEIGHT * scale + 8;
+        let left = (64 * window_scale).saturating_sub(width) as i32 / 2;
+        let message = message.clone();
+
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 200));
+        self.canvas.fill_rect(Rect::new(left, 8, width, height))?;
+        self.canvas.set_blend_mode(BlendMode::None);
+        draw_text(&mut self.canvas, left + 4, 12, scale, &message, Color::RGB(255, 255, 255))
+    }
+
+    // Frame around the display while the sound timer runs, for --visual-buzzer
+    pub fn draw_buzzer_border(&mut self, window_scale: u32) -> Result<(), String> {
+        let (width, height) = (64 * window_scale, 32 * window_scale);
+        let thickness = window_scale.max(4) / 2;
+        self.canvas.set_draw_color(Color::RGB(255, 160, 0));
+        self.canvas.fill_rects(&[
+            Rect::new(0, 0, width, thickness),
+            Rect::new(0, (height - thickness) as i32, width, thickness),
+            Rect::new(0, 0, thickness, height),
+            Rect::new((width - thickness) as i32, 0, thickness, height),
+        ])
+    }
+
+    // Small tag in the top right corner while audio is muted
+    pub fn draw_mute_indicator(&mut self, window_scale: u32) -> Result<(), String> {
+        let scale = 2;
+        let width = text_width("MUTE", scale) + 6;
+        let left = (64 * window_scale - width - 4) as i32;
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 180));
+        self.canvas.fill_rect(Rect::new(left, 4, width, GLYPH_HEIGHT * scale + 6))?;
+        self.canvas.set_blend_mode(BlendMode::None);
+        draw_text(&mut self.canvas, left + 3, 7, scale, "MUTE", Color::RGB(255, 80, 80))
+    }
+
+    // 4x4 keypad in the bottom right corner, lit keys are the ones the VM sees as pressed
+    pub fn draw_keypad_overlay(&mut self, window_scale: u32) -> Result<(), String> {
+        let size = keypad_size(KEYPAD_CELL);
+        let left = (64 * window_scale - size) as i32;
+        let top = (32 * window_scale).saturating_sub(size + 22) as i32;
+
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 180));
+        self.canvas.fill_rect(Rect::new(left, top, size, size))?;
+        self.canvas.set_blend_mode(BlendMode::None);
+        self.draw_keypad(left, top, KEYPAD_CELL)
+    }
+
+    // The 4x4 keypad with pressed keys lit, `cell` pixels per key
+    pub fn draw_keypad(&mut self, left: i32, top: i32, cell: u32) -> Result<(), String> {
+        let scale = (cell / KEYPAD_CELL).max(1);
+        for (row, keys) in KEYPAD_LAYOUT.iter().enumerate() {
+            for (column, key) in keys.iter().enumerate() {
+                let x = left + 3 + (column as u32 * cell) as i32;
+                let y = top + 3 + (row as u32 * cell) as i32;
+                let pressed = self.keypad[*key];
+                self.canvas.set_draw_color(if pressed { Color::RGB(0, 200, 0) } else { Color::RGB(60, 60, 60) });
+                self.canvas.fill_rect(Rect::new(x, y, cell - 2, cell - 2))?;
+                let color = if pressed { Color::RGB(0, 0, 0) } else { Color::RGB(200, 200, 200) };
+                draw_text(&mut self.canvas, x + 4 * scale as i32, y + 2 * scale as i32, scale, &format!("{:X}", key), color)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Render the `rows` bytes at I as a sprite in the top right corner
+    fn draw_sprite_preview(&mut self, rows: usize) -> Result<(), String> {
+        let scale = 2;
+        let pixel = 6;
+        let line_height = ((GLYPH_HEIGHT + 1) * scale) as i32;
+        let label = format!("I {:03X} N {}", self.i, rows);
+        let label_width = text_width(&label, scale);
+        let (window_width, _) = self.canvas.output_size()?;
+
+        let width = label_width.max(8 * pixel) + 8;
+        let height = (line_height as u32) + rows as u32 * pixel + 12;
+        let left = window_width as i32 - width as i32;
+
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 180));
+        self.canvas.fill_rect(Rect::new(left, 0, width, height))?;
+        self.canvas.set_blend_mode(BlendMode::None);
+        draw_text(&mut self.canvas, left + 4, 4, scale, &label, Color::RGB(0, 255, 0))?;
+
+        let top = 8 + line_height;
+        self.canvas.set_draw_color(Color::RGB(40, 40, 40));
+        self.canvas.fill_rect(Rect::new(left + 4, top, 8 * pixel, rows as u32 * pixel))?;
+
+        let mut rects = Vec::new();
+        for row in 0..rows {
+            let byte = self.memory[(self.i as usize + row) % 4096];
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) != 0 {
+                    rects.push(Rect::new(left + 4 + bit * pixel as i32, top + (row as u32 * pixel) as i32, pixel, pixel));
+                }
+            }
+        }
+        self.canvas.set_draw_color(Color::RGB(255, 255, 255));
+        self.canvas.fill_rects(&rects)
+    }
+}
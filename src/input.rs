@@ -0,0 +1,471 @@
+// Input profiles: which host inputs press which CHIP-8 keys. The profile is read from --input, or
+// from input.txt in the data directory, which Ctrl+K remapping saves to. Without either the keys
+// in the position of 1234/QWER/ASDF/ZXCV on a US keyboard are the keypad, by scancode, so the
+// block stays in place whatever the keyboard layout. A profile has one binding per line, and if
+// it binds any keyboard keys they replace the default layout:
+//   key Q = 4          keyboard key by SDL name, wherever the layout puts it
+//   scancode Q = 4     keyboard key by SDL scancode name, the physical position on a US keyboard
+//   layout = azerty    keypad block by keycode for a layout, see LAYOUTS, same as --keys
+//   axis 0- = 4        joystick axis 0 pushed towards negative values, usually left or up
+//   axis 1+ = 6
+//   hat 0 up = 2       hat direction: up, down, left or right; diagonals press both
+//   button 3 = 5       joystick button
+//   deadzone = 8000    axis travel around the centre that presses nothing, 0-32767 (default 8000)
+//   turbo = 5          autofire: holding anything bound to key 5 presses and releases it repeatedly
+//   turbo rate = 10    autofire presses per second, 1-30 (default 10)
+// Axes and hats work on any joystick, including ones SDL doesn't know as game controllers.
+//
+// For two players, joystick bindings after a [player 1] or [player 2] line only answer that
+// player's joystick, the first and second plugged in, while the ones before work on any. Keyboard
+// bindings are shared, so the players split the keyboard by which keys they bind:
+//   [player 1]
+//   key 1 = 1          Pong's left paddle
+//   key Q = 4
+//   axis 1- = 1
+//   axis 1+ = 4
+//   [player 2]
+//   axis 1- = C        right paddle on the second pad
+//   axis 1+ = D
+//
+// A [hotkeys] section rebinds the emulator's own hotkeys, see hotkeys.rs.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use sdl2::joystick::{HatState, Joystick};
+use sdl2::keyboard::{Keycode, Mod, Scancode};
+use sdl2::JoystickSubsystem;
+
+use crate::chip8::{parse_key, VM};
+use crate::debugger::KEYPAD_LAYOUT;
+use crate::hotkeys::{describe_chord, hotkey_name, parse_chord, parse_hotkey, Chord, Hotkey, Hotkeys};
+use crate::savestate::data_directory;
+
+const DEFAULT_DEADZONE: i16 = 8000;
+const DEFAULT_TURBO_RATE: u32 = 10;
+pub const PLAYERS: usize = 2;
+
+// Keypad positions in reading order, the same order as the layouts below
+const DEFAULT_SCANCODES: [Scancode; 16] = [
+    Scancode::Num1, Scancode::Num2, Scancode::Num3, Scancode::Num4,
+    Scancode::Q, Scancode::W, Scancode::E, Scancode::R,
+    Scancode::A, Scancode::S, Scancode::D, Scancode::F,
+    Scancode::Z, Scancode::X, Scancode::C, Scancode::V,
+];
+
+// The characters in the keypad's positions on each layout. SDL's keycode for a key that types a
+// character is the character, so these are keycodes. For when scancodes don't match the
+// keyboard, as happens over some remote desktops and with remapping tools.
+pub const LAYOUTS: [(&str, &str); 5] = [
+    ("qwerty", "1234qwerasdfzxcv"),
+    ("azerty", "&\u{e9}\"'azerqsdfwxcv"),
+    ("qwertz", "1234qwerasdfyxcv"),
+    ("dvorak", "1234',.paoeu;qjk"),
+    ("colemak", "1234qwfparstzxcd"),
+];
+
+pub fn parse_layout(text: &str) -> Result<&'static str, String> {
+    match LAYOUTS.iter().find(|(name, _)| *name == text) {
+        Some((_, keys)) => Ok(keys),
+        None => {
+            let names: Vec<&str> = LAYOUTS.iter().map(|(name, _)| *name).collect();
+            Err(format!("unknown keyboard layout {}, expected {}", text, names.join(", ")))
+        }
+    }
+}
+
+// CHIP-8 key at a keypad position in reading order
+pub fn key_at(position: usize) -> usize {
+    KEYPAD_LAYOUT[position / 4][position % 4]
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Input {
+    Key(Keycode),
+    Scancode(Scancode),
+    // Axis number and whether it's the positive direction
+    Axis(u8, bool),
+    // Hat number and direction as an SDL hat bit
+    Hat(u8, u8),
+    Button(u8),
+}
+
+pub struct InputProfile {
+    pub bindings: Vec<(Input, usize)>,
+    // Joystick bindings for each player's joystick only
+    pub players: [Vec<(Input, usize)>; PLAYERS],
+    pub deadzone: i16,
+    // Where remapped keys are saved
+    pub path: PathBuf,
+    // Keys with autofire, and since when each is held
+    pub turbo: [bool; 16],
+    pub turbo_rate: u32,
+    turbo_held: [Option<Instant>; 16],
+    pub hotkeys: Hotkeys,
+}
+
+impl InputProfile {
+    pub fn new() -> Self {
+        Self {
+            bindings: DEFAULT_SCANCODES.iter().enumerate().map(|(position, scancode)| (Input::Scancode(*scancode), key_at(position))).collect(),
+            players: Default::default(),
+            deadzone: DEFAULT_DEADZONE,
+            path: data_directory().join("input.txt"),
+            turbo: [false; 16],
+            turbo_rate: DEFAULT_TURBO_RATE,
+            turbo_held: [None; 16],
+            hotkeys: Hotkeys::default(),
+        }
+    }
+
+    // The --input profile if given, otherwise the saved one or the defaults
+    pub fn open(path: Option<&str>) -> Result<Self, String> {
+        let saved = Self::new();
+        match path {
+            Some(path) => Self::load(path),
+            None if saved.path.exists() => Self::load(&saved.path.display().to_string()),
+            None => Ok(saved),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("Error loading input profile {}, {}", path, e))?;
+        let mut profile = Self::new();
+        profile.path = PathBuf::from(path);
+        let mut default_keys = true;
+        let mut player = None;
+        let mut in_hotkeys = false;
+        // Hotkeys the profile binds, checked against the keypad once it's all read
+        let mut bound_hotkeys = Vec::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(section) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                in_hotkeys = section.trim() == "hotkeys";
+                if in_hotkeys {
+                    continue;
+                }
+                player = match section.trim().strip_prefix("player").map(|number| number.trim().parse::<usize>()) {
+                    Some(Ok(number)) if (1..=PLAYERS).contains(&number) => Some(number - 1),
+                    _ => return Err(format!("{}:{}: unknown section [{}], expected [player 1], [player 2] or [hotkeys]", path, index + 1, section)),
+                };
+                continue;
+            }
+            if in_hotkeys {
+                // Split at the first = since a chord can end in the = key
+                let (name, chords) = line.split_once('=').ok_or_else(|| format!("{}:{}: expected hotkey = chord", path, index + 1))?;
+                let hotkey = parse_hotkey(name.trim()).map_err(|e| format!("{}:{}: {}", path, index + 1, e))?;
+                let chords = match chords.trim() {
+                    "none" => Vec::new(),
+                    chords => chords.split(',').map(parse_chord).collect::<Result<Vec<_>, _>>().map_err(|e| format!("{}:{}: {}", path, index + 1, e))?,
+                };
+                profile.hotkeys.set(hotkey, &chords);
+                bound_hotkeys.push(hotkey);
+                continue;
+            }
+            let (input, value) = line.rsplit_once('=').ok_or_else(|| format!("{}:{}: expected input = key", path, index + 1))?;
+            let (input, value) = (input.trim(), value.trim());
+            if input == "layout" {
+                let keys = parse_layout(value).map_err(|e| format!("{}:{}: {}", path, index + 1, e))?;
+                profile.set_layout(keys);
+                default_keys = false;
+                continue;
+            }
+            if input == "turbo" {
+                profile.turbo[parse_key(value).map_err(|e| format!("{}:{}: {}", path, index + 1, e))?] = true;
+                continue;
+            }
+            if input == "turbo rate" {
+                profile.turbo_rate = match value.parse() {
+                    Ok(rate) if (1..=30).contains(&rate) => rate,
+                    _ => return Err(format!("{}:{}: invalid turbo rate {}, expected 1-30", path, index + 1, value)),
+                };
+                continue;
+            }
+            if input == "deadzone" {
+                profile.deadzone = match value.parse() {
+                    Ok(deadzone) if deadzone >= 0 => deadzone,
+                    _ => return Err(format!("{}:{}: invalid deadzone {}, expected 0-32767", path, index + 1, value)),
+                };
+                continue;
+            }
+            let input = parse_input(input).map_err(|e| format!("{}:{}: {}", path, index + 1, e))?;
+            let key = parse_key(value).map_err(|e| format!("{}:{}: {}", path, index + 1, e))?;
+            if input.is_keyboard() && default_keys {
+                profile.bindings.retain(|(bound, _)| !bound.is_keyboard());
+                default_keys = false;
+            }
+            match player {
+                Some(player) if !input.is_keyboard() => profile.players[player].push((input, key)),
+                _ => profile.bind(input, key),
+            }
+        }
+        for hotkey in bound_hotkeys {
+            profile.check_hotkey(hotkey).map_err(|e| format!("{}: {}", path, e))?;
+        }
+        Ok(profile)
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory).map_err(|e| format!("Error creating {}, {}", directory.display(), e))?;
+        }
+        let mut text = format!("deadzone = {}\nturbo rate = {}\n", self.deadzone, self.turbo_rate);
+        for key in (0..16).filter(|key| self.turbo[*key]) {
+            text.push_str(&format!("turbo = {:X}\n", key));
+        }
+        for (input, key) in &self.bindings {
+            text.push_str(&format!("{} = {:X}\n", describe_input(input), key));
+        }
+        for (player, bindings) in self.players.iter().enumerate().filter(|(_, bindings)| !bindings.is_empty()) {
+            text.push_str(&format!("[player {}]\n", player + 1));
+            for (input, key) in bindings {
+                text.push_str(&format!("{} = {:X}\n", describe_input(input), key));
+            }
+        }
+        let changed = self.hotkeys.changed();
+        if !changed.is_empty() {
+            text.push_str("[hotkeys]\n");
+        }
+        for hotkey in changed {
+            let chords: Vec<String> = self.hotkeys.chords(hotkey).map(describe_chord).collect();
+            text.push_str(&format!("{} = {}\n", hotkey_name(hotkey), if chords.is_empty() { "none".to_string() } else { chords.join(", ") }));
+        }
+        fs::write(&self.path, text).map_err(|e| format!("Error writing {}, {}", self.path.display(), e))
+    }
+
+    // A hotkey's chords must not be on the keypad or shared with another hotkey
+    fn check_hotkey(&self, hotkey: Hotkey) -> Result<(), String> {
+        for chord in self.hotkeys.chords(hotkey) {
+            if let Some(key) = self.keypad_key(chord) {
+                return Err(format!("hotkey {} {} is keypad key {:X}", hotkey_name(hotkey), describe_chord(chord), key));
+            }
+            if let Some((_, other)) = self.hotkeys.bindings.iter().find(|(bound, other)| bound == chord && *other != hotkey) {
+                return Err(format!("hotkey {} {} is also {}", hotkey_name(hotkey), describe_chord(chord), hotkey_name(*other)));
+            }
+        }
+        Ok(())
+    }
+
+    // Keypad key a chord would press instead, if it has no Ctrl or Alt
+    fn keypad_key(&self, chord: &Chord) -> Option<usize> {
+        chord.is_plain().then(|| self.key(chord.keycode, Scancode::from_keycode(chord.keycode))).flatten()
+    }
+
+    // Hotkey for a key press, unless the keypad takes it
+    pub fn hotkey(&self, keycode: Keycode, scancode: Option<Scancode>, keymod: Mod) -> Option<Hotkey> {
+        let (chord, hotkey) = self.hotkeys.find(keycode, keymod)?;
+        if chord.is_plain() && self.key(keycode, scancode).is_some() {
+            return None;
+        }
+        Some(hotkey)
+    }
+
+    // The hotkey's keys, for telling when they are let go
+    pub fn is_hotkey_key(&self, hotkey: Hotkey, keycode: Keycode) -> bool {
+        self.hotkeys.chords(hotkey).any(|chord| chord.keycode == keycode)
+    }
+
+    // Replace the keyboard bindings with the keypad block of a layout from LAYOUTS
+    pub fn set_layout(&mut self, keys: &str) {
+        self.bindings.retain(|(bound, _)| !bound.is_keyboard());
+        for (position, c) in keys.chars().enumerate() {
+            if let Some(keycode) = Keycode::from_i32(c as i32) {
+                self.bindings.push((Input::Key(keycode), key_at(position)));
+            }
+        }
+    }
+
+    // Make `keycode` the only keyboard key for `key`
+    pub fn rebind_key(&mut self, keycode: Keycode, key: usize) {
+        self.bindings.retain(|(bound, bound_key)| !bound.is_keyboard() || *bound_key != key);
+        self.bind(Input::Key(keycode), key);
+    }
+
+    // Name of the keyboard key currently pressing `key`, for showing the mapping
+    pub fn key_name(&self, key: usize) -> Option<String> {
+        self.bindings.iter().find_map(|(input, bound)| match input {
+            Input::Key(keycode) if *bound == key => Some(keycode.name()),
+            Input::Scancode(scancode) if *bound == key => Some(scancode.name().to_string()),
+            _ => None,
+        })
+    }
+
+    // A keyboard key presses one CHIP-8 key, joystick inputs can be bound more than once
+    pub fn bind(&mut self, input: Input, key: usize) {
+        if input.is_keyboard() {
+            self.bindings.retain(|(bound, _)| *bound != input);
+        }
+        self.bindings.push((input, key));
+    }
+
+    // Bindings by keycode come first, so a key named in the profile wins over its position
+    pub fn key(&self, keycode: Keycode, scancode: Option<Scancode>) -> Option<usize> {
+        let by_keycode = self.bindings.iter().find(|(input, _)| *input == Input::Key(keycode));
+        let by_scancode = || self.bindings.iter().find(|(input, _)| scancode.is_some_and(|scancode| *input == Input::Scancode(scancode)));
+        by_keycode.or_else(by_scancode).map(|(_, key)| *key)
+    }
+
+    // Press or release a CHIP-8 key from any input
+    pub fn set(&mut self, vm: &mut VM, key: usize, pressed: bool) {
+        vm.keypad[key] = pressed;
+        if self.turbo[key] {
+            self.turbo_held[key] = pressed.then(Instant::now);
+        }
+    }
+
+    // Bindings a joystick answers to, the shared ones and those of its player if it has one
+    fn joystick_bindings(&self, player: Option<usize>) -> impl Iterator<Item = &(Input, usize)> {
+        self.bindings.iter().chain(player.into_iter().flat_map(|player| &self.players[player]))
+    }
+
+    pub fn update_button(&mut self, vm: &mut VM, player: Option<usize>, button: u8, pressed: bool) {
+        let keys: Vec<usize> = self.joystick_bindings(player).filter(|(input, _)| *input == Input::Button(button)).map(|(_, key)| *key).collect();
+        for key in keys {
+            self.set(vm, key, pressed);
+        }
+    }
+
+    // Press or release the keys bound to either direction of an axis
+    pub fn update_axis(&mut self, vm: &mut VM, player: Option<usize>, axis: u8, value: i16) {
+        let mut changes = Vec::new();
+        for (input, key) in self.joystick_bindings(player) {
+            match input {
+                Input::Axis(bound, true) if *bound == axis => changes.push((*key, value > self.deadzone)),
+                Input::Axis(bound, false) if *bound == axis => changes.push((*key, value < -self.deadzone)),
+                _ => {}
+            }
+        }
+        for (key, pressed) in changes {
+            self.set(vm, key, pressed);
+        }
+    }
+
+    pub fn update_hat(&mut self, vm: &mut VM, player: Option<usize>, hat: u8, state: HatState) {
+        let mut changes = Vec::new();
+        for (input, key) in self.joystick_bindings(player) {
+            if let Input::Hat(bound, direction) = input {
+                if *bound == hat {
+                    changes.push((*key, state as u8 & direction != 0));
+                }
+            }
+        }
+        for (key, pressed) in changes {
+            self.set(vm, key, pressed);
+        }
+    }
+
+    // Called every pass of the main loop: held turbo keys are down for the first half of each
+    // period, counted from when they were pressed so a tap always registers
+    pub fn autofire(&self, vm: &mut VM, now: Instant) {
+        for (key, held) in self.turbo_held.iter().enumerate() {
+            if let Some(since) = held {
+                let half_periods = (now.duration_since(*since).as_secs_f64() * self.turbo_rate as f64 * 2.0) as u64;
+                vm.keypad[key] = half_periods.is_multiple_of(2);
+            }
+        }
+    }
+}
+
+impl Input {
+    fn is_keyboard(&self) -> bool {
+        matches!(self, Input::Key(_) | Input::Scancode(_))
+    }
+}
+
+impl Default for InputProfile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// "key Q", "scancode Q", "axis 0-", "hat 0 up" or "button 3"
+pub fn parse_input(text: &str) -> Result<Input, String> {
+    let (kind, rest) = text.split_once(char::is_whitespace).ok_or(format!("invalid input {}", text))?;
+    let rest = rest.trim();
+    let number = |text: &str| text.trim().parse::<u8>().map_err(|_| format!("invalid {} number {}", kind, text));
+    match kind {
+        "key" => Keycode::from_name(rest).map(Input::Key).ok_or(format!("unknown key {}", rest)),
+        "scancode" => Scancode::from_name(rest).map(Input::Scancode).ok_or(format!("unknown scancode {}", rest)),
+        "axis" => match rest.strip_suffix('+') {
+            Some(axis) => Ok(Input::Axis(number(axis)?, true)),
+            None => Ok(Input::Axis(number(rest.strip_suffix('-').ok_or(format!("axis {} needs a + or - direction", rest))?)?, false)),
+        },
+        "hat" => {
+            let (hat, direction) = rest.split_once(char::is_whitespace).ok_or(format!("hat {} needs a direction", rest))?;
+            let direction = match direction.trim() {
+                "up" => HatState::Up,
+                "right" => HatState::Right,
+                "down" => HatState::Down,
+                "left" => HatState::Left,
+                other => return Err(format!("invalid hat direction {}, expected up, down, left or right", other)),
+            };
+            Ok(Input::Hat(number(hat)?, direction as u8))
+        }
+        "button" => Ok(Input::Button(number(rest)?)),
+        _ => Err(format!("unknown input {}, expected key, scancode, axis, hat or button", kind)),
+    }
+}
+
+// The profile line form of an input, the reverse of parse_input
+fn describe_input(input: &Input) -> String {
+    match input {
+        Input::Key(keycode) => format!("key {}", keycode.name()),
+        Input::Scancode(scancode) => format!("scancode {}", scancode.name()),
+        Input::Axis(axis, positive) => format!("axis {}{}", axis, if *positive { '+' } else { '-' }),
+        Input::Hat(hat, direction) => {
+            let name = match direction {
+                1 => "up",
+                2 => "right",
+                4 => "down",
+                _ => "left",
+            };
+            format!("hat {} {}", hat, name)
+        }
+        Input::Button(button) => format!("button {}", button),
+    }
+}
+
+// Joysticks stay open for as long as they are kept, so they are collected here as they appear.
+// The first two get the player numbers and keep them until unplugged.
+pub struct Joysticks {
+    subsystem: JoystickSubsystem,
+    open: Vec<Joystick>,
+    players: [Option<u32>; PLAYERS],
+}
+
+impl Joysticks {
+    pub fn new(subsystem: JoystickSubsystem) -> Self {
+        Self { subsystem, open: Vec::new(), players: [None; PLAYERS] }
+    }
+
+    // Player of the joystick with an SDL instance id, counted from 0
+    pub fn player(&self, instance_id: u32) -> Option<usize> {
+        self.players.iter().position(|id| *id == Some(instance_id))
+    }
+
+    // Called for SDL's device added events, which also arrive for joysticks present at startup
+    pub fn add(&mut self, index: u32) {
+        match self.subsystem.open(index) {
+            Ok(joystick) => {
+                println!("Joystick {}: {}, {} axes, {} hats, {} buttons", index, joystick.name(), joystick.num_axes(), joystick.num_hats(), joystick.num_buttons());
+                if let Some(player) = self.players.iter().position(Option::is_none) {
+                    println!("Joystick {} is player {}", index, player + 1);
+                    self.players[player] = Some(joystick.instance_id());
+                }
+                self.open.push(joystick);
+            }
+            Err(e) => eprintln!("Error opening joystick {}, {}", index, e),
+        }
+    }
+
+    pub fn remove(&mut self, instance_id: u32) {
+        self.open.retain(|joystick| joystick.instance_id() != instance_id);
+        if let Some(player) = self.player(instance_id) {
+            self.players[player] = None;
+        }
+    }
+}
@@ -0,0 +1,108 @@
+// Keypad input from a text stream, given with --input-script <file>, where - is stdin and a named
+// pipe works like any file, so test harnesses and other programs can play without the window
+// having focus. One command per line, applied at the start of a frame:
+//
+//   key down <key>         press CHIP-8 key 0-F
+//   key up <key>           release it
+//   wait <n> [frames]      apply the next command <n> frames later
+//   quit                   exit the emulator
+//
+// Lines starting with # are comments. From stdin or a pipe in real time, commands are read as they
+// arrive, so a harness can write them as it goes; --no-focus-pause keeps the emulator running while
+// it sits in the background. A regular file, or any stream when headless runs without real time,
+// is followed exactly: each frame waits for its commands, so the same script gives the same run.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use crate::chip8::parse_key;
+
+enum Command {
+    Key(usize, bool),
+    Wait(u32),
+    Quit,
+}
+
+fn parse_command(line: &str) -> Result<Option<Command>, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        [] => Ok(None),
+        [comment, ..] if comment.starts_with('#') => Ok(None),
+        ["key", "down", key] => Ok(Some(Command::Key(parse_key(key)?, true))),
+        ["key", "up", key] => Ok(Some(Command::Key(parse_key(key)?, false))),
+        ["wait", frames] | ["wait", frames, "frames" | "frame"] => match frames.parse() {
+            Ok(frames) => Ok(Some(Command::Wait(frames))),
+            Err(_) => Err(format!("invalid frame count {}", frames)),
+        },
+        ["quit"] => Ok(Some(Command::Quit)),
+        _ => Err(format!("unknown command {}", line.trim())),
+    }
+}
+
+pub struct InputScript {
+    // Filled by a thread reading the stream, since reading blocks until a line arrives
+    commands: Receiver<Command>,
+    // Frames left before the next command
+    wait: u32,
+    // Wait for the next command rather than taking only what has arrived
+    blocking: bool,
+}
+
+impl InputScript {
+    pub fn open(path: &str, real_time: bool) -> Result<Self, String> {
+        let blocking = !real_time || (path != "-" && std::fs::metadata(path).is_ok_and(|metadata| metadata.is_file()));
+        let reader: Box<dyn BufRead + Send> = if path == "-" {
+            Box::new(BufReader::new(io::stdin()))
+        } else {
+            Box::new(BufReader::new(File::open(path).map_err(|e| format!("Error opening input script {}, {}", path, e))?))
+        };
+        let (sender, commands) = mpsc::channel();
+        let name = if path == "-" { "stdin".to_string() } else { path.to_string() };
+        thread::spawn(move || {
+            for (index, line) in reader.lines().enumerate() {
+                let Ok(line) = line else { break };
+                match parse_command(&line) {
+                    Ok(Some(command)) => {
+                        if sender.send(command).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => {}
+                    // A typo shouldn't end a session another program is driving
+                    Err(e) => eprintln!("{}:{}: {}", name, index + 1, e),
+                }
+            }
+        });
+        Ok(Self { commands, wait: 0, blocking })
+    }
+
+    // Called at the start of every frame with what presses and releases a key. Returns true on quit.
+    pub fn next_frame(&mut self, mut set_key: impl FnMut(usize, bool)) -> bool {
+        self.wait = self.wait.saturating_sub(1);
+        if self.wait > 0 {
+            return false;
+        }
+        while let Some(command) = self.receive() {
+            match command {
+                Command::Key(key, pressed) => set_key(key, pressed),
+                Command::Wait(frames) => {
+                    self.wait = frames;
+                    break;
+                }
+                Command::Quit => return true,
+            }
+        }
+        false
+    }
+
+    // Blocking stops at a wait or quit since next_frame does, or at the end of the stream
+    fn receive(&self) -> Option<Command> {
+        if self.blocking {
+            self.commands.recv().ok()
+        } else {
+            self.commands.try_recv().ok()
+        }
+    }
+}
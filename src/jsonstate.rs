@@ -0,0 +1,304 @@
+// Save states as pretty-printed JSON, for reading and hand editing. Memory is written as rows of
+// 16 hex bytes and the display as rows of '.' and '#', so both stay editable in a text editor.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+
+use crate::chip8::VM;
+use crate::snapshot::Snapshot;
+
+impl Snapshot {
+    pub fn to_json(&self) -> String {
+        let numbers = |values: &mut dyn Iterator<Item = u64>| values.map(|v| v.to_string()).collect::<Vec<_>>().join(", ");
+        let mut json = String::from("{\n  \"version\": 1,\n");
+        for (name, value) in [("pc", self.pc as u64), ("i", self.i as u64), ("sp", self.sp as u64), ("delay", self.delay as u64), ("sound", self.sound as u64), ("frame", self.frame)] {
+            writeln!(json, "  \"{}\": {},", name, value).unwrap();
+        }
+        // Quoted so tools that read numbers as doubles don't round them
+        writeln!(json, "  \"seed\": \"{}\",", self.seed).unwrap();
+        writeln!(json, "  \"random_draws\": \"{}\",", self.random_draws).unwrap();
+        writeln!(json, "  \"v\": [{}],", numbers(&mut self.v.iter().map(|v| *v as u64))).unwrap();
+        writeln!(json, "  \"stack\": [{}],", numbers(&mut self.stack.iter().map(|v| *v as u64))).unwrap();
+
+        json.push_str("  \"memory\": [\n");
+        let rows: Vec<String> = self.memory.chunks(16).map(|row| format!("    \"{}\"", row.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" "))).collect();
+        json.push_str(&rows.join(",\n"));
+        json.push_str("\n  ],\n  \"display\": [\n");
+        let rows: Vec<String> = self.display.chunks(64).map(|row| format!("    \"{}\"", row.iter().map(|p| if *p == 1 { '#' } else { '.' }).collect::<String>())).collect();
+        json.push_str(&rows.join(",\n"));
+        json.push_str("\n  ]\n}\n");
+        json
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let mut parser = Parser { text: text.as_bytes(), position: 0 };
+        let Json::Object(fields) = parser.value()? else { return Err("state must be a JSON object".to_string()) };
+        let field = |name: &str| fields.get(name).ok_or(format!("missing \"{}\"", name));
+        let number = |name: &str, max: u64| match field(name)? {
+            Json::Number(value) if *value >= 0.0 && *value <= max as f64 && value.fract() == 0.0 => Ok(*value as u64),
+            Json::String(value) => value.parse::<u64>().ok().filter(|v| *v <= max).ok_or(format!("\"{}\" is out of range", name)),
+            _ => Err(format!("\"{}\" must be a number up to {}", name, max)),
+        };
+        let numbers = |name: &str, max: u64, out: &mut [u64]| -> Result<(), String> {
+            let Json::Array(items) = field(name)? else { return Err(format!("\"{}\" must be an array", name)) };
+            if items.len() != out.len() {
+                return Err(format!("\"{}\" must have {} entries", name, out.len()));
+            }
+            for (slot, item) in out.iter_mut().zip(items) {
+                match item {
+                    Json::Number(value) if *value >= 0.0 && *value <= max as f64 && value.fract() == 0.0 => *slot = *value as u64,
+                    _ => return Err(format!("\"{}\" entries must be numbers up to {}", name, max)),
+                }
+            }
+            Ok(())
+        };
+        let lines = |name: &str, count: usize| -> Result<Vec<String>, String> {
+            let Json::Array(items) = field(name)? else { return Err(format!("\"{}\" must be an array", name)) };
+            if items.len() != count {
+                return Err(format!("\"{}\" must have {} rows", name, count));
+            }
+            items.iter().map(|item| match item {
+                Json::String(line) => Ok(line.clone()),
+                _ => Err(format!("\"{}\" rows must be strings", name)),
+            }).collect()
+        };
+
+        let mut v = [0u64; 16];
+        numbers("v", 0xFF, &mut v)?;
+        let mut stack = [0u64; 16];
+        // Returning goes to the entry plus 2, which has to leave room to fetch an instruction as PC does
+        numbers("stack", 0xFFD, &mut stack)?;
+        let mut snapshot = Snapshot {
+            v: v.map(|value| value as u8),
+            i: number("i", 0xFFF)? as u16,
+            pc: number("pc", 0xFFE)? as u16,
+            stack: stack.map(|value| value as u16),
+            sp: number("sp", 15)? as u16,
+            delay: number("delay", 0xFF)? as u8,
+            sound: number("sound", 0xFF)? as u8,
+            memory: [0; 4096],
+            display: [0; 64 * 32],
+            frame: number("frame", u64::MAX)?,
+            seed: number("seed", u64::MAX)?,
+            random_draws: number("random_draws", u64::MAX)?,
+        };
+
+        for (row, line) in lines("memory", 256)?.iter().enumerate() {
+            let bytes: Vec<&str> = line.split_whitespace().collect();
+            if bytes.len() != 16 {
+                return Err(format!("memory row {} must have 16 bytes", row));
+            }
+            for (column, byte) in bytes.iter().enumerate() {
+                snapshot.memory[row * 16 + column] = u8::from_str_radix(byte, 16).map_err(|_| format!("memory row {}: bad byte '{}'", row, byte))?;
+            }
+        }
+        for (row, line) in lines("display", 32)?.iter().enumerate() {
+            if line.chars().count() != 64 {
+                return Err(format!("display row {} must have 64 pixels", row));
+            }
+            for (column, pixel) in line.chars().enumerate() {
+                snapshot.display[row * 64 + column] = match pixel {
+                    '#' => 1,
+                    '.' => 0,
+                    _ => return Err(format!("display row {}: pixels are '.' or '#'", row)),
+                };
+            }
+        }
+        Ok(snapshot)
+    }
+}
+
+impl VM<'_> {
+    pub fn export_json(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.snapshot().to_json()).map_err(|e| format!("Error writing {}, {}", path, e))
+    }
+
+    pub fn import_json(&mut self, path: &str) -> Result<(), String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Error reading {}, {}", path, e))?;
+        let snapshot = Snapshot::from_json(&text).map_err(|e| format!("{}: {}", path, e))?;
+        self.restore(&snapshot);
+        Ok(())
+    }
+}
+
+// No state field is a boolean or null, so those are parsed only to be skipped
+enum Json {
+    Literal,
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.text.get(self.position).is_some_and(|c| c.is_ascii_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        let line = self.text[..self.position.min(self.text.len())].iter().filter(|c| **c == b'\n').count() + 1;
+        Err(format!("line {}: {}", line, message))
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.text.get(self.position) != Some(&byte) {
+            return self.error(&format!("expected '{}'", byte as char));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.text.get(self.position) {
+            Some(b'{') => {
+                self.position += 1;
+                let mut fields = BTreeMap::new();
+                self.skip_whitespace();
+                if self.text.get(self.position) == Some(&b'}') {
+                    self.position += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let Json::String(key) = self.value()? else { return self.error("expected a field name") };
+                    self.expect(b':')?;
+                    fields.insert(key, self.value()?);
+                    self.skip_whitespace();
+                    match self.text.get(self.position) {
+                        Some(b',') => self.position += 1,
+                        Some(b'}') => {
+                            self.position += 1;
+                            return Ok(Json::Object(fields));
+                        }
+                        _ => return self.error("expected ',' or '}'"),
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.position += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.text.get(self.position) == Some(&b']') {
+                    self.position += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.text.get(self.position) {
+                        Some(b',') => self.position += 1,
+                        Some(b']') => {
+                            self.position += 1;
+                            return Ok(Json::Array(items));
+                        }
+                        _ => return self.error("expected ',' or ']'"),
+                    }
+                }
+            }
+            Some(b'"') => {
+                self.position += 1;
+                let mut string = String::new();
+                loop {
+                    match self.text.get(self.position) {
+                        Some(b'"') => break,
+                        Some(b'\\') => {
+                            self.position += 1;
+                            match self.text.get(self.position) {
+                                Some(b'n') => string.push('\n'),
+                                Some(b't') => string.push('\t'),
+                                Some(c @ (b'"' | b'\\' | b'/')) => string.push(*c as char),
+                                _ => return self.error("unsupported escape"),
+                            }
+                            self.position += 1;
+                        }
+                        Some(_) => {
+                            let rest = std::str::from_utf8(&self.text[self.position..]).map_err(|_| "invalid UTF-8".to_string())?;
+                            let c = rest.chars().next().unwrap();
+                            string.push(c);
+                            self.position += c.len_utf8();
+                        }
+                        None => return self.error("unterminated string"),
+                    }
+                }
+                self.position += 1;
+                Ok(Json::String(string))
+            }
+            Some(c) if c.is_ascii_digit() || *c == b'-' => {
+                let start = self.position;
+                while self.text.get(self.position).is_some_and(|c| c.is_ascii_digit() || b"+-.eE".contains(c)) {
+                    self.position += 1;
+                }
+                let number = std::str::from_utf8(&self.text[start..self.position]).unwrap();
+                number.parse().map(Json::Number).or_else(|_| self.error(&format!("bad number '{}'", number)))
+            }
+            _ => {
+                for word in ["true", "false", "null"] {
+                    if self.text[self.position..].starts_with(word.as_bytes()) {
+                        self.position += word.len();
+                        return Ok(Json::Literal);
+                    }
+                }
+                self.error("expected a value")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Snapshot {
+        let mut snapshot = Snapshot {
+            v: [0; 16],
+            i: 0x3A0,
+            pc: 0x2F4,
+            stack: [0; 16],
+            sp: 1,
+            delay: 0,
+            sound: 0,
+            memory: [0; 4096],
+            display: [0; 64 * 32],
+            frame: 60,
+            seed: u64::MAX,
+            random_draws: 3,
+        };
+        snapshot.stack[0] = 0x204;
+        snapshot.memory[0x200] = 0xA2;
+        snapshot.display[130] = 1;
+        snapshot
+    }
+
+    #[test]
+    fn round_trip() {
+        let json = sample().to_json();
+        let loaded = Snapshot::from_json(&json).unwrap();
+        assert_eq!(loaded.to_json(), json);
+        assert_eq!(loaded.seed, u64::MAX);
+    }
+
+    #[test]
+    fn stack_entry_past_memory_is_rejected() {
+        let json = sample().to_json().replace("\"stack\": [516,", "\"stack\": [65535,");
+        assert_eq!(Snapshot::from_json(&json).err().unwrap(), "\"stack\" entries must be numbers up to 4093");
+    }
+
+    #[test]
+    fn pc_and_i_past_memory_are_rejected() {
+        let json = sample().to_json();
+        let pc = json.replace("\"pc\": 756,", "\"pc\": 4095,");
+        assert_eq!(Snapshot::from_json(&pc).err().unwrap(), "\"pc\" must be a number up to 4094");
+        let i = json.replace("\"i\": 928,", "\"i\": 65535,");
+        assert_eq!(Snapshot::from_json(&i).err().unwrap(), "\"i\" must be a number up to 4095");
+    }
+}
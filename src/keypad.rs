@@ -0,0 +1,101 @@
+// Clickable keypad beside the display, shown with --keypad. It's laid out like the COSMAC VIP's
+// keypad, so it doubles as a map of which key is where, and pressing a key with the mouse holds it
+// until the button is let go. The panel sits to the right of the display and the debugger panels.
+//
+// Touchscreens press keys too, one per finger, and sliding a finger (or the mouse with the button
+// held) from key to key moves the press along. The first touch shows the panel if it isn't already.
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+use crate::chip8::VM;
+use crate::debugger::{KEYPAD_LAYOUT, PANEL_WIDTH};
+use crate::text::{draw_text, text_width, GLYPH_HEIGHT};
+
+// Gap around and between the keys, in layout pixels at any scale
+const GAP: u32 = 4;
+
+// SDL's id for mouse events it makes up from touches, which are handled as touches instead
+pub const TOUCH_MOUSE_ID: u32 = u32::MAX;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Pointer {
+    Mouse,
+    Finger(i64),
+}
+
+#[derive(Default)]
+pub struct OnscreenKeypad {
+    // Keys held down by the mouse and each finger
+    pointers: Vec<(Pointer, usize)>,
+}
+
+impl OnscreenKeypad {
+    // Move a pointer onto a key, or off the keypad with None. Returns the key to release and the
+    // key to press, leaving alone keys another pointer holds.
+    pub fn point(&mut self, pointer: Pointer, key: Option<usize>) -> (Option<usize>, Option<usize>) {
+        let old = self.pointers.iter().position(|(held, _)| *held == pointer).map(|index| self.pointers.remove(index).1);
+        if old == key {
+            if let Some(key) = key {
+                self.pointers.push((pointer, key));
+            }
+            return (None, None);
+        }
+        let held = |pointers: &[(Pointer, usize)], key: usize| pointers.iter().any(|(_, held)| *held == key);
+        let released = old.filter(|old| !held(&self.pointers, *old));
+        let pressed = key.filter(|key| !held(&self.pointers, *key));
+        if let Some(key) = key {
+            self.pointers.push((pointer, key));
+        }
+        (released, pressed)
+    }
+}
+
+// Four keys across and the panel as tall as the display
+pub fn onscreen_keypad_width(window_scale: u32) -> u32 {
+    32 * window_scale
+}
+
+fn cell_size(window_scale: u32) -> u32 {
+    8 * window_scale
+}
+
+impl VM<'_> {
+    fn onscreen_keypad_left(&self, window_scale: u32) -> i32 {
+        (64 * window_scale + if self.show_debugger { PANEL_WIDTH } else { 0 }) as i32
+    }
+
+    // CHIP-8 key under a point in layout coordinates
+    pub fn onscreen_keypad_key(&self, x: i32, y: i32, window_scale: u32) -> Option<usize> {
+        self.onscreen_keypad.as_ref()?;
+        let cell = cell_size(window_scale) as i32;
+        let (column, row) = ((x - self.onscreen_keypad_left(window_scale)) / cell, y / cell);
+        if x < self.onscreen_keypad_left(window_scale) || y < 0 || column > 3 || row > 3 {
+            return None;
+        }
+        Some(KEYPAD_LAYOUT[row as usize][column as usize])
+    }
+
+    pub fn draw_onscreen_keypad(&mut self, window_scale: u32) -> Result<(), String> {
+        let left = self.onscreen_keypad_left(window_scale);
+        let cell = cell_size(window_scale);
+        let scale = (window_scale / 2).max(1);
+        self.canvas.set_draw_color(Color::RGB(20, 20, 20));
+        self.canvas.fill_rect(Rect::new(left, 0, onscreen_keypad_width(window_scale), 32 * window_scale))?;
+        for (row, keys) in KEYPAD_LAYOUT.iter().enumerate() {
+            for (column, key) in keys.iter().enumerate() {
+                let x = left + (column as u32 * cell + GAP) as i32;
+                let y = (row as u32 * cell + GAP) as i32;
+                let pressed = self.keypad[*key];
+                self.canvas.set_draw_color(if pressed { Color::RGB(0, 200, 0) } else { Color::RGB(60, 60, 60) });
+                self.canvas.fill_rect(Rect::new(x, y, cell - 2 * GAP, cell - 2 * GAP))?;
+                let label = format!("{:X}", key);
+                let label_x = x + (cell - 2 * GAP).saturating_sub(text_width(&label, scale)) as i32 / 2;
+                let label_y = y + (cell - 2 * GAP).saturating_sub(GLYPH_HEIGHT * scale) as i32 / 2;
+                let color = if pressed { Color::RGB(0, 0, 0) } else { Color::RGB(200, 200, 200) };
+                draw_text(&mut self.canvas, label_x, label_y, scale, &label, color)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+// FX0A, wait for a key. Interpreters disagree on which key it reports when several are held, and
+// the odd rom depends on it, so --key-wait picks one:
+//   lowest    the lowest numbered key that is down, as soon as one is (default)
+//   recent    the key pressed most recently of the ones that are down
+//   release   the first key let go during the wait, like the COSMAC VIP, which waited for a
+//             press and its release
+// With the press policies the reported key is released in the keypad, otherwise holding it would
+// satisfy the next FX0A straight away.
+
+use crate::chip8::VM;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum KeyWaitPolicy {
+    Lowest,
+    Recent,
+    Release,
+}
+
+pub fn parse_key_wait(text: &str) -> Result<KeyWaitPolicy, String> {
+    match text {
+        "lowest" => Ok(KeyWaitPolicy::Lowest),
+        "recent" => Ok(KeyWaitPolicy::Recent),
+        "release" => Ok(KeyWaitPolicy::Release),
+        _ => Err(format!("invalid key wait policy {}, expected lowest, recent or release", text)),
+    }
+}
+
+pub fn describe_key_wait(policy: KeyWaitPolicy) -> &'static str {
+    match policy {
+        KeyWaitPolicy::Lowest => "lowest",
+        KeyWaitPolicy::Recent => "recent",
+        KeyWaitPolicy::Release => "release",
+    }
+}
+
+// What an FX0A in progress has seen of the keypad, from one execution of it to the next
+pub struct KeyWait {
+    previous: [bool; 16],
+    // Keys that are down, in the order they were pressed
+    order: Vec<usize>,
+}
+
+impl VM<'_> {
+    // Called each time FX0A executes, returns the key to report once the wait is over
+    pub fn waited_key(&mut self) -> Option<usize> {
+        let keypad = self.keypad;
+        // Keys already down when the wait starts count as pressed in key order
+        let wait = self.key_wait.get_or_insert_with(|| KeyWait { previous: keypad, order: (0..16).filter(|key| keypad[*key]).collect() });
+        let mut released = None;
+        for (key, down) in keypad.iter().enumerate() {
+            if *down != wait.previous[key] {
+                wait.order.retain(|held| *held != key);
+                if *down {
+                    wait.order.push(key);
+                } else {
+                    released = released.or(Some(key));
+                }
+            }
+        }
+        wait.previous = keypad;
+
+        let key = match self.key_wait_policy {
+            KeyWaitPolicy::Lowest => (0..16).find(|key| keypad[*key]),
+            KeyWaitPolicy::Recent => wait.order.last().copied(),
+            KeyWaitPolicy::Release => released,
+        }?;
+        self.key_wait = None;
+        if self.key_wait_policy != KeyWaitPolicy::Release {
+            self.keypad[key] = false;
+        }
+        Some(key)
+    }
+}
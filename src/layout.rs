@@ -0,0 +1,206 @@
+// Fitting the layout into the window. Everything is drawn in layout coordinates, the display at
+// window_scale plus the debugger panels when open, and scaled to whatever size the window has.
+// Sizes come from the renderer's output, which on high-DPI displays is the drawable in real pixels
+// rather than the window size in points, so nothing is drawn at low resolution and stretched.
+//
+// A --bezel adds its margins around the layout, see bezel.rs. The --keypad panel goes on the right.
+//
+// With a rotation the frame is drawn into a texture at the layout size and copied to the window
+// turned, so overlays rotate with the display as they would on a monitor mounted on its side.
+
+use std::ptr;
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::video::FullscreenType;
+
+use crate::chip8::VM;
+use crate::debugger::{PANEL_HEIGHT, PANEL_WIDTH};
+use crate::keypad::onscreen_keypad_width;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FullscreenMode {
+    // SDL's fullscreen window at the desktop resolution
+    Desktop,
+    // Switches the display mode, the window owns the screen
+    Exclusive,
+    // An ordinary window without decorations covering the display, quickest to alt-tab away from
+    // and it stays on its monitor
+    Borderless,
+}
+
+pub fn parse_fullscreen_mode(text: &str) -> Result<FullscreenMode, String> {
+    match text {
+        "desktop" => Ok(FullscreenMode::Desktop),
+        "exclusive" => Ok(FullscreenMode::Exclusive),
+        "borderless" => Ok(FullscreenMode::Borderless),
+        _ => Err(format!("invalid fullscreen mode {}, expected desktop, exclusive or borderless", text)),
+    }
+}
+
+impl VM<'_> {
+    pub fn layout_size(&self, window_scale: u32) -> (u32, u32) {
+        let (mut width, height) = if self.show_debugger {
+            (64 * window_scale + PANEL_WIDTH, 32 * window_scale + PANEL_HEIGHT)
+        } else {
+            (64 * window_scale, 32 * window_scale)
+        };
+        if self.onscreen_keypad.is_some() {
+            width += onscreen_keypad_width(window_scale);
+        }
+        match &self.bezel {
+            Some(bezel) => (width + bezel.margins.left + bezel.margins.right, height + bezel.margins.top + bezel.margins.bottom),
+            None => (width, height),
+        }
+    }
+
+    pub fn set_fullscreen(&mut self, mode: FullscreenMode, fullscreen: bool) -> Result<(), String> {
+        let window = self.canvas.window_mut();
+        if fullscreen == self.windowed_bounds.is_some() {
+            return Ok(());
+        }
+        if fullscreen {
+            let (x, y) = window.position();
+            let (width, height) = window.size();
+            self.windowed_bounds = Some(Rect::new(x, y, width, height));
+            match mode {
+                FullscreenMode::Desktop => window.set_fullscreen(FullscreenType::Desktop)?,
+                FullscreenMode::Exclusive => window.set_fullscreen(FullscreenType::True)?,
+                FullscreenMode::Borderless => {
+                    let bounds = window.subsystem().display_bounds(window.display_index()?)?;
+                    window.set_bordered(false);
+                    window.set_position(bounds.x().into(), bounds.y().into());
+                    window.set_size(bounds.width(), bounds.height()).map_err(|e| e.to_string())?;
+                }
+            }
+        } else {
+            let bounds = self.windowed_bounds.take().unwrap();
+            window.set_fullscreen(FullscreenType::Off)?;
+            window.set_bordered(true);
+            window.set_size(bounds.width(), bounds.height()).map_err(|e| e.to_string())?;
+            window.set_position(bounds.x().into(), bounds.y().into());
+        }
+        Ok(())
+    }
+
+    // Layout size turned by the rotation, the shape the window should have
+    pub fn window_size(&self, window_scale: u32) -> (u32, u32) {
+        let (width, height) = self.layout_size(window_scale);
+        if self.rotation % 180 == 90 {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    // Turn a mouse position, which SDL gives relative to the viewport at the logical size, into the
+    // layout coordinates things are drawn at, undoing the rotation and the bezel
+    pub fn layout_point(&self, x: i32, y: i32, window_scale: u32) -> (i32, i32) {
+        let (width, height) = self.layout_size(window_scale);
+        let (window_width, window_height) = self.window_size(window_scale);
+        let (dx, dy) = (x - window_width as i32 / 2, y - window_height as i32 / 2);
+        let (dx, dy) = match self.rotation {
+            90 => (dy, -dx),
+            180 => (-dx, -dy),
+            270 => (-dy, dx),
+            _ => (dx, dy),
+        };
+        let (x, y) = (dx + width as i32 / 2, dy + height as i32 / 2);
+        match &self.bezel {
+            Some(bezel) => (x - bezel.margins.left as i32, y - bezel.margins.top as i32),
+            None => (x, y),
+        }
+    }
+
+    // Turn a finger position, a fraction of the window, into layout coordinates. SDL maps it onto
+    // the logical size when there is one; with integer scaling there isn't, so undo the scale and
+    // viewport here.
+    pub fn touch_point(&self, x: f32, y: f32, window_scale: u32) -> Result<(i32, i32), String> {
+        let (x, y) = if self.integer_scaling {
+            let (window_width, window_height) = self.canvas.output_size()?;
+            let (scale_x, scale_y) = self.canvas.scale();
+            let viewport = self.canvas.viewport();
+            ((x * window_width as f32 / scale_x) as i32 - viewport.x(), (y * window_height as f32 / scale_y) as i32 - viewport.y())
+        } else {
+            let (width, height) = self.window_size(window_scale);
+            ((x * width as f32) as i32, (y * height as f32) as i32)
+        };
+        Ok(self.layout_point(x, y, window_scale))
+    }
+
+    pub fn set_rotation(&mut self, rotation: u16, window_scale: u32) -> Result<(), String> {
+        self.rotation = rotation;
+        self.fit_window(window_scale)
+    }
+
+    // Resize the window to the layout, after something changed its size
+    pub fn fit_window(&mut self, window_scale: u32) -> Result<(), String> {
+        let (width, height) = self.window_size(window_scale);
+        self.canvas.window_mut().set_size(width, height).map_err(|e| e.to_string())?;
+        self.apply_layout(window_scale)
+    }
+
+    // Call after anything that changes the window size or the layout. SDL's logical size keeps the
+    // aspect ratio and centres the layout. Integer scaling instead picks the largest whole number of
+    // window pixels per CHIP-8 pixel that fits, which SDL's own integer mode can't do because it
+    // works in multiples of the whole layout.
+    pub fn apply_layout(&mut self, window_scale: u32) -> Result<(), String> {
+        let (width, height) = self.window_size(window_scale);
+        if !self.integer_scaling {
+            self.canvas.set_scale(1.0, 1.0)?;
+            return self.canvas.set_logical_size(width, height).map_err(|e| e.to_string());
+        }
+
+        self.canvas.set_logical_size(0, 0).map_err(|e| e.to_string())?;
+        let (window_width, window_height) = self.canvas.output_size()?;
+        let pixel = (window_width * window_scale / width).min(window_height * window_scale / height).max(1);
+        self.canvas.set_scale(pixel as f32 / window_scale as f32, pixel as f32 / window_scale as f32)?;
+        // The viewport is in layout coordinates; keep its offset a whole number of CHIP-8 pixels so
+        // every pixel lands on the same grid
+        let left = window_width.saturating_sub(width * pixel / window_scale) / 2 / pixel * window_scale;
+        let top = window_height.saturating_sub(height * pixel / window_scale) / 2 / pixel * window_scale;
+        self.canvas.set_viewport(Rect::new(left as i32, top as i32, width, height));
+        Ok(())
+    }
+
+    // Called before drawing a frame, sends the drawing to the rotation texture when rotated
+    pub fn begin_frame(&mut self, window_scale: u32) -> Result<(), String> {
+        if self.rotation == 0 {
+            return Ok(());
+        }
+        let (width, height) = self.layout_size(window_scale);
+        let stale = self.rotation_target.as_ref().is_none_or(|texture| {
+            let query = texture.query();
+            (query.width, query.height) != (width, height)
+        });
+        if stale {
+            self.rotation_target = Some(self.texture_creator.create_texture_target(None, width, height).map_err(|e| e.to_string())?);
+        }
+        // The sdl2 crate only offers render targets inside a closure that borrows the canvas, where
+        // the draw methods on VM can't be called
+        let target = self.rotation_target.as_ref().unwrap();
+        if unsafe { sdl2::sys::SDL_SetRenderTarget(self.canvas.raw(), target.raw()) } != 0 {
+            return Err(sdl2::get_error());
+        }
+        Ok(())
+    }
+
+    // Called after drawing a frame, copies the rotation texture to the window and presents
+    pub fn end_frame(&mut self, window_scale: u32) -> Result<(), String> {
+        if self.rotation != 0 {
+            if unsafe { sdl2::sys::SDL_SetRenderTarget(self.canvas.raw(), ptr::null_mut()) } != 0 {
+                return Err(sdl2::get_error());
+            }
+            let (width, height) = self.layout_size(window_scale);
+            let (window_width, window_height) = self.window_size(window_scale);
+            let [r, g, b] = self.display_palette()[0];
+            self.canvas.set_draw_color(Color::RGB(r, g, b));
+            self.canvas.clear();
+            // copy_ex turns around the centre of the destination, so centre the unturned rectangle
+            let destination = Rect::new((window_width as i32 - width as i32) / 2, (window_height as i32 - height as i32) / 2, width, height);
+            self.canvas.copy_ex(self.rotation_target.as_ref().unwrap(), None, destination, self.rotation as f64, None, false, false)?;
+        }
+        self.canvas.present();
+        Ok(())
+    }
+}
@@ -0,0 +1,50 @@
+// The library target, which only has something in it for the browser build, the libretro core and
+// embedded displays:
+//   wasm-pack build --target web -- --no-default-features --features web
+//   cargo build --release --lib --no-default-features --features libretro
+//   chip8-rust = { default-features = false, features = ["embedded"] } as a dependency
+// It's the emulator core from main.rs, the modules that don't need SDL, with the wasm-bindgen
+// interface in web.rs, the libretro API in libretro.rs or embedded-graphics drawing in embedded.rs
+// on top. web/index.html is an example page.
+
+#![cfg(all(any(feature = "web", feature = "libretro", feature = "embedded"), not(feature = "sdl")))]
+
+pub mod chip8;
+pub mod command;
+pub mod coverage;
+pub mod crashdump;
+pub mod disasm;
+#[cfg(feature = "embedded")]
+pub mod embedded;
+pub mod frameblend;
+pub mod gdb;
+pub mod gif;
+pub mod inputscript;
+pub mod jsonstate;
+pub mod keywait;
+#[cfg(feature = "libretro")]
+pub mod libretro;
+pub mod lint;
+pub mod loopdetect;
+pub mod macros;
+pub mod movie;
+pub mod octo;
+pub mod palette;
+pub mod phosphor;
+pub mod png;
+pub mod profile;
+pub mod rewind;
+pub mod romsettings;
+pub mod rpl;
+pub mod savestate;
+pub mod screenshot;
+pub mod script;
+pub mod smc;
+pub mod snapshot;
+pub mod stats;
+pub mod symbols;
+pub mod trace;
+pub mod video;
+pub mod watch;
+#[cfg(feature = "web")]
+pub mod web;
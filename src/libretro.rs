@@ -0,0 +1,368 @@
+// A libretro core, so RetroArch and other libretro frontends can run CHIP-8 roms with their
+// shaders, netplay, rewind and the rest. Build it without the default features, see lib.rs:
+//   cargo build --release --lib --no-default-features --features libretro
+// and install target/release/libchip8_rust.so (chip8_rust.dll, libchip8_rust.dylib) as
+// chip8_libretro.so in the frontend's cores directory.
+//
+// Input is the keyboard's 1234/QWER/ASDF/ZXCV block, or on a joypad the d-pad as 2 4 6 8, A as 5,
+// B as 0, X as A, Y as B, L as 1, R as 3, Select as E and Start as F. The instructions per frame
+// are a core option. States are the save state format from savestate.rs, which has a fixed size
+// as netplay and rewind need.
+//
+// The libretro API is C and these functions are called by the frontend as it documents, from one
+// thread, so the core lives in a thread local and the pointers handed in are taken at its word.
+
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::{Cell, RefCell};
+use std::ffi::{c_char, c_uint, c_void, CStr};
+use std::ptr;
+
+use crate::chip8::VM;
+use crate::snapshot::Snapshot;
+
+const API_VERSION: c_uint = 1;
+const ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const ENVIRONMENT_GET_VARIABLE: c_uint = 15;
+const ENVIRONMENT_SET_VARIABLES: c_uint = 16;
+const ENVIRONMENT_GET_VARIABLE_UPDATE: c_uint = 17;
+const PIXEL_FORMAT_XRGB8888: c_uint = 1;
+const DEVICE_JOYPAD: c_uint = 1;
+const DEVICE_KEYBOARD: c_uint = 3;
+const MEMORY_SYSTEM_RAM: c_uint = 2;
+const REGION_NTSC: c_uint = 0;
+
+const SAMPLE_RATE: u32 = 44100;
+const PITCH: u32 = 440;
+const AMPLITUDE: i16 = 3000;
+const IPF_VARIABLE: &CStr = c"chip8_ipf";
+
+// Keyboard keys for CHIP-8 keys 0 to F, libretro's key codes being ASCII for these
+const KEYS: [u8; 16] = *b"x123qweasdzc4rfv";
+// Joypad buttons, by libretro id, and the CHIP-8 keys they press
+const BUTTONS: [(c_uint, usize); 12] = [
+    (4, 0x2), (5, 0x8), (6, 0x4), (7, 0x6), // up, down, left, right
+    (8, 0x5), (0, 0x0), (9, 0xA), (1, 0xB), // A, B, X, Y
+    (10, 0x1), (11, 0x3), (2, 0xE), (3, 0xF), // L, R, select, start
+];
+
+type EnvironmentFn = unsafe extern "C" fn(command: c_uint, data: *mut c_void) -> bool;
+type VideoRefreshFn = unsafe extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+type AudioSampleFn = unsafe extern "C" fn(left: i16, right: i16);
+type AudioSampleBatchFn = unsafe extern "C" fn(data: *const i16, frames: usize) -> usize;
+type InputPollFn = unsafe extern "C" fn();
+type InputStateFn = unsafe extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+
+#[repr(C)]
+pub struct SystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+}
+
+#[repr(C)]
+pub struct GameGeometry {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct SystemTiming {
+    fps: f64,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct SystemAvInfo {
+    geometry: GameGeometry,
+    timing: SystemTiming,
+}
+
+#[repr(C)]
+pub struct GameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+#[repr(C)]
+struct Variable {
+    key: *const c_char,
+    value: *const c_char,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+}
+
+struct Core {
+    vm: VM<'static>,
+    instructions_per_frame: u32,
+    // Position in the buzzer's square wave, in samples
+    phase: u32,
+    frame: Vec<u32>,
+}
+
+thread_local! {
+    static CALLBACKS: Cell<Callbacks> = Cell::new(Callbacks::default());
+    static CORE: RefCell<Option<Core>> = const { RefCell::new(None) };
+}
+
+fn callbacks() -> Callbacks {
+    CALLBACKS.with(Cell::get)
+}
+
+fn set_callback(set: impl FnOnce(&mut Callbacks)) {
+    let mut current = callbacks();
+    set(&mut current);
+    CALLBACKS.with(|callbacks| callbacks.set(current));
+}
+
+unsafe fn environment(command: c_uint, data: *mut c_void) -> bool {
+    callbacks().environment.is_some_and(|environment| environment(command, data))
+}
+
+// The instructions per frame the core option is set to, if the frontend has options
+unsafe fn instructions_per_frame() -> Option<u32> {
+    let mut variable = Variable { key: IPF_VARIABLE.as_ptr(), value: ptr::null() };
+    if !environment(ENVIRONMENT_GET_VARIABLE, &mut variable as *mut Variable as *mut c_void) || variable.value.is_null() {
+        return None;
+    }
+    CStr::from_ptr(variable.value).to_str().ok()?.parse().ok()
+}
+
+fn with_core<T>(default: T, f: impl FnOnce(&mut Core) -> T) -> T {
+    CORE.with(|core| core.borrow_mut().as_mut().map_or(default, f))
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    API_VERSION
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_set_environment(callback: EnvironmentFn) {
+    set_callback(|callbacks| callbacks.environment = Some(callback));
+    let variables = [
+        Variable { key: IPF_VARIABLE.as_ptr(), value: c"Instructions per frame; 8|10|12|15|20|30|50|100|4|6".as_ptr() },
+        Variable { key: ptr::null(), value: ptr::null() },
+    ];
+    environment(ENVIRONMENT_SET_VARIABLES, variables.as_ptr() as *mut c_void);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: VideoRefreshFn) {
+    set_callback(|callbacks| callbacks.video_refresh = Some(callback));
+}
+
+// Audio goes out a frame at a time through the batch callback
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: AudioSampleBatchFn) {
+    set_callback(|callbacks| callbacks.audio_sample_batch = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: InputPollFn) {
+    set_callback(|callbacks| callbacks.input_poll = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: InputStateFn) {
+    set_callback(|callbacks| callbacks.input_state = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    CORE.with(|core| core.borrow_mut().take());
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    *info = SystemInfo {
+        library_name: c"chip8-rust".as_ptr(),
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+        valid_extensions: c"ch8|c8|8o".as_ptr(),
+        // Roms are loaded by path, which is how .8o sources get assembled
+        need_fullpath: true,
+        block_extract: false,
+    };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    *info = SystemAvInfo {
+        geometry: GameGeometry { base_width: 64, base_height: 32, max_width: 64, max_height: 32, aspect_ratio: 2.0 },
+        timing: SystemTiming { fps: 60.0, sample_rate: SAMPLE_RATE as f64 },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const GameInfo) -> bool {
+    if game.is_null() || (*game).path.is_null() {
+        return false;
+    }
+    let Ok(path) = CStr::from_ptr((*game).path).to_str() else { return false };
+    let mut format = PIXEL_FORMAT_XRGB8888;
+    if !environment(ENVIRONMENT_SET_PIXEL_FORMAT, &mut format as *mut c_uint as *mut c_void) {
+        return false;
+    }
+    let mut vm = VM::new();
+    vm.init_font_set();
+    if let Err(e) = vm.load_rom(path) {
+        eprintln!("{}", e);
+        return false;
+    }
+    let instructions_per_frame = instructions_per_frame().unwrap_or(8);
+    CORE.with(|core| *core.borrow_mut() = Some(Core { vm, instructions_per_frame, phase: 0, frame: vec![0; 64 * 32] }));
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(_game_type: c_uint, _info: *const GameInfo, _num_info: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    CORE.with(|core| core.borrow_mut().take());
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    REGION_NTSC
+}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    with_core((), |core| core.vm.restart());
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_run() {
+    let callbacks = callbacks();
+    let mut updated = false;
+    if environment(ENVIRONMENT_GET_VARIABLE_UPDATE, &mut updated as *mut bool as *mut c_void) && updated {
+        if let Some(instructions) = instructions_per_frame() {
+            with_core((), |core| core.instructions_per_frame = instructions);
+        }
+    }
+    if let Some(poll) = callbacks.input_poll {
+        poll();
+    }
+    let input_state = callbacks.input_state;
+    with_core((), |core| {
+        if let Some(state) = input_state {
+            let mut keypad = [false; 16];
+            for (key, code) in KEYS.iter().enumerate() {
+                keypad[key] = state(0, DEVICE_KEYBOARD, 0, *code as c_uint) != 0;
+            }
+            for (button, key) in BUTTONS {
+                keypad[key] |= state(0, DEVICE_JOYPAD, 0, button) != 0;
+            }
+            core.vm.keypad = keypad;
+        }
+
+        // A halt stops the program until a reset, as there is nothing to resume it with
+        if !core.vm.paused {
+            if let Err(e) = core.vm.step_frame(core.instructions_per_frame) {
+                eprintln!("{}", e);
+                core.vm.pause();
+            }
+        }
+
+        let palette = core.vm.display_palette();
+        for (pixel, value) in core.frame.iter_mut().zip(core.vm.display) {
+            let [r, g, b] = palette[value.min(1) as usize];
+            *pixel = u32::from_be_bytes([0, r, g, b]);
+        }
+        if let Some(video_refresh) = callbacks.video_refresh {
+            video_refresh(core.frame.as_ptr() as *const c_void, 64, 32, 64 * 4);
+        }
+
+        let frames = (SAMPLE_RATE / 60) as usize;
+        let mut samples = vec![0i16; frames * 2];
+        if core.vm.sound > 0 {
+            let period = SAMPLE_RATE / PITCH;
+            for frame in samples.chunks_mut(2) {
+                let level = if core.phase < period / 2 { AMPLITUDE } else { -AMPLITUDE };
+                frame.fill(level);
+                core.phase = (core.phase + 1) % period;
+            }
+        }
+        if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+            audio_sample_batch(samples.as_ptr(), frames);
+        }
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    with_core(0, |core| core.vm.snapshot().to_bytes().len())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    with_core(false, |core| {
+        let bytes = core.vm.snapshot().to_bytes();
+        if bytes.len() > size {
+            return false;
+        }
+        ptr::copy_nonoverlapping(bytes.as_ptr(), data as *mut u8, bytes.len());
+        true
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    let bytes = std::slice::from_raw_parts(data as *const u8, size);
+    with_core(false, |core| match Snapshot::from_bytes(bytes) {
+        Ok(snapshot) => {
+            core.vm.restore(&snapshot);
+            true
+        }
+        Err(_) => false,
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+// The 4 KB of CHIP-8 memory, for RetroAchievements and memory watching in the frontend
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    if id != MEMORY_SYSTEM_RAM {
+        return ptr::null_mut();
+    }
+    with_core(ptr::null_mut(), |core| core.vm.memory.as_mut_ptr() as *mut c_void)
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    if id != MEMORY_SYSTEM_RAM {
+        return 0;
+    }
+    with_core(0, |core| core.vm.memory.len())
+}
@@ -0,0 +1,138 @@
+// Static rom checks for `chip8-rust check <rom>`. Control flow is followed from 0x200 so that
+// data which is never executed doesn't produce warnings.
+
+use std::collections::{BTreeSet, HashSet};
+use std::process;
+
+use crate::chip8::read_rom;
+use crate::disasm::{disassemble, opcode_class};
+
+const START_ADDRESS: usize = 0x200;
+
+pub struct Warning {
+    pub address: Option<u16>,
+    pub message: String,
+}
+
+pub fn check_command(args: &[String]) -> Result<(), String> {
+    let [rom] = args else { return Err("usage: chip8-rust check <rom>".to_string()) };
+    let warnings = check(&read_rom(rom)?);
+    for warning in &warnings {
+        match warning.address {
+            Some(address) => println!("{}:{:03X}: warning: {}", rom, address, warning.message),
+            None => println!("{}: warning: {}", rom, warning.message),
+        }
+    }
+    println!("{} warning(s)", warnings.len());
+    if !warnings.is_empty() {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+// Instructions from SUPER-CHIP and XO-CHIP that plain CHIP-8 doesn't have, apart from the RPL flags
+// FX75 and FX85, which this emulator runs
+pub fn variant_instruction(op: u16) -> Option<&'static str> {
+    match op {
+        0x00FB..=0x00FF => Some("SUPER-CHIP"),
+        _ if op & 0xFFF0 == 0x00C0 => Some("SUPER-CHIP"),
+        _ if op & 0xFFF0 == 0x00D0 => Some("XO-CHIP"),
+        _ if op & 0xF00F == 0xD000 => Some("SUPER-CHIP 16x16 sprite"),
+        _ if op & 0xF0FF == 0xF030 => Some("SUPER-CHIP"),
+        _ if op & 0xF00F == 0x5002 || op & 0xF00F == 0x5003 => Some("XO-CHIP"),
+        0xF000 | 0xF002 => Some("XO-CHIP"),
+        _ if op & 0xF0FF == 0xF001 || op & 0xF0FF == 0xF03A => Some("XO-CHIP"),
+        _ => None,
+    }
+}
+
+pub fn check(rom: &[u8]) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let end = START_ADDRESS + rom.len();
+    if rom.is_empty() {
+        warnings.push(Warning { address: None, message: "rom is empty".to_string() });
+        return warnings;
+    }
+    if !rom.len().is_multiple_of(2) {
+        warnings.push(Warning { address: None, message: format!("rom has odd length {}, the last instruction is truncated", rom.len()) });
+    }
+
+    let fetch = |address: usize| -> Option<u16> {
+        if address < START_ADDRESS || address + 1 >= end {
+            return None;
+        }
+        Some((rom[address - START_ADDRESS] as u16) << 8 | rom[address + 1 - START_ADDRESS] as u16)
+    };
+
+    let mut visited = HashSet::new();
+    let mut reported = BTreeSet::new();
+    // (address, value of I if it is known at this point)
+    let mut pending = vec![(START_ADDRESS, None::<u16>)];
+    let mut warn = |address: usize, message: String| {
+        if reported.insert((address, message.clone())) {
+            warnings.push(Warning { address: Some(address as u16), message });
+        }
+    };
+
+    while let Some((pc, i)) = pending.pop() {
+        if !visited.insert(pc) {
+            continue;
+        }
+        let Some(op) = fetch(pc) else {
+            warn(pc, "execution can run past the end of the rom".to_string());
+            continue;
+        };
+        let x = (op & 0x0F00) >> 8;
+        let nnn = (op & 0x0FFF) as usize;
+        let next = pc + 2;
+
+        if let Some(variant) = variant_instruction(op) {
+            warn(pc, format!("{} instruction {:04X} is not supported by CHIP-8", variant, op));
+            pending.push((next, None));
+            continue;
+        }
+
+        match opcode_class(op) {
+            "????" => warn(pc, format!("undecodable opcode {:04X} on a reachable path", op)),
+            "00EE" => {}
+            "0NNN" => {
+                warn(pc, format!("{} calls native machine code, which is not emulated", disassemble(op)));
+                pending.push((next, i));
+            }
+            "1NNN" | "2NNN" => {
+                if nnn < START_ADDRESS || nnn >= end {
+                    warn(pc, format!("{} targets {:03X}, outside the rom ({:03X}-{:03X})", disassemble(op), nnn, START_ADDRESS, end - 1));
+                } else if nnn != pc {
+                    pending.push((nnn, None));
+                }
+                if op & 0xF000 == 0x2000 {
+                    pending.push((next, None));
+                }
+            }
+            "3XKK" | "4XKK" | "5XY0" | "9XY0" | "EX9E" | "EXA1" => {
+                pending.push((next, i));
+                pending.push((next + 2, i));
+            }
+            "BNNN" => warn(pc, format!("{} is a computed jump, its targets are not checked", disassemble(op))),
+            "ANNN" => pending.push((next, Some(nnn as u16))),
+            "FX1E" | "FX29" | "FX65" => pending.push((next, None)),
+            "FX33" | "FX55" => {
+                let length = if op & 0x00FF == 0x0033 { 3 } else { x + 1 };
+                match i {
+                    Some(i) if (i as usize) < START_ADDRESS => {
+                        warn(pc, format!("{} writes to {:03X}, below the rom start at 200", disassemble(op), i))
+                    }
+                    Some(i) if i as usize + length as usize > 4096 => {
+                        warn(pc, format!("{} writes past the end of memory", disassemble(op)))
+                    }
+                    _ => {}
+                }
+                pending.push((next, i));
+            }
+            _ => pending.push((next, i)),
+        }
+    }
+
+    warnings.sort_by_key(|w| w.address);
+    warnings
+}
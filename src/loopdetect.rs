@@ -0,0 +1,44 @@
+// Detects programs that have finished by spinning in place, either with the `1NNN` jump-to-self
+// idiom or by executing the same address `threshold` times in a row
+
+pub struct LoopDetector {
+    pub threshold: u32,
+    last_pc: u16,
+    repeats: u32,
+    // Set after the user resumes so the same loop doesn't halt again straight away
+    ignored_pc: Option<u16>,
+}
+
+impl LoopDetector {
+    pub fn new(threshold: u32) -> Self {
+        Self { threshold, last_pc: 0xFFFF, repeats: 0, ignored_pc: None }
+    }
+
+    // Called before executing `op` at `pc`, returns true if the program should be halted
+    pub fn check(&mut self, pc: u16, op: u16) -> bool {
+        if self.threshold == 0 {
+            return false;
+        }
+        if pc == self.last_pc {
+            self.repeats += 1;
+        } else {
+            self.last_pc = pc;
+            self.repeats = 1;
+            self.ignored_pc = None;
+        }
+        if self.ignored_pc == Some(pc) {
+            return false;
+        }
+
+        // FX0A waits for a key by not advancing, that is not a hang
+        if op & 0xF0FF == 0xF00A {
+            return false;
+        }
+        let jump_to_self = op & 0xF000 == 0x1000 && op & 0x0FFF == pc;
+        jump_to_self || self.repeats >= self.threshold
+    }
+
+    pub fn ignore_current(&mut self) {
+        self.ignored_pc = Some(self.last_pc);
+    }
+}
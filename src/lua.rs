@@ -0,0 +1,223 @@
+// Lua scripts, in builds with the lua feature: --script <file.lua> runs the file once at start, and
+// the functions it registers on the chip8 table are called as the rom runs, for cheats, bots,
+// automated tests and overlays. A script that fails pauses the emulator and prints the error.
+//
+// Hooks:
+//   chip8.on_frame(f)                f() after every 60 Hz frame
+//   chip8.on_instruction(f)          f(pc, opcode) before every instruction, slows emulation down
+//   chip8.on_address(address, f)     f(pc, opcode) before every instruction at address
+// State:
+//   chip8.v(n), chip8.set_v(n, value)          registers V0-VF
+//   chip8.i(), chip8.set_i(value)               also pc/set_pc, delay/set_delay, sound/set_sound
+//   chip8.peek(address), chip8.poke(address, value)
+//   chip8.pixel(x, y)                           true when the display pixel is lit
+//   chip8.key(k), chip8.press(k, pressed)       read and set keypad key 0-15
+//   chip8.frame()                               60 Hz frames since power on
+//   chip8.pause([message]), chip8.command(line) pause, or run a debugger console command
+// Overlay, in display pixels, drawn over the game until the end of the next frame so on_frame
+// hooks redraw it each time:
+//   chip8.text(x, y, text, [color])             color as RRGGBB, white by default
+//   chip8.rect(x, y, width, height, [color])
+//
+// e.g. infinite lives and a counter:
+//   chip8.on_frame(function()
+//     chip8.set_v(3, 9)
+//     chip8.text(1, 1, "frame " .. chip8.frame())
+//   end)
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::rc::Rc;
+
+use mlua::{Function, Lua, Table};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+use crate::chip8::VM;
+use crate::command;
+use crate::palette::parse_color;
+use crate::text::draw_text;
+
+pub enum Shape {
+    Text { x: i32, y: i32, text: String, color: [u8; 3] },
+    Rect { x: i32, y: i32, width: u32, height: u32, color: [u8; 3] },
+}
+
+#[derive(Default)]
+struct Hooks {
+    frame: Vec<Function>,
+    instruction: Vec<Function>,
+    addresses: HashMap<u16, Vec<Function>>,
+}
+
+pub struct LuaScript {
+    lua: Lua,
+    hooks: Rc<RefCell<Hooks>>,
+}
+
+impl LuaScript {
+    pub fn load(path: &str, vm: &mut VM) -> Result<Self, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("Error loading script {}, {}", path, e))?;
+        let lua = Lua::new();
+        let hooks = Rc::new(RefCell::new(Hooks::default()));
+        register_hooks(&lua, &hooks).map_err(|e| e.to_string())?;
+        let script = Self { lua, hooks };
+        script
+            .with_vm(vm, |lua| lua.load(&source).set_name(format!("@{}", path)).exec())
+            .map_err(|e| format!("Error in script {}, {}", path, e))?;
+        Ok(script)
+    }
+
+    pub fn before_instruction(&self, vm: &mut VM) {
+        let hooks = self.hooks.borrow();
+        let mut due = hooks.instruction.clone();
+        due.extend(hooks.addresses.get(&vm.pc).into_iter().flatten().cloned());
+        drop(hooks);
+        if due.is_empty() {
+            return;
+        }
+        let (pc, op) = (vm.pc, vm.current_op());
+        self.call(vm, &due, (pc, op));
+    }
+
+    pub fn after_frame(&self, vm: &mut VM) {
+        vm.overlay.clear();
+        let due = self.hooks.borrow().frame.clone();
+        if !due.is_empty() {
+            self.call(vm, &due, ());
+        }
+    }
+
+    fn call(&self, vm: &mut VM, hooks: &[Function], args: impl mlua::IntoLuaMulti + Clone) {
+        let result = self.with_vm(vm, |_| hooks.iter().try_for_each(|hook| hook.call::<()>(args.clone())));
+        if let Err(e) = result {
+            eprintln!("Lua error, {}", e);
+            vm.paused = true;
+            vm.pause_message = Some("LUA ERROR, SEE THE TERMINAL".to_string());
+        }
+    }
+
+    // Run `f` with the chip8 table's state functions working on `vm`. They're only valid while it
+    // runs, Lua can't hold on to the VM.
+    fn with_vm<R>(&self, vm: &mut VM, f: impl FnOnce(&Lua) -> mlua::Result<R>) -> mlua::Result<R> {
+        let vm = RefCell::new(vm);
+        let vm = &vm;
+        let chip8: Table = self.lua.globals().get("chip8")?;
+        self.lua.scope(|scope| {
+            let register = |n: usize| if n < 16 { Ok(n) } else { Err(mlua::Error::runtime(format!("no register V{}", n))) };
+            // Setters clear the rewind history, going back can't replay a script's edits
+            chip8.set("v", scope.create_function(move |_, n: usize| Ok(vm.borrow().v[register(n)?]))?)?;
+            chip8.set("set_v", scope.create_function(move |_, (n, value): (usize, u8)| {
+                let mut vm = vm.borrow_mut();
+                vm.v[register(n)?] = value;
+                vm.rewind.clear();
+                Ok(())
+            })?)?;
+            chip8.set("i", scope.create_function(move |_, ()| Ok(vm.borrow().i))?)?;
+            chip8.set("set_i", scope.create_function(move |_, value: u16| {
+                let mut vm = vm.borrow_mut();
+                vm.i = value & 0xFFF;
+                vm.rewind.clear();
+                Ok(())
+            })?)?;
+            chip8.set("pc", scope.create_function(move |_, ()| Ok(vm.borrow().pc))?)?;
+            chip8.set("set_pc", scope.create_function(move |_, value: u16| {
+                let mut vm = vm.borrow_mut();
+                vm.pc = value.min(0xFFE);
+                vm.rewind.clear();
+                Ok(())
+            })?)?;
+            chip8.set("delay", scope.create_function(move |_, ()| Ok(vm.borrow().delay))?)?;
+            chip8.set("set_delay", scope.create_function(move |_, value: u8| {
+                let mut vm = vm.borrow_mut();
+                vm.delay = value;
+                vm.rewind.clear();
+                Ok(())
+            })?)?;
+            chip8.set("sound", scope.create_function(move |_, ()| Ok(vm.borrow().sound))?)?;
+            chip8.set("set_sound", scope.create_function(move |_, value: u8| {
+                let mut vm = vm.borrow_mut();
+                vm.sound = value;
+                vm.rewind.clear();
+                Ok(())
+            })?)?;
+            chip8.set("peek", scope.create_function(move |_, address: usize| Ok(vm.borrow().memory[address % 4096]))?)?;
+            chip8.set("poke", scope.create_function(move |_, (address, value): (usize, u8)| {
+                let mut vm = vm.borrow_mut();
+                vm.memory[address % 4096] = value;
+                vm.rewind.clear();
+                Ok(())
+            })?)?;
+            chip8.set("pixel", scope.create_function(move |_, (x, y): (usize, usize)| Ok(vm.borrow().display[y % 32 * 64 + x % 64] != 0))?)?;
+            chip8.set("key", scope.create_function(move |_, key: usize| Ok(vm.borrow().keypad[key % 16]))?)?;
+            chip8.set("press", scope.create_function(move |_, (key, pressed): (usize, bool)| {
+                vm.borrow_mut().keypad[key % 16] = pressed;
+                Ok(())
+            })?)?;
+            chip8.set("frame", scope.create_function(move |_, ()| Ok(vm.borrow().frame))?)?;
+            chip8.set("pause", scope.create_function(move |_, message: Option<String>| {
+                let mut vm = vm.borrow_mut();
+                vm.paused = true;
+                vm.pause_message = Some(message.map_or("PAUSED BY SCRIPT".to_string(), |message| message.to_uppercase()));
+                Ok(())
+            })?)?;
+            chip8.set("command", scope.create_function(move |_, line: String| {
+                command::execute(&mut vm.borrow_mut(), &line).map_err(mlua::Error::runtime)
+            })?)?;
+            chip8.set("text", scope.create_function(move |_, (x, y, text, color): (i32, i32, String, Option<String>)| {
+                let color = parse_shape_color(color)?;
+                vm.borrow_mut().overlay.push(Shape::Text { x, y, text, color });
+                Ok(())
+            })?)?;
+            chip8.set("rect", scope.create_function(move |_, (x, y, width, height, color): (i32, i32, u32, u32, Option<String>)| {
+                let color = parse_shape_color(color)?;
+                vm.borrow_mut().overlay.push(Shape::Rect { x, y, width, height, color });
+                Ok(())
+            })?)?;
+            f(&self.lua)
+        })
+    }
+}
+
+fn register_hooks(lua: &Lua, hooks: &Rc<RefCell<Hooks>>) -> mlua::Result<()> {
+    let chip8 = lua.create_table()?;
+    let frame = hooks.clone();
+    chip8.set("on_frame", lua.create_function(move |_, hook: Function| {
+        frame.borrow_mut().frame.push(hook);
+        Ok(())
+    })?)?;
+    let instruction = hooks.clone();
+    chip8.set("on_instruction", lua.create_function(move |_, hook: Function| {
+        instruction.borrow_mut().instruction.push(hook);
+        Ok(())
+    })?)?;
+    let addresses = hooks.clone();
+    chip8.set("on_address", lua.create_function(move |_, (address, hook): (u16, Function)| {
+        addresses.borrow_mut().addresses.entry(address).or_default().push(hook);
+        Ok(())
+    })?)?;
+    lua.globals().set("chip8", chip8)
+}
+
+fn parse_shape_color(color: Option<String>) -> mlua::Result<[u8; 3]> {
+    color.map_or(Ok([255, 255, 255]), |color| parse_color(&color).map_err(mlua::Error::runtime))
+}
+
+impl VM<'_> {
+    pub fn draw_overlay(&mut self, window_scale: u32) -> Result<(), String> {
+        let scale = window_scale as i32;
+        for shape in &self.overlay {
+            match shape {
+                Shape::Text { x, y, text, color: [r, g, b] } => {
+                    draw_text(&mut self.canvas, x * scale, y * scale, 2, &text.to_uppercase(), Color::RGB(*r, *g, *b))?
+                }
+                Shape::Rect { x, y, width, height, color: [r, g, b] } => {
+                    self.canvas.set_draw_color(Color::RGB(*r, *g, *b));
+                    self.canvas.fill_rect(Rect::new(x * scale, y * scale, width * window_scale, height * window_scale))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+// Keypad macros: Ctrl+Shift+M starts recording key presses and stops again, Ctrl+M plays them
+// back. Like a movie the macro is the keypad for each 60 Hz frame, so it keeps its timing at any
+// speed, and while it plays it replaces the keypad. One macro per rom is kept in the data
+// directory, keyed by the rom's content like save states.
+//
+// Macro files are plain text:
+//   chip8-macro 1
+//   <one line per frame: pressed keys as a 16 bit mask in hex, bit N = key N>
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::chip8::VM;
+use crate::savestate::data_directory;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MacroState {
+    Idle,
+    Recording,
+    // Frame to play next
+    Playing(usize),
+}
+
+pub struct InputMacro {
+    pub state: MacroState,
+    frames: Vec<u16>,
+}
+
+impl Default for InputMacro {
+    fn default() -> Self {
+        Self { state: MacroState::Idle, frames: Vec::new() }
+    }
+}
+
+impl InputMacro {
+    pub fn path(rom_hash: u64) -> PathBuf {
+        data_directory().join("macros").join(format!("{:016x}.txt", rom_hash))
+    }
+
+    // The rom's macro, empty if it has none or the file is unreadable
+    pub fn load(rom_hash: u64) -> Self {
+        let mut input_macro = Self::default();
+        let Ok(text) = fs::read_to_string(Self::path(rom_hash)) else { return input_macro };
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+        if lines.next() != Some("chip8-macro 1") {
+            return input_macro;
+        }
+        input_macro.frames = lines.map_while(|line| u16::from_str_radix(line, 16).ok()).collect();
+        input_macro
+    }
+
+    pub fn save(&self, rom_hash: u64) -> Result<(), String> {
+        let path = Self::path(rom_hash);
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|e| format!("Error creating {}, {}", directory.display(), e))?;
+        }
+        let mut text = String::from("chip8-macro 1\n");
+        for mask in &self.frames {
+            text.push_str(&format!("{:04X}\n", mask));
+        }
+        fs::write(&path, text).map_err(|e| format!("Error writing {}, {}", path.display(), e))
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn start_recording(&mut self) {
+        self.frames.clear();
+        self.state = MacroState::Recording;
+    }
+
+    // Drop the frames before the first press and after the last release, so the macro does
+    // something as soon as it is played
+    pub fn stop_recording(&mut self) {
+        let first = self.frames.iter().position(|mask| *mask != 0).unwrap_or(self.frames.len());
+        let last = self.frames.iter().rposition(|mask| *mask != 0).map_or(0, |last| last + 1);
+        self.frames = self.frames.get(first..last).unwrap_or_default().to_vec();
+        self.state = MacroState::Idle;
+    }
+}
+
+impl VM<'_> {
+    // Capture or apply the keypad for the next frame. Playing runs one frame past the end, which
+    // lets go of the keys the macro held.
+    pub fn next_macro_frame(&mut self) {
+        match self.input_macro.state {
+            MacroState::Idle => {}
+            MacroState::Recording => {
+                let mask = self.keypad.iter().enumerate().filter(|(_, pressed)| **pressed).fold(0u16, |mask, (key, _)| mask | 1 << key);
+                self.input_macro.frames.push(mask);
+            }
+            MacroState::Playing(position) => {
+                let mask = self.input_macro.frames.get(position).copied().unwrap_or(0);
+                for (key, pressed) in self.keypad.iter_mut().enumerate() {
+                    *pressed = mask & (1 << key) != 0;
+                }
+                self.input_macro.state = if position < self.input_macro.frames.len() { MacroState::Playing(position + 1) } else { MacroState::Idle };
+            }
+        }
+    }
+}
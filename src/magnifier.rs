@@ -0,0 +1,103 @@
+// Magnifier for inspecting single pixels while the rom runs, toggled with Ctrl+Z. The arrow keys
+// move the cursor (with Shift 8 pixels at a time), Page Up and Page Down change the magnification.
+// The region around the cursor is drawn large with a pixel grid in the corner away from it.
+
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+
+use crate::chip8::VM;
+use crate::text::{draw_text, text_width, GLYPH_HEIGHT};
+
+// Width of the magnified region in CHIP-8 pixels, the height is half of it
+const REGION_WIDTHS: [u32; 4] = [4, 8, 16, 32];
+
+pub struct Magnifier {
+    pub open: bool,
+    // Pixel under the cursor
+    pub x: u32,
+    pub y: u32,
+    // Index into REGION_WIDTHS
+    pub zoom: usize,
+}
+
+impl Magnifier {
+    pub fn new() -> Self {
+        Self { open: false, x: 32, y: 16, zoom: 1 }
+    }
+
+    // True if the key belonged to the magnifier
+    pub fn handle_key(&mut self, keycode: Keycode, keymod: Mod) -> bool {
+        let step = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) { 8 } else { 1 };
+        match keycode {
+            Keycode::Left => self.x = self.x.saturating_sub(step),
+            Keycode::Right => self.x = (self.x + step).min(63),
+            Keycode::Up => self.y = self.y.saturating_sub(step),
+            Keycode::Down => self.y = (self.y + step).min(31),
+            Keycode::PageUp => self.zoom = self.zoom.saturating_sub(1),
+            Keycode::PageDown => self.zoom = (self.zoom + 1).min(REGION_WIDTHS.len() - 1),
+            _ => return false,
+        }
+        true
+    }
+
+    // Displayed region around the cursor, kept inside the display
+    pub fn region(&self) -> Rect {
+        let width = REGION_WIDTHS[self.zoom];
+        let height = width / 2;
+        let left = self.x.saturating_sub(width / 2).min(64 - width);
+        let top = self.y.saturating_sub(height / 2).min(32 - height);
+        Rect::new(left as i32, top as i32, width, height)
+    }
+}
+
+impl Default for Magnifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VM<'_> {
+    pub fn draw_magnifier(&mut self, window_scale: u32) -> Result<(), String> {
+        let region = self.magnifier.region();
+        let scale = window_scale as i32;
+
+        // Outline the region on the display itself
+        self.canvas.set_draw_color(Color::RGB(255, 64, 64));
+        self.canvas.draw_rect(Rect::new(region.x() * scale - 1, region.y() * scale - 1, region.width() * window_scale + 2, region.height() * window_scale + 2))?;
+
+        // Half the display in size, in the corner horizontally away from the region
+        let panel = Rect::new(0, 0, 32 * window_scale, 16 * window_scale);
+        let cell = panel.width() / region.width();
+        let left = if region.x() + region.width() as i32 / 2 < 32 { (64 * window_scale - panel.width()) as i32 - 4 } else { 4 };
+        let top = (32 * window_scale - panel.height()) as i32 - 4;
+        let panel = Rect::new(left, top, region.width() * cell, region.height() * cell);
+
+        self.canvas.set_draw_color(Color::RGB(255, 64, 64));
+        self.canvas.fill_rect(Rect::new(panel.x() - 2, panel.y() - 2, panel.width() + 4, panel.height() + 4))?;
+        self.canvas.copy(self.display_texture.as_ref().unwrap(), Some(region), Some(panel))?;
+
+        if cell >= 4 {
+            self.canvas.set_draw_color(Color::RGB(96, 96, 96));
+            for column in 1..region.width() as i32 {
+                let x = panel.x() + column * cell as i32;
+                self.canvas.draw_line(Point::new(x, panel.top()), Point::new(x, panel.bottom() - 1))?;
+            }
+            for row in 1..region.height() as i32 {
+                let y = panel.y() + row * cell as i32;
+                self.canvas.draw_line(Point::new(panel.left(), y), Point::new(panel.right() - 1, y))?;
+            }
+        }
+
+        let (x, y) = (self.magnifier.x as i32, self.magnifier.y as i32);
+        self.canvas.set_draw_color(Color::RGB(255, 200, 0));
+        self.canvas.draw_rect(Rect::new(panel.x() + (x - region.x()) * cell as i32, panel.y() + (y - region.y()) * cell as i32, cell, cell))?;
+
+        let lit = self.display[(y * 64 + x) as usize] != 0;
+        let label = format!("X {:02} Y {:02} {}", x, y, if lit { "ON" } else { "OFF" });
+        let label_y = panel.y() - 2 - (GLYPH_HEIGHT + 4) as i32;
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.fill_rect(Rect::new(panel.x() - 2, label_y, text_width(&label, 1) + 4, GLYPH_HEIGHT + 4))?;
+        draw_text(&mut self.canvas, panel.x(), label_y + 2, 1, &label, Color::RGB(255, 200, 0))
+    }
+}
@@ -2,125 +2,121 @@
 // https://github.com/bradford-hamilton/chippy/blob/master/internal/chip8/instructions.go#L25
 // http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#Dxyn
 
-
-extern crate sdl2;
-use std::time::{Duration, Instant};
-
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-
-use crate::chip8::VM;
-
+use std::env;
+
+pub mod api;
+#[cfg(feature = "sdl")]
+pub mod app;
+#[cfg(feature = "sdl")]
+pub mod audio;
+#[cfg(feature = "cpal")]
+pub mod audio_cpal;
+#[cfg(feature = "sdl")]
+pub mod bezel;
+pub mod chat;
 pub mod chip8;
-
-const FONT_BITMAP: [u8; 80] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80  // F
-];
-
+pub mod command;
+#[cfg(feature = "sdl")]
+pub mod config;
+#[cfg(feature = "sdl")]
+pub mod console;
+pub mod coverage;
+pub mod crashdump;
+#[cfg(feature = "sdl")]
+pub mod crt;
+#[cfg(feature = "sdl")]
+pub mod debugger;
+#[cfg(feature = "egui")]
+pub mod debugwindow;
+pub mod disasm;
+pub mod frameblend;
+pub mod gdb;
+pub mod gif;
+#[cfg(not(any(feature = "sdl", feature = "winit")))]
+pub mod headless;
+#[cfg(feature = "sdl")]
+pub mod heatmap;
+#[cfg(feature = "sdl")]
+pub mod hotkeys;
+#[cfg(feature = "sdl")]
+pub mod input;
+pub mod inputscript;
+pub mod jsonstate;
+#[cfg(feature = "sdl")]
+pub mod keypad;
+pub mod keywait;
+pub mod lint;
+#[cfg(feature = "sdl")]
+pub mod hud;
+#[cfg(feature = "sdl")]
+pub mod layout;
+pub mod loopdetect;
+#[cfg(feature = "lua")]
+pub mod lua;
+pub mod macros;
+#[cfg(feature = "sdl")]
+pub mod magnifier;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "sdl")]
+pub mod memview;
+pub mod movie;
+pub mod octo;
+pub mod palette;
+pub mod phosphor;
+pub mod png;
+pub mod profile;
+#[cfg(feature = "sdl")]
+pub mod remap;
+#[cfg(feature = "sdl")]
+pub mod renderer;
+pub mod rewind;
+pub mod romsettings;
+pub mod rpl;
+pub mod savestate;
+pub mod screenshot;
+pub mod script;
+#[cfg(feature = "sdl")]
+pub mod slotpicker;
+pub mod smc;
+pub mod snapshot;
+#[cfg(all(feature = "winit", not(feature = "sdl")))]
+pub mod softwindow;
+#[cfg(feature = "sdl")]
+pub mod speed;
+pub mod stats;
+pub mod stream;
+pub mod symbols;
+#[cfg(feature = "sdl")]
+pub mod text;
+#[cfg(feature = "sdl")]
+pub mod title;
+#[cfg(feature = "sdl")]
+pub mod toolwindow;
+pub mod trace;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod video;
+pub mod watch;
+#[cfg(feature = "sdl")]
+pub mod wav;
+pub mod websocket;
 
 pub fn main() -> Result<(), String> {
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-    let window_scale = 10;
-    let window = video_subsystem.window("CHIP-8", 64 * window_scale, 32 * window_scale)
-        .position_centered()
-        .build()
-        .unwrap();
-
-    let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
-    canvas.set_draw_color(Color::RGB(255, 255, 255));
-    canvas.clear();
-    canvas.present();
-
-    let texture_creator = canvas.texture_creator();
-    let mut vm = VM::new(canvas, &texture_creator);
-    vm.initialize_texture()?;
-    vm.init_font_set();
-    vm.load_rom("D:\\Downloads\\IBM Logo.ch8");
-
-    let mut last_timer_update = Instant::now();
-    let timer_interval = Duration::from_secs_f64(1.0 / 60.0);
-    let emulation_interval = Duration::from_secs_f64(1.0 / 500.0);
-    let mut last_emulation_cycle = Instant::now();
-
-    // SDL event loop to keep the window open
-    let mut event_pump = sdl_context.event_pump()?;
-    'running: loop {
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. } => { break 'running }
-                Event::KeyDown { keycode, .. } => {
-                    if let Some(k) = keycode {
-                        println!("Key down: {}", k);
-                        update_keypad(&mut vm, k, true);
-                    }
-                }
-                Event::KeyUp { keycode, .. } => {
-                    if let Some(k) = keycode {
-                        println!("Key up: {}", k);
-                        update_keypad(&mut vm, k, false);
-                    }
-                }
-                _ => {}
-            }
-        }
-
-        let now = Instant::now();
-        if now.duration_since(last_emulation_cycle) >= emulation_interval {
-            vm.emulate_cycle();
-            if vm.drawflag { vm.draw_display(window_scale) }
-            last_emulation_cycle = now;
-        }
-
-        if now.duration_since(last_timer_update) >= timer_interval {
-            if vm.delay > 0 {
-                vm.delay -= 1;
-            }
-            last_timer_update = now;
-        }
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("check") => return lint::check_command(&args[1..]),
+        Some("stats") => return stats::stats_command(&args[1..]),
+        _ => {}
     }
-
-    Ok(())
-}
-
-fn update_keypad(vm: &mut VM, keycode: Keycode, pressed: bool) {
-    let key_mapping = match keycode {
-        Keycode::Num1 => Some(0x1),
-        Keycode::Num2 => Some(0x2),
-        Keycode::Num3 => Some(0x3),
-        Keycode::Num4 => Some(0xc),
-        Keycode::Q => Some(0x4),
-        Keycode::W => Some(0x5),
-        Keycode::E => Some(0x6),
-        Keycode::R => Some(0xd),
-        Keycode::A => Some(0x7),
-        Keycode::S => Some(0x8),
-        Keycode::D => Some(0x9),
-        Keycode::F => Some(0xe),
-        Keycode::Z => Some(0xa),
-        Keycode::X => Some(0x0),
-        Keycode::C => Some(0xb),
-        Keycode::V => Some(0xf),
-        _ => None,
-    };
-
-    if let Some(key) = key_mapping {
-        vm.keypad[key] = pressed;
+    #[cfg(feature = "tui")]
+    if args.iter().any(|arg| arg == "--tui") {
+        return tui::run(args);
     }
-}
\ No newline at end of file
+    #[cfg(feature = "sdl")]
+    return app::run(args);
+    #[cfg(all(feature = "winit", not(feature = "sdl")))]
+    return softwindow::run(args);
+    #[cfg(not(any(feature = "sdl", feature = "winit")))]
+    headless::run(args)
+}
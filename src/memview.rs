@@ -0,0 +1,131 @@
+// Scrollable hex view of the 4 KB memory, toggled with F2. Emulation is frozen while it is open.
+
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+use crate::chip8::VM;
+use crate::text::{draw_text, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+const BYTES_PER_ROW: usize = 8;
+const ROWS: usize = 4096 / BYTES_PER_ROW;
+const VISIBLE_ROWS: usize = 17;
+
+pub enum KeyResult {
+    Ignored,
+    Handled,
+    Command(String),
+}
+
+pub struct MemoryView {
+    pub open: bool,
+    pub top_row: usize,
+    // Command being typed after pressing Enter, e.g. "poke 0x300 0xFF"
+    pub input: Option<String>,
+    pub message: String,
+}
+
+impl MemoryView {
+    pub fn new() -> Self {
+        Self { open: false, top_row: 0, input: None, message: String::new() }
+    }
+
+    // Open the view scrolled so that `address` is on screen
+    pub fn open_at(&mut self, address: u16) {
+        self.open = true;
+        self.scroll_to(address);
+    }
+
+    pub fn scroll_to(&mut self, address: u16) {
+        let row = address as usize / BYTES_PER_ROW;
+        self.top_row = row.saturating_sub(VISIBLE_ROWS / 2).min(ROWS - VISIBLE_ROWS);
+    }
+
+    pub fn handle_text(&mut self, text: &str) {
+        if let Some(input) = self.input.as_mut() {
+            input.push_str(text);
+        }
+    }
+
+    pub fn handle_key(&mut self, keycode: Keycode) -> KeyResult {
+        if let Some(input) = self.input.as_mut() {
+            match keycode {
+                Keycode::Return | Keycode::KpEnter => return KeyResult::Command(self.input.take().unwrap()),
+                Keycode::Escape => self.input = None,
+                Keycode::Backspace => { input.pop(); }
+                _ => {}
+            }
+            return KeyResult::Handled;
+        }
+
+        let max_top = ROWS - VISIBLE_ROWS;
+        self.top_row = match keycode {
+            Keycode::Return | Keycode::KpEnter => {
+                self.input = Some(String::new());
+                return KeyResult::Handled;
+            }
+            Keycode::Up => self.top_row.saturating_sub(1),
+            Keycode::Down => (self.top_row + 1).min(max_top),
+            Keycode::PageUp => self.top_row.saturating_sub(VISIBLE_ROWS),
+            Keycode::PageDown => (self.top_row + VISIBLE_ROWS).min(max_top),
+            Keycode::Home => 0,
+            Keycode::End => max_top,
+            _ => return KeyResult::Ignored,
+        };
+        KeyResult::Handled
+    }
+}
+
+impl Default for MemoryView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VM<'_> {
+    pub fn draw_memory_view(&mut self) -> Result<(), String> {
+        let scale = 2;
+        let char_width = ((GLYPH_WIDTH + 1) * scale) as i32;
+        let line_height = ((GLYPH_HEIGHT + 1) * scale) as i32;
+        let (width, height) = self.canvas.output_size()?;
+
+        self.canvas.set_draw_color(Color::RGB(16, 16, 32));
+        self.canvas.fill_rect(Rect::new(0, 0, width, height))?;
+
+        let header = format!("MEMORY  PC {:03X}  I {:03X}  UP/DOWN PGUP/PGDN HOME/END", self.pc, self.i);
+        draw_text(&mut self.canvas, 4, 4, scale, &header, Color::RGB(255, 255, 255))?;
+
+        let top_row = self.memory_view.top_row;
+        for (line, row) in (top_row..top_row + VISIBLE_ROWS).enumerate() {
+            let y = 4 + (line as i32 + 1) * line_height;
+            let address = row * BYTES_PER_ROW;
+            draw_text(&mut self.canvas, 4, y, scale, &format!("{:03X}:", address), Color::RGB(128, 128, 160))?;
+
+            for column in 0..BYTES_PER_ROW {
+                let cell = address + column;
+                let x = 4 + (5 + column as i32 * 3) * char_width;
+                let highlight = if cell == self.pc as usize || cell == self.pc as usize + 1 {
+                    Some(Color::RGB(160, 32, 32))
+                } else if cell == self.i as usize {
+                    Some(Color::RGB(32, 64, 160))
+                } else {
+                    None
+                };
+
+                if let Some(color) = highlight {
+                    self.canvas.set_draw_color(color);
+                    self.canvas.fill_rect(Rect::new(x - scale as i32, y - scale as i32, (char_width * 2) as u32 + scale, line_height as u32))?;
+                }
+                draw_text(&mut self.canvas, x, y, scale, &format!("{:02X}", self.memory[cell]), Color::RGB(220, 220, 220))?;
+            }
+        }
+
+        let y = 4 + (VISIBLE_ROWS as i32 + 1) * line_height;
+        let (status, color) = match &self.memory_view.input {
+            Some(input) => (format!("> {}_", input), Color::RGB(255, 255, 255)),
+            None if !self.memory_view.message.is_empty() => (self.memory_view.message.clone(), Color::RGB(255, 200, 0)),
+            None => ("ENTER: COMMAND (SET V4 0X20, POKE 0X300 0XFF)".to_string(), Color::RGB(128, 128, 160)),
+        };
+        draw_text(&mut self.canvas, 4, y, scale, &status, color)
+    }
+}
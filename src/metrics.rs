@@ -0,0 +1,109 @@
+// Prometheus metrics, in builds with the metrics feature:
+//   cargo build --features metrics
+//   chip8-rust --metrics :9164 rom.ch8
+// serves http://127.0.0.1:9164/metrics for Prometheus to scrape, to watch a kiosk or a headless
+// stream server that runs for days:
+//   chip8_instructions_total         instructions executed
+//   chip8_frames_total               60 Hz frames emulated
+//   chip8_frames_rendered_total      frames drawn to the window, every emulated one in the headless build
+//   chip8_draw_calls_total           DXYN instructions executed
+//   chip8_instructions_per_second    over the last second or so
+//   chip8_audio_underruns_total      times the audio device ran dry waiting for samples
+//   chip8_paused                     1 while paused or halted
+// The counters keep counting across resets, rewinds and loaded states, as Prometheus expects.
+//
+// The address defaults to 127.0.0.1 as with --gdb.
+
+use std::fmt::Write;
+use std::net::TcpListener;
+use std::time::{Duration, Instant};
+
+use crate::chip8::VM;
+use crate::websocket::{self, Connection};
+
+// How often instructions per second is worked out
+const RATE_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct MetricsServer {
+    listener: TcpListener,
+    clients: Vec<Connection>,
+    // Counters, and the VM's values they were last brought up to date with
+    instructions: u64,
+    frames: u64,
+    last_cycles: u64,
+    last_frame: u64,
+    // Set by the frontend
+    pub audio_underruns: u64,
+    instructions_per_second: f64,
+    rate_start: (Instant, u64),
+}
+
+impl MetricsServer {
+    pub fn listen(address: &str) -> Result<Self, String> {
+        let (listener, address) = websocket::listen(address, "metrics server")?;
+        println!("Metrics on http://{}/metrics", address);
+        Ok(Self {
+            listener,
+            clients: Vec::new(),
+            instructions: 0,
+            frames: 0,
+            last_cycles: 0,
+            last_frame: 0,
+            audio_underruns: 0,
+            instructions_per_second: 0.0,
+            rate_start: (Instant::now(), 0),
+        })
+    }
+
+    // Bring the counters up to date and answer scrapes, every pass of the main loop
+    pub fn poll(&mut self, vm: &VM) {
+        self.update(vm);
+        let mut clients = std::mem::take(&mut self.clients);
+        clients.extend(websocket::accept(&self.listener));
+        for client in &mut clients {
+            client.read();
+            let Some(request) = client.request() else { continue };
+            match (request.method.as_str(), request.path.split('?').next().unwrap_or_default()) {
+                ("GET", "/metrics") => {
+                    let body = self.exposition(vm);
+                    client.respond("200 OK", "text/plain; version=0.0.4; charset=utf-8", body.as_bytes());
+                }
+                _ => client.respond("404 Not Found", "text/plain; charset=utf-8", b"metrics are at /metrics\n"),
+            }
+        }
+        clients.retain(|client| !client.finished());
+        self.clients = clients;
+    }
+
+    fn update(&mut self, vm: &VM) {
+        // A value below the last one was reset or rewound, so it only moves the baseline
+        let advance = |counter: &mut u64, last: &mut u64, now: u64| {
+            *counter += now.saturating_sub(*last);
+            *last = now;
+        };
+        advance(&mut self.instructions, &mut self.last_cycles, vm.cycles);
+        advance(&mut self.frames, &mut self.last_frame, vm.frame);
+
+        let (start, instructions) = self.rate_start;
+        let elapsed = start.elapsed();
+        if elapsed >= RATE_INTERVAL {
+            self.instructions_per_second = (self.instructions - instructions) as f64 / elapsed.as_secs_f64();
+            self.rate_start = (Instant::now(), self.instructions);
+        }
+    }
+
+    fn exposition(&self, vm: &VM) -> String {
+        let mut text = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+            writeln!(text, "# HELP {} {}\n# TYPE {} {}\n{} {}", name, help, name, kind, name, value).unwrap();
+        };
+        metric("chip8_instructions_total", "counter", "Instructions executed.", self.instructions.to_string());
+        metric("chip8_frames_total", "counter", "60 Hz frames emulated.", self.frames.to_string());
+        metric("chip8_frames_rendered_total", "counter", "Frames presented.", vm.frames_rendered.to_string());
+        metric("chip8_draw_calls_total", "counter", "DXYN instructions executed.", vm.draw_calls.to_string());
+        metric("chip8_instructions_per_second", "gauge", "Instructions executed per second.", format!("{:.1}", self.instructions_per_second));
+        metric("chip8_audio_underruns_total", "counter", "Times the audio device ran out of samples.", self.audio_underruns.to_string());
+        metric("chip8_paused", "gauge", "1 while emulation is paused or halted.", (vm.paused as u8).to_string());
+        text
+    }
+}
@@ -0,0 +1,166 @@
+// Input movies for frame exact replays. While a movie records or plays, emulation runs in lockstep:
+// every 60 Hz frame applies that frame's keypad input, runs exactly --ipf instructions and ticks
+// the timers, so a replay with the same rom, settings and random seed reproduces the run.
+//
+// Movie files are plain text:
+//   chip8-movie 1
+//   rom <fnv-1a hash of the rom image>
+//   ipf <instructions per frame>
+//   quirks <quirk settings: "default", or key-wait=<policy> for a --key-wait other than lowest>
+//   seed <random generator seed>
+//   state <hex of a save state>          only for movies that start from a save state
+//   frames
+//   <one line per frame: pressed keys as a 16 bit mask in hex, bit N = key N>
+
+use std::fs;
+
+use crate::chip8::VM;
+use crate::keywait::{describe_key_wait, parse_key_wait, KeyWaitPolicy};
+use crate::snapshot::Snapshot;
+
+// FX0A's policy is the only quirk that changes how a run goes
+fn quirks(policy: KeyWaitPolicy) -> String {
+    match policy {
+        KeyWaitPolicy::Lowest => "default".to_string(),
+        _ => format!("key-wait={}", describe_key_wait(policy)),
+    }
+}
+
+fn parse_quirks(text: &str) -> Option<KeyWaitPolicy> {
+    match text {
+        "default" => Some(KeyWaitPolicy::Lowest),
+        _ => parse_key_wait(text.strip_prefix("key-wait=")?).ok(),
+    }
+}
+
+#[derive(PartialEq)]
+pub enum MovieMode {
+    Recording,
+    Playing,
+}
+
+pub struct Movie {
+    pub mode: MovieMode,
+    pub path: String,
+    rom_hash: u64,
+    ipf: u32,
+    key_wait_policy: KeyWaitPolicy,
+    seed: u64,
+    start: Option<Snapshot>,
+    frames: Vec<u16>,
+    position: usize,
+    // Instructions still to run in the current frame, when a breakpoint or pause interrupted it
+    pub cycles_left: u32,
+}
+
+impl Movie {
+    // Start recording from the machine's current state, which is stored in the movie unless it is
+    // a fresh power on
+    pub fn record(path: &str, vm: &mut VM, ipf: u32) -> Self {
+        let seed = rand::random::<u64>();
+        let start = if vm.frame == 0 && vm.random_draws == 0 {
+            vm.reseed(seed, 0);
+            None
+        } else {
+            Some(vm.snapshot())
+        };
+        let seed = start.as_ref().map_or(seed, |snapshot| snapshot.seed);
+        Self { mode: MovieMode::Recording, path: path.to_string(), rom_hash: vm.rom_hash, ipf, key_wait_policy: vm.key_wait_policy, seed, start, frames: Vec::new(), position: 0, cycles_left: 0 }
+    }
+
+    // Load a movie and put the machine in its starting state
+    pub fn play(path: &str, vm: &mut VM, ipf: u32) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("Error loading movie {}, {}", path, e))?;
+        let mut lines = content.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#'));
+        if lines.next() != Some("chip8-movie 1") {
+            return Err(format!("{} is not a chip8-rust movie", path));
+        }
+
+        let mut movie = Self { mode: MovieMode::Playing, path: path.to_string(), rom_hash: 0, ipf, key_wait_policy: vm.key_wait_policy, seed: 0, start: None, frames: Vec::new(), position: 0, cycles_left: 0 };
+        for line in lines.by_ref() {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "rom" => movie.rom_hash = u64::from_str_radix(value, 16).map_err(|_| format!("{}: bad rom hash", path))?,
+                "ipf" => movie.ipf = value.parse().map_err(|_| format!("{}: bad ipf", path))?,
+                "quirks" => movie.key_wait_policy = parse_quirks(value).ok_or(format!("{}: unsupported quirks '{}'", path, value))?,
+                "seed" => movie.seed = value.parse().map_err(|_| format!("{}: bad seed", path))?,
+                "state" => movie.start = Some(Snapshot::from_bytes(&decode_hex(value).ok_or(format!("{}: bad state", path))?)?),
+                "frames" => break,
+                _ => return Err(format!("{}: unknown header '{}'", path, key)),
+            }
+        }
+        for line in lines {
+            movie.frames.push(u16::from_str_radix(line, 16).map_err(|_| format!("{}: bad frame '{}'", path, line))?);
+        }
+
+        if movie.rom_hash != vm.rom_hash {
+            println!("Warning: movie {} was recorded with a different rom", path);
+        }
+        if movie.ipf != ipf {
+            println!("Movie {} was recorded at {} instructions per frame, using that", path, movie.ipf);
+        }
+        if movie.key_wait_policy != vm.key_wait_policy {
+            println!("Movie {} was recorded with key wait policy {}, using that", path, describe_key_wait(movie.key_wait_policy));
+            vm.key_wait_policy = movie.key_wait_policy;
+        }
+        match &movie.start {
+            Some(snapshot) => vm.restore(snapshot),
+            None => vm.reseed(movie.seed, 0),
+        }
+        Ok(movie)
+    }
+
+    // Apply (playing) or capture (recording) the keypad for the next frame. Returns false once a
+    // movie being played has run out of frames.
+    pub fn next_frame(&mut self, vm: &mut VM) -> bool {
+        self.cycles_left = self.ipf;
+        match self.mode {
+            MovieMode::Recording => {
+                let mask = vm.keypad.iter().enumerate().filter(|(_, pressed)| **pressed).fold(0u16, |mask, (key, _)| mask | 1 << key);
+                self.frames.push(mask);
+                true
+            }
+            MovieMode::Playing => {
+                let Some(mask) = self.frames.get(self.position) else { return false };
+                for (key, pressed) in vm.keypad.iter_mut().enumerate() {
+                    *pressed = mask & (1 << key) != 0;
+                }
+                self.position += 1;
+                true
+            }
+        }
+    }
+
+    // Switch a playing movie to recording from the current frame on, dropping the frames after it
+    pub fn resume_recording(&mut self) {
+        self.frames.truncate(self.position);
+        self.mode = MovieMode::Recording;
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let mut content = format!("chip8-movie 1\nrom {:016x}\nipf {}\nquirks {}\nseed {}\n", self.rom_hash, self.ipf, quirks(self.key_wait_policy), self.seed);
+        if let Some(snapshot) = &self.start {
+            content.push_str(&format!("state {}\n", encode_hex(&snapshot.to_bytes())));
+        }
+        content.push_str("frames\n");
+        for mask in &self.frames {
+            content.push_str(&format!("{:04x}\n", mask));
+        }
+        fs::write(&self.path, content).map_err(|e| format!("Error writing movie {}, {}", self.path, e))
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
@@ -0,0 +1,608 @@
+// Octo (.8o) assembler
+// https://github.com/JohnEarnest/Octo/blob/gh-pages/docs/Manual.md
+//
+// Supports the core CHIP-8 subset of the language: labels, :const, :alias, :org,
+// register/index statements, if..then, if..begin..else..end, loop..again and while.
+// As in Octo, the program starts with a jump to the label main, so sprites and subroutines can
+// come before it.
+
+use std::collections::HashMap;
+use std::fmt;
+
+const START_ADDRESS: u16 = 0x200;
+
+#[derive(Debug)]
+pub struct AssembleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+struct Token {
+    text: String,
+    line: usize,
+}
+
+enum Condition {
+    EqualByte(u8, u8),
+    NotEqualByte(u8, u8),
+    EqualRegister(u8, u8),
+    NotEqualRegister(u8, u8),
+    Key(u8),
+    NotKey(u8),
+}
+
+enum Flow {
+    If { jump: u16, line: usize },
+    Else { jump: u16, line: usize },
+    Loop { start: u16, breaks: Vec<u16>, line: usize },
+}
+
+struct Assembler {
+    tokens: Vec<Token>,
+    pos: usize,
+    rom: Vec<u8>,
+    here: u16,
+    labels: HashMap<String, u16>,
+    consts: HashMap<String, u16>,
+    aliases: HashMap<String, u8>,
+    // (address of the opcode, label, line) for addresses resolved after the first pass
+    fixups: Vec<(u16, String, usize)>,
+    flow: Vec<Flow>,
+}
+
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    assemble_with_labels(source).map(|(rom, _)| rom)
+}
+
+// Assemble and also return the address of every label, for use as debugger symbols
+pub fn assemble_with_labels(source: &str) -> Result<(Vec<u8>, HashMap<String, u16>), AssembleError> {
+    let mut assembler = Assembler::new(source);
+    assembler.emit(0x1000, 1)?;
+    assembler.fixups.push((START_ADDRESS, "main".to_string(), 1));
+    while assembler.pos < assembler.tokens.len() {
+        assembler.statement()?;
+    }
+
+    if let Some(flow) = assembler.flow.last() {
+        let (line, what) = match flow {
+            Flow::If { line, .. } | Flow::Else { line, .. } => (*line, "begin without matching end"),
+            Flow::Loop { line, .. } => (*line, "loop without matching again"),
+        };
+        return Err(AssembleError { line, message: what.to_string() });
+    }
+    if !assembler.labels.contains_key("main") {
+        return Err(AssembleError { line: 1, message: "the program has no main label to start at".to_string() });
+    }
+
+    for (address, label, line) in std::mem::take(&mut assembler.fixups) {
+        let target = match assembler.labels.get(&label) {
+            Some(t) => *t,
+            None => return Err(AssembleError { line, message: format!("undefined label '{}'", label) }),
+        };
+        assembler.patch(address, target);
+    }
+
+    Ok((assembler.rom, assembler.labels))
+}
+
+impl Assembler {
+    fn new(source: &str) -> Self {
+        let mut tokens = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            let code = match line.find('#') {
+                Some(comment) => &line[..comment],
+                None => line,
+            };
+            for word in code.split_whitespace() {
+                tokens.push(Token { text: word.to_string(), line: index + 1 });
+            }
+        }
+
+        Self {
+            tokens,
+            pos: 0,
+            rom: Vec::new(),
+            here: START_ADDRESS,
+            labels: HashMap::new(),
+            consts: HashMap::new(),
+            aliases: HashMap::new(),
+            fixups: Vec::new(),
+            flow: Vec::new(),
+        }
+    }
+
+    fn error<T>(&self, line: usize, message: String) -> Result<T, AssembleError> {
+        Err(AssembleError { line, message })
+    }
+
+    fn line(&self) -> usize {
+        match self.tokens.get(self.pos) {
+            Some(t) => t.line,
+            None => self.tokens.last().map_or(1, |t| t.line),
+        }
+    }
+
+    fn next(&mut self) -> Result<(String, usize), AssembleError> {
+        match self.tokens.get(self.pos) {
+            Some(t) => {
+                self.pos += 1;
+                Ok((t.text.clone(), t.line))
+            }
+            None => self.error(self.line(), "unexpected end of file".to_string()),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), AssembleError> {
+        let (token, line) = self.next()?;
+        if token != expected {
+            return self.error(line, format!("expected '{}', found '{}'", expected, token));
+        }
+        Ok(())
+    }
+
+    fn emit_byte(&mut self, byte: u8, line: usize) -> Result<(), AssembleError> {
+        let offset = (self.here - START_ADDRESS) as usize;
+        if self.here as usize >= 4096 {
+            return self.error(line, "program does not fit in memory".to_string());
+        }
+        if offset >= self.rom.len() {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.here += 1;
+        Ok(())
+    }
+
+    fn emit(&mut self, op: u16, line: usize) -> Result<(), AssembleError> {
+        self.emit_byte((op >> 8) as u8, line)?;
+        self.emit_byte(op as u8, line)
+    }
+
+    // Overwrite the nnn part of the opcode at `address`
+    fn patch(&mut self, address: u16, target: u16) {
+        let offset = (address - START_ADDRESS) as usize;
+        self.rom[offset] = (self.rom[offset] & 0xF0) | ((target >> 8) as u8 & 0x0F);
+        self.rom[offset + 1] = target as u8;
+    }
+
+    fn register(&self, token: &str) -> Option<u8> {
+        if let Some(r) = self.aliases.get(token) {
+            return Some(*r);
+        }
+        let lower = token.to_ascii_lowercase();
+        if lower.len() == 2 && lower.starts_with('v') {
+            return u8::from_str_radix(&lower[1..], 16).ok();
+        }
+        None
+    }
+
+    fn expect_register(&mut self) -> Result<u8, AssembleError> {
+        let (token, line) = self.next()?;
+        match self.register(&token) {
+            Some(r) => Ok(r),
+            None => self.error(line, format!("expected a register, found '{}'", token)),
+        }
+    }
+
+    fn number(&self, token: &str) -> Option<i32> {
+        if let Some(c) = self.consts.get(token) {
+            return Some(*c as i32);
+        }
+        let (negative, digits) = match token.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, token),
+        };
+        let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+            i32::from_str_radix(hex, 16).ok()?
+        } else if let Some(bin) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+            i32::from_str_radix(bin, 2).ok()?
+        } else {
+            digits.parse::<i32>().ok()?
+        };
+        Some(if negative { -value } else { value })
+    }
+
+    fn expect_byte(&mut self) -> Result<u8, AssembleError> {
+        let (token, line) = self.next()?;
+        match self.number(&token) {
+            Some(n) if (-128..=255).contains(&n) => Ok(n as u8),
+            Some(n) => self.error(line, format!("value {} does not fit in a byte", n)),
+            None => self.error(line, format!("expected a number, found '{}'", token)),
+        }
+    }
+
+    fn expect_nibble(&mut self) -> Result<u8, AssembleError> {
+        let (token, line) = self.next()?;
+        match self.number(&token) {
+            Some(n) if (0..=15).contains(&n) => Ok(n as u8),
+            _ => self.error(line, format!("expected a number between 0 and 15, found '{}'", token)),
+        }
+    }
+
+    // Emit an opcode whose low 12 bits are an address, which may be a forward reference
+    fn emit_address(&mut self, op: u16) -> Result<(), AssembleError> {
+        let (token, line) = self.next()?;
+        if let Some(n) = self.number(&token) {
+            if !(0..=0xFFF).contains(&n) {
+                return self.error(line, format!("address {:#x} is out of range", n));
+            }
+            return self.emit(op | n as u16, line);
+        }
+        if let Some(address) = self.labels.get(&token) {
+            let address = *address;
+            return self.emit(op | address, line);
+        }
+        if !is_identifier(&token) {
+            return self.error(line, format!("expected an address, found '{}'", token));
+        }
+        self.fixups.push((self.here, token, line));
+        self.emit(op, line)
+    }
+
+    fn statement(&mut self) -> Result<(), AssembleError> {
+        let (token, line) = self.next()?;
+
+        if let Some(x) = self.register(&token) {
+            return self.register_statement(x, line);
+        }
+        if let Some(n) = self.number(&token) {
+            if !(-128..=255).contains(&n) {
+                return self.error(line, format!("value {} does not fit in a byte", n));
+            }
+            return self.emit_byte(n as u8, line);
+        }
+
+        match token.as_str() {
+            ":" => {
+                let (name, name_line) = self.next()?;
+                if !is_identifier(&name) {
+                    return self.error(name_line, format!("invalid label name '{}'", name));
+                }
+                if self.labels.insert(name.clone(), self.here).is_some() {
+                    return self.error(name_line, format!("label '{}' is defined more than once", name));
+                }
+                Ok(())
+            }
+            ":const" => {
+                let (name, _) = self.next()?;
+                let (value, value_line) = self.next()?;
+                match self.number(&value) {
+                    Some(n) if (0..=0xFFFF).contains(&n) => {
+                        self.consts.insert(name, n as u16);
+                        Ok(())
+                    }
+                    _ => self.error(value_line, format!("expected a constant value, found '{}'", value)),
+                }
+            }
+            ":alias" => {
+                let (name, _) = self.next()?;
+                let r = self.expect_register()?;
+                self.aliases.insert(name, r);
+                Ok(())
+            }
+            ":org" => {
+                let (value, value_line) = self.next()?;
+                match self.number(&value) {
+                    Some(n) if (START_ADDRESS as i32..0x1000).contains(&n) => {
+                        self.here = n as u16;
+                        Ok(())
+                    }
+                    _ => self.error(value_line, format!("invalid :org address '{}'", value)),
+                }
+            }
+            ":byte" => {
+                let b = self.expect_byte()?;
+                self.emit_byte(b, line)
+            }
+            ":call" => self.emit_address(0x2000),
+            "clear" => self.emit(0x00E0, line),
+            "return" | ";" => self.emit(0x00EE, line),
+            "jump" => self.emit_address(0x1000),
+            "jump0" => self.emit_address(0xB000),
+            "bcd" => {
+                let x = self.expect_register()?;
+                self.emit(0xF033 | (x as u16) << 8, line)
+            }
+            "save" => {
+                let x = self.expect_register()?;
+                self.emit(0xF055 | (x as u16) << 8, line)
+            }
+            "load" => {
+                let x = self.expect_register()?;
+                self.emit(0xF065 | (x as u16) << 8, line)
+            }
+            "saveflags" => {
+                let x = self.expect_register()?;
+                self.emit(0xF075 | (x as u16) << 8, line)
+            }
+            "loadflags" => {
+                let x = self.expect_register()?;
+                self.emit(0xF085 | (x as u16) << 8, line)
+            }
+            "sprite" => {
+                let x = self.expect_register()?;
+                let y = self.expect_register()?;
+                let n = self.expect_nibble()?;
+                self.emit(0xD000 | (x as u16) << 8 | (y as u16) << 4 | n as u16, line)
+            }
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let x = self.expect_register()?;
+                let op = if token == "delay" { 0xF015 } else { 0xF018 };
+                self.emit(op | (x as u16) << 8, line)
+            }
+            "i" => self.index_statement(line),
+            "if" => self.if_statement(),
+            "else" => match self.flow.pop() {
+                Some(Flow::If { jump, .. }) => {
+                    let else_jump = self.here;
+                    self.emit(0x1000, line)?;
+                    self.patch(jump, self.here);
+                    self.flow.push(Flow::Else { jump: else_jump, line });
+                    Ok(())
+                }
+                _ => self.error(line, "else without matching begin".to_string()),
+            },
+            "end" => match self.flow.pop() {
+                Some(Flow::If { jump, .. }) | Some(Flow::Else { jump, .. }) => {
+                    self.patch(jump, self.here);
+                    Ok(())
+                }
+                _ => self.error(line, "end without matching begin".to_string()),
+            },
+            "loop" => {
+                self.flow.push(Flow::Loop { start: self.here, breaks: Vec::new(), line });
+                Ok(())
+            }
+            "while" => {
+                let condition = self.condition()?;
+                self.emit(skip_if_true(&condition), line)?;
+                let jump = self.here;
+                self.emit(0x1000, line)?;
+                match self.flow.iter_mut().rev().find(|f| matches!(f, Flow::Loop { .. })) {
+                    Some(Flow::Loop { breaks, .. }) => {
+                        breaks.push(jump);
+                        Ok(())
+                    }
+                    _ => self.error(line, "while outside of a loop".to_string()),
+                }
+            }
+            "again" => match self.flow.pop() {
+                Some(Flow::Loop { start, breaks, .. }) => {
+                    self.emit(0x1000 | start, line)?;
+                    for jump in breaks {
+                        self.patch(jump, self.here);
+                    }
+                    Ok(())
+                }
+                _ => self.error(line, "again without matching loop".to_string()),
+            },
+            _ if is_identifier(&token) => {
+                // A bare label name is a subroutine call
+                self.pos -= 1;
+                self.emit_address(0x2000)
+            }
+            _ => self.error(line, format!("unknown or unsupported statement '{}'", token)),
+        }
+    }
+
+    fn register_statement(&mut self, x: u8, line: usize) -> Result<(), AssembleError> {
+        let (operator, operator_line) = self.next()?;
+        let x_op = (x as u16) << 8;
+        let (operand, operand_line) = self.next()?;
+
+        if let Some(y) = self.register(&operand) {
+            let y_op = (y as u16) << 4;
+            let low = match operator.as_str() {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xE,
+                _ => return self.error(operator_line, format!("unknown operator '{}'", operator)),
+            };
+            return self.emit(0x8000 | x_op | y_op | low, line);
+        }
+
+        match (operator.as_str(), operand.as_str()) {
+            (":=", "delay") => self.emit(0xF007 | x_op, line),
+            (":=", "key") => self.emit(0xF00A | x_op, line),
+            (":=", "random") => {
+                let mask = self.expect_byte()?;
+                self.emit(0xC000 | x_op | mask as u16, line)
+            }
+            (":=" | "+=" | "-=", _) => {
+                let n = match self.number(&operand) {
+                    Some(n) if (-128..=255).contains(&n) => n as u8,
+                    _ => return self.error(operand_line, format!("expected a register or byte, found '{}'", operand)),
+                };
+                match operator.as_str() {
+                    ":=" => self.emit(0x6000 | x_op | n as u16, line),
+                    "+=" => self.emit(0x7000 | x_op | n as u16, line),
+                    _ => self.emit(0x7000 | x_op | n.wrapping_neg() as u16, line),
+                }
+            }
+            _ => self.error(operand_line, format!("unsupported operand '{}' for '{}'", operand, operator)),
+        }
+    }
+
+    fn index_statement(&mut self, line: usize) -> Result<(), AssembleError> {
+        let (operator, operator_line) = self.next()?;
+        match operator.as_str() {
+            ":=" => {
+                if self.peek() == Some("hex") {
+                    self.pos += 1;
+                    let x = self.expect_register()?;
+                    return self.emit(0xF029 | (x as u16) << 8, line);
+                }
+                self.emit_address(0xA000)
+            }
+            "+=" => {
+                let x = self.expect_register()?;
+                self.emit(0xF01E | (x as u16) << 8, line)
+            }
+            _ => self.error(operator_line, format!("unknown operator '{}' for i", operator)),
+        }
+    }
+
+    fn if_statement(&mut self) -> Result<(), AssembleError> {
+        let condition = self.condition()?;
+        let (keyword, line) = self.next()?;
+        match keyword.as_str() {
+            "then" => {
+                self.emit(skip_if_false(&condition), line)?;
+                if self.pos >= self.tokens.len() {
+                    return self.error(line, "expected a statement after 'then'".to_string());
+                }
+                self.statement()
+            }
+            "begin" => {
+                self.emit(skip_if_true(&condition), line)?;
+                let jump = self.here;
+                self.emit(0x1000, line)?;
+                self.flow.push(Flow::If { jump, line });
+                Ok(())
+            }
+            _ => self.error(line, format!("expected 'then' or 'begin', found '{}'", keyword)),
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition, AssembleError> {
+        let x = self.expect_register()?;
+        let (operator, operator_line) = self.next()?;
+        match operator.as_str() {
+            "key" => return Ok(Condition::Key(x)),
+            "-key" => return Ok(Condition::NotKey(x)),
+            "==" | "!=" => {}
+            _ => return self.error(operator_line, format!("unsupported comparison '{}'", operator)),
+        }
+
+        let (operand, operand_line) = self.next()?;
+        let equal = operator == "==";
+        if let Some(y) = self.register(&operand) {
+            return Ok(if equal { Condition::EqualRegister(x, y) } else { Condition::NotEqualRegister(x, y) });
+        }
+        match self.number(&operand) {
+            Some(n) if (-128..=255).contains(&n) => {
+                Ok(if equal { Condition::EqualByte(x, n as u8) } else { Condition::NotEqualByte(x, n as u8) })
+            }
+            _ => self.error(operand_line, format!("expected a register or byte, found '{}'", operand)),
+        }
+    }
+}
+
+// Instruction that skips the next one when the condition holds
+fn skip_if_true(condition: &Condition) -> u16 {
+    match *condition {
+        Condition::EqualByte(x, n) => 0x3000 | (x as u16) << 8 | n as u16,
+        Condition::NotEqualByte(x, n) => 0x4000 | (x as u16) << 8 | n as u16,
+        Condition::EqualRegister(x, y) => 0x5000 | (x as u16) << 8 | (y as u16) << 4,
+        Condition::NotEqualRegister(x, y) => 0x9000 | (x as u16) << 8 | (y as u16) << 4,
+        Condition::Key(x) => 0xE09E | (x as u16) << 8,
+        Condition::NotKey(x) => 0xE0A1 | (x as u16) << 8,
+    }
+}
+
+// Instruction that skips the next one when the condition does not hold
+fn skip_if_false(condition: &Condition) -> u16 {
+    match *condition {
+        Condition::EqualByte(x, n) => skip_if_true(&Condition::NotEqualByte(x, n)),
+        Condition::NotEqualByte(x, n) => skip_if_true(&Condition::EqualByte(x, n)),
+        Condition::EqualRegister(x, y) => skip_if_true(&Condition::NotEqualRegister(x, y)),
+        Condition::NotEqualRegister(x, y) => skip_if_true(&Condition::EqualRegister(x, y)),
+        Condition::Key(x) => skip_if_true(&Condition::NotKey(x)),
+        Condition::NotKey(x) => skip_if_true(&Condition::Key(x)),
+    }
+}
+
+fn is_identifier(token: &str) -> bool {
+    let mut chars = token.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(rom: &[u8]) -> Vec<u16> {
+        rom.chunks(2).map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)])).collect()
+    }
+
+    #[test]
+    fn starts_with_jump_to_main() {
+        let rom = assemble(": main\n  clear\n  v0 := 5\n").unwrap();
+        assert_eq!(words(&rom), [0x1202, 0x00E0, 0x6005]);
+    }
+
+    #[test]
+    fn data_before_main_is_jumped_over() {
+        let source = ": dot\n  0x80\n: main\n  i := dot\n  sprite v0 v0 1\n";
+        let (rom, labels) = assemble_with_labels(source).unwrap();
+        assert_eq!(labels["dot"], 0x202);
+        assert_eq!(labels["main"], 0x203);
+        assert_eq!(rom, [0x12, 0x03, 0x80, 0xA2, 0x02, 0xD0, 0x01]);
+    }
+
+    #[test]
+    fn missing_main_is_an_error() {
+        let error = assemble(": start\n  clear\n").unwrap_err();
+        assert_eq!(error.line, 1);
+        assert!(error.message.contains("main"));
+    }
+
+    #[test]
+    fn subroutine_before_main() {
+        let rom = assemble(": blank\n  clear\n;\n: main\n  blank\n").unwrap();
+        assert_eq!(words(&rom), [0x1206, 0x00E0, 0x00EE, 0x2202]);
+    }
+
+    #[test]
+    fn if_then_skips_when_false() {
+        let rom = assemble(": main\n  if v1 == 3 then v2 := 1\n").unwrap();
+        assert_eq!(words(&rom), [0x1202, 0x4103, 0x6201]);
+    }
+
+    #[test]
+    fn if_begin_else_end() {
+        let rom = assemble(": main\n  if v0 key begin\n    v1 := 1\n  else\n    v1 := 2\n  end\n").unwrap();
+        assert_eq!(words(&rom), [0x1202, 0xE09E, 0x120A, 0x6101, 0x120C, 0x6102]);
+    }
+
+    #[test]
+    fn loop_with_while() {
+        let rom = assemble(": main\n  loop\n    while v0 != 10\n    v0 += 1\n  again\n").unwrap();
+        assert_eq!(words(&rom), [0x1202, 0x400A, 0x120A, 0x7001, 0x1202]);
+    }
+
+    #[test]
+    fn const_alias_and_forward_label() {
+        let rom = assemble(":const speed 4\n:alias x v3\n: main\n  x += speed\n  jump done\n: done\n  jump done\n").unwrap();
+        assert_eq!(words(&rom), [0x1202, 0x7304, 0x1206, 0x1206]);
+    }
+
+    #[test]
+    fn errors_report_their_line() {
+        let error = assemble(": main\n  clear\n  jump nowhere\n").unwrap_err();
+        assert_eq!((error.line, error.message.as_str()), (3, "undefined label 'nowhere'"));
+        assert_eq!(assemble(": main\n  v0 := 300\n").unwrap_err().line, 2);
+        assert_eq!(assemble(": main\n  loop\n").unwrap_err().message, "loop without matching again");
+    }
+}
@@ -0,0 +1,97 @@
+// Display colours: named presets for --palette and the Ctrl+P hotkey, or a custom pair of colours.
+// A palette is [background, foreground], indexed by the pixel value. --contrast and --gamma adjust
+// it when drawing, so Ctrl+P still finds the preset it started from.
+
+use crate::chip8::VM;
+
+pub type Palette = [[u8; 3]; 2];
+
+pub const PRESETS: [(&str, Palette); 8] = [
+    ("classic", [[0x00, 0x00, 0x00], [0xFF, 0xFF, 0xFF]]),
+    ("amber", [[0x1A, 0x0F, 0x00], [0xFF, 0xB0, 0x00]]),
+    ("green", [[0x00, 0x14, 0x00], [0x33, 0xFF, 0x33]]),
+    ("gameboy", [[0x9B, 0xBC, 0x0F], [0x0F, 0x38, 0x0F]]),
+    ("octo", [[0x99, 0x66, 0x00], [0xFF, 0xCC, 0x00]]),
+    ("paper", [[0xF0, 0xEA, 0xD6], [0x22, 0x22, 0x22]]),
+    // Unlit grey-green LCD segments with dark ones on, like pocket calculators and early handhelds
+    ("lcd", [[0xB4, 0xC4, 0x9C], [0x28, 0x34, 0x20]]),
+    // Orange neon gas plasma panels of portable computers
+    ("plasma", [[0x24, 0x08, 0x00], [0xFF, 0x6A, 0x10]]),
+];
+
+// Contrast and gamma applied on top of the palette when the display is turned into RGB
+#[derive(Clone, Copy, PartialEq)]
+pub struct Adjustment {
+    // Percent of the palette's own difference between background and foreground, 100 leaves it
+    pub contrast: u32,
+    pub gamma: f32,
+}
+
+impl Default for Adjustment {
+    fn default() -> Self {
+        Self { contrast: 100, gamma: 1.0 }
+    }
+}
+
+impl Adjustment {
+    pub fn apply(&self, palette: &Palette) -> Palette {
+        if *self == Self::default() {
+            return *palette;
+        }
+        let mut adjusted = *palette;
+        for channel in 0..3 {
+            let (background, foreground) = (palette[0][channel] as f32, palette[1][channel] as f32);
+            let middle = (background + foreground) / 2.0;
+            for (index, value) in [background, foreground].into_iter().enumerate() {
+                let stretched = (middle + (value - middle) * self.contrast as f32 / 100.0).clamp(0.0, 255.0);
+                adjusted[index][channel] = (255.0 * (stretched / 255.0).powf(1.0 / self.gamma)).round() as u8;
+            }
+        }
+        adjusted
+    }
+}
+
+pub fn parse_contrast(text: &str) -> Result<u32, String> {
+    match text.parse() {
+        Ok(contrast) if contrast <= 200 => Ok(contrast),
+        _ => Err(format!("invalid contrast {}, expected 0-200", text)),
+    }
+}
+
+pub fn parse_gamma(text: &str) -> Result<f32, String> {
+    match text.parse::<f32>() {
+        Ok(gamma) if (0.2..=5.0).contains(&gamma) => Ok(gamma),
+        _ => Err(format!("invalid gamma {}, expected 0.2-5.0", text)),
+    }
+}
+
+// A preset name, or a custom "foreground,background" pair of RRGGBB colours
+pub fn parse_palette(text: &str) -> Result<Palette, String> {
+    if let Some((_, palette)) = PRESETS.iter().find(|(name, _)| *name == text) {
+        return Ok(*palette);
+    }
+    let Some((foreground, background)) = text.split_once(',') else {
+        let names: Vec<&str> = PRESETS.iter().map(|(name, _)| *name).collect();
+        return Err(format!("unknown palette {}, expected one of {} or foreground,background as RRGGBB", text, names.join(", ")));
+    };
+    Ok([parse_color(background)?, parse_color(foreground)?])
+}
+
+pub fn parse_color(text: &str) -> Result<[u8; 3], String> {
+    let hex = text.trim().trim_start_matches('#');
+    let value = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6).ok_or(format!("invalid colour {}, expected RRGGBB", text))?;
+    Ok([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+// Preset after the one matching `palette`, the first preset for custom colours
+pub fn next_preset(palette: &Palette) -> (&'static str, Palette) {
+    let index = PRESETS.iter().position(|(_, preset)| preset == palette).map_or(0, |index| (index + 1) % PRESETS.len());
+    PRESETS[index]
+}
+
+impl VM<'_> {
+    // Colours the display is actually drawn with
+    pub fn display_palette(&self) -> Palette {
+        self.adjustment.apply(&self.palette)
+    }
+}
@@ -0,0 +1,39 @@
+// Phosphor decay: pixels that turn off fade out over a few frames instead of vanishing, which hides
+// most of the flicker from games erasing and redrawing sprites with XOR. Lit pixels are at full
+// brightness straight away.
+
+use std::time::Instant;
+
+use crate::palette::Palette;
+
+pub struct Phosphor {
+    // Brightness of each pixel, 0 to 1
+    intensity: [f32; 64 * 32],
+    // How long a pixel takes to fade from full brightness to off, in seconds
+    decay: f32,
+    last_update: Instant,
+}
+
+impl Phosphor {
+    pub fn new(frames: u32) -> Self {
+        Self { intensity: [0.0; 64 * 32], decay: frames as f32 / 60.0, last_update: Instant::now() }
+    }
+
+    // Fade by the time since the last call and light the pixels that are on. Time based rather than
+    // per frame because the display is redrawn at whatever rate the main loop gets to it.
+    pub fn update(&mut self, display: &[u8; 64 * 32]) -> &[f32; 64 * 32] {
+        let now = Instant::now();
+        let fade = now.duration_since(self.last_update).as_secs_f32() / self.decay;
+        self.last_update = now;
+        for (intensity, pixel) in self.intensity.iter_mut().zip(display) {
+            *intensity = if *pixel != 0 { 1.0 } else { (*intensity - fade).max(0.0) };
+        }
+        &self.intensity
+    }
+}
+
+// Colour between background and foreground
+pub fn blend(palette: &Palette, intensity: f32) -> [u8; 3] {
+    let [background, foreground] = palette;
+    [0, 1, 2].map(|channel| (background[channel] as f32 + (foreground[channel] as f32 - background[channel] as f32) * intensity).round() as u8)
+}
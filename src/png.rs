@@ -0,0 +1,167 @@
+// Minimal PNG encoder for 8-bit indexed images, with a small deflate compressor (LZ77 with fixed
+// Huffman codes) since the display compresses extremely well
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+const WINDOW: usize = 32768;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+
+// `pixels` holds one palette index per pixel, row by row
+pub fn encode_indexed(width: u32, height: u32, palette: &[[u8; 3]], pixels: &[u8]) -> Vec<u8> {
+    let mut header = Vec::new();
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per pixel, indexed color, default compression, filter and interlacing
+    header.extend_from_slice(&[8, 3, 0, 0, 0]);
+
+    let mut raw = Vec::with_capacity(pixels.len() + height as usize);
+    for row in pixels.chunks(width as usize) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"PLTE", &palette.concat());
+    write_chunk(&mut png, b"IDAT", &zlib(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, tag: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(tag);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut output = vec![0x78, 0x01];
+    output.extend_from_slice(&deflate(data));
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    output.extend_from_slice(&((b << 16) | a).to_be_bytes());
+    output
+}
+
+// One final block with fixed Huffman codes
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut bits = BitWriter::default();
+    bits.write(0b1, 1);
+    bits.write(0b01, 2);
+
+    let mut heads = vec![usize::MAX; 1 << HASH_BITS];
+    let hash = |position: usize| {
+        let value = (data[position] as u32) << 16 | (data[position + 1] as u32) << 8 | data[position + 2] as u32;
+        (value.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+    };
+
+    let mut position = 0;
+    while position < data.len() {
+        let mut length = 0;
+        let mut distance = 0;
+        if position + 3 <= data.len() {
+            let key = hash(position);
+            let candidate = heads[key];
+            heads[key] = position;
+            if candidate != usize::MAX && position - candidate <= WINDOW {
+                let limit = MAX_MATCH.min(data.len() - position);
+                while length < limit && data[candidate + length] == data[position + length] {
+                    length += 1;
+                }
+                distance = position - candidate;
+            }
+        }
+
+        if length >= 3 {
+            write_length(&mut bits, length);
+            write_distance(&mut bits, distance);
+            // Keep the hash table filled for positions inside the match
+            for inner in position + 1..(position + length).min(data.len().saturating_sub(2)) {
+                heads[hash(inner)] = inner;
+            }
+            position += length;
+        } else {
+            write_symbol(&mut bits, data[position] as u16);
+            position += 1;
+        }
+    }
+    write_symbol(&mut bits, 256);
+    bits.finish()
+}
+
+fn write_symbol(bits: &mut BitWriter, symbol: u16) {
+    let (code, length) = match symbol {
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xC0 + symbol - 280, 8),
+    };
+    bits.write_reversed(code as u32, length);
+}
+
+fn write_length(bits: &mut BitWriter, length: usize) {
+    let index = LENGTH_BASE.iter().rposition(|base| *base as usize <= length).unwrap();
+    write_symbol(bits, 257 + index as u16);
+    bits.write((length - LENGTH_BASE[index] as usize) as u32, LENGTH_EXTRA[index] as u32);
+}
+
+fn write_distance(bits: &mut BitWriter, distance: usize) {
+    let index = DISTANCE_BASE.iter().rposition(|base| *base as usize <= distance).unwrap();
+    bits.write_reversed(index as u32, 5);
+    bits.write((distance - DISTANCE_BASE[index] as usize) as u32, DISTANCE_EXTRA[index] as u32);
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    // Least significant bit first, as deflate stores everything but Huffman codes
+    fn write(&mut self, value: u32, count: u32) {
+        self.buffer |= (value as u64) << self.bits;
+        self.bits += count;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    // Huffman codes are stored most significant bit first
+    fn write_reversed(&mut self, code: u32, count: u32) {
+        self.write(code.reverse_bits() >> (32 - count), count);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
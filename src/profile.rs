@@ -0,0 +1,55 @@
+// Per-opcode profiler: how many times each instruction class ran and how much host time the
+// interpreter spent executing it
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::disasm::{opcode_class, OPCODE_CLASSES};
+
+#[derive(Default)]
+pub struct Profiler {
+    classes: HashMap<&'static str, (u64, Duration)>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, op: u16, elapsed: Duration) {
+        let entry = self.classes.entry(opcode_class(op)).or_insert((0, Duration::ZERO));
+        entry.0 += 1;
+        entry.1 += elapsed;
+    }
+
+    pub fn clear(&mut self) {
+        self.classes.clear();
+    }
+
+    // Classes sorted by total host time, most expensive first
+    pub fn report(&self) -> String {
+        let total: Duration = self.classes.values().map(|(_, time)| *time).sum();
+        let mut rows: Vec<_> = OPCODE_CLASSES
+            .iter()
+            .chain(["????"].iter())
+            .filter_map(|class| self.classes.get(class).map(|entry| (*class, *entry)))
+            .collect();
+        rows.sort_by_key(|(_, (_, time))| std::cmp::Reverse(*time));
+
+        let mut report = String::from("Opcode profile:\n    class       count     total us   ns/op   time%\n");
+        for (class, (count, time)) in rows {
+            let percent = if total.is_zero() { 0.0 } else { time.as_secs_f64() * 100.0 / total.as_secs_f64() };
+            report.push_str(&format!(
+                "    {}  {:>10}  {:>11}  {:>6}  {:>5.1}%\n",
+                class,
+                count,
+                time.as_micros(),
+                time.as_nanos() / count.max(1) as u128,
+                percent
+            ));
+        }
+        let count: u64 = self.classes.values().map(|(count, _)| *count).sum();
+        report.push_str(&format!("    total {:>10}  {:>11}\n", count, total.as_micros()));
+        report
+    }
+}
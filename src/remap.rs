@@ -0,0 +1,79 @@
+// Keypad remapping from inside the emulator, started with Ctrl+K. Each CHIP-8 key in turn waits
+// for the keyboard key that should press it; Backspace keeps the current one and Escape abandons
+// the lot. The result goes into the input profile and is saved, see input.rs. Emulation is
+// frozen meanwhile.
+
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::render::BlendMode;
+
+use crate::chip8::VM;
+use crate::input::{key_at, InputProfile};
+use crate::text::{draw_text, text_width, GLYPH_HEIGHT};
+
+pub enum RemapResult {
+    Waiting,
+    Done(Vec<(Keycode, usize)>),
+    Cancelled,
+}
+
+pub struct Remap {
+    // Index into the keypad in reading order
+    position: usize,
+    // Keyboard key for each CHIP-8 key before remapping, shown as the one Backspace keeps
+    current: [Option<String>; 16],
+    chosen: Vec<(Keycode, usize)>,
+}
+
+impl Remap {
+    pub fn new(input: &InputProfile) -> Self {
+        Self { position: 0, current: std::array::from_fn(|key| input.key_name(key)), chosen: Vec::new() }
+    }
+
+    pub fn handle_key(&mut self, keycode: Keycode) -> RemapResult {
+        match keycode {
+            Keycode::Escape => return RemapResult::Cancelled,
+            Keycode::Backspace => {}
+            _ => {
+                let key = key_at(self.position);
+                // A keyboard key can only press one CHIP-8 key, the latest choice wins
+                self.chosen.retain(|(chosen, _)| *chosen != keycode);
+                self.chosen.push((keycode, key));
+            }
+        }
+        self.position += 1;
+        if self.position == 16 {
+            return RemapResult::Done(std::mem::take(&mut self.chosen));
+        }
+        RemapResult::Waiting
+    }
+}
+
+impl VM<'_> {
+    pub fn draw_remap(&mut self, window_scale: u32) -> Result<(), String> {
+        let Some(remap) = &self.remap else { return Ok(()) };
+        let key = key_at(remap.position);
+        let current = remap.current[key].as_deref().unwrap_or("NONE").to_uppercase();
+        let lines = [
+            format!("PRESS THE KEY FOR CHIP-8 KEY {:X}", key),
+            format!("{} OF 16, BACKSPACE KEEPS {}, ESC CANCELS", remap.position + 1, current),
+        ];
+
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 220));
+        self.canvas.fill_rect(None)?;
+        self.canvas.set_blend_mode(BlendMode::None);
+        for (row, (line, scale)) in lines.iter().zip([2, 1]).enumerate() {
+            let x = ((64 * window_scale).saturating_sub(text_width(line, scale)) / 2) as i32;
+            let y = (32 * window_scale / 2) as i32 - 16 + row as i32 * (GLYPH_HEIGHT as i32 * 2 + 8);
+            draw_text(&mut self.canvas, x, y, scale, line, Color::RGB(255, 200, 0))?;
+        }
+        // Keypad with the key being asked for lit
+        let pressed = self.keypad;
+        self.keypad = [false; 16];
+        self.keypad[key] = true;
+        let result = self.draw_keypad(4, 4, 14);
+        self.keypad = pressed;
+        result
+    }
+}
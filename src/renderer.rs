@@ -0,0 +1,69 @@
+// Choosing the SDL render backend for --renderer. GPU drivers in virtual machines and remote X
+// servers are often broken, so any failure to get a hardware renderer falls back to software.
+
+use sdl2::render::WindowCanvas;
+use sdl2::VideoSubsystem;
+
+#[derive(Clone, PartialEq)]
+pub enum Renderer {
+    // Whatever SDL picks first
+    Default,
+    Accelerated,
+    Software,
+    // An SDL render driver by name, e.g. opengl or direct3d11
+    Driver(String),
+}
+
+pub fn parse_renderer(text: &str) -> Renderer {
+    match text {
+        "default" => Renderer::Default,
+        "accelerated" => Renderer::Accelerated,
+        "software" => Renderer::Software,
+        name => Renderer::Driver(name.to_string()),
+    }
+}
+
+pub fn driver_names() -> Vec<String> {
+    sdl2::render::drivers().map(|info| info.name.to_string()).collect()
+}
+
+pub fn create_canvas(video: &VideoSubsystem, renderer: &Renderer, vsync: bool, width: u32, height: u32) -> Result<WindowCanvas, String> {
+    match build(video, renderer, vsync, width, height) {
+        Ok(canvas) => Ok(canvas),
+        Err(e) if *renderer != Renderer::Software => {
+            eprintln!("Renderer failed, {}. Falling back to software rendering", e);
+            build(video, &Renderer::Software, vsync, width, height)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// A failed canvas build consumes the window, so each attempt makes its own
+fn build(video: &VideoSubsystem, renderer: &Renderer, vsync: bool, width: u32, height: u32) -> Result<WindowCanvas, String> {
+    let window = video.window("CHIP-8", width, height)
+        .position_centered()
+        .resizable()
+        .allow_highdpi()
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let mut builder = window.into_canvas();
+    builder = match renderer {
+        Renderer::Default => builder,
+        Renderer::Accelerated => builder.accelerated(),
+        Renderer::Software => builder.software(),
+        Renderer::Driver(name) => {
+            let index = driver_names()
+                .iter()
+                .position(|driver| driver == name)
+                .ok_or(format!("no render driver {}, available: {}", name, driver_names().join(", ")))?;
+            builder.index(index as u32)
+        }
+    };
+    if vsync {
+        builder = builder.present_vsync();
+    }
+    let canvas = builder.build().map_err(|e| e.to_string())?;
+    println!("Rendering with {}", canvas.info().name);
+    Ok(canvas)
+}
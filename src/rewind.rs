@@ -0,0 +1,158 @@
+// Execution history for stepping backwards. A snapshot is kept every CHECKPOINT_INTERVAL
+// instructions together with a log of everything from outside the CPU that changed the machine
+// in between (keypad changes and 60 Hz timer ticks). Going back re-runs from the nearest snapshot
+// up to the wanted instruction with the same inputs, which reproduces the state exactly.
+//
+// Edits the log can't replay, from the console, gdb, scripts and the debugger windows, clear the
+// history instead, so going back never undoes them or reaches a state that never existed.
+
+use std::collections::VecDeque;
+use std::mem;
+
+use crate::chip8::VM;
+use crate::loopdetect::LoopDetector;
+use crate::rpl::RPL_FLAGS;
+use crate::snapshot::Snapshot;
+
+const CHECKPOINT_INTERVAL: u64 = 1000;
+const MAX_CHECKPOINTS: usize = 100;
+
+enum Input {
+    Keys(u16),
+    Tick,
+}
+
+struct Checkpoint {
+    // Instructions executed, and the state before the next one
+    at: u64,
+    snapshot: Snapshot,
+    keys: u16,
+    // Not part of save states, they belong to the rom rather than the run
+    rpl: [u8; RPL_FLAGS],
+}
+
+#[derive(Default)]
+pub struct Rewind {
+    checkpoints: VecDeque<Checkpoint>,
+    // Inputs that arrived after the given number of instructions, oldest first
+    inputs: VecDeque<(u64, Input)>,
+    keys: u16,
+    // Set on the stand-in the VM has while step_back re-runs history
+    replaying: bool,
+}
+
+impl Rewind {
+    // Called before each instruction, returns true when a checkpoint should be taken
+    pub fn before_instruction(&mut self, cycles: u64, keys: u16) -> bool {
+        if keys != self.keys {
+            self.keys = keys;
+            self.inputs.push_back((cycles, Input::Keys(keys)));
+        }
+        match self.checkpoints.back() {
+            Some(checkpoint) => cycles.is_multiple_of(CHECKPOINT_INTERVAL) && checkpoint.at != cycles,
+            None => true,
+        }
+    }
+
+    pub fn add_checkpoint(&mut self, cycles: u64, snapshot: Snapshot, keys: u16, rpl: [u8; RPL_FLAGS]) {
+        self.checkpoints.push_back(Checkpoint { at: cycles, snapshot, keys, rpl });
+        if self.checkpoints.len() > MAX_CHECKPOINTS {
+            self.checkpoints.pop_front();
+            let oldest = self.checkpoints.front().map_or(0, |checkpoint| checkpoint.at);
+            while self.inputs.front().is_some_and(|(at, _)| *at < oldest) {
+                self.inputs.pop_front();
+            }
+        }
+    }
+
+    pub fn tick(&mut self, cycles: u64) {
+        self.inputs.push_back((cycles, Input::Tick));
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    // Whether instructions are being re-run by step_back, when they shouldn't touch the world outside
+    pub fn replaying(&self) -> bool {
+        self.replaying
+    }
+
+    // Forget everything after `cycles`, history is rewritten from there on
+    fn truncate(&mut self, cycles: u64) {
+        while self.checkpoints.back().is_some_and(|checkpoint| checkpoint.at > cycles) {
+            self.checkpoints.pop_back();
+        }
+        while self.inputs.back().is_some_and(|(at, _)| *at > cycles) {
+            self.inputs.pop_back();
+        }
+    }
+}
+
+pub fn keypad_mask(keypad: &[bool; 16]) -> u16 {
+    keypad.iter().enumerate().filter(|(_, pressed)| **pressed).fold(0, |mask, (key, _)| mask | 1 << key)
+}
+
+impl VM<'_> {
+    // Go back `count` instructions. Debugging aids that would fire again while re-running
+    // (tracer, coverage, tracepoints, loop detection and so on) are switched off meanwhile.
+    pub fn step_back(&mut self, count: u64) -> Result<(), String> {
+        let target = self.cycles.checked_sub(count).ok_or("not that many instructions executed yet")?;
+        let mut rewind = mem::take(&mut self.rewind);
+        let Some(checkpoint) = rewind.checkpoints.iter().rev().find(|checkpoint| checkpoint.at <= target) else {
+            self.rewind = rewind;
+            return Err("history doesn't go back that far".to_string());
+        };
+        let start = checkpoint.at;
+
+        let tracer = self.tracer.take();
+        let trace_comparer = self.trace_comparer.take();
+        let coverage = self.coverage.take();
+        let smc_watch = self.smc_watch.take();
+        let profiler = self.profiler.take();
+        let movie = self.movie.take();
+        let tracepoints = mem::take(&mut self.tracepoints);
+        let break_on_draw = mem::replace(&mut self.break_on_draw, false);
+        let loop_detector = mem::replace(&mut self.loop_detector, LoopDetector::new(0));
+
+        self.restore(&checkpoint.snapshot);
+        // After restore, which starts the stand-in history afresh
+        self.rewind.replaying = true;
+        self.rpl = checkpoint.rpl;
+        set_keypad(self, checkpoint.keys);
+        self.cycles = start;
+        let mut inputs = rewind.inputs.iter().skip_while(|(at, _)| *at <= start).peekable();
+        while self.cycles < target {
+            // Only the trace comparer can fail a cycle, and it is switched off here
+            let _ = self.emulate_cycle();
+            while let Some((_, input)) = inputs.next_if(|(at, _)| *at <= self.cycles) {
+                match input {
+                    Input::Keys(mask) => set_keypad(self, *mask),
+                    Input::Tick => self.tick_timers(),
+                }
+            }
+        }
+
+        self.tracer = tracer;
+        self.trace_comparer = trace_comparer;
+        self.coverage = coverage;
+        self.smc_watch = smc_watch;
+        self.profiler = profiler;
+        self.movie = movie;
+        self.tracepoints = tracepoints;
+        self.break_on_draw = break_on_draw;
+        self.loop_detector = loop_detector;
+        rewind.truncate(target);
+        rewind.keys = keypad_mask(&self.keypad);
+        self.rewind = rewind;
+        self.paused = true;
+        self.pause_message = None;
+        Ok(())
+    }
+}
+
+fn set_keypad(vm: &mut VM, mask: u16) {
+    for (key, pressed) in vm.keypad.iter_mut().enumerate() {
+        *pressed = mask & (1 << key) != 0;
+    }
+}
@@ -0,0 +1,53 @@
+// Settings remembered per rom, kept in the data directory and keyed by the rom's content like save
+// states. One "key value" pair per line.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::savestate::data_directory;
+
+#[derive(Default)]
+pub struct RomSettings {
+    // Display rotation in degrees, set with Ctrl+R
+    pub rotation: Option<u16>,
+}
+
+impl RomSettings {
+    pub fn path(rom_hash: u64) -> PathBuf {
+        data_directory().join("roms").join(format!("{:016x}.txt", rom_hash))
+    }
+
+    // Missing files and unknown or malformed lines are ignored, so a bad edit only loses that setting
+    pub fn load(rom_hash: u64) -> Self {
+        let mut settings = Self::default();
+        let Ok(text) = fs::read_to_string(Self::path(rom_hash)) else { return settings };
+        for line in text.lines() {
+            if let Some(("rotation", value)) = line.split_once(' ') {
+                settings.rotation = parse_rotation(value).ok();
+            }
+        }
+        settings
+    }
+
+    pub fn save(&self, rom_hash: u64) -> Result<(), String> {
+        let path = Self::path(rom_hash);
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|e| format!("Error creating {}, {}", directory.display(), e))?;
+        }
+        let mut text = String::new();
+        if let Some(rotation) = self.rotation {
+            text.push_str(&format!("rotation {}\n", rotation));
+        }
+        fs::write(&path, text).map_err(|e| format!("Error writing {}, {}", path.display(), e))
+    }
+}
+
+pub fn parse_rotation(text: &str) -> Result<u16, String> {
+    match text.trim() {
+        "0" => Ok(0),
+        "90" => Ok(90),
+        "180" => Ok(180),
+        "270" => Ok(270),
+        _ => Err(format!("invalid rotation {}, expected 0, 90, 180 or 270", text)),
+    }
+}
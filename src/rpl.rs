@@ -0,0 +1,34 @@
+// SUPER-CHIP RPL user flags (FX75/FX85). Games use them for high scores, so like battery saves
+// on a console they are kept per rom in the data directory and survive restarts.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::chip8::VM;
+use crate::savestate::data_directory;
+
+pub const RPL_FLAGS: usize = 8;
+
+impl VM<'_> {
+    pub fn rpl_path(&self) -> PathBuf {
+        data_directory().join("rpl").join(format!("{:016x}.rpl", self.rom_hash))
+    }
+
+    // Flags saved for the loaded rom, all zero if it never wrote any
+    pub fn load_rpl_flags(&mut self) {
+        self.rpl = [0; RPL_FLAGS];
+        if let Ok(bytes) = fs::read(self.rpl_path()) {
+            let count = bytes.len().min(RPL_FLAGS);
+            self.rpl[..count].copy_from_slice(&bytes[..count]);
+        }
+    }
+
+    // Written on every FX75 rather than on exit, so a crash or a killed process doesn't lose a score
+    pub fn save_rpl_flags(&self) -> Result<(), String> {
+        let path = self.rpl_path();
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|e| format!("Error creating {}, {}", directory.display(), e))?;
+        }
+        fs::write(&path, self.rpl).map_err(|e| format!("Error writing {}, {}", path.display(), e))
+    }
+}
@@ -0,0 +1,320 @@
+// Save states: snapshots written to disk in a small chunked container, ten numbered slots per
+// rom under the platform data directory.
+//
+// File layout: "C8SS", version byte, then chunks of a four byte tag, a little endian u32 length
+// and the data. Readers skip chunks they don't know, so new chunks can be added freely.
+//   REGS  V0-VF, I, PC, SP, DT, ST, 16 stack entries, frame counter
+//   MEM   4096 bytes of memory
+//   DISP  2048 display pixels, one byte each
+//   RAND  optional random generator seed and number of values drawn, two u64
+//   THMB  optional 32x16 thumbnail, one byte per 2x2 block of the display holding the lit count
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::chip8::VM;
+use crate::snapshot::Snapshot;
+
+const MAGIC: &[u8; 4] = b"C8SS";
+const VERSION: u8 = 1;
+pub const SLOTS: u8 = 10;
+pub const THUMBNAIL_WIDTH: usize = 32;
+pub const THUMBNAIL_HEIGHT: usize = 16;
+
+impl Snapshot {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut registers = Vec::new();
+        registers.extend_from_slice(&self.v);
+        for value in [self.i, self.pc, self.sp] {
+            registers.extend_from_slice(&value.to_le_bytes());
+        }
+        registers.push(self.delay);
+        registers.push(self.sound);
+        for entry in self.stack {
+            registers.extend_from_slice(&entry.to_le_bytes());
+        }
+        registers.extend_from_slice(&self.frame.to_le_bytes());
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        write_chunk(&mut bytes, b"REGS", &registers);
+        write_chunk(&mut bytes, b"MEM ", &self.memory);
+        write_chunk(&mut bytes, b"DISP", &self.display);
+        write_chunk(&mut bytes, b"RAND", &[self.seed.to_le_bytes(), self.random_draws.to_le_bytes()].concat());
+        write_chunk(&mut bytes, b"THMB", &self.thumbnail());
+        bytes
+    }
+
+    fn thumbnail(&self) -> Vec<u8> {
+        let mut thumbnail = vec![0; THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT];
+        for (index, pixel) in self.display.iter().enumerate() {
+            let (x, y) = (index % 64, index / 64);
+            thumbnail[(y / 2) * THUMBNAIL_WIDTH + x / 2] += pixel.min(&1);
+        }
+        thumbnail
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 5 || &bytes[..4] != MAGIC {
+            return Err("not a save state".to_string());
+        }
+        if bytes[4] != VERSION {
+            return Err(format!("unsupported save state version {}", bytes[4]));
+        }
+
+        let mut snapshot = Snapshot {
+            v: [0; 16],
+            i: 0,
+            pc: 0x200,
+            stack: [0; 16],
+            sp: 0,
+            delay: 0,
+            sound: 0,
+            memory: [0; 4096],
+            display: [0; 64 * 32],
+            frame: 0,
+            seed: 0,
+            random_draws: 0,
+        };
+        let mut found = (false, false, false);
+        for (tag, data) in chunks(&bytes[5..])? {
+            match &tag {
+                b"REGS" if data.len() == 16 + 6 + 2 + 32 + 8 => {
+                    let word = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+                    snapshot.v.copy_from_slice(&data[..16]);
+                    snapshot.i = word(16) & 0xFFF;
+                    // PC has to leave room to fetch an instruction, and returning to a stack entry
+                    // plus 2 has to as well
+                    snapshot.pc = word(18);
+                    if snapshot.pc > 0xFFE {
+                        return Err(format!("PC {:04X} is past the end of memory", snapshot.pc));
+                    }
+                    snapshot.sp = word(20).min(15);
+                    snapshot.delay = data[22];
+                    snapshot.sound = data[23];
+                    for (index, entry) in snapshot.stack.iter_mut().enumerate() {
+                        *entry = word(24 + index * 2);
+                        if *entry > 0xFFD {
+                            return Err(format!("stack entry {:04X} is past the end of memory", entry));
+                        }
+                    }
+                    snapshot.frame = u64::from_le_bytes(data[56..64].try_into().unwrap());
+                    found.0 = true;
+                }
+                b"MEM " if data.len() == 4096 => {
+                    snapshot.memory.copy_from_slice(data);
+                    found.1 = true;
+                }
+                b"DISP" if data.len() == 64 * 32 => {
+                    // Pixels are 0 or 1, whatever a damaged file has
+                    for (pixel, byte) in snapshot.display.iter_mut().zip(data) {
+                        *pixel = (*byte != 0) as u8;
+                    }
+                    found.2 = true;
+                }
+                b"RAND" if data.len() == 16 => {
+                    snapshot.seed = u64::from_le_bytes(data[..8].try_into().unwrap());
+                    snapshot.random_draws = u64::from_le_bytes(data[8..].try_into().unwrap());
+                }
+                b"REGS" | b"MEM " | b"DISP" => return Err(format!("{} chunk has the wrong size", String::from_utf8_lossy(&tag))),
+                _ => {}
+            }
+        }
+        if found != (true, true, true) {
+            return Err("save state is missing registers, memory or display".to_string());
+        }
+        Ok(snapshot)
+    }
+}
+
+fn write_chunk(bytes: &mut Vec<u8>, tag: &[u8; 4], data: &[u8]) {
+    bytes.extend_from_slice(tag);
+    bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(data);
+}
+
+// The thumbnail stored in a state file, if the file exists and has one
+pub fn read_thumbnail(path: &PathBuf) -> Option<Vec<u8>> {
+    let bytes = fs::read(path).ok()?;
+    if bytes.len() < 5 || &bytes[..4] != MAGIC {
+        return None;
+    }
+    chunks(&bytes[5..])
+        .ok()?
+        .into_iter()
+        .find(|(tag, data)| tag == b"THMB" && data.len() == THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT)
+        .map(|(_, data)| data.to_vec())
+}
+
+type Chunk<'a> = ([u8; 4], &'a [u8]);
+
+fn chunks(mut bytes: &[u8]) -> Result<Vec<Chunk<'_>>, String> {
+    let mut chunks = Vec::new();
+    while !bytes.is_empty() {
+        if bytes.len() < 8 {
+            return Err("save state is truncated".to_string());
+        }
+        let tag = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        let length = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+        let data = bytes.get(8..8 + length).ok_or("save state is truncated")?;
+        chunks.push((tag, data));
+        bytes = &bytes[8 + length..];
+    }
+    Ok(chunks)
+}
+
+impl VM<'_> {
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.v = snapshot.v;
+        self.i = snapshot.i;
+        self.pc = snapshot.pc;
+        self.stack = snapshot.stack;
+        self.sp = snapshot.sp;
+        self.delay = snapshot.delay;
+        self.sound = snapshot.sound;
+        self.memory = snapshot.memory;
+        self.display = snapshot.display;
+        self.frame = snapshot.frame;
+        if (snapshot.seed, snapshot.random_draws) != (self.seed, self.random_draws) {
+            self.reseed(snapshot.seed, snapshot.random_draws);
+        }
+        self.drawflag = true;
+        self.pause_message = None;
+        self.loop_detector.ignore_current();
+        // History from before the jump no longer leads to this state
+        self.rewind.clear();
+    }
+
+    pub fn save_state(&self, path: &PathBuf) -> Result<(), String> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|e| format!("Error creating {}, {}", directory.display(), e))?;
+        }
+        fs::write(path, self.snapshot().to_bytes()).map_err(|e| format!("Error writing {}, {}", path.display(), e))
+    }
+
+    pub fn load_state(&mut self, path: &PathBuf) -> Result<(), String> {
+        let bytes = fs::read(path).map_err(|e| format!("Error reading {}, {}", path.display(), e))?;
+        let snapshot = Snapshot::from_bytes(&bytes).map_err(|e| format!("{}: {}", path.display(), e))?;
+        self.restore(&snapshot);
+        Ok(())
+    }
+
+    // Where the states of the loaded rom live, keyed by the rom's content so renamed files keep them
+    pub fn state_directory(&self) -> PathBuf {
+        data_directory().join("states").join(format!("{:016x}", self.rom_hash))
+    }
+
+    pub fn slot_path(&self, slot: u8) -> PathBuf {
+        self.state_directory().join(format!("slot{}.state", slot))
+    }
+
+    // Written on a clean exit and offered for resuming on the next launch
+    pub fn autosave_path(&self) -> PathBuf {
+        self.state_directory().join("autosave.state")
+    }
+}
+
+// Per-user data directory following each platform's convention
+pub fn data_directory() -> PathBuf {
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library").join("Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")))
+    };
+    base.unwrap_or_else(|| PathBuf::from(".")).join("chip8-rust")
+}
+
+// FNV-1a, enough to tell roms apart
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Snapshot {
+        let mut snapshot = Snapshot {
+            v: [0; 16],
+            i: 0x3A0,
+            pc: 0x2F4,
+            stack: [0; 16],
+            sp: 2,
+            delay: 30,
+            sound: 4,
+            memory: [0; 4096],
+            display: [0; 64 * 32],
+            frame: 1234,
+            seed: 99,
+            random_draws: 7,
+        };
+        snapshot.v[0xF] = 1;
+        snapshot.stack[..2].copy_from_slice(&[0x204, 0x310]);
+        snapshot.memory[0x200..0x204].copy_from_slice(&[0x60, 0x05, 0x12, 0x00]);
+        snapshot.display[65] = 1;
+        snapshot
+    }
+
+    // The REGS chunk's data within a state file, to corrupt
+    fn registers(bytes: &mut [u8]) -> &mut [u8] {
+        let start = 5 + 8;
+        &mut bytes[start..start + 64]
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = sample().to_bytes();
+        let loaded = Snapshot::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.to_bytes(), bytes);
+        assert_eq!((loaded.i, loaded.pc, loaded.sp, loaded.frame, loaded.seed), (0x3A0, 0x2F4, 2, 1234, 99));
+        assert_eq!(loaded.stack[..2], [0x204, 0x310]);
+    }
+
+    #[test]
+    fn display_bytes_load_as_pixels() {
+        let mut snapshot = sample();
+        snapshot.display[..4].copy_from_slice(&[255, 255, 7, 0]);
+        let loaded = Snapshot::from_bytes(&snapshot.to_bytes()).unwrap();
+        assert_eq!(loaded.display[..4], [1, 1, 1, 0]);
+        assert_eq!(loaded.thumbnail()[..2], [3, 1]);
+    }
+
+    #[test]
+    fn unknown_chunks_are_skipped() {
+        let mut bytes = sample().to_bytes();
+        write_chunk(&mut bytes, b"NEWS", &[1, 2, 3]);
+        assert!(Snapshot::from_bytes(&bytes).is_ok());
+    }
+
+    #[test]
+    fn pc_past_memory_is_rejected() {
+        let mut bytes = sample().to_bytes();
+        registers(&mut bytes)[18..20].copy_from_slice(&0xFFFu16.to_le_bytes());
+        assert_eq!(Snapshot::from_bytes(&bytes).err().unwrap(), "PC 0FFF is past the end of memory");
+    }
+
+    #[test]
+    fn stack_entry_past_memory_is_rejected() {
+        let mut bytes = sample().to_bytes();
+        registers(&mut bytes)[26..28].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        assert_eq!(Snapshot::from_bytes(&bytes).err().unwrap(), "stack entry FFFF is past the end of memory");
+    }
+
+    #[test]
+    fn bad_files_are_rejected() {
+        let bytes = sample().to_bytes();
+        assert_eq!(Snapshot::from_bytes(b"PNG").err().unwrap(), "not a save state");
+        assert_eq!(Snapshot::from_bytes(&bytes[..bytes.len() - 1]).err().unwrap(), "save state is truncated");
+        let mut version = bytes.clone();
+        version[4] = 9;
+        assert_eq!(Snapshot::from_bytes(&version).err().unwrap(), "unsupported save state version 9");
+        let mut short = bytes.clone();
+        short[9..13].copy_from_slice(&63u32.to_le_bytes());
+        assert!(Snapshot::from_bytes(&short).is_err());
+    }
+}
@@ -0,0 +1,69 @@
+// F12 screenshots and --dump-frames sequences: the display as PNGs in the active palette. The
+// "screen" console command prints it as text instead.
+
+use std::fs;
+use std::path::Path;
+
+use crate::chip8::VM;
+use crate::png::encode_indexed;
+
+impl VM<'_> {
+    // Writes the display scaled up `scale` times into `directory` and returns the file name
+    pub fn save_screenshot(&self, directory: &str, scale: u32, name: &str) -> Result<String, String> {
+        fs::create_dir_all(directory).map_err(|e| format!("Error creating {}, {}", directory, e))?;
+        let path = Path::new(directory).join(name);
+        fs::write(&path, self.display_png(scale)).map_err(|e| format!("Error writing {}, {}", path.display(), e))?;
+        Ok(path.display().to_string())
+    }
+
+    pub fn display_png(&self, scale: u32) -> Vec<u8> {
+        let (width, height) = (64 * scale, 32 * scale);
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(self.display[((y / scale) * 64 + x / scale) as usize].min(1));
+            }
+        }
+        encode_indexed(width, height, &self.display_palette(), &pixels)
+    }
+
+    // The display as text, a full block per lit pixel inside a frame so blank edges still show
+    pub fn display_ascii(&self) -> String {
+        let border = format!("+{}+\n", "-".repeat(64));
+        let mut text = border.clone();
+        for row in self.display.chunks(64) {
+            text.push('|');
+            text.extend(row.iter().map(|pixel| if *pixel != 0 { '\u{2588}' } else { ' ' }));
+            text.push_str("|\n");
+        }
+        text.push_str(&border);
+        text
+    }
+}
+
+// Numbered PNGs of every frame, optionally stopping the emulator after `limit` frames
+pub struct FrameDump {
+    directory: String,
+    pub scale: u32,
+    written: u64,
+    limit: Option<u64>,
+}
+
+impl FrameDump {
+    pub fn new(directory: &str, scale: u32, limit: Option<u64>) -> Result<Self, String> {
+        fs::create_dir_all(directory).map_err(|e| format!("Error creating {}, {}", directory, e))?;
+        Ok(Self { directory: directory.to_string(), scale, written: 0, limit })
+    }
+
+    // Returns true once the limit has been reached
+    pub fn write(&mut self, png: &[u8]) -> Result<bool, String> {
+        self.written += 1;
+        let path = Path::new(&self.directory).join(format!("frame-{:06}.png", self.written));
+        fs::write(&path, png).map_err(|e| format!("Error writing {}, {}", path.display(), e))?;
+        Ok(self.limit.is_some_and(|limit| self.written >= limit))
+    }
+
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+}
@@ -0,0 +1,101 @@
+// Debugger hook scripts, loaded with --script <file>. Each line attaches a console command to an event:
+//
+//   at <address> <command>       run before every instruction executed at <address>
+//   every <n> <command>          run after every <n>th frame
+//   frame <n> <command>          run once after frame <n>
+//
+// e.g. "at 0x2F4 set V3 9" keeps a lives counter topped up. Lines starting with # are comments.
+// A .lua file is a Lua script instead, in builds with the lua feature, see lua.rs.
+
+use std::collections::HashMap;
+use std::fs;
+
+use crate::chip8::VM;
+use crate::command::{self, parse_number};
+#[cfg(feature = "lua")]
+use crate::lua::LuaScript;
+
+pub struct Script {
+    address_hooks: HashMap<u16, Vec<String>>,
+    frame_hooks: Vec<(u64, bool, String)>,
+    #[cfg(feature = "lua")]
+    lua: Option<LuaScript>,
+}
+
+impl Script {
+    // `vm` is for Lua scripts, which can look at it as they start
+    #[cfg_attr(not(feature = "lua"), allow(unused_variables))]
+    pub fn load(path: &str, vm: &mut VM) -> Result<Self, String> {
+        let mut script = Self {
+            address_hooks: HashMap::new(),
+            frame_hooks: Vec::new(),
+            #[cfg(feature = "lua")]
+            lua: None,
+        };
+        if path.ends_with(".lua") {
+            #[cfg(feature = "lua")]
+            {
+                script.lua = Some(LuaScript::load(path, vm)?);
+                return Ok(script);
+            }
+            #[cfg(not(feature = "lua"))]
+            return Err("this build has no Lua support, rebuild with --features lua".to_string());
+        }
+        let content = fs::read_to_string(path).map_err(|e| format!("Error loading script {}, {}", path, e))?;
+
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("{}:{}: {}", path, index + 1, message);
+            let mut parts = line.splitn(3, char::is_whitespace);
+            let (event, argument, command) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(e), Some(a), Some(c)) => (e, a, c.trim().to_string()),
+                _ => return Err(error("expected '<event> <argument> <command>'")),
+            };
+            let value = parse_number(argument).map_err(|e| error(&e))?;
+
+            match event {
+                "at" => script.address_hooks.entry(value).or_default().push(command),
+                "every" if value > 0 => script.frame_hooks.push((value as u64, true, command)),
+                "frame" => script.frame_hooks.push((value as u64, false, command)),
+                _ => return Err(error(&format!("unknown event '{}'", event))),
+            }
+        }
+        Ok(script)
+    }
+
+    pub fn before_instruction(&self, vm: &mut VM) {
+        if let Some(commands) = self.address_hooks.get(&vm.pc) {
+            for line in commands {
+                run(vm, line);
+            }
+        }
+        #[cfg(feature = "lua")]
+        if let Some(lua) = &self.lua {
+            lua.before_instruction(vm);
+        }
+    }
+
+    pub fn after_frame(&self, vm: &mut VM) {
+        for (frame, repeat, line) in &self.frame_hooks {
+            let due = if *repeat { vm.frame.is_multiple_of(*frame) } else { vm.frame == *frame };
+            if due {
+                run(vm, line);
+            }
+        }
+        #[cfg(feature = "lua")]
+        if let Some(lua) = &self.lua {
+            lua.after_frame(vm);
+        }
+    }
+}
+
+fn run(vm: &mut VM, line: &str) {
+    match command::execute(vm, line) {
+        Ok(output) if !output.is_empty() => println!("script: {}", output),
+        Ok(_) => {}
+        Err(error) => println!("script: {}: {}", line, error),
+    }
+}
@@ -0,0 +1,90 @@
+// Save state slot picker, toggled with F10. Shows the thumbnail stored in each slot; arrow keys
+// pick a slot, Enter loads it and F7 saves to it. Emulation is frozen while it is open.
+
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::BlendMode;
+
+use crate::chip8::VM;
+use crate::text::{draw_text, GLYPH_HEIGHT};
+use crate::savestate::{read_thumbnail, SLOTS, THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH};
+
+const PIXEL: u32 = 3;
+const COLUMNS: u32 = 5;
+const MARGIN: u32 = 12;
+
+#[derive(Default)]
+pub struct SlotPicker {
+    pub open: bool,
+    thumbnails: Vec<Option<Vec<u8>>>,
+}
+
+impl VM<'_> {
+    pub fn open_slot_picker(&mut self) {
+        self.slot_picker.thumbnails = (0..SLOTS).map(|slot| read_thumbnail(&self.slot_path(slot))).collect();
+        self.slot_picker.open = true;
+    }
+
+    // Returns true when the key asks to load the selected slot
+    pub fn slot_picker_key(&mut self, keycode: Keycode) -> bool {
+        let slot = self.state_slot as i32;
+        let moved = match keycode {
+            Keycode::Left => slot - 1,
+            Keycode::Right => slot + 1,
+            Keycode::Up => slot - COLUMNS as i32,
+            Keycode::Down => slot + COLUMNS as i32,
+            Keycode::Return | Keycode::KpEnter => return true,
+            Keycode::Escape => {
+                self.slot_picker.open = false;
+                return false;
+            }
+            _ => slot,
+        };
+        self.state_slot = moved.rem_euclid(SLOTS as i32) as u8;
+        false
+    }
+
+    pub fn draw_slot_picker(&mut self, window_scale: u32) -> Result<(), String> {
+        let line_height = (GLYPH_HEIGHT + 1) * 2;
+        let cell_width = THUMBNAIL_WIDTH as u32 * PIXEL + MARGIN;
+        let cell_height = THUMBNAIL_HEIGHT as u32 * PIXEL + line_height + MARGIN;
+        let rows = (SLOTS as u32).div_ceil(COLUMNS);
+        let left = ((64 * window_scale).saturating_sub(COLUMNS * cell_width) / 2) as i32;
+        let top = ((32 * window_scale).saturating_sub(rows * cell_height) / 2) as i32;
+
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 220));
+        self.canvas.fill_rect(None)?;
+        self.canvas.set_blend_mode(BlendMode::None);
+
+        for slot in 0..SLOTS {
+            let x = left + ((slot as u32 % COLUMNS) * cell_width) as i32 + MARGIN as i32 / 2;
+            let y = top + ((slot as u32 / COLUMNS) * cell_height) as i32;
+            let selected = slot == self.state_slot;
+            let color = if selected { Color::RGB(255, 200, 0) } else { Color::RGB(160, 160, 160) };
+            let thumbnail = self.slot_picker.thumbnails.get(slot as usize).cloned().flatten();
+            let label = format!("{}{}", slot, if thumbnail.is_some() { "" } else { " EMPTY" });
+            draw_text(&mut self.canvas, x, y, 2, &label, color)?;
+
+            let image = Rect::new(x, y + line_height as i32, THUMBNAIL_WIDTH as u32 * PIXEL, THUMBNAIL_HEIGHT as u32 * PIXEL);
+            self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+            self.canvas.fill_rect(image)?;
+            if let Some(pixels) = thumbnail {
+                for (index, level) in pixels.iter().enumerate().filter(|(_, level)| **level > 0) {
+                    // Each thumbnail pixel covers 2x2 display pixels, brighter the more are lit
+                    let shade = (*level as u32 * 255 / 4) as u8;
+                    self.canvas.set_draw_color(Color::RGB(shade, shade, shade));
+                    let px = image.x() + ((index % THUMBNAIL_WIDTH) as u32 * PIXEL) as i32;
+                    let py = image.y() + ((index / THUMBNAIL_WIDTH) as u32 * PIXEL) as i32;
+                    self.canvas.fill_rect(Rect::new(px, py, PIXEL, PIXEL))?;
+                }
+            }
+            self.canvas.set_draw_color(color);
+            self.canvas.draw_rect(image)?;
+        }
+
+        let hint = "ARROWS: SLOT  ENTER: LOAD  F7: SAVE  F10: CLOSE";
+        draw_text(&mut self.canvas, left + MARGIN as i32 / 2, top + (rows * cell_height) as i32, 2, hint, Color::RGB(200, 200, 200))
+    }
+}
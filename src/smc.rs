@@ -0,0 +1,35 @@
+// Self-modifying code detection: reports stores into memory that has already been executed
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SmcMode {
+    Log,
+    Break,
+}
+
+pub struct SmcWatch {
+    pub mode: SmcMode,
+    executed: Vec<bool>,
+}
+
+impl SmcWatch {
+    pub fn new(mode: SmcMode) -> Self {
+        Self { mode, executed: vec![false; 4096] }
+    }
+
+    pub fn record_execution(&mut self, pc: u16) {
+        self.executed[pc as usize % 4096] = true;
+        self.executed[(pc as usize + 1) % 4096] = true;
+    }
+
+    pub fn is_code(&self, address: u16) -> bool {
+        self.executed[address as usize % 4096]
+    }
+}
+
+pub fn parse_mode(mode: &str) -> Result<SmcMode, String> {
+    match mode {
+        "log" => Ok(SmcMode::Log),
+        "break" => Ok(SmcMode::Break),
+        _ => Err(format!("invalid --smc mode {}, expected log or break", mode)),
+    }
+}
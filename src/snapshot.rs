@@ -0,0 +1,117 @@
+// Copies of the machine state that can be compared later, to answer what a stretch of
+// execution actually changed
+
+use std::fmt::Write;
+
+use crate::chip8::VM;
+
+#[derive(Clone)]
+pub struct Snapshot {
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub stack: [u16; 16],
+    pub sp: u16,
+    pub delay: u8,
+    pub sound: u8,
+    pub memory: [u8; 4096],
+    pub display: [u8; 64 * 32],
+    pub frame: u64,
+    pub seed: u64,
+    pub random_draws: u64,
+}
+
+impl VM<'_> {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            v: self.v,
+            i: self.i,
+            pc: self.pc,
+            stack: self.stack,
+            sp: self.sp,
+            delay: self.delay,
+            sound: self.sound,
+            memory: self.memory,
+            display: self.display,
+            frame: self.frame,
+            seed: self.seed,
+            random_draws: self.random_draws,
+        }
+    }
+}
+
+impl Snapshot {
+    // Everything that differs going from `self` to `after`
+    pub fn diff(&self, after: &Snapshot) -> String {
+        let mut report = String::new();
+        if self.frame != after.frame {
+            writeln!(report, "frames: {} -> {} (+{})", self.frame, after.frame, after.frame.wrapping_sub(self.frame)).unwrap();
+        }
+
+        let mut registers = Vec::new();
+        for (index, (before, now)) in self.v.iter().zip(after.v.iter()).enumerate() {
+            if before != now {
+                registers.push(format!("V{:X} {:02X}->{:02X}", index, before, now));
+            }
+        }
+        for (name, before, now) in [("PC", self.pc, after.pc), ("I", self.i, after.i), ("SP", self.sp, after.sp)] {
+            if before != now {
+                registers.push(format!("{} {:03X}->{:03X}", name, before, now));
+            }
+        }
+        for (name, before, now) in [("DT", self.delay, after.delay), ("ST", self.sound, after.sound)] {
+            if before != now {
+                registers.push(format!("{} {:02X}->{:02X}", name, before, now));
+            }
+        }
+        if !registers.is_empty() {
+            writeln!(report, "registers: {}", registers.join(" ")).unwrap();
+        }
+        if self.stack[1..=self.sp as usize] != after.stack[1..=after.sp as usize] {
+            let format_stack = |stack: &[u16]| stack.iter().map(|a| format!("{:03X}", a)).collect::<Vec<_>>().join(" ");
+            writeln!(report, "stack: [{}] -> [{}]", format_stack(&self.stack[1..=self.sp as usize]), format_stack(&after.stack[1..=after.sp as usize])).unwrap();
+        }
+
+        // Changed memory as ranges, with the new bytes for short ones
+        let mut address = 0;
+        while address < 4096 {
+            if self.memory[address] == after.memory[address] {
+                address += 1;
+                continue;
+            }
+            let start = address;
+            while address < 4096 && self.memory[address] != after.memory[address] {
+                address += 1;
+            }
+            let bytes = &after.memory[start..address];
+            if bytes.len() <= 8 {
+                let values: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                writeln!(report, "memory {:03X}: {}", start, values.join(" ")).unwrap();
+            } else {
+                writeln!(report, "memory {:03X}-{:03X}: {} bytes", start, address - 1, bytes.len()).unwrap();
+            }
+        }
+
+        let changed: Vec<usize> = (0..self.display.len()).filter(|p| self.display[*p] != after.display[*p]).collect();
+        if !changed.is_empty() {
+            let turned_on = changed.iter().filter(|p| after.display[**p] == 1).count();
+            let (xs, ys): (Vec<usize>, Vec<usize>) = changed.iter().map(|p| (p % 64, p / 64)).unzip();
+            writeln!(
+                report,
+                "display: {} pixels on, {} off, within x {}-{} y {}-{}",
+                turned_on,
+                changed.len() - turned_on,
+                xs.iter().min().unwrap(),
+                xs.iter().max().unwrap(),
+                ys.iter().min().unwrap(),
+                ys.iter().max().unwrap()
+            )
+            .unwrap();
+        }
+
+        if report.is_empty() {
+            report.push_str("no changes\n");
+        }
+        report
+    }
+}
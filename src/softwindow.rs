@@ -0,0 +1,254 @@
+// A window without SDL, for machines that don't have SDL2 or people who'd rather not install it:
+//   cargo build --no-default-features --features winit
+// winit opens the window and takes the keyboard, softbuffer gets the pixels to it, both plain Rust
+// with no system libraries to link. It's the basic emulator only, no debugger, overlays or sound;
+// the SDL build has those. Options:
+//   --ipf <n>                 instructions executed per 60 Hz frame (default 8)
+//   --seed <n>                seed the random number generator
+//   --key-wait <policy>       which held key FX0A reports, see keywait.rs
+//   --palette <name>          display colours, like the SDL build's --palette
+//   --fullscreen              start fullscreen
+//
+// The keypad is the 1234/QWER/ASDF/ZXCV block wherever the layout puts its letters, P pauses, F11
+// toggles fullscreen and Esc quits. The display is scaled by whole pixels and centred.
+
+use std::num::NonZeroU32;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use softbuffer::{Context, Surface};
+use winit::application::ApplicationHandler;
+use winit::dpi::LogicalSize;
+use winit::event::{ElementState, KeyEvent, WindowEvent};
+use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
+use winit::keyboard::{KeyCode, PhysicalKey};
+use winit::window::{Fullscreen, Window, WindowId};
+
+use crate::chip8::VM;
+use crate::keywait::{parse_key_wait, KeyWaitPolicy};
+use crate::palette::{parse_palette, Palette, PRESETS};
+
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+// Frames run at most to catch up after the window was stuck, e.g. being dragged
+const MAX_CATCH_UP: u32 = 5;
+
+// Physical keys for CHIP-8 keys 0 to F
+const KEYS: [KeyCode; 16] = [
+    KeyCode::KeyX, KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3,
+    KeyCode::KeyQ, KeyCode::KeyW, KeyCode::KeyE, KeyCode::KeyA,
+    KeyCode::KeyS, KeyCode::KeyD, KeyCode::KeyZ, KeyCode::KeyC,
+    KeyCode::Digit4, KeyCode::KeyR, KeyCode::KeyF, KeyCode::KeyV,
+];
+
+struct Options {
+    rom: String,
+    instructions_per_frame: u32,
+    seed: Option<u64>,
+    key_wait: KeyWaitPolicy,
+    palette: Palette,
+    fullscreen: bool,
+}
+
+fn parse_options(args: Vec<String>) -> Result<Options, String> {
+    let mut options = Options {
+        rom: String::new(),
+        instructions_per_frame: 8,
+        seed: None,
+        key_wait: KeyWaitPolicy::Lowest,
+        palette: PRESETS[0].1,
+        fullscreen: false,
+    };
+    let mut rom = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ipf" => {
+                let value = args.next().ok_or("--ipf needs a number")?;
+                options.instructions_per_frame = match value.parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(format!("invalid instructions per frame {}", value)),
+                };
+            }
+            "--seed" => {
+                let value = args.next().ok_or("--seed needs a number")?;
+                options.seed = Some(value.parse().map_err(|_| format!("invalid seed {}", value))?);
+            }
+            "--key-wait" => options.key_wait = parse_key_wait(&args.next().ok_or("--key-wait needs a policy")?)?,
+            "--palette" => options.palette = parse_palette(&args.next().ok_or("--palette needs a name or colours")?)?,
+            "--fullscreen" => options.fullscreen = true,
+            _ if arg.starts_with("--") => return Err(format!("unknown option {} in the winit build", arg)),
+            _ => rom = Some(arg),
+        }
+    }
+    options.rom = rom.ok_or("usage: chip8-rust [options] <rom>")?;
+    Ok(options)
+}
+
+// The window and what draws into it, made once the event loop is running
+struct Display {
+    window: Rc<Window>,
+    surface: Surface<Rc<Window>, Rc<Window>>,
+}
+
+struct App {
+    vm: VM<'static>,
+    options: Options,
+    name: String,
+    display: Option<Display>,
+    next_frame: Instant,
+    error: Option<String>,
+}
+
+impl App {
+    fn open_display(&mut self, event_loop: &ActiveEventLoop) -> Result<(), String> {
+        let attributes = Window::default_attributes()
+            .with_title(format!("CHIP-8 - {}", self.name))
+            .with_inner_size(LogicalSize::new(640, 320))
+            .with_min_inner_size(LogicalSize::new(64, 32));
+        let window = Rc::new(event_loop.create_window(attributes).map_err(|e| e.to_string())?);
+        if self.options.fullscreen {
+            window.set_fullscreen(Some(Fullscreen::Borderless(None)));
+        }
+        let context = Context::new(window.clone()).map_err(|e| e.to_string())?;
+        let surface = Surface::new(&context, window.clone()).map_err(|e| e.to_string())?;
+        self.display = Some(Display { window, surface });
+        Ok(())
+    }
+
+    fn draw(&mut self) -> Result<(), String> {
+        let Some(display) = self.display.as_mut() else { return Ok(()) };
+        let size = display.window.inner_size();
+        let (Some(width), Some(height)) = (NonZeroU32::new(size.width), NonZeroU32::new(size.height)) else { return Ok(()) };
+        display.surface.resize(width, height).map_err(|e| e.to_string())?;
+        let mut buffer = display.surface.buffer_mut().map_err(|e| e.to_string())?;
+        buffer.fill(0);
+
+        let (width, height) = (size.width as usize, size.height as usize);
+        let scale = (width / 64).min(height / 32).max(1);
+        let (left, top) = (width.saturating_sub(64 * scale) / 2, height.saturating_sub(32 * scale) / 2);
+        let palette = self.vm.display_palette();
+        // Dimmed while paused, like the SDL build
+        let dim = if self.vm.paused { 2 } else { 1 };
+        for (index, pixel) in self.vm.display.iter().enumerate() {
+            let [r, g, b] = palette[(*pixel).min(1) as usize].map(|channel| channel as u32 / dim);
+            let color = r << 16 | g << 8 | b;
+            let (x, y) = (left + index % 64 * scale, top + index / 64 * scale);
+            for row in y..(y + scale).min(height) {
+                let start = row * width + x;
+                buffer[start..start + scale.min(width - x)].fill(color);
+            }
+        }
+        buffer.present().map_err(|e| e.to_string())
+    }
+
+    fn update_title(&self) {
+        if let Some(display) = &self.display {
+            let title = match &self.vm.pause_message {
+                Some(message) => format!("CHIP-8 - {} - {}", self.name, message),
+                None => format!("CHIP-8 - {}", self.name),
+            };
+            display.window.set_title(&title);
+        }
+    }
+
+    fn key(&mut self, event_loop: &ActiveEventLoop, event: KeyEvent) {
+        let PhysicalKey::Code(code) = event.physical_key else { return };
+        let pressed = event.state == ElementState::Pressed;
+        if let Some(key) = KEYS.iter().position(|bound| *bound == code) {
+            self.vm.keypad[key] = pressed;
+            return;
+        }
+        if !pressed || event.repeat {
+            return;
+        }
+        match code {
+            KeyCode::Escape => event_loop.exit(),
+            KeyCode::KeyP => {
+                if self.vm.paused { self.vm.resume() } else { self.vm.pause() }
+                self.update_title();
+            }
+            KeyCode::F11 => {
+                if let Some(display) = &self.display {
+                    let fullscreen = display.window.fullscreen().is_none().then_some(Fullscreen::Borderless(None));
+                    display.window.set_fullscreen(fullscreen);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn fail(&mut self, event_loop: &ActiveEventLoop, error: String) {
+        self.error = Some(error);
+        event_loop.exit();
+    }
+}
+
+impl ApplicationHandler for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.display.is_none() {
+            if let Err(e) = self.open_display(event_loop) {
+                self.fail(event_loop, e);
+            }
+        }
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _window_id: WindowId, event: WindowEvent) {
+        match event {
+            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::KeyboardInput { event, .. } => self.key(event_loop, event),
+            // Keys let go in another window never come back released
+            WindowEvent::Focused(false) => self.vm.keypad = [false; 16],
+            WindowEvent::RedrawRequested => {
+                if let Err(e) = self.draw() {
+                    self.fail(event_loop, e);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        let now = Instant::now();
+        if now < self.next_frame {
+            event_loop.set_control_flow(ControlFlow::WaitUntil(self.next_frame));
+            return;
+        }
+        let mut frames = 0;
+        while self.next_frame <= now && frames < MAX_CATCH_UP {
+            if !self.vm.paused {
+                if let Err(e) = self.vm.step_frame(self.options.instructions_per_frame) {
+                    return self.fail(event_loop, e);
+                }
+                if self.vm.paused {
+                    // Halted
+                    self.update_title();
+                }
+            }
+            self.next_frame += FRAME;
+            frames += 1;
+        }
+        self.next_frame = self.next_frame.max(now);
+        if let Some(display) = &self.display {
+            display.window.request_redraw();
+        }
+        event_loop.set_control_flow(ControlFlow::WaitUntil(self.next_frame));
+    }
+}
+
+pub fn run(args: Vec<String>) -> Result<(), String> {
+    let options = parse_options(args)?;
+    let mut vm = VM::new();
+    vm.key_wait_policy = options.key_wait;
+    vm.palette = options.palette;
+    vm.init_font_set();
+    vm.load_rom(&options.rom)?;
+    if let Some(seed) = options.seed {
+        vm.reseed(seed, 0);
+    }
+    let name = options.rom.rsplit(['/', '\\']).next().unwrap_or(&options.rom).to_string();
+
+    let event_loop = EventLoop::new().map_err(|e| e.to_string())?;
+    let mut app = App { vm, options, name, display: None, next_frame: Instant::now(), error: None };
+    event_loop.run_app(&mut app).map_err(|e| e.to_string())?;
+    app.error.map_or(Ok(()), Err)
+}
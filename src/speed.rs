@@ -0,0 +1,57 @@
+// Emulation speed. Holding Tab fast-forwards by the --fast-forward factor, or as fast as the host
+// can go with "max", where the timers tick every --ipf instructions instead of on the clock.
+// Shift+Tab toggles slow motion at --slow-motion percent. The speed is shown in the top left
+// corner while it isn't normal.
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::BlendMode;
+
+use crate::chip8::VM;
+use crate::text::{draw_text, text_width, GLYPH_HEIGHT};
+
+pub const NORMAL_SPEED: f64 = 1.0;
+pub const DEFAULT_FAST_FORWARD: f64 = 4.0;
+pub const DEFAULT_SLOW_MOTION: f64 = 0.25;
+
+// A factor above 1, or "max" for uncapped
+pub fn parse_fast_forward(text: &str) -> Result<f64, String> {
+    if text == "max" {
+        return Ok(f64::INFINITY);
+    }
+    match text.trim_end_matches(['x', 'X']).parse::<f64>() {
+        Ok(factor) if factor > 1.0 && factor <= 1000.0 => Ok(factor),
+        _ => Err(format!("invalid fast forward speed {}, expected a factor over 1 or max", text)),
+    }
+}
+
+// Percent of normal speed, 1-99
+pub fn parse_slow_motion(text: &str) -> Result<f64, String> {
+    match text.trim_end_matches('%').parse::<u32>() {
+        Ok(percent) if (1..100).contains(&percent) => Ok(percent as f64 / 100.0),
+        _ => Err(format!("invalid slow motion speed {}, expected a percentage from 1 to 99", text)),
+    }
+}
+
+pub fn describe_speed(speed: f64) -> String {
+    if speed.is_infinite() {
+        "MAX".to_string()
+    } else if speed < NORMAL_SPEED {
+        format!("{}%", (speed * 100.0).round())
+    } else {
+        format!("{}X", speed)
+    }
+}
+
+impl VM<'_> {
+    pub fn draw_speed_indicator(&mut self) -> Result<(), String> {
+        let scale = 2;
+        let label = format!("{} {}", if self.speed > NORMAL_SPEED { ">>" } else { "SLOW" }, describe_speed(self.speed));
+        let width = text_width(&label, scale) + 6;
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 180));
+        self.canvas.fill_rect(Rect::new(4, 4, width, GLYPH_HEIGHT * scale + 6))?;
+        self.canvas.set_blend_mode(BlendMode::None);
+        draw_text(&mut self.canvas, 7, 7, scale, &label, Color::RGB(255, 200, 0))
+    }
+}
@@ -0,0 +1,135 @@
+// Rom statistics for `chip8-rust stats <rom>`: opcode histogram over reachable code, an estimate
+// of sprite data, the deepest static call chain and the memory the program touches.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::chip8::read_rom;
+use crate::disasm::opcode_class;
+
+const START_ADDRESS: usize = 0x200;
+
+struct Function {
+    instructions: Vec<(usize, u16, Option<u16>)>,
+    callees: BTreeSet<usize>,
+}
+
+pub fn stats_command(args: &[String]) -> Result<(), String> {
+    let [path] = args else { return Err("usage: chip8-rust stats <rom>".to_string()) };
+    let rom = read_rom(path)?;
+    print!("{}", report(path, &rom));
+    Ok(())
+}
+
+pub fn report(path: &str, rom: &[u8]) -> String {
+    let end = START_ADDRESS + rom.len();
+    let mut functions = BTreeMap::new();
+    let mut pending = vec![START_ADDRESS];
+    while let Some(entry) = pending.pop() {
+        if functions.contains_key(&entry) {
+            continue;
+        }
+        let function = walk_function(rom, entry);
+        pending.extend(function.callees.iter().copied());
+        functions.insert(entry, function);
+    }
+
+    let mut code = BTreeMap::new();
+    for function in functions.values() {
+        for (address, op, i) in &function.instructions {
+            code.insert(*address, (*op, *i));
+        }
+    }
+
+    let mut histogram: HashMap<&str, usize> = HashMap::new();
+    let mut sprite_bytes = BTreeSet::new();
+    let mut highest_write = None;
+    for (op, i) in code.values() {
+        *histogram.entry(opcode_class(*op)).or_insert(0) += 1;
+        let Some(i) = i.map(|i| i as usize) else { continue };
+        match opcode_class(*op) {
+            "DXYN" => sprite_bytes.extend(i..i + (op & 0x000F) as usize),
+            "FX33" => highest_write = highest_write.max(Some(i + 2)),
+            "FX55" => highest_write = highest_write.max(Some(i + ((op & 0x0F00) >> 8) as usize)),
+            _ => {}
+        }
+    }
+    let code_bytes: HashSet<usize> = code.keys().flat_map(|a| [*a, *a + 1]).filter(|a| *a < end).collect();
+
+    let mut report = format!("rom: {}\n", path);
+    report.push_str(&format!("size: {} bytes ({:03X}-{:03X})\n", rom.len(), START_ADDRESS, end.saturating_sub(1)));
+    report.push_str(&format!(
+        "code: {} bytes in {} reachable instructions, {} subroutines\n",
+        code_bytes.len(),
+        code.len(),
+        functions.len() - 1
+    ));
+    report.push_str(&format!("data: {} bytes\n", rom.len() - code_bytes.len()));
+    report.push_str(&format!("sprite data (estimated): {} bytes", sprite_bytes.len()));
+    if let (Some(first), Some(last)) = (sprite_bytes.first(), sprite_bytes.last()) {
+        report.push_str(&format!(" in {:03X}-{:03X}", first, last));
+    }
+    report.push('\n');
+    match call_depth(&functions, START_ADDRESS, &mut Vec::new()) {
+        Some(depth) => report.push_str(&format!("max call depth: {}\n", depth)),
+        None => report.push_str("max call depth: unbounded (recursive calls)\n"),
+    }
+    let touched = sprite_bytes.last().copied().max(highest_write).max(Some(end.saturating_sub(1)));
+    report.push_str(&format!("memory footprint: {:03X}-{:03X}\n", START_ADDRESS, touched.unwrap_or(START_ADDRESS)));
+
+    let mut classes: Vec<(&str, usize)> = histogram.into_iter().collect();
+    classes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    report.push_str("opcode histogram:\n");
+    for (class, count) in classes {
+        report.push_str(&format!("    {}  {:>5}\n", class, count));
+    }
+    report
+}
+
+// Follow control flow from `entry` without descending into subroutine calls
+fn walk_function(rom: &[u8], entry: usize) -> Function {
+    let end = START_ADDRESS + rom.len();
+    let mut function = Function { instructions: Vec::new(), callees: BTreeSet::new() };
+    let mut visited = HashSet::new();
+    let mut pending = vec![(entry, None::<u16>)];
+
+    while let Some((pc, i)) = pending.pop() {
+        if pc < START_ADDRESS || pc + 1 >= end || !visited.insert(pc) {
+            continue;
+        }
+        let op = (rom[pc - START_ADDRESS] as u16) << 8 | rom[pc + 1 - START_ADDRESS] as u16;
+        let nnn = (op & 0x0FFF) as usize;
+        function.instructions.push((pc, op, i));
+
+        match opcode_class(op) {
+            "00EE" | "BNNN" | "????" => {}
+            "1NNN" => pending.push((nnn, None)),
+            "2NNN" => {
+                function.callees.insert(nnn);
+                pending.push((pc + 2, None));
+            }
+            "3XKK" | "4XKK" | "5XY0" | "9XY0" | "EX9E" | "EXA1" => {
+                pending.push((pc + 2, i));
+                pending.push((pc + 4, i));
+            }
+            "ANNN" => pending.push((pc + 2, Some(nnn as u16))),
+            "FX1E" | "FX29" => pending.push((pc + 2, None)),
+            _ => pending.push((pc + 2, i)),
+        }
+    }
+    function
+}
+
+// Longest chain of calls starting at `entry`, or None if a function can call itself
+fn call_depth(functions: &BTreeMap<usize, Function>, entry: usize, path: &mut Vec<usize>) -> Option<usize> {
+    if path.contains(&entry) {
+        return None;
+    }
+    let Some(function) = functions.get(&entry) else { return Some(0) };
+    path.push(entry);
+    let mut deepest = 0;
+    for callee in &function.callees {
+        deepest = deepest.max(call_depth(functions, *callee, path)? + 1);
+    }
+    path.pop();
+    Some(deepest)
+}
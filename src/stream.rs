@@ -0,0 +1,145 @@
+// Remote display over WebSocket, with --stream <[host]:port>: a browser tab opened on
+// http://<host>:<port>/ shows the display, beeps with the buzzer and sends its key presses back,
+// so the emulator can run somewhere without a screen, like a headless Raspberry Pi. The page is
+// served from here too; any number of tabs can watch and all of them can press keys.
+//
+// Protocol, after the usual WebSocket handshake:
+//   server to browser   binary message whenever something changed: background and foreground
+//                       colours as RGB, 6 bytes, then 1 if the buzzer sounds else 0, then the
+//                       display as 256 bytes, 64x32 bits row by row, most significant bit first
+//   browser to server   text message "down <key>" or "up <key>", the key 0-F
+//
+// The address defaults to 127.0.0.1 as with --gdb, so --stream 0.0.0.0:8064 to let other
+// machines connect. They have to use its IP address, and pages of other sites can't connect, see
+// websocket::check_origin.
+
+use std::net::TcpListener;
+
+use crate::chip8::{parse_key, VM};
+use crate::websocket::{self, Connection, Message};
+
+const VIEWER_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>CHIP-8</title>
+<style>
+  body { background: #202020; color: #c8c8c8; font-family: sans-serif; text-align: center; }
+  canvas { width: 640px; max-width: 100%; image-rendering: pixelated; background: #000; margin: 1em; }
+</style>
+</head>
+<body>
+<canvas id="display" width="64" height="32"></canvas>
+<p>Keypad: 1234 / QWER / ASDF / ZXCV. <span id="status">Connecting</span></p>
+<script>
+  const keys = ["KeyX", "Digit1", "Digit2", "Digit3", "KeyQ", "KeyW", "KeyE", "KeyA",
+                "KeyS", "KeyD", "KeyZ", "KeyC", "Digit4", "KeyR", "KeyF", "KeyV"];
+  const context = document.getElementById("display").getContext("2d");
+  const status = document.getElementById("status");
+  const socket = new WebSocket(`ws://${location.host}/`);
+  socket.binaryType = "arraybuffer";
+  let audio = null, gain = null;
+
+  socket.onopen = () => status.textContent = "Connected";
+  socket.onclose = () => status.textContent = "Disconnected";
+  socket.onmessage = (message) => {
+    const bytes = new Uint8Array(message.data);
+    const image = context.createImageData(64, 32);
+    for (let pixel = 0; pixel < 64 * 32; pixel++) {
+      const lit = (bytes[7 + (pixel >> 3)] >> (7 - (pixel & 7))) & 1;
+      image.data.set([...bytes.subarray(lit * 3, lit * 3 + 3), 255], pixel * 4);
+    }
+    context.putImageData(image, 0, 0);
+    if (gain) gain.gain.value = bytes[6] ? 0.1 : 0;
+  };
+
+  function key(event, pressed) {
+    const key = keys.indexOf(event.code);
+    if (key < 0 || event.repeat) return;
+    event.preventDefault();
+    // Browsers only allow sound after the user has done something
+    if (!audio) {
+      audio = new AudioContext();
+      const oscillator = audio.createOscillator();
+      oscillator.type = "square";
+      oscillator.frequency.value = 440;
+      gain = audio.createGain();
+      gain.gain.value = 0;
+      oscillator.connect(gain).connect(audio.destination);
+      oscillator.start();
+    }
+    if (socket.readyState === WebSocket.OPEN) socket.send(`${pressed ? "down" : "up"} ${key.toString(16)}`);
+  }
+  addEventListener("keydown", (event) => key(event, true));
+  addEventListener("keyup", (event) => key(event, false));
+</script>
+</body>
+</html>
+"#;
+
+pub struct StreamServer {
+    listener: TcpListener,
+    viewers: Vec<Connection>,
+    // The last message sent, so unchanged frames aren't sent again
+    last: Vec<u8>,
+}
+
+impl StreamServer {
+    pub fn listen(address: &str) -> Result<Self, String> {
+        let (listener, address) = websocket::listen(address, "stream server")?;
+        println!("Streaming the display on http://{}/", address);
+        Ok(Self { listener, viewers: Vec::new(), last: Vec::new() })
+    }
+
+    // Accept connections and pass on the keys the browsers pressed and released
+    pub fn poll(&mut self, mut set_key: impl FnMut(usize, bool)) {
+        self.viewers.extend(websocket::accept(&self.listener));
+        for viewer in &mut self.viewers {
+            viewer.read();
+            if let Some(request) = viewer.request() {
+                if let Err(e) = websocket::check_origin(&request, &self.listener) {
+                    viewer.respond("403 Forbidden", "text/plain; charset=utf-8", format!("{}\n", e).as_bytes());
+                    continue;
+                }
+                match request.websocket_key {
+                    Some(key) => {
+                        println!("Stream viewer connected from {}", viewer.peer());
+                        viewer.upgrade(&key);
+                        if !self.last.is_empty() {
+                            viewer.send(&Message::Binary(self.last.clone()));
+                        }
+                    }
+                    None => viewer.respond("200 OK", "text/html; charset=utf-8", VIEWER_HTML.as_bytes()),
+                }
+            }
+            for message in viewer.messages() {
+                let Message::Text(text) = message else { continue };
+                match text.split_once(' ') {
+                    Some(("down", key)) => parse_key(key).into_iter().for_each(|key| set_key(key, true)),
+                    Some(("up", key)) => parse_key(key).into_iter().for_each(|key| set_key(key, false)),
+                    _ => {}
+                }
+            }
+        }
+        self.viewers.retain(|viewer| !viewer.finished());
+    }
+
+    // Send the display to the browsers if it changed
+    pub fn send(&mut self, vm: &VM) {
+        let message = display_message(vm);
+        if message == self.last {
+            return;
+        }
+        for viewer in self.viewers.iter_mut().filter(|viewer| viewer.open) {
+            viewer.send(&Message::Binary(message.clone()));
+        }
+        self.last = message;
+    }
+}
+
+fn display_message(vm: &VM) -> Vec<u8> {
+    let mut message = vm.display_palette().concat();
+    message.push((vm.sound > 0 && !vm.paused) as u8);
+    message.extend(vm.display.chunks(8).map(|pixels| pixels.iter().fold(0, |byte, pixel| byte << 1 | (*pixel != 0) as u8)));
+    message
+}
@@ -0,0 +1,68 @@
+// Symbol tables mapping addresses to label names, used by the disassembler, tracer and debugger.
+// Loaded from Octo sources automatically, or from a --symbols file with one "addr=name" per line
+// (either order is accepted, so "name=addr" and Octo's ": name" exports work too).
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+
+use crate::command::parse_number;
+
+#[derive(Default)]
+pub struct Symbols {
+    names: BTreeMap<u16, String>,
+}
+
+impl Symbols {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("Error loading symbols {}, {}", path, e))?;
+        let mut symbols = Self::default();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (left, right) = line
+                .split_once('=')
+                .or_else(|| line.split_once(char::is_whitespace))
+                .ok_or_else(|| format!("{}:{}: expected addr=name", path, index + 1))?;
+            let (left, right) = (left.trim(), right.trim());
+            match (parse_number(left), parse_number(right)) {
+                (Ok(address), _) => symbols.insert(address, right),
+                (_, Ok(address)) => symbols.insert(address, left),
+                _ => return Err(format!("{}:{}: no address in '{}'", path, index + 1, line)),
+            }
+        }
+        Ok(symbols)
+    }
+
+    pub fn from_labels(labels: &HashMap<String, u16>) -> Self {
+        let mut symbols = Self::default();
+        for (name, address) in labels {
+            symbols.insert(*address, name);
+        }
+        symbols
+    }
+
+    // Addresses keep the first name they were given
+    pub fn insert(&mut self, address: u16, name: &str) {
+        self.names.entry(address).or_insert_with(|| name.trim_start_matches(':').trim().to_string());
+    }
+
+    pub fn merge(&mut self, other: Symbols) {
+        for (address, name) in other.names {
+            self.names.entry(address).or_insert(name);
+        }
+    }
+
+    pub fn name(&self, address: u16) -> Option<&str> {
+        self.names.get(&address).map(String::as_str)
+    }
+
+    // The label for `address`, or the address in hex if it has none
+    pub fn address(&self, address: u16) -> String {
+        match self.name(address) {
+            Some(name) => name.to_string(),
+            None => format!("{:#05x}", address),
+        }
+    }
+}
@@ -0,0 +1,37 @@
+// Window title showing the rom, the machine it runs as and what the emulator is doing
+
+use std::path::Path;
+
+use crate::chip8::VM;
+use crate::movie::MovieMode;
+
+// Only plain CHIP-8 is emulated for now; this becomes the selected variant once there is a choice
+const VARIANT: &str = "CHIP-8";
+
+impl VM<'_> {
+    pub fn window_title(&self) -> String {
+        let rom = Path::new(&self.rom_path).file_name().and_then(|name| name.to_str()).unwrap_or("no rom");
+        let mut title = format!("{} - {}", rom, VARIANT);
+        if self.paused {
+            title.push_str(" [PAUSED]");
+        }
+        match self.movie.as_ref().map(|movie| &movie.mode) {
+            Some(MovieMode::Recording) => title.push_str(" [RECORDING MOVIE]"),
+            Some(MovieMode::Playing) => title.push_str(" [PLAYBACK]"),
+            None => {}
+        }
+        if self.gif.is_some() || self.video.is_some() || self.wav.is_some() {
+            title.push_str(" [CAPTURING]");
+        }
+        title
+    }
+
+    // Called every pass of the main loop, the window is only touched when the title changes
+    pub fn update_title(&mut self) -> Result<(), String> {
+        let title = self.window_title();
+        if self.canvas.window().title() != title {
+            self.canvas.window_mut().set_title(&title).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
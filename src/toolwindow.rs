@@ -0,0 +1,146 @@
+// Extra windows next to the display, opened with --tool-windows: the register panel, the memory
+// view or the keypad, each in its own window that keeps updating while the rom runs.
+//
+// The overlays draw on the VM's canvas, so a tool window's canvas is swapped in for the duration
+// of its draw. Events are routed by window id: the memory window takes the memory view keys and
+// typing, everything else falls through to the main loop so the game stays playable whichever
+// window has focus. Closing a tool window only closes that window.
+
+use std::mem;
+use std::time::{Duration, Instant};
+
+use sdl2::event::{Event, WindowEvent};
+use sdl2::pixels::Color;
+use sdl2::render::WindowCanvas;
+use sdl2::VideoSubsystem;
+
+use crate::chip8::VM;
+use crate::command;
+use crate::hud::keypad_size;
+use crate::memview::KeyResult;
+
+const KEYPAD_CELL: u32 = 36;
+const REDRAW_INTERVAL: Duration = Duration::from_millis(33);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ToolView {
+    Registers,
+    Memory,
+    Keypad,
+}
+
+impl ToolView {
+    fn title(self) -> &'static str {
+        match self {
+            ToolView::Registers => "CHIP-8 registers",
+            ToolView::Memory => "CHIP-8 memory",
+            ToolView::Keypad => "CHIP-8 keypad",
+        }
+    }
+
+    fn size(self) -> (u32, u32) {
+        match self {
+            ToolView::Registers => (480, 420),
+            ToolView::Memory => (680, 330),
+            ToolView::Keypad => (keypad_size(KEYPAD_CELL), keypad_size(KEYPAD_CELL)),
+        }
+    }
+}
+
+pub fn parse_tool_views(text: &str) -> Result<Vec<ToolView>, String> {
+    text.split(',')
+        .map(|view| match view.trim() {
+            "registers" => Ok(ToolView::Registers),
+            "memory" => Ok(ToolView::Memory),
+            "keypad" => Ok(ToolView::Keypad),
+            other => Err(format!("unknown tool window {}, expected registers, memory or keypad", other)),
+        })
+        .collect()
+}
+
+struct ToolWindow {
+    view: ToolView,
+    canvas: WindowCanvas,
+}
+
+pub struct ToolWindows {
+    windows: Vec<ToolWindow>,
+    next_redraw: Instant,
+}
+
+impl ToolWindows {
+    pub fn open(video: &VideoSubsystem, views: &[ToolView]) -> Result<Self, String> {
+        let mut windows = Vec::new();
+        for view in views {
+            let (width, height) = view.size();
+            let window = video.window(view.title(), width, height)
+                .allow_highdpi()
+                .build()
+                .map_err(|e| e.to_string())?;
+            let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+            windows.push(ToolWindow { view: *view, canvas });
+        }
+        Ok(Self { windows, next_redraw: Instant::now() })
+    }
+
+    // Handle an event meant for one of the tool windows, false if it should go to the main loop
+    pub fn handle_event(&mut self, vm: &mut VM, event: &Event) -> bool {
+        let Some(index) = event.get_window_id().and_then(|id| self.windows.iter().position(|w| w.canvas.window().id() == id)) else {
+            return false;
+        };
+        let view = self.windows[index].view;
+        match event {
+            Event::Window { win_event: WindowEvent::Close, .. } => {
+                self.windows.remove(index);
+            }
+            Event::Window { .. } => self.next_redraw = Instant::now(),
+            Event::KeyDown { keycode: Some(k), .. } if view == ToolView::Memory => {
+                match vm.memory_view.handle_key(*k) {
+                    KeyResult::Ignored => return false,
+                    KeyResult::Handled => {}
+                    KeyResult::Command(line) => {
+                        vm.memory_view.message = match command::execute(vm, &line) {
+                            Ok(message) => message,
+                            Err(error) => format!("ERROR: {}", error),
+                        };
+                    }
+                }
+                self.next_redraw = Instant::now();
+            }
+            Event::TextInput { text, .. } if view == ToolView::Memory && vm.memory_view.input.is_some() => {
+                vm.memory_view.handle_text(text);
+                self.next_redraw = Instant::now();
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // Redraw every tool window, at most about 30 times a second
+    pub fn draw(&mut self, vm: &mut VM) -> Result<(), String> {
+        let now = Instant::now();
+        if now < self.next_redraw {
+            return Ok(());
+        }
+        self.next_redraw = now + REDRAW_INTERVAL;
+
+        for window in &mut self.windows {
+            mem::swap(&mut vm.canvas, &mut window.canvas);
+            let result = draw_view(vm, window.view);
+            mem::swap(&mut vm.canvas, &mut window.canvas);
+            result?;
+            window.canvas.present();
+        }
+        Ok(())
+    }
+}
+
+fn draw_view(vm: &mut VM, view: ToolView) -> Result<(), String> {
+    vm.canvas.set_draw_color(Color::RGB(0, 0, 0));
+    vm.canvas.clear();
+    match view {
+        ToolView::Registers => vm.draw_hud(),
+        ToolView::Memory => vm.draw_memory_view(),
+        ToolView::Keypad => vm.draw_keypad(0, 0, KEYPAD_CELL),
+    }
+}
@@ -0,0 +1,128 @@
+// Instruction tracer, one line per executed instruction:
+// PC, opcode, mnemonic and the registers the instruction changed
+
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+
+use crate::disasm::{disassemble, disassemble_with};
+use crate::symbols::Symbols;
+
+// Registers as they were before an instruction ran
+pub struct Registers {
+    pub v: [u8; 16],
+    pub i: u16,
+    pub sp: u16,
+    pub delay: u8,
+    pub sound: u8,
+}
+
+pub struct Tracer {
+    writer: BufWriter<File>,
+}
+
+impl Tracer {
+    pub fn create(path: &str) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("Error creating trace file {}, {}", path, e))?;
+        Ok(Self { writer: BufWriter::new(file) })
+    }
+
+    pub fn record(&mut self, pc: u16, op: u16, before: &Registers, after: &Registers, symbols: &Symbols) {
+        let mut line = format!("{:03X}  {:04X}  {:<16}", pc, op, disassemble_with(op, symbols));
+        for (r, (old, new)) in before.v.iter().zip(after.v.iter()).enumerate() {
+            if old != new {
+                line.push_str(&format!(" V{:X}:{:02X}->{:02X}", r, old, new));
+            }
+        }
+        if before.i != after.i {
+            line.push_str(&format!(" I:{:03X}->{:03X}", before.i, after.i));
+        }
+        if before.sp != after.sp {
+            line.push_str(&format!(" SP:{:X}->{:X}", before.sp, after.sp));
+        }
+        if before.delay != after.delay {
+            line.push_str(&format!(" DT:{:02X}->{:02X}", before.delay, after.delay));
+        }
+        if before.sound != after.sound {
+            line.push_str(&format!(" ST:{:02X}->{:02X}", before.sound, after.sound));
+        }
+        if let Some(label) = symbols.name(pc) {
+            line.push_str(&format!("  <{}>", label));
+        }
+
+        // A failing trace write should not take the emulator down with it
+        let _ = writeln!(self.writer, "{}", line.trim_end());
+    }
+
+    // Free-form line from a tracepoint. The leading '#' keeps it out of --compare-trace.
+    pub fn note(&mut self, pc: u16, message: &str) {
+        let _ = writeln!(self.writer, "# {:03X}  {}", pc, message);
+    }
+}
+
+// Checks executed instructions against a reference trace, e.g. one written by --trace or by
+// another emulator. Only the first two hex columns of each line (PC and opcode) are compared.
+pub struct TraceComparer {
+    reference: Vec<(usize, u16, u16, String)>,
+    position: usize,
+    history: VecDeque<String>,
+    finished: bool,
+}
+
+const HISTORY_LENGTH: usize = 8;
+
+impl TraceComparer {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("Error loading reference trace {}, {}", path, e))?;
+        let mut reference = Vec::new();
+        for (index, line) in content.lines().enumerate() {
+            let mut columns = line.split_whitespace().map(|c| c.trim_start_matches("0x").trim_end_matches(':'));
+            let pc = columns.next().and_then(|c| u16::from_str_radix(c, 16).ok());
+            let op = columns.next().and_then(|c| u16::from_str_radix(c, 16).ok());
+            if let (Some(pc), Some(op)) = (pc, op) {
+                reference.push((index + 1, pc, op, line.to_string()));
+            }
+        }
+        if reference.is_empty() {
+            return Err(format!("Reference trace {} contains no instructions", path));
+        }
+
+        Ok(Self { reference, position: 0, history: VecDeque::new(), finished: false })
+    }
+
+    // Returns a report describing the divergence if the instruction is not the expected one
+    pub fn check(&mut self, pc: u16, op: u16, registers: &Registers) -> Result<(), String> {
+        if self.finished {
+            return Ok(());
+        }
+        let Some((line, expected_pc, expected_op, raw)) = self.reference.get(self.position) else {
+            println!("Reference trace ended after {} matching instructions", self.position);
+            self.finished = true;
+            return Ok(());
+        };
+
+        let actual = format!("{:03X}  {:04X}  {}", pc, op, disassemble(op));
+        if (*expected_pc, *expected_op) != (pc, op) {
+            let mut report = format!("Trace diverged at instruction {} (reference line {})\n", self.position + 1, line);
+            report.push_str("Last matching instructions:\n");
+            for previous in &self.history {
+                report.push_str(&format!("    {}\n", previous));
+            }
+            report.push_str(&format!("Expected: {}\n", raw.trim()));
+            report.push_str(&format!("Actual:   {}\n", actual));
+            report.push_str(&format!("Registers: I:{:03X} SP:{:X} DT:{:02X} ST:{:02X}", registers.i, registers.sp, registers.delay, registers.sound));
+            for (r, value) in registers.v.iter().enumerate() {
+                report.push_str(&format!(" V{:X}:{:02X}", r, value));
+            }
+            self.finished = true;
+            return Err(report);
+        }
+
+        if self.history.len() == HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(actual);
+        self.position += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+// Terminal frontend, for SSH sessions and the like: chip8-rust --tui <rom>, in builds with the tui
+// feature (cargo build --features tui). The display is drawn with half block characters, two
+// CHIP-8 rows per line, so it needs a terminal of at least 64x17. Options:
+//   --ipf <n>                 instructions executed per 60 Hz frame (default 8)
+//   --seed <n>                seed the random number generator
+//   --key-wait <policy>       which held key FX0A reports, see keywait.rs
+//   --mute                    don't ring the terminal bell when the buzzer starts
+//
+// The keypad is the 1234/QWER/ASDF/ZXCV block, P pauses and Esc or Ctrl+C quits. Most terminals
+// only report key presses, so a press holds the key for half a second, about as long as key repeat
+// takes to start, and each repeat extends it. Terminals with the kitty keyboard protocol report
+// releases as well and keys are held for exactly as long as they are down.
+//
+// In builds with the sdl feature SDL still has to initialize, on its dummy video driver, since the
+// emulator core keeps its canvas; nothing is shown.
+
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant};
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
+    PushKeyboardEnhancementFlags,
+};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use crate::chip8::VM;
+use crate::keywait::{parse_key_wait, KeyWaitPolicy};
+
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+// How long a press holds a key when the terminal doesn't report releases, and how long a repeat
+// extends it
+const PRESS_HOLD: Duration = Duration::from_millis(500);
+const REPEAT_HOLD: Duration = Duration::from_millis(100);
+
+// Keypad keys in the order of the CHIP-8 keypad, 1 2 3 C / 4 5 6 D / 7 8 9 E / A 0 B F
+const KEYS: [(char, usize); 16] = [
+    ('1', 0x1), ('2', 0x2), ('3', 0x3), ('4', 0xC),
+    ('q', 0x4), ('w', 0x5), ('e', 0x6), ('r', 0xD),
+    ('a', 0x7), ('s', 0x8), ('d', 0x9), ('f', 0xE),
+    ('z', 0xA), ('x', 0x0), ('c', 0xB), ('v', 0xF),
+];
+
+struct Options {
+    rom: String,
+    instructions_per_frame: u32,
+    seed: Option<u64>,
+    key_wait: KeyWaitPolicy,
+    mute: bool,
+}
+
+fn parse_options(args: Vec<String>) -> Result<Options, String> {
+    let mut options = Options { rom: String::new(), instructions_per_frame: 8, seed: None, key_wait: KeyWaitPolicy::Lowest, mute: false };
+    let mut rom = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tui" => {}
+            "--ipf" => {
+                let value = args.next().ok_or("--ipf needs a number")?;
+                options.instructions_per_frame = match value.parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(format!("invalid instructions per frame {}", value)),
+                };
+            }
+            "--seed" => {
+                let value = args.next().ok_or("--seed needs a number")?;
+                options.seed = Some(value.parse().map_err(|_| format!("invalid seed {}", value))?);
+            }
+            "--key-wait" => options.key_wait = parse_key_wait(&args.next().ok_or("--key-wait needs a policy")?)?,
+            "--mute" => options.mute = true,
+            _ if arg.starts_with("--") => return Err(format!("unknown option {} with --tui", arg)),
+            _ => rom = Some(arg),
+        }
+    }
+    options.rom = rom.ok_or("usage: chip8-rust --tui [options] <rom>")?;
+    Ok(options)
+}
+
+// Keys held by the terminal, each until it is released or its hold runs out
+struct TerminalKeys {
+    releases: bool,
+    held: [Option<Instant>; 16],
+}
+
+impl TerminalKeys {
+    fn key_event(&mut self, key: usize, kind: KeyEventKind, now: Instant) {
+        self.held[key] = match kind {
+            KeyEventKind::Release => None,
+            // Without releases a press can't be told from a repeat, except that the key is
+            // already held
+            _ if self.releases => Some(now + Duration::from_secs(3600)),
+            _ => Some(self.held[key].map_or(now + PRESS_HOLD, |until| until.max(now + REPEAT_HOLD))),
+        };
+    }
+
+    fn update(&mut self, vm: &mut VM, now: Instant) {
+        for (key, until) in self.held.iter_mut().enumerate() {
+            if until.is_some_and(|until| until <= now) {
+                *until = None;
+            }
+            vm.keypad[key] = until.is_some();
+        }
+    }
+}
+
+// The terminal in raw mode on the alternate screen, put back however the frontend exits
+struct Terminal {
+    stdout: Stdout,
+    releases: bool,
+}
+
+impl Terminal {
+    fn open() -> io::Result<Self> {
+        let mut stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(stdout, EnterAlternateScreen, Hide)?;
+        let releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if releases {
+            execute!(stdout, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+        }
+        Ok(Self { stdout, releases })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if self.releases {
+            let _ = execute!(self.stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(self.stdout, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn draw(stdout: &mut Stdout, vm: &VM, status: &str) -> io::Result<()> {
+    let (columns, rows) = terminal::size()?;
+    queue!(stdout, Clear(ClearType::All), MoveTo(0, 0))?;
+    if columns < 64 || rows < 17 {
+        queue!(stdout, Print(format!("The terminal needs to be at least 64x17, it is {}x{}", columns, rows)))?;
+        return stdout.flush();
+    }
+    for line in 0..16 {
+        let text: String = (0..64)
+            .map(|x| match (vm.display[line * 128 + x] != 0, vm.display[line * 128 + 64 + x] != 0) {
+                (true, true) => '\u{2588}',
+                (true, false) => '\u{2580}',
+                (false, true) => '\u{2584}',
+                (false, false) => ' ',
+            })
+            .collect();
+        queue!(stdout, MoveTo(0, line as u16), Print(text))?;
+    }
+    queue!(stdout, MoveTo(0, 16), Print(status))?;
+    stdout.flush()
+}
+
+#[cfg(feature = "sdl")]
+fn offscreen_canvas() -> Result<(sdl2::Sdl, sdl2::render::WindowCanvas), String> {
+    sdl2::hint::set("SDL_VIDEODRIVER", "dummy");
+    let sdl_context = sdl2::init()?;
+    let window = sdl_context.video()?.window("CHIP-8", 64, 32).hidden().build().map_err(|e| e.to_string())?;
+    let canvas = window.into_canvas().software().build().map_err(|e| e.to_string())?;
+    Ok((sdl_context, canvas))
+}
+
+pub fn run(args: Vec<String>) -> Result<(), String> {
+    let options = parse_options(args)?;
+    #[cfg(feature = "sdl")]
+    let (_sdl_context, canvas) = offscreen_canvas()?;
+    #[cfg(feature = "sdl")]
+    let texture_creator = canvas.texture_creator();
+    #[cfg(feature = "sdl")]
+    let mut vm = VM::new(canvas, &texture_creator);
+    #[cfg(not(feature = "sdl"))]
+    let mut vm = VM::new();
+    vm.key_wait_policy = options.key_wait;
+    vm.init_font_set();
+    vm.load_rom(&options.rom)?;
+    if let Some(seed) = options.seed {
+        vm.reseed(seed, 0);
+    }
+
+    let mut terminal = Terminal::open().map_err(|e| format!("Error setting up the terminal, {}", e))?;
+    let mut keys = TerminalKeys { releases: terminal.releases, held: [None; 16] };
+    let name = options.rom.rsplit(['/', '\\']).next().unwrap_or(&options.rom).to_string();
+    let mut shown = None;
+    let mut next_frame = Instant::now();
+    loop {
+        while event::poll(next_frame.saturating_duration_since(Instant::now())).map_err(|e| e.to_string())? {
+            let now = Instant::now();
+            match event::read().map_err(|e| e.to_string())? {
+                Event::Key(KeyEvent { code: KeyCode::Esc, kind: KeyEventKind::Press, .. }) => return Ok(()),
+                Event::Key(KeyEvent { code: KeyCode::Char('c'), modifiers, kind: KeyEventKind::Press, .. }) if modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                Event::Key(KeyEvent { code: KeyCode::Char('p'), kind: KeyEventKind::Press, .. }) => {
+                    if vm.paused { vm.resume() } else { vm.pause() }
+                }
+                Event::Key(KeyEvent { code: KeyCode::Char(c), kind, .. }) => {
+                    if let Some((_, key)) = KEYS.iter().find(|(bound, _)| *bound == c.to_ascii_lowercase()) {
+                        keys.key_event(*key, kind, now);
+                    }
+                }
+                // Redraw everything at the new size
+                Event::Resize(..) => shown = None,
+                _ => {}
+            }
+        }
+
+        let now = Instant::now();
+        keys.update(&mut vm, now);
+        if !vm.paused {
+            let buzzing = vm.sound > 0;
+            vm.step_frame(options.instructions_per_frame)?;
+            if !buzzing && vm.sound > 0 && !options.mute {
+                queue!(terminal.stdout, Print('\u{7}')).map_err(|e| e.to_string())?;
+            }
+        }
+
+        let status = match &vm.pause_message {
+            Some(message) => format!("{}  {}  P resume, Esc quit", name, message),
+            None => format!("{}  P pause, Esc quit", name),
+        };
+        if shown.as_ref() != Some(&(vm.display, status.clone())) {
+            draw(&mut terminal.stdout, &vm, &status).map_err(|e| e.to_string())?;
+            shown = Some((vm.display, status));
+        }
+        next_frame = (next_frame + FRAME).max(now);
+    }
+}
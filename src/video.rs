@@ -0,0 +1,90 @@
+// Video capture through an ffmpeg child process. Raw RGB frames are piped to ffmpeg at 60 fps
+// while the buzzer is rendered to a temporary PCM file; when recording stops a second ffmpeg run
+// muxes the two into the requested file.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+const SAMPLE_RATE: u32 = 44100;
+const SAMPLES_PER_FRAME: u32 = SAMPLE_RATE / 60;
+const TONE: u32 = 440;
+
+pub struct VideoRecorder {
+    ffmpeg: Child,
+    frames: Option<ChildStdin>,
+    audio: BufWriter<File>,
+    pub path: String,
+    video_path: String,
+    audio_path: String,
+    scale: usize,
+    palette: [[u8; 3]; 2],
+    // Position in the buzzer square wave, in samples
+    phase: u32,
+}
+
+impl VideoRecorder {
+    pub fn create(path: &str, scale: usize, palette: [[u8; 3]; 2]) -> Result<Self, String> {
+        let video_path = format!("{}.video.mkv", path);
+        let audio_path = format!("{}.audio.pcm", path);
+        let size = format!("{}x{}", WIDTH * scale, HEIGHT * scale);
+        let mut ffmpeg = Command::new("ffmpeg")
+            .args(["-loglevel", "error", "-y", "-f", "rawvideo", "-pix_fmt", "rgb24", "-s", &size, "-r", "60", "-i", "-"])
+            .args(["-c:v", "libx264", "-preset", "veryfast", "-crf", "0", "-pix_fmt", "yuv444p", &video_path])
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Error starting ffmpeg, is it installed? {}", e))?;
+        let frames = ffmpeg.stdin.take();
+        let audio = File::create(&audio_path).map_err(|e| format!("Error creating {}, {}", audio_path, e))?;
+
+        Ok(Self { ffmpeg, frames, audio: BufWriter::new(audio), path: path.to_string(), video_path, audio_path, scale, palette, phase: 0 })
+    }
+
+    // Called once per 60 Hz frame with the display and whether the buzzer is sounding
+    pub fn add_frame(&mut self, display: &[u8; WIDTH * HEIGHT], buzzing: bool) -> Result<(), String> {
+        let scale = self.scale;
+        let mut rgb = Vec::with_capacity(WIDTH * HEIGHT * scale * scale * 3);
+        for y in 0..HEIGHT * scale {
+            for x in 0..WIDTH * scale {
+                rgb.extend_from_slice(&self.palette[display[(y / scale) * WIDTH + x / scale].min(1) as usize]);
+            }
+        }
+        let frames = self.frames.as_mut().ok_or("ffmpeg input is closed")?;
+        frames.write_all(&rgb).map_err(|e| format!("Error piping video to ffmpeg, {}", e))?;
+
+        let mut samples = Vec::with_capacity(SAMPLES_PER_FRAME as usize * 2);
+        for _ in 0..SAMPLES_PER_FRAME {
+            let high = (self.phase * TONE * 2 / SAMPLE_RATE).is_multiple_of(2);
+            let sample: i16 = if !buzzing { 0 } else if high { 6000 } else { -6000 };
+            samples.extend_from_slice(&sample.to_le_bytes());
+            self.phase = (self.phase + 1) % SAMPLE_RATE;
+        }
+        self.audio.write_all(&samples).map_err(|e| format!("Error writing {}, {}", self.audio_path, e))
+    }
+
+    // Wait for the video encode, then mux in the audio and clean up the temporary files
+    pub fn finish(mut self) -> Result<(), String> {
+        // Closing ffmpeg's input ends the encode
+        self.frames = None;
+        self.audio.flush().map_err(|e| format!("Error writing {}, {}", self.audio_path, e))?;
+        let status = self.ffmpeg.wait().map_err(|e| format!("Error waiting for ffmpeg, {}", e))?;
+        if !status.success() {
+            return Err(format!("ffmpeg failed encoding {}", self.path));
+        }
+
+        let rate = SAMPLE_RATE.to_string();
+        let status = Command::new("ffmpeg")
+            .args(["-loglevel", "error", "-y", "-i", &self.video_path, "-f", "s16le", "-ar", &rate, "-ac", "1", "-i", &self.audio_path])
+            .args(["-c:v", "copy", "-c:a", "aac", "-shortest", &self.path])
+            .status()
+            .map_err(|e| format!("Error starting ffmpeg, {}", e))?;
+        if !status.success() {
+            return Err(format!("ffmpeg failed writing {}, the video alone is in {}", self.path, self.video_path));
+        }
+        let _ = fs::remove_file(&self.video_path);
+        let _ = fs::remove_file(&self.audio_path);
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+// Watch expressions shown live in the HUD and debugger. An expression combines registers
+// (V0-VF, I, PC, SP, DT, ST), numbers and memory reads ([addr] reads a byte) with + - * / % & | ^
+// << >> and parentheses, e.g. "[I+2]", "V3 * 256 + V4" or "[0x3A0] & 0x0F".
+
+use crate::chip8::VM;
+use crate::command::parse_number;
+
+pub struct Watch {
+    pub source: String,
+    expression: Expr,
+}
+
+enum Expr {
+    Number(u16),
+    Register(Register),
+    Memory(Box<Expr>),
+    Binary(Box<Expr>, Operator, Box<Expr>),
+}
+
+#[derive(Clone, Copy)]
+enum Register {
+    V(usize),
+    I,
+    Pc,
+    Sp,
+    Delay,
+    Sound,
+}
+
+#[derive(Clone, Copy)]
+enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+}
+
+impl Watch {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, position: 0 };
+        let expression = parser.expression(0)?;
+        if let Some(token) = parser.tokens.get(parser.position) {
+            return Err(format!("unexpected '{}' in watch expression", token));
+        }
+        Ok(Self { source: source.to_string(), expression })
+    }
+
+    pub fn evaluate(&self, vm: &VM) -> u16 {
+        evaluate(&self.expression, vm)
+    }
+
+    // "SOURCE = 12 (0C)" as shown in the panels
+    pub fn describe(&self, vm: &VM) -> String {
+        let value = self.evaluate(vm);
+        if value > 0xFF {
+            format!("{} = {} ({:04X})", self.source, value, value)
+        } else {
+            format!("{} = {} ({:02X})", self.source, value, value)
+        }
+    }
+}
+
+// Replace every {expression} in `template` with its value in hex, e.g. "score {[I]} lives {V5}"
+pub fn format_message(template: &str, vm: &VM) -> Result<String, String> {
+    let mut message = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}').ok_or("missing '}' in message")? + start;
+        message.push_str(&rest[..start]);
+        let value = Watch::parse(&rest[start + 1..end])?.evaluate(vm);
+        message.push_str(&format!("{:02X}", value));
+        rest = &rest[end + 1..];
+    }
+    message.push_str(rest);
+    Ok(message)
+}
+
+fn evaluate(expression: &Expr, vm: &VM) -> u16 {
+    match expression {
+        Expr::Number(value) => *value,
+        Expr::Register(register) => match register {
+            Register::V(index) => vm.v[*index] as u16,
+            Register::I => vm.i,
+            Register::Pc => vm.pc,
+            Register::Sp => vm.sp,
+            Register::Delay => vm.delay as u16,
+            Register::Sound => vm.sound as u16,
+        },
+        Expr::Memory(address) => vm.memory[evaluate(address, vm) as usize % 4096] as u16,
+        Expr::Binary(left, operator, right) => {
+            let (a, b) = (evaluate(left, vm), evaluate(right, vm));
+            match operator {
+                Operator::Add => a.wrapping_add(b),
+                Operator::Sub => a.wrapping_sub(b),
+                Operator::Mul => a.wrapping_mul(b),
+                Operator::Div => a.checked_div(b).unwrap_or(0),
+                Operator::Rem => a.checked_rem(b).unwrap_or(0),
+                Operator::And => a & b,
+                Operator::Or => a | b,
+                Operator::Xor => a ^ b,
+                Operator::Shl => a.checked_shl(b as u32).unwrap_or(0),
+                Operator::Shr => a.checked_shr(b as u32).unwrap_or(0),
+            }
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' => {}
+            '<' | '>' => {
+                if chars.next() != Some(c) {
+                    return Err(format!("expected '{}{}' in watch expression", c, c));
+                }
+                tokens.push(format!("{}{}", c, c));
+            }
+            '+' | '-' | '*' | '/' | '%' | '&' | '|' | '^' | '(' | ')' | '[' | ']' => tokens.push(c.to_string()),
+            c if c.is_ascii_alphanumeric() || c == '$' => {
+                let mut word = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if !next.is_ascii_alphanumeric() {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+                tokens.push(word);
+            }
+            _ => return Err(format!("unexpected '{}' in watch expression", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            _ => Err(format!("expected '{}' in watch expression", expected)),
+        }
+    }
+
+    // Precedence climbing, loosest binding first: | ^ & << >> + - * / %
+    fn expression(&mut self, level: usize) -> Result<Expr, String> {
+        const LEVELS: [&[(&str, Operator)]; 6] = [
+            &[("|", Operator::Or)],
+            &[("^", Operator::Xor)],
+            &[("&", Operator::And)],
+            &[("<<", Operator::Shl), (">>", Operator::Shr)],
+            &[("+", Operator::Add), ("-", Operator::Sub)],
+            &[("*", Operator::Mul), ("/", Operator::Div), ("%", Operator::Rem)],
+        ];
+        if level == LEVELS.len() {
+            return self.operand();
+        }
+
+        let mut left = self.expression(level + 1)?;
+        while let Some(token) = self.tokens.get(self.position) {
+            let Some((_, operator)) = LEVELS[level].iter().find(|(symbol, _)| symbol == token) else { break };
+            self.position += 1;
+            let right = self.expression(level + 1)?;
+            left = Expr::Binary(Box::new(left), *operator, Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn operand(&mut self) -> Result<Expr, String> {
+        let token = self.next().ok_or("watch expression ends early")?;
+        match token.as_str() {
+            "(" => {
+                let inner = self.expression(0)?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            "[" => {
+                let address = self.expression(0)?;
+                self.expect("]")?;
+                Ok(Expr::Memory(Box::new(address)))
+            }
+            _ => {
+                let register = match token.to_ascii_uppercase().as_str() {
+                    "I" => Some(Register::I),
+                    "PC" => Some(Register::Pc),
+                    "SP" => Some(Register::Sp),
+                    "DT" => Some(Register::Delay),
+                    "ST" => Some(Register::Sound),
+                    name if name.len() == 2 && name.starts_with('V') => usize::from_str_radix(&name[1..], 16).ok().map(Register::V),
+                    _ => None,
+                };
+                match register {
+                    Some(register) => Ok(Expr::Register(register)),
+                    None => parse_number(&token).map(Expr::Number),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,78 @@
+// Buzzer capture to a 16-bit mono WAV file. Samples come from the same generator as the speakers,
+// so waveform, pitch, volume and envelope match what you hear, but they are produced per emulated
+// frame from the sound timer and don't depend on an audio device, or on it being muted.
+
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+
+use crate::audio::Tone;
+use crate::config::Config;
+
+const HEADER_SIZE: u32 = 44;
+
+pub struct WavRecorder {
+    file: BufWriter<File>,
+    pub path: String,
+    tone: Tone,
+    sample_rate: u32,
+    samples: u32,
+    // Emulated frames so far, to spread sample_rate / 60 evenly when it isn't a whole number
+    frames: u64,
+}
+
+impl WavRecorder {
+    pub fn create(path: &str, config: &Config) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("Error creating {}, {}", path, e))?;
+        let mut recorder = Self {
+            file: BufWriter::new(file),
+            path: path.to_string(),
+            tone: Tone::new(config, config.sample_rate),
+            sample_rate: config.sample_rate,
+            samples: 0,
+            frames: 0,
+        };
+        // Sizes are patched in by finish
+        recorder.write_header()?;
+        Ok(recorder)
+    }
+
+    // Called once per 60 Hz frame with whether the buzzer is sounding
+    pub fn add_frame(&mut self, buzzing: bool) -> Result<(), String> {
+        self.frames += 1;
+        let due = (self.frames * self.sample_rate as u64 / 60) as u32;
+        self.tone.set_active(buzzing);
+        let mut bytes = Vec::with_capacity((due - self.samples) as usize * 2);
+        for _ in self.samples..due {
+            let sample = (self.tone.next_sample() * i16::MAX as f32) as i16;
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        self.samples = due;
+        self.file.write_all(&bytes).map_err(|e| format!("Error writing {}, {}", self.path, e))
+    }
+
+    // Returns the recorded length in seconds
+    pub fn finish(mut self) -> Result<f64, String> {
+        self.file.seek(SeekFrom::Start(0)).map_err(|e| format!("Error writing {}, {}", self.path, e))?;
+        self.write_header()?;
+        self.file.flush().map_err(|e| format!("Error writing {}, {}", self.path, e))?;
+        Ok(self.samples as f64 / self.sample_rate as f64)
+    }
+
+    fn write_header(&mut self) -> Result<(), String> {
+        let data_size = self.samples * 2;
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(HEADER_SIZE - 8 + data_size).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        header.extend_from_slice(&1u16.to_le_bytes()); // mono
+        header.extend_from_slice(&self.sample_rate.to_le_bytes());
+        header.extend_from_slice(&(self.sample_rate * 2).to_le_bytes()); // bytes per second
+        header.extend_from_slice(&2u16.to_le_bytes()); // bytes per sample frame
+        header.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&data_size.to_le_bytes());
+        self.file.write_all(&header).map_err(|e| format!("Error writing {}, {}", self.path, e))
+    }
+}
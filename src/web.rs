@@ -0,0 +1,154 @@
+// The browser frontend, a wasm-bindgen interface over the emulator for web pages, see lib.rs for
+// building it and web/index.html for a page using it:
+//   const emulator = new Emulator(canvas, romBytes);
+//   requestAnimationFrame(function frame() { emulator.frame(); requestAnimationFrame(frame); });
+//   addEventListener("keydown", (event) => { if (emulator.key(event.code, true)) event.preventDefault(); });
+//   addEventListener("keyup", (event) => emulator.key(event.code, false));
+// The page calls frame() 60 times a second, which runs a frame and draws the display into the
+// 64x32 canvas, scaled up by CSS. Keys are by KeyboardEvent.code, the 1234/QWER/ASDF/ZXCV block
+// wherever the layout puts its letters. The buzzer is a Web Audio square wave, which browsers
+// only allow to start after the user has done something, so it's set up on the first key press.
+//
+// Nothing touches the file system or the clock here: rom settings, macros, RPL flags and the
+// like are read from a data directory the browser doesn't have and fall back to their defaults.
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::Clamped;
+use web_sys::{AudioContext, CanvasRenderingContext2d, GainNode, HtmlCanvasElement, ImageData, OscillatorType};
+
+use crate::chip8::VM;
+use crate::keywait::parse_key_wait;
+use crate::palette::parse_palette;
+
+// Physical keys, by KeyboardEvent.code, for CHIP-8 keys 0 to F
+const KEYS: [&str; 16] = [
+    "KeyX", "Digit1", "Digit2", "Digit3", "KeyQ", "KeyW", "KeyE", "KeyA",
+    "KeyS", "KeyD", "KeyZ", "KeyC", "Digit4", "KeyR", "KeyF", "KeyV",
+];
+const PITCH: f32 = 440.0;
+const VOLUME: f32 = 0.1;
+
+struct Beep {
+    // Kept so the oscillator keeps playing, it is silenced through the gain instead
+    _context: AudioContext,
+    gain: GainNode,
+}
+
+impl Beep {
+    fn new() -> Result<Self, JsValue> {
+        let context = AudioContext::new()?;
+        let oscillator = context.create_oscillator()?;
+        oscillator.set_type(OscillatorType::Square);
+        oscillator.frequency().set_value(PITCH);
+        let gain = context.create_gain()?;
+        gain.gain().set_value(0.0);
+        oscillator.connect_with_audio_node(&gain)?;
+        gain.connect_with_audio_node(&context.destination())?;
+        oscillator.start()?;
+        Ok(Self { _context: context, gain })
+    }
+}
+
+#[wasm_bindgen]
+pub struct Emulator {
+    vm: VM<'static>,
+    context: CanvasRenderingContext2d,
+    beep: Option<Beep>,
+    instructions_per_frame: u32,
+    pub muted: bool,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    // `canvas` should be 64x32, `rom` is the rom's bytes, e.g. from fetch() or a file input
+    #[wasm_bindgen(constructor)]
+    pub fn new(canvas: HtmlCanvasElement, rom: &[u8]) -> Result<Emulator, JsValue> {
+        if rom.len() > 4096 - 0x200 {
+            return Err(JsValue::from_str("Selected rom is too large for chip8"));
+        }
+        let context: CanvasRenderingContext2d = canvas
+            .get_context("2d")?
+            .ok_or_else(|| JsValue::from_str("The canvas has no 2d context"))?
+            .dyn_into()?;
+        let mut vm = VM::new();
+        vm.init_font_set();
+        vm.load_rom_content("rom.ch8", rom.to_vec());
+        Ok(Emulator { vm, context, beep: None, instructions_per_frame: 8, muted: false })
+    }
+
+    // Instructions executed per 60 Hz frame, 8 unless set
+    #[wasm_bindgen(setter)]
+    pub fn set_instructions_per_frame(&mut self, instructions: u32) {
+        self.instructions_per_frame = instructions.max(1);
+    }
+
+    // A palette name or two colours, like --palette
+    pub fn set_palette(&mut self, palette: &str) -> Result<(), JsValue> {
+        self.vm.palette = parse_palette(palette).map_err(|e| JsValue::from_str(&e))?;
+        self.draw()
+    }
+
+    // Which held key FX0A reports, like --key-wait
+    pub fn set_key_wait(&mut self, policy: &str) -> Result<(), JsValue> {
+        self.vm.key_wait_policy = parse_key_wait(policy).map_err(|e| JsValue::from_str(&e))?;
+        Ok(())
+    }
+
+    // Run one 60 Hz frame and draw it
+    pub fn frame(&mut self) -> Result<(), JsValue> {
+        if !self.vm.paused {
+            self.vm.step_frame(self.instructions_per_frame).map_err(|e| JsValue::from_str(&e))?;
+        }
+        if let Some(beep) = &self.beep {
+            let level = if self.vm.sound > 0 && !self.muted && !self.vm.paused { VOLUME } else { 0.0 };
+            beep.gain.gain().set_value(level);
+        }
+        self.draw()
+    }
+
+    // Press or release the CHIP-8 key on a physical key. Returns whether the key is on the
+    // keypad, so the page can keep it from scrolling and the like.
+    pub fn key(&mut self, code: &str, pressed: bool) -> bool {
+        let Some(key) = KEYS.iter().position(|bound| *bound == code) else { return false };
+        if self.beep.is_none() {
+            // No sound is better than no emulator, in browsers without Web Audio
+            self.beep = Beep::new().ok();
+        }
+        self.vm.keypad[key] = pressed;
+        true
+    }
+
+    pub fn pause(&mut self) {
+        self.vm.pause();
+    }
+
+    pub fn resume(&mut self) {
+        self.vm.resume();
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn paused(&self) -> bool {
+        self.vm.paused
+    }
+
+    // Why it's paused, such as the program halting in an endless loop
+    #[wasm_bindgen(getter)]
+    pub fn pause_message(&self) -> Option<String> {
+        self.vm.pause_message.clone()
+    }
+
+    pub fn restart(&mut self) {
+        self.vm.restart();
+    }
+
+    fn draw(&self) -> Result<(), JsValue> {
+        let palette = self.vm.display_palette();
+        let mut pixels = Vec::with_capacity(64 * 32 * 4);
+        for pixel in self.vm.display {
+            let [r, g, b] = palette[pixel.min(1) as usize];
+            pixels.extend([r, g, b, 0xFF]);
+        }
+        let image = ImageData::new_with_u8_clamped_array_and_sh(Clamped(&pixels), 64, 32)?;
+        self.context.put_image_data(&image, 0.0, 0.0)
+    }
+}
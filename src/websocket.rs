@@ -0,0 +1,336 @@
+// Just enough HTTP and WebSocket for --stream and --api to talk to browsers and scripts: one
+// request per connection, answered and closed, or upgraded to a WebSocket carrying unfragmented
+// messages. Connections are non-blocking and polled from the main loop like the gdb stub.
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{IpAddr, TcpListener, TcpStream};
+
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC11B85";
+// Requests and messages are small, anything bigger is not a client of ours
+const MAX_REQUEST: usize = 1 << 16;
+// A client this far behind on messages isn't reading them, and is dropped
+const MAX_PENDING: usize = 1 << 20;
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    // Set when the client asks to switch to a WebSocket
+    pub websocket_key: Option<String>,
+    // For telling scripts and our own pages from other web sites, see check_origin
+    pub host: Option<String>,
+    pub origin: Option<String>,
+    pub authorization: Option<String>,
+    pub body: Vec<u8>,
+}
+
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+pub struct Connection {
+    stream: TcpStream,
+    // Received and not yet handled, the HTTP request and then WebSocket frames
+    input: Vec<u8>,
+    // Waiting to be written while the socket is full
+    output: Vec<u8>,
+    // Upgraded to a WebSocket
+    pub open: bool,
+    // Set once the response has been queued or the connection failed
+    closing: bool,
+}
+
+pub fn listen(address: &str, what: &str) -> Result<(TcpListener, String), String> {
+    let address = if address.starts_with(':') { format!("127.0.0.1{}", address) } else { address.to_string() };
+    let listener = TcpListener::bind(&address).map_err(|e| format!("Error starting {} on {}, {}", what, address, e))?;
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+    Ok((listener, address))
+}
+
+// Whether a request comes from a script or a page served by `listener` rather than from another web
+// site open in the user's browser, which can send requests here too. Host has to name the server
+// by IP address or as localhost, which DNS rebinding can't fake, and Origin, which browsers send
+// with cross-site requests and WebSocket handshakes, has to be that same host.
+pub fn check_origin(request: &Request, listener: &TcpListener) -> Result<(), String> {
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+    let host = request.host.as_deref().ok_or("no Host header")?;
+    let (name, host_port) = match host.rsplit_once(':') {
+        Some((name, host_port)) if !host_port.contains(']') => (name, host_port.parse().ok()),
+        _ => (host, Some(80)),
+    };
+    let name = name.trim_start_matches('[').trim_end_matches(']');
+    if host_port != Some(port) || !(name.eq_ignore_ascii_case("localhost") || name.parse::<IpAddr>().is_ok()) {
+        return Err(format!("Host {} isn't this server", host));
+    }
+    match &request.origin {
+        Some(origin) if !origin.eq_ignore_ascii_case(&format!("http://{}", host)) => Err(format!("Origin {} isn't allowed", origin)),
+        _ => Ok(()),
+    }
+}
+
+// Connections waiting on the listener
+pub fn accept(listener: &TcpListener) -> Vec<Connection> {
+    let mut connections = Vec::new();
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let _ = stream.set_nonblocking(true);
+                let _ = stream.set_nodelay(true);
+                connections.push(Connection { stream, input: Vec::new(), output: Vec::new(), open: false, closing: false });
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return connections,
+            Err(e) => {
+                eprintln!("{}", e);
+                return connections;
+            }
+        }
+    }
+}
+
+impl Connection {
+    pub fn peer(&self) -> String {
+        self.stream.peer_addr().map_or("unknown".to_string(), |peer| peer.to_string())
+    }
+
+    // Whether it's done with, to be dropped
+    pub fn finished(&self) -> bool {
+        self.closing && self.output.is_empty()
+    }
+
+    pub fn read(&mut self) {
+        let mut buffer = [0; 4096];
+        loop {
+            match self.stream.read(&mut buffer) {
+                // A client sending faster than it's handled is flooding us
+                Ok(n) if n > 0 && self.input.len() + n <= MAX_PENDING => self.input.extend_from_slice(&buffer[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                _ => {
+                    self.closing = true;
+                    self.output.clear();
+                    return;
+                }
+            }
+        }
+    }
+
+    // The HTTP request, once all of it has arrived
+    pub fn request(&mut self) -> Option<Request> {
+        if self.open || self.closing {
+            return None;
+        }
+        let Some(end) = self.input.windows(4).position(|window| window == b"\r\n\r\n") else {
+            if self.input.len() > MAX_REQUEST {
+                self.closing = true;
+            }
+            return None;
+        };
+        let head = String::from_utf8_lossy(&self.input[..end]).to_string();
+        let header = |wanted: &str| {
+            head.lines().skip(1).find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.trim().eq_ignore_ascii_case(wanted).then(|| value.trim().to_string())
+            })
+        };
+        let length: usize = header("content-length").and_then(|length| length.parse().ok()).unwrap_or(0);
+        if length > MAX_REQUEST {
+            self.closing = true;
+            return None;
+        }
+        if end + 4 + length > self.input.len() {
+            return None;
+        }
+        let mut words = head.split_whitespace();
+        let method = words.next().unwrap_or_default().to_string();
+        let path = words.next().unwrap_or_default().to_string();
+        let websocket_key = header("sec-websocket-key");
+        let (host, origin, authorization) = (header("host"), header("origin"), header("authorization"));
+        let body = self.input[end + 4..end + 4 + length].to_vec();
+        self.input.drain(..end + 4 + length);
+        Some(Request { method, path, websocket_key, host, origin, authorization, body })
+    }
+
+    // Answer the request and close the connection
+    pub fn respond(&mut self, status: &str, content_type: &str, body: &[u8]) {
+        let head = format!("HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, content_type, body.len());
+        self.output.extend_from_slice(head.as_bytes());
+        self.output.extend_from_slice(body);
+        self.closing = true;
+        self.flush();
+    }
+
+    // Switch to a WebSocket, `key` being the request's websocket_key
+    pub fn upgrade(&mut self, key: &str) {
+        let accept = base64(&sha1(format!("{}{}", key, WEBSOCKET_GUID).as_bytes()));
+        let head = format!("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n", accept);
+        self.output.extend_from_slice(head.as_bytes());
+        self.open = true;
+        self.flush();
+    }
+
+    // The complete messages received so far, answering pings and closes. A frame that isn't from a
+    // browser closes the connection.
+    pub fn messages(&mut self) -> Vec<Message> {
+        let mut messages = Vec::new();
+        if !self.open || self.closing {
+            return messages;
+        }
+        loop {
+            let (opcode, payload, length) = match parse_frame(&self.input) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break,
+                Err(e) => {
+                    eprintln!("Closing WebSocket from {}, {}", self.peer(), e);
+                    self.input.clear();
+                    self.output.extend(frame(0x8, &[]));
+                    self.closing = true;
+                    break;
+                }
+            };
+            self.input.drain(..length);
+            match opcode {
+                0x1 => messages.push(Message::Text(String::from_utf8_lossy(&payload).to_string())),
+                0x2 => messages.push(Message::Binary(payload)),
+                0x8 => {
+                    self.output.extend(frame(0x8, &[]));
+                    self.closing = true;
+                }
+                0x9 => self.output.extend(frame(0xA, &payload)),
+                _ => {}
+            }
+        }
+        self.flush();
+        messages
+    }
+
+    pub fn send(&mut self, message: &Message) {
+        match message {
+            Message::Text(text) => self.output.extend(frame(0x1, text.as_bytes())),
+            Message::Binary(bytes) => self.output.extend(frame(0x2, bytes)),
+        }
+        self.flush();
+    }
+
+    pub fn flush(&mut self) {
+        while !self.output.is_empty() {
+            match self.stream.write(&self.output) {
+                Ok(n) if n > 0 => {
+                    self.output.drain(..n);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                _ => {
+                    self.closing = true;
+                    self.output.clear();
+                }
+            }
+        }
+        if self.output.len() > MAX_PENDING {
+            self.closing = true;
+            self.output.clear();
+        }
+    }
+}
+
+// An unfragmented, unmasked frame, as servers send them
+fn frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        length @ 0..=125 => frame.push(length as u8),
+        length @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+// A frame from a browser, which are always masked: its opcode, unmasked payload and the bytes it
+// took, or None until all of it has arrived. Browsers don't fragment messages this small.
+fn parse_frame(bytes: &[u8]) -> Result<Option<(u8, Vec<u8>, usize)>, String> {
+    let (Some(first), Some(second)) = (bytes.first(), bytes.get(1)) else { return Ok(None) };
+    if second & 0x80 == 0 {
+        return Err("unmasked frame".to_string());
+    }
+    let (length, start) = match second & 0x7F {
+        126 => match bytes.get(2..4) {
+            Some(length) => (u16::from_be_bytes(length.try_into().unwrap()) as u64, 4),
+            None => return Ok(None),
+        },
+        127 => match bytes.get(2..10) {
+            Some(length) => (u64::from_be_bytes(length.try_into().unwrap()), 10),
+            None => return Ok(None),
+        },
+        length => (length as u64, 2),
+    };
+    if length > MAX_REQUEST as u64 {
+        return Err(format!("frame of {} bytes", length));
+    }
+    let end = start + 4 + length as usize;
+    let Some(frame) = bytes.get(..end) else { return Ok(None) };
+    let mask = &frame[start..start + 4];
+    let payload = frame[start + 4..].iter().enumerate().map(|(index, byte)| byte ^ mask[index % 4]).collect();
+    Ok(Some((first & 0x0F, payload, end)))
+}
+
+// SHA-1, only for the handshake's Sec-WebSocket-Accept
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut words = [0u32; 80];
+        for (index, word) in block.chunks(4).enumerate() {
+            words[index] = u32::from_be_bytes(word.try_into().unwrap());
+        }
+        for index in 16..80 {
+            words[index] = (words[index - 3] ^ words[index - 8] ^ words[index - 14] ^ words[index - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (index, word) in words.iter().enumerate() {
+            let (f, k) = match index {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+    let mut digest = [0; 20];
+    for (bytes, value) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (index, byte)| bits | (*byte as u32) << (16 - 8 * index));
+        for index in 0..4 {
+            if index <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * index) & 0x3F) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}